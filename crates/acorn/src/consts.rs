@@ -1,8 +1,19 @@
 use std::time::Duration;
 
-use crate::utils::units::{GIB, KIB};
+use crate::{
+	files::checksum::ChecksumAlgorithm,
+	utils::{
+		cache::ReplacementPolicyKind,
+		units::{GIB, KIB, MIB},
+	},
+};
 
-pub(crate) const PAGE_SIZE: usize = 32 * KIB;
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 32 * KIB;
+pub(crate) const MIN_PAGE_SIZE: usize = 4 * KIB;
+// In-page offsets are 16-bit, so pages can't be larger than this
+pub(crate) const MAX_PAGE_SIZE: usize = 64 * KIB;
+pub(crate) const DEFAULT_CHECKSUM_ALGORITHM: ChecksumAlgorithm = ChecksumAlgorithm::Crc32c;
+pub(crate) const DEFAULT_SEGMENT_EXTENT_SIZE: usize = 16 * MIB;
 pub(crate) const DEFAULT_MAX_NUM_OPEN_SEGMENTS: usize = 512;
 pub(crate) const DEFAULT_MAX_WAL_GENERATION_SIZE: usize = 4 * GIB;
 pub(crate) const DEFAULT_PAGE_CACHE_SIZE: usize = 2 * GIB;
@@ -10,3 +21,9 @@ pub(crate) const DEFAULT_MAX_DIRTY_PAGES: f32 = 0.2;
 pub(crate) const DEFAULT_NUM_WORKERS: usize = 2;
 pub(crate) const DEFAULT_CHECKPOINT_PERIOD: Duration = Duration::from_secs(60);
 pub(crate) const DEFAULT_FLUSH_PERIOD: Duration = Duration::from_secs(3 * 60);
+// In bytes per second
+pub(crate) const DEFAULT_MAX_WRITE_RATE: Option<usize> = Some(256 * MIB);
+pub(crate) const DEFAULT_EVICTION_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+pub(crate) const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const DEFAULT_REPLACEMENT_POLICY: ReplacementPolicyKind = ReplacementPolicyKind::Car;
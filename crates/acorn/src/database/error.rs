@@ -0,0 +1,168 @@
+use thiserror::Error;
+
+use crate::{doc_store::DatabaseError, files::FileError, page_store::StorageError};
+
+use super::DocumentId;
+
+/// What kind of failure an [`Error`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+	/// The options passed to `create` or `open` are invalid.
+	InvalidOptions,
+	/// The database or the document doesn't exist.
+	NotFound,
+	/// A database already exists at the path.
+	AlreadyExists,
+	/// The database is in use by another process.
+	Locked,
+	/// The database can't be written to, either because it was opened
+	/// read-only, or because a background job failed.
+	ReadOnly,
+	/// The database was closed.
+	Closed,
+	/// The transaction was aborted because the database was closed.
+	Aborted,
+	/// The document doesn't fit into a page.
+	DocumentTooLarge,
+	/// The document doesn't match its schema.
+	InvalidDocument,
+	/// Too many transactions or pages are in use at the same time.
+	ResourceExhausted,
+	/// The database was written by an incompatible version of acorn.
+	Incompatible,
+	/// The database is encrypted, and the key is missing or wrong.
+	Encryption,
+	/// The database files are corrupted.
+	Corrupted,
+	/// An IO operation failed.
+	Io,
+}
+
+/// The error returned by all operations of a [`Database`](super::Database).
+#[derive(Debug, Error)]
+#[error(transparent)]
+pub struct Error(Repr);
+
+#[derive(Debug, Error)]
+enum Repr {
+	#[error("Invalid options: {0}")]
+	InvalidOptions(String),
+
+	#[error("Document {0} doesn't exist")]
+	NotFound(DocumentId),
+
+	#[error(transparent)]
+	Database(#[from] DatabaseError),
+
+	#[error(transparent)]
+	Storage(#[from] StorageError),
+
+	#[error(transparent)]
+	File(#[from] FileError),
+}
+
+impl Error {
+	pub(super) fn invalid_options(message: impl Into<String>) -> Self {
+		Self(Repr::InvalidOptions(message.into()))
+	}
+
+	pub(super) fn not_found(id: DocumentId) -> Self {
+		Self(Repr::NotFound(id))
+	}
+
+	pub fn kind(&self) -> ErrorKind {
+		match &self.0 {
+			Repr::InvalidOptions(..) => ErrorKind::InvalidOptions,
+			Repr::NotFound(..) => ErrorKind::NotFound,
+			Repr::Database(error) => database_error_kind(error),
+			Repr::Storage(error) => storage_error_kind(error),
+			Repr::File(error) => file_error_kind(error),
+		}
+	}
+}
+
+impl From<DatabaseError> for Error {
+	fn from(value: DatabaseError) -> Self {
+		Self(value.into())
+	}
+}
+
+impl From<StorageError> for Error {
+	fn from(value: StorageError) -> Self {
+		Self(value.into())
+	}
+}
+
+impl From<FileError> for Error {
+	fn from(value: FileError) -> Self {
+		Self(value.into())
+	}
+}
+
+fn database_error_kind(error: &DatabaseError) -> ErrorKind {
+	match error {
+		DatabaseError::DocumentTooLarge { .. } => ErrorKind::DocumentTooLarge,
+		DatabaseError::PageFull => ErrorKind::ResourceExhausted,
+		DatabaseError::Validation(..) => ErrorKind::InvalidDocument,
+		DatabaseError::Storage(error) => storage_error_kind(error),
+		DatabaseError::PageFormat(..)
+		| DatabaseError::UnexpectedPageKind { .. }
+		| DatabaseError::PageOutOfBounds
+		| DatabaseError::InvalidRecordIndex
+		| DatabaseError::StringEncoding(..) => ErrorKind::Corrupted,
+		DatabaseError::UnknownPageKind(..) => ErrorKind::Incompatible,
+	}
+}
+
+fn storage_error_kind(error: &StorageError) -> ErrorKind {
+	match error {
+		StorageError::ReadOnly | StorageError::Degraded(..) => ErrorKind::ReadOnly,
+		StorageError::Closed => ErrorKind::Closed,
+		StorageError::TransactionAborted | StorageError::TransactionsAborted(..) => {
+			ErrorKind::Aborted
+		}
+		StorageError::TransactionLimitReached
+		| StorageError::PinLimitReached
+		| StorageError::CacheExhausted => ErrorKind::ResourceExhausted,
+		StorageError::WalNotInitialized => ErrorKind::Corrupted,
+		StorageError::File(error) => file_error_kind(error),
+	}
+}
+
+fn file_error_kind(error: &FileError) -> ErrorKind {
+	match error {
+		FileError::MissingManifest(..) => ErrorKind::NotFound,
+		FileError::AlreadyExists(..) => ErrorKind::AlreadyExists,
+		FileError::Locked(..) => ErrorKind::Locked,
+		FileError::ReadOnly => ErrorKind::ReadOnly,
+		FileError::InvalidPageSize(..)
+		| FileError::InvalidSegmentSize(..)
+		| FileError::InvalidExtentSize(..)
+		| FileError::CompressedEncryption => ErrorKind::InvalidOptions,
+		FileError::IncompatibleVersion(..)
+		| FileError::IncompatibleDatabaseVersion(..)
+		| FileError::IncompatiblePageVersion(..) => ErrorKind::Incompatible,
+		FileError::MissingKey | FileError::InvalidKey | FileError::NotEncrypted => {
+			ErrorKind::Encryption
+		}
+		FileError::MissingMagic
+		| FileError::Corrupted(..)
+		| FileError::WrongFileType(..)
+		| FileError::PageOutOfRange(..)
+		| FileError::PageSizeMismatch { .. }
+		| FileError::ChecksumAlgorithmMismatch { .. }
+		| FileError::Tampered
+		| FileError::UnexpectedEof
+		| FileError::ChecksumMismatch
+		| FileError::UnexpectedFile(..)
+		| FileError::MissingSegment(..) => ErrorKind::Corrupted,
+		FileError::TooManyConcurrent
+		| FileError::ConcurrentReadFail(..)
+		| FileError::ConcurrentWriteFail(..)
+		| FileError::Unexpected
+		| FileError::Io(..) => ErrorKind::Io,
+		#[cfg(feature = "io_uring")]
+		FileError::IoQueuePush(..) => ErrorKind::Io,
+	}
+}
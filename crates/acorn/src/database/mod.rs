@@ -0,0 +1,483 @@
+use std::{fmt, num::NonZeroU16, path::PathBuf, sync::Arc};
+
+use futures::executor::ThreadPool;
+use log::warn;
+use static_assertions::assert_impl_all;
+
+use crate::{
+	doc_store::{DbPointer, DocStore, Document, Schema},
+	files::{DatabaseFolder, FileError},
+	page_store::{self, PageAddress, PageStorage, PageStorageApi, TransactionApi},
+	tasks::Scheduler,
+};
+
+pub use error::{Error, ErrorKind};
+pub use options::Options;
+
+mod error;
+mod options;
+
+/// Identifies a document in a database. Ids stay valid until the document is
+/// deleted, after which they may be reused for new documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DocumentId(DbPointer);
+
+impl DocumentId {
+	/// Packs the id into 64 bits, e.g. to store it in another document.
+	pub fn to_bits(self) -> u64 {
+		let page_address = self.0.page_address();
+		(u64::from(page_address.segment_num) << 32)
+			| (u64::from(page_address.page_num.get()) << 16)
+			| u64::from(self.0.index())
+	}
+
+	/// Unpacks an id packed by [`DocumentId::to_bits`]. Returns `None` if the
+	/// bits can't be an id.
+	pub fn from_bits(bits: u64) -> Option<Self> {
+		let segment_num = u32::try_from(bits >> 32).ok()?;
+		let page_num = NonZeroU16::new(u16::try_from((bits >> 16) & 0xffff).ok()?)?;
+		let index = u16::try_from(bits & 0xffff).ok()?;
+		Some(Self(DbPointer::new(
+			PageAddress::new(segment_num, page_num),
+			index,
+		)))
+	}
+}
+
+impl fmt::Display for DocumentId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{:04x}", self.0.page_address(), self.0.index())
+	}
+}
+
+/// A database stored in a folder on disk.
+///
+/// All reads and writes happen in a [`Transaction`]. Dropping the database
+/// closes it like [`Database::close`], but only logs errors.
+pub struct Database {
+	storage: PageStorage,
+	schema: Schema,
+	closed: bool,
+}
+assert_impl_all!(Database: Send, Sync);
+
+impl Database {
+	/// Creates a new database in the folder at `path`, which must not contain a
+	/// database yet.
+	pub fn create(path: impl Into<PathBuf>, options: Options) -> Result<Self, Error> {
+		options.validate()?;
+		options.validate_page_cache_size(options.segment.page_size)?;
+		let Some(schema) = options.schema.clone() else {
+			return Err(Error::invalid_options(
+				"A schema is required to create a database",
+			));
+		};
+		let folder = Arc::new(match &options.key_provider {
+			Some(key_provider) => DatabaseFolder::create_encrypted(
+				path.into(),
+				options.segment.clone(),
+				key_provider.0.as_ref(),
+			)?,
+			None => DatabaseFolder::create(path.into(), options.segment.clone())?,
+		});
+		let storage = PageStorage::create(folder, Self::scheduler(&options)?, &options.storage)?;
+		let mut t = storage.transaction()?;
+		DocStore::init(&mut t, options.segment.last_page_num(), &schema)?;
+		t.commit()?;
+		Ok(Self {
+			storage,
+			schema,
+			closed: false,
+		})
+	}
+
+	/// Opens the database in the folder at `path`. If it wasn't closed cleanly,
+	/// committed transactions are restored, and incomplete ones are undone.
+	pub fn open(path: impl Into<PathBuf>, options: Options) -> Result<Self, Error> {
+		options.validate()?;
+		let path = path.into();
+		let key_provider = options
+			.key_provider
+			.as_ref()
+			.map(|key_provider| key_provider.0.as_ref());
+		let folder = match (options.read_only, key_provider) {
+			(false, None) => DatabaseFolder::open(path)?,
+			(false, Some(key_provider)) => DatabaseFolder::open_encrypted(path, key_provider)?,
+			(true, None) => DatabaseFolder::open_read_only(path)?,
+			(true, Some(key_provider)) => {
+				DatabaseFolder::open_read_only_encrypted(path, key_provider)?
+			}
+		};
+		options.validate_page_cache_size(folder.segment_config().page_size)?;
+		let storage = PageStorage::open(
+			Arc::new(folder),
+			Self::scheduler(&options)?,
+			&options.storage,
+		)?;
+		storage.recover()?;
+		let schema = DocStore::schema(&storage)?;
+		if options
+			.schema
+			.as_ref()
+			.is_some_and(|expected| *expected != schema)
+		{
+			return Err(Error::invalid_options(format!(
+				"The database was created with a different schema: {schema}"
+			)));
+		}
+		Ok(Self {
+			storage,
+			schema,
+			closed: false,
+		})
+	}
+
+	fn scheduler(options: &Options) -> Result<Arc<dyn Scheduler>, Error> {
+		let thread_pool = ThreadPool::builder()
+			.pool_size(options.num_workers)
+			.name_prefix("acorn-worker-")
+			.create()
+			.map_err(FileError::from)?;
+		Ok(Arc::new(thread_pool))
+	}
+
+	/// Starts a transaction. Fails with [`ErrorKind::ReadOnly`] if the database
+	/// was opened read-only; documents can still be read with a [`Reader`].
+	pub fn transaction(&self) -> Result<Transaction<'_>, Error> {
+		Ok(Transaction {
+			t: self.storage.transaction()?,
+			schema: &self.schema,
+		})
+	}
+
+	/// Reads documents without starting a transaction, which also works if the
+	/// database was opened read-only. See [`Reader`] for what its reads see.
+	pub fn reader(&self) -> Reader<'_> {
+		Reader {
+			storage: &self.storage,
+			schema: &self.schema,
+		}
+	}
+
+	/// The schema that every document matches.
+	pub fn schema(&self) -> &Schema {
+		&self.schema
+	}
+
+	/// Waits for active transactions to finish, writes all changes to disk and
+	/// closes the database, so that the next open doesn't need to recover it.
+	/// Transactions that don't finish within the shutdown timeout are aborted.
+	pub fn close(mut self) -> Result<(), Error> {
+		self.closed = true;
+		self.storage.close()?;
+		Ok(())
+	}
+}
+
+impl Drop for Database {
+	fn drop(&mut self) {
+		if self.closed {
+			return;
+		}
+		if let Err(err) = self.storage.close() {
+			warn!("Failed to close the database: {err}");
+		}
+	}
+}
+
+/// A set of reads and writes that is applied atomically once it is committed.
+///
+/// Documents that are written are locked until the transaction ends. A
+/// transaction that is dropped without being committed is aborted.
+pub struct Transaction<'db> {
+	t: page_store::Transaction<'db>,
+	schema: &'db Schema,
+}
+
+impl Transaction<'_> {
+	/// Stores a new document, and returns its id. Fails with
+	/// [`ErrorKind::InvalidDocument`] if it doesn't match the schema.
+	pub fn insert(&mut self, document: &Document) -> Result<DocumentId, Error> {
+		Ok(DocumentId(DocStore::insert(
+			&mut self.t,
+			self.schema,
+			document,
+		)?))
+	}
+
+	/// Reads a document, or returns `None` if it doesn't exist.
+	pub fn get(&self, id: DocumentId) -> Result<Option<Document>, Error> {
+		Ok(DocStore::get(&self.t, self.schema, id.0)?)
+	}
+
+	/// Replaces a document. Fails with [`ErrorKind::NotFound`] if it doesn't
+	/// exist.
+	pub fn update(&mut self, id: DocumentId, document: &Document) -> Result<(), Error> {
+		if !DocStore::update(&mut self.t, self.schema, id.0, document)? {
+			return Err(Error::not_found(id));
+		}
+		Ok(())
+	}
+
+	/// Deletes a document. Fails with [`ErrorKind::NotFound`] if it doesn't
+	/// exist.
+	pub fn delete(&mut self, id: DocumentId) -> Result<(), Error> {
+		if !DocStore::delete(&mut self.t, self.schema, id.0)? {
+			return Err(Error::not_found(id));
+		}
+		Ok(())
+	}
+
+	/// Makes all changes of the transaction durable.
+	pub fn commit(self) -> Result<(), Error> {
+		self.t.commit()?;
+		Ok(())
+	}
+
+	/// Undoes all changes of the transaction.
+	pub fn abort(self) -> Result<(), Error> {
+		self.t.undo()?;
+		Ok(())
+	}
+}
+
+/// Reads documents outside of a transaction.
+///
+/// A reader is not a snapshot: every read sees the latest committed state of
+/// the pages it loads, waiting for transactions that are writing them. Two
+/// reads may therefore see the database before and after the same transaction.
+///
+/// A read waits for the transaction that wrote the document to end, so reading
+/// a document on a thread whose open transaction has written it never returns.
+/// Use [`Transaction::get`] there instead.
+pub struct Reader<'db> {
+	storage: &'db PageStorage,
+	schema: &'db Schema,
+}
+
+impl Reader<'_> {
+	/// Reads a document, or returns `None` if it doesn't exist.
+	pub fn get(&self, id: DocumentId) -> Result<Option<Document>, Error> {
+		Ok(DocStore::get(self.storage, self.schema, id.0)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use tempfile::tempdir;
+
+	use uuid::Uuid;
+
+	use crate::{
+		doc_store::{Primitive, Value},
+		files::encryption::{EncryptionKey, KeyProvider},
+		utils::units::{KIB, MIB},
+	};
+
+	use super::*;
+
+	fn schema() -> Schema {
+		Schema::Struct(HashMap::from([(
+			"name".to_string(),
+			Box::new(Schema::Primitive(Primitive::String)),
+		)]))
+	}
+
+	fn options() -> Options {
+		Options::new().page_cache_size(4 * MIB).schema(schema())
+	}
+
+	fn document(name: &str) -> Document {
+		Document::Struct(HashMap::from([(
+			"name".to_string(),
+			Document::Value(Value::String(name.to_string())),
+		)]))
+	}
+
+	struct StaticKeyProvider(EncryptionKey);
+
+	impl KeyProvider for StaticKeyProvider {
+		fn key(&self, _database_id: Uuid) -> Option<EncryptionKey> {
+			Some(self.0)
+		}
+	}
+
+	#[test]
+	fn write_and_read_documents() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let path = tempdir.path().join("db");
+		let db = Database::create(&path, options()).unwrap();
+
+		// when
+		let mut t = db.transaction().unwrap();
+		let kept = t.insert(&document("kept")).unwrap();
+		let deleted = t.insert(&document("deleted")).unwrap();
+		t.commit().unwrap();
+		let mut t = db.transaction().unwrap();
+		t.delete(deleted).unwrap();
+		t.commit().unwrap();
+		let mut t = db.transaction().unwrap();
+		t.update(kept, &document("aborted")).unwrap();
+		t.abort().unwrap();
+		db.close().unwrap();
+
+		let db = Database::open(&path, options()).unwrap();
+		let mut t = db.transaction().unwrap();
+
+		// then
+		assert_eq!(db.schema(), &schema());
+		assert_eq!(t.get(kept).unwrap(), Some(document("kept")));
+		assert_eq!(t.get(deleted).unwrap(), None);
+		assert_eq!(t.delete(deleted).unwrap_err().kind(), ErrorKind::NotFound);
+	}
+
+	#[test]
+	fn read_documents_after_read_only_open() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let path = tempdir.path().join("db");
+		let db = Database::create(&path, options()).unwrap();
+		let mut t = db.transaction().unwrap();
+		let kept = t.insert(&document("kept")).unwrap();
+		let deleted = t.insert(&document("deleted")).unwrap();
+		t.delete(deleted).unwrap();
+		t.commit().unwrap();
+		db.close().unwrap();
+
+		// when
+		let db = Database::open(&path, Options::new().read_only(true)).unwrap();
+		let reader = db.reader();
+
+		// then
+		assert_eq!(reader.get(kept).unwrap(), Some(document("kept")));
+		assert_eq!(reader.get(deleted).unwrap(), None);
+		assert_eq!(
+			db.transaction().map(|_| ()).unwrap_err().kind(),
+			ErrorKind::ReadOnly
+		);
+	}
+
+	#[test]
+	fn read_later_commits_with_reader() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let db = Database::create(tempdir.path().join("db"), options()).unwrap();
+		let mut t = db.transaction().unwrap();
+		let id = t.insert(&document("before")).unwrap();
+		t.commit().unwrap();
+		let reader = db.reader();
+		let before = reader.get(id).unwrap();
+
+		// when
+		let mut t = db.transaction().unwrap();
+		t.update(id, &document("after")).unwrap();
+		t.commit().unwrap();
+
+		// then
+		assert_eq!(before, Some(document("before")));
+		assert_eq!(reader.get(id).unwrap(), Some(document("after")));
+	}
+
+	#[test]
+	fn validate_page_cache_size_with_stored_page_size() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let path = tempdir.path().join("db");
+		Database::create(&path, options().page_size(64 * KIB))
+			.unwrap()
+			.close()
+			.unwrap();
+
+		// when
+		let result = Database::open(&path, options().page_cache_size(512 * KIB)).map(|_| ());
+
+		// then
+		assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidOptions);
+		assert!(Database::open(&path, options().page_cache_size(MIB)).is_ok());
+	}
+
+	#[test]
+	fn open_encrypted_database() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let path = tempdir.path().join("db");
+		let db =
+			Database::create(&path, options().key_provider(StaticKeyProvider([1; 32]))).unwrap();
+		let mut t = db.transaction().unwrap();
+		let id = t.insert(&document("secret")).unwrap();
+		t.commit().unwrap();
+		db.close().unwrap();
+
+		// when
+		let missing_key = Database::open(&path, options()).map(|_| ());
+		let wrong_key =
+			Database::open(&path, options().key_provider(StaticKeyProvider([2; 32]))).map(|_| ());
+		let db = Database::open(&path, options().key_provider(StaticKeyProvider([1; 32]))).unwrap();
+
+		// then
+		assert_eq!(missing_key.unwrap_err().kind(), ErrorKind::Encryption);
+		assert_eq!(wrong_key.unwrap_err().kind(), ErrorKind::Encryption);
+		assert_eq!(db.reader().get(id).unwrap(), Some(document("secret")));
+	}
+
+	#[test]
+	fn report_error_kinds() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let path = tempdir.path().join("db");
+		Database::create(&path, options()).unwrap().close().unwrap();
+
+		// when
+		let exists = Database::create(&path, options()).map(|_| ());
+		let missing = Database::open(tempdir.path().join("missing"), options()).map(|_| ());
+		let invalid = Database::open(&path, options().num_workers(0)).map(|_| ());
+		let no_schema =
+			Database::create(tempdir.path().join("no_schema"), Options::new()).map(|_| ());
+		let other_schema = Database::open(&path, options().schema(Schema::Empty)).map(|_| ());
+		let compressed_encryption = Database::create(
+			tempdir.path().join("compressed"),
+			options()
+				.compress_pages(true)
+				.key_provider(StaticKeyProvider([1; 32])),
+		)
+		.map(|_| ());
+		let db = Database::open(&path, options()).unwrap();
+		let locked = Database::open(&path, options()).map(|_| ());
+		let read_only_locked = Database::open(&path, options().read_only(true)).map(|_| ());
+		let mut t = db.transaction().unwrap();
+		let mismatch = t
+			.insert(&Document::Value(Value::String("acorn".to_string())))
+			.map(|_| ());
+
+		// then
+		assert_eq!(exists.unwrap_err().kind(), ErrorKind::AlreadyExists);
+		assert_eq!(missing.unwrap_err().kind(), ErrorKind::NotFound);
+		assert_eq!(invalid.unwrap_err().kind(), ErrorKind::InvalidOptions);
+		assert_eq!(no_schema.unwrap_err().kind(), ErrorKind::InvalidOptions);
+		assert_eq!(other_schema.unwrap_err().kind(), ErrorKind::InvalidOptions);
+		assert_eq!(
+			compressed_encryption.unwrap_err().kind(),
+			ErrorKind::InvalidOptions
+		);
+		assert_eq!(locked.unwrap_err().kind(), ErrorKind::Locked);
+		assert_eq!(read_only_locked.unwrap_err().kind(), ErrorKind::Locked);
+		assert_eq!(mismatch.unwrap_err().kind(), ErrorKind::InvalidDocument);
+	}
+
+	#[test]
+	fn pack_document_ids() {
+		// given
+		let id = DocumentId(DbPointer::new(PageAddress::new_unwrap(69, 420), 3));
+
+		// when
+		let bits = id.to_bits();
+
+		// then
+		assert_eq!(DocumentId::from_bits(bits), Some(id));
+		assert_eq!(DocumentId::from_bits(0), None);
+		assert_eq!(id.to_string(), "00000045:01a4:0003");
+	}
+}
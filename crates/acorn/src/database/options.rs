@@ -0,0 +1,335 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{
+	consts::{DEFAULT_NUM_WORKERS, MAX_PAGE_SIZE, MIN_PAGE_SIZE},
+	doc_store::Schema,
+	files::{checksum::ChecksumAlgorithm, encryption::KeyProvider, segment::SegmentConfig},
+	page_store::PageStorageConfig,
+};
+
+use super::Error;
+
+/// The fewest pages the page cache has to hold, so that a transaction can lock
+/// every page a document operation needs.
+const MIN_CACHED_PAGES: usize = 16;
+
+/// Options for creating or opening a [`Database`](super::Database).
+///
+/// Every option has a sensible default, and the builder methods only need to
+/// be called for the ones that should differ. The options are validated when
+/// the database is created or opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+	pub(super) segment: SegmentConfig,
+	pub(super) storage: PageStorageConfig,
+	pub(super) num_workers: usize,
+	pub(super) read_only: bool,
+	pub(super) schema: Option<Schema>,
+	pub(super) key_provider: Option<SharedKeyProvider>,
+}
+
+/// A key provider that is shared by all clones of the options.
+#[derive(Clone)]
+pub(super) struct SharedKeyProvider(pub Arc<dyn KeyProvider + Send + Sync>);
+
+impl fmt::Debug for SharedKeyProvider {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("KeyProvider")
+	}
+}
+
+impl PartialEq for SharedKeyProvider {
+	fn eq(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			segment: SegmentConfig::default(),
+			storage: PageStorageConfig::default(),
+			num_workers: DEFAULT_NUM_WORKERS,
+			read_only: false,
+			schema: None,
+			key_provider: None,
+		}
+	}
+}
+
+impl Options {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The size of a page in bytes, which must be a power of two between 4 KiB
+	/// and 64 KiB. This only applies when a database is created; an existing
+	/// database keeps the page size it was created with.
+	pub fn page_size(mut self, page_size: usize) -> Self {
+		self.segment.page_size = page_size;
+		self
+	}
+
+	/// Whether pages are compressed before they are written. This only applies
+	/// when a database is created.
+	pub fn compress_pages(mut self, compress_pages: bool) -> Self {
+		self.segment.compress_pages = compress_pages;
+		self
+	}
+
+	/// The algorithm that protects pages and WAL items against corruption. This
+	/// only applies when a database is created.
+	pub fn checksum(mut self, checksum: ChecksumAlgorithm) -> Self {
+		self.segment.checksum = checksum;
+		self
+	}
+
+	/// The size in bytes up to which a segment file grows before pages are put
+	/// into the next one, or `None` for the largest size that 16-bit page
+	/// numbers can address. It must hold at least two pages. This only applies
+	/// when a database is created.
+	pub fn max_segment_size(mut self, max_segment_size: Option<usize>) -> Self {
+		self.segment.max_segment_size = max_segment_size;
+		self
+	}
+
+	/// The amount in bytes by which a segment file is extended when a page is
+	/// written past its end. This only applies when a database is created.
+	pub fn segment_extent_size(mut self, extent_size: usize) -> Self {
+		self.segment.extent_size = extent_size;
+		self
+	}
+
+	/// Encrypts the database with the key that the provider returns for its id.
+	/// It is required to open an encrypted database, and can't be combined with
+	/// compressed pages.
+	pub fn key_provider(mut self, key_provider: impl KeyProvider + Send + Sync + 'static) -> Self {
+		self.key_provider = Some(SharedKeyProvider(Arc::new(key_provider)));
+		self
+	}
+
+	/// How many segment files may be open at the same time.
+	pub fn max_open_segments(mut self, max_open_segments: usize) -> Self {
+		self.storage.physical_storage.max_num_open_segments = max_open_segments;
+		self
+	}
+
+	/// The size of the page cache in bytes.
+	pub fn page_cache_size(mut self, page_cache_size: usize) -> Self {
+		self.storage.page_cache.page_cache_size = page_cache_size;
+		self
+	}
+
+	/// The fraction of cached pages that may be dirty, between 0 and 1.
+	pub fn max_dirty_pages(mut self, max_dirty_pages: f32) -> Self {
+		self.storage.page_cache.max_dirty_pages = max_dirty_pages;
+		self
+	}
+
+	/// How often all dirty pages are written back in the background.
+	pub fn flush_period(mut self, flush_period: Duration) -> Self {
+		self.storage.page_cache.flush_period = flush_period;
+		self
+	}
+
+	/// The maximum number of bytes per second that are written back in the
+	/// background, or `None` if it isn't limited.
+	pub fn max_write_rate(mut self, max_write_rate: Option<usize>) -> Self {
+		self.storage.page_cache.max_write_rate = max_write_rate;
+		self
+	}
+
+	/// How long loading a page waits for a cached page to be unlocked when every
+	/// cached page is in use.
+	pub fn eviction_timeout(mut self, eviction_timeout: Duration) -> Self {
+		self.storage.page_cache.eviction_timeout = eviction_timeout;
+		self
+	}
+
+	/// The size in bytes after which the WAL starts a new file.
+	pub fn max_wal_file_size(mut self, max_wal_file_size: usize) -> Self {
+		self.storage.wal.max_generation_size = max_wal_file_size;
+		self
+	}
+
+	/// How often a WAL checkpoint is taken in the background.
+	pub fn checkpoint_period(mut self, checkpoint_period: Duration) -> Self {
+		self.storage.wal.checkpoint_period = checkpoint_period;
+		self
+	}
+
+	/// How long closing the database waits for active transactions before
+	/// aborting them.
+	pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+		self.storage.shutdown_timeout = shutdown_timeout;
+		self
+	}
+
+	/// The number of threads that run background jobs.
+	pub fn num_workers(mut self, num_workers: usize) -> Self {
+		self.num_workers = num_workers;
+		self
+	}
+
+	/// Whether the database is opened read-only. A read-only database never
+	/// writes to its files. Any number of read-only handles can have it open at
+	/// the same time, but not while it is open for writing; opening then fails
+	/// with [`ErrorKind::Locked`](super::ErrorKind::Locked).
+	pub fn read_only(mut self, read_only: bool) -> Self {
+		self.read_only = read_only;
+		self
+	}
+
+	/// The schema that every document has to match. It is required to create a
+	/// database, and is stored with it. When a database is opened, it can be
+	/// left out, and otherwise has to be the one the database was created with.
+	pub fn schema(mut self, schema: Schema) -> Self {
+		self.schema = Some(schema);
+		self
+	}
+
+	pub(super) fn validate(&self) -> Result<(), Error> {
+		let page_size = self.segment.page_size;
+		if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+			return Err(Error::invalid_options(format!(
+				"The page size must be a power of two between {MIN_PAGE_SIZE} and {MAX_PAGE_SIZE} bytes, but is {page_size}"
+			)));
+		}
+
+		let physical_storage = &self.storage.physical_storage;
+		if physical_storage.max_num_open_segments == 0 {
+			return Err(Error::invalid_options(
+				"At least one segment file must be allowed to be open",
+			));
+		}
+
+		let page_cache = &self.storage.page_cache;
+		if !(page_cache.max_dirty_pages > 0.0 && page_cache.max_dirty_pages <= 1.0) {
+			return Err(Error::invalid_options(format!(
+				"The fraction of dirty pages must lie in (0, 1], but is {}",
+				page_cache.max_dirty_pages
+			)));
+		}
+		if page_cache.flush_period.is_zero() {
+			return Err(Error::invalid_options("The flush period must not be zero"));
+		}
+		if page_cache.max_write_rate == Some(0) {
+			return Err(Error::invalid_options(
+				"The write rate must not be zero; use `None` to not limit it",
+			));
+		}
+
+		let wal = &self.storage.wal;
+		if wal.max_generation_size == 0 {
+			return Err(Error::invalid_options(
+				"The maximum WAL file size must not be zero",
+			));
+		}
+		if wal.checkpoint_period.is_zero() {
+			return Err(Error::invalid_options(
+				"The checkpoint period must not be zero",
+			));
+		}
+
+		if self.num_workers == 0 {
+			return Err(Error::invalid_options(
+				"At least one worker thread is needed",
+			));
+		}
+		Ok(())
+	}
+
+	/// Checks that the page cache holds enough pages of `page_size`, which is
+	/// the configured one for a new database, and the stored one for an
+	/// existing database.
+	pub(super) fn validate_page_cache_size(&self, page_size: usize) -> Result<(), Error> {
+		let page_cache_size = self.storage.page_cache.page_cache_size;
+		let min_page_cache_size = MIN_CACHED_PAGES * page_size;
+		if page_cache_size < min_page_cache_size {
+			return Err(Error::invalid_options(format!(
+				"The page cache must hold at least {MIN_CACHED_PAGES} pages, which takes {min_page_cache_size} bytes, but is {page_cache_size} bytes"
+			)));
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		database::ErrorKind,
+		utils::units::{KIB, MIB},
+	};
+
+	use super::*;
+
+	#[test]
+	fn build_options() {
+		// when
+		let options = Options::new()
+			.page_size(4 * KIB)
+			.page_cache_size(64 * KIB)
+			.checksum(ChecksumAlgorithm::Crc32c)
+			.max_segment_size(Some(MIB))
+			.segment_extent_size(256 * KIB)
+			.max_write_rate(None)
+			.checkpoint_period(Duration::from_secs(5));
+
+		// then
+		assert!(options.validate().is_ok());
+		assert!(options.validate_page_cache_size(4 * KIB).is_ok());
+		assert_eq!(options.segment.page_size, 4 * KIB);
+		assert_eq!(options.segment.checksum, ChecksumAlgorithm::Crc32c);
+		assert_eq!(options.segment.max_segment_size, Some(MIB));
+		assert_eq!(options.segment.extent_size, 256 * KIB);
+		assert_eq!(options.storage.page_cache.page_cache_size, 64 * KIB);
+		assert_eq!(options.storage.page_cache.max_write_rate, None);
+		assert_eq!(
+			options.storage.wal.checkpoint_period,
+			Duration::from_secs(5)
+		);
+		assert!(Options::default().validate().is_ok());
+	}
+
+	#[test]
+	fn reject_invalid_options() {
+		for options in [
+			Options::new().page_size(3000),
+			Options::new().page_size(128 * KIB),
+			Options::new().max_open_segments(0),
+			Options::new().max_dirty_pages(0.0),
+			Options::new().max_dirty_pages(1.5),
+			Options::new().max_dirty_pages(f32::NAN),
+			Options::new().flush_period(Duration::ZERO),
+			Options::new().max_write_rate(Some(0)),
+			Options::new().max_wal_file_size(0),
+			Options::new().checkpoint_period(Duration::ZERO),
+			Options::new().num_workers(0),
+		] {
+			// when
+			let result = options.validate();
+
+			// then
+			assert_eq!(
+				result.map_err(|err| err.kind()).unwrap_err(),
+				ErrorKind::InvalidOptions,
+				"{options:?} should be invalid"
+			);
+		}
+	}
+
+	#[test]
+	fn reject_small_page_cache() {
+		// given
+		let options = Options::new().page_size(4 * KIB).page_cache_size(64 * KIB);
+
+		// when
+		let result = options.validate_page_cache_size(8 * KIB);
+
+		// then
+		assert_eq!(
+			result.map_err(|err| err.kind()).unwrap_err(),
+			ErrorKind::InvalidOptions
+		);
+	}
+}
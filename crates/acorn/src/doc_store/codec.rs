@@ -0,0 +1,939 @@
+//! Stores documents that match a schema. A document is laid out as a
+//! [`StoredDocument`], whose fixed-size part is a record on a records page,
+//! so that all documents of a schema can share the same pages. Strings, lists
+//! and maps are stored out of line and referenced by a [`DbPointer`]: values
+//! that fit into a page are records on slotted pages, larger ones are chains of
+//! overflow pages.
+
+use std::{collections::HashMap, mem};
+
+use crate::page_store::{PageAddress, PageSource, ReadPage, TransactionApi};
+
+use super::{
+	document::{Document, HashableValue, Schema, SchemaError, ValidationReport, Value},
+	document_repr::{Reader, StoredDocument, StoredValue},
+	overflow::Overflow,
+	page_alloc::PageAllocator,
+	pages::{PageKind, RecordsPage, SlottedPage},
+	DatabaseError, DbPointer,
+};
+
+/// Every record starts with a byte that tells whether it holds a document, so
+/// that records of deleted documents can be reused.
+const RECORD_DELETED: u8 = 0;
+const RECORD_PRESENT: u8 = 1;
+
+/// The pages that documents and their out-of-line values are put on if they
+/// have room, so that documents share pages instead of each getting new ones.
+/// The hints are updated by every operation, and have to be passed to the next
+/// one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct PageHints {
+	/// The records page that the last document was put on or deleted from
+	pub records: Option<PageAddress>,
+	/// The slotted page that the last out-of-line value was put on or deleted
+	/// from
+	pub heap: Option<PageAddress>,
+}
+
+pub(super) struct Codec;
+
+impl Codec {
+	/// Validates the document and stores it. The document is put on the records
+	/// page of the hints if it has room, and on a new page otherwise.
+	pub fn insert(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		document: &Document,
+		hints: &mut PageHints,
+	) -> Result<DbPointer, DatabaseError> {
+		schema.validate(document)?;
+		let record_length = Self::record_length(t, schema)?;
+		let record = Self::encode(t, schema, document, hints)?;
+
+		if let Some(page_address) = hints.records {
+			if let Some(index) = Self::insert_into(t, page_address, &record)? {
+				return Ok(Self::pointer(page_address, index));
+			}
+		}
+		let page_address = PageAllocator::alloc(t)?;
+		let mut page = RecordsPage::new_unchecked(t.get_page_mut(page_address)?);
+		page.init(record_length)?;
+		let index = page.push_record(&record)?;
+		page.set_first_free(index + 1)?;
+		hints.records = Some(page_address);
+		Ok(Self::pointer(page_address, index))
+	}
+
+	/// Reads the document at the pointer, or returns `None` if there is none.
+	pub fn get(
+		t: &impl PageSource,
+		schema: &Schema,
+		pointer: DbPointer,
+	) -> Result<Option<Document>, DatabaseError> {
+		let Some(stored) = Self::read_stored(t, schema, pointer)? else {
+			return Ok(None);
+		};
+		Ok(Some(Self::decode(t, schema, &stored)?))
+	}
+
+	/// Validates the document and replaces the one at the pointer with it.
+	/// Returns `false` if there is none.
+	pub fn update(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		pointer: DbPointer,
+		document: &Document,
+		hints: &mut PageHints,
+	) -> Result<bool, DatabaseError> {
+		schema.validate(document)?;
+		let Some(stored) = Self::read_stored(t, schema, pointer)? else {
+			return Ok(false);
+		};
+		// The page is locked before the new values are stored, so that writing
+		// the record can't fail afterwards
+		let page_address = pointer.page_address();
+		mem::drop(t.get_page_mut(page_address)?);
+		let record = Self::encode(t, schema, document, hints)?;
+		let mut page = RecordsPage::new(t.get_page_mut(page_address)?)?;
+		page.set_record(pointer.index().into(), &record)?;
+		mem::drop(page);
+		// The old values are only freed once the record no longer points to
+		// them, so that a failed update leaves the document intact
+		Self::free(t, schema, &stored, hints)?;
+		Ok(true)
+	}
+
+	/// Deletes the document at the pointer, and frees everything it stores out
+	/// of line. Returns `false` if there is none.
+	pub fn delete(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		pointer: DbPointer,
+		hints: &mut PageHints,
+	) -> Result<bool, DatabaseError> {
+		let Some(stored) = Self::read_stored(t, schema, pointer)? else {
+			return Ok(false);
+		};
+		Self::free(t, schema, &stored, hints)?;
+		let record = vec![RECORD_DELETED; 1 + StoredDocument::size(schema)];
+		let index = pointer.index().into();
+		let mut page = RecordsPage::new(t.get_page_mut(pointer.page_address())?)?;
+		page.set_record(index, &record)?;
+		if index < page.get_first_free()? {
+			page.set_first_free(index)?;
+		}
+		hints.records = Some(pointer.page_address());
+		Ok(true)
+	}
+
+	fn record_length(t: &impl PageSource, schema: &Schema) -> Result<usize, DatabaseError> {
+		let record_length = 1 + StoredDocument::size(schema);
+		let page_size = t.get_page(PageAllocator::META_PAGE_ADDRESS)?.size();
+		let max_size = RecordsPage::<()>::max_record_length(page_size);
+		if record_length > max_size {
+			return Err(DatabaseError::DocumentTooLarge {
+				size: record_length,
+				max_size,
+			});
+		}
+		Ok(record_length)
+	}
+
+	fn pointer(page_address: PageAddress, index: usize) -> DbPointer {
+		DbPointer::new(
+			page_address,
+			index.try_into().expect("Record index must be 16-bit!"),
+		)
+	}
+
+	/// Puts the record on the page, reusing the record of a deleted document if
+	/// there is one. Returns `None` if the page can't hold it.
+	fn insert_into(
+		t: &mut impl TransactionApi,
+		page_address: PageAddress,
+		record: &[u8],
+	) -> Result<Option<usize>, DatabaseError> {
+		let mut page = match RecordsPage::new(t.get_page_mut(page_address)?) {
+			Ok(page) => page,
+			Err(DatabaseError::UnexpectedPageKind { .. }) => return Ok(None),
+			Err(err) => return Err(err),
+		};
+		if page.get_record_length()? != record.len() {
+			return Ok(None);
+		}
+		// Only the records from the first free one on are searched, and the hint
+		// is moved past every record that is found in use
+		let num_records = page.get_num_records()?;
+		let mut buf = vec![0; record.len()];
+		for index in page.get_first_free()?..num_records {
+			page.get_record(index, &mut buf)?;
+			if buf[0] == RECORD_DELETED {
+				page.set_record(index, record)?;
+				page.set_first_free(index + 1)?;
+				return Ok(Some(index));
+			}
+		}
+		match page.push_record(record) {
+			Ok(index) => {
+				page.set_first_free(index + 1)?;
+				Ok(Some(index))
+			}
+			Err(DatabaseError::PageOutOfBounds) => {
+				page.set_first_free(num_records)?;
+				Ok(None)
+			}
+			Err(err) => Err(err),
+		}
+	}
+
+	fn read_stored(
+		t: &impl PageSource,
+		schema: &Schema,
+		pointer: DbPointer,
+	) -> Result<Option<StoredDocument>, DatabaseError> {
+		let page = match RecordsPage::new(t.get_page(pointer.page_address())?) {
+			Ok(page) => page,
+			Err(DatabaseError::UnexpectedPageKind { .. } | DatabaseError::UnknownPageKind(..)) => {
+				return Ok(None)
+			}
+			Err(err) => return Err(err),
+		};
+		let record_length = page.get_record_length()?;
+		if record_length != 1 + StoredDocument::size(schema) {
+			return Ok(None);
+		}
+		let mut buf = vec![0; record_length];
+		match page.get_record(pointer.index().into(), &mut buf) {
+			Ok(()) => {}
+			Err(DatabaseError::InvalidRecordIndex) => return Ok(None),
+			Err(err) => return Err(err),
+		}
+		if buf[0] != RECORD_PRESENT {
+			return Ok(None);
+		}
+		let stored = StoredDocument::read(schema, &mut Reader::new(&buf[1..]))?;
+		Ok(Some(stored))
+	}
+
+	/// Stores the out-of-line parts of the document, and returns its record. If
+	/// that fails, the values that were already stored are freed again.
+	fn encode(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		document: &Document,
+		hints: &mut PageHints,
+	) -> Result<Vec<u8>, DatabaseError> {
+		let mut encoder = Encoder {
+			t,
+			heap: hints.heap,
+			values: Vec::new(),
+		};
+		let result = encoder.encode(schema, document);
+		hints.heap = encoder.heap;
+		let values = mem::take(&mut encoder.values);
+		let stored = match result {
+			Ok(stored) => stored,
+			Err(err) => {
+				for pointer in values {
+					Self::free_value(t, pointer, hints)?;
+				}
+				return Err(err);
+			}
+		};
+		let mut record = vec![RECORD_PRESENT];
+		stored.write(schema, &mut record);
+		Ok(record)
+	}
+
+	fn decode(
+		t: &impl PageSource,
+		schema: &Schema,
+		stored: &StoredDocument,
+	) -> Result<Document, DatabaseError> {
+		let document = match (schema, stored) {
+			(Schema::Empty, StoredDocument::Empty) | (_, StoredDocument::Option(None)) => {
+				Document::Nil
+			}
+			(Schema::Primitive(..), StoredDocument::Value(value)) => {
+				let value = match value {
+					StoredValue::String(pointer) => {
+						Value::String(String::from_utf8(Self::load(t, *pointer)?)?)
+					}
+					StoredValue::Bool(bool) => Value::Bool(*bool),
+					StoredValue::Int(int) => Value::Int(*int),
+					StoredValue::Uint(uint) => Value::Uint(*uint),
+					StoredValue::Float(float) => Value::Float(*float),
+				};
+				Document::Value(value)
+			}
+			(Schema::Option(inner), StoredDocument::Option(Some(value))) => {
+				Self::decode(t, inner, value)?
+			}
+			(Schema::List(items), StoredDocument::List(pointer)) => {
+				let buf = Self::load(t, *pointer)?;
+				let mut items_reader = ItemsReader::new(&buf, StoredDocument::size(items))?;
+				let mut documents = Vec::with_capacity(items_reader.len().min(buf.len()));
+				while let Some(item) = items_reader.next(items)? {
+					documents.push(Self::decode(t, items, &item)?);
+				}
+				Document::List(documents)
+			}
+			(Schema::Map(key, values), StoredDocument::List(pointer)) => {
+				let key = Schema::Primitive((*key).into());
+				let buf = Self::load(t, *pointer)?;
+				let mut items_reader = ItemsReader::for_map(&buf, &key, values)?;
+				let mut entries = HashMap::with_capacity(items_reader.len().min(buf.len()));
+				while let Some((stored_key, value)) = items_reader.next_entry(&key, values)? {
+					let Document::Value(key) = Self::decode(t, &key, &stored_key)? else {
+						unreachable!("Primitives must decode to values!");
+					};
+					entries.insert(Self::hashable(key), Self::decode(t, values, &value)?);
+				}
+				Document::Map(entries)
+			}
+			(Schema::Struct(fields), StoredDocument::StructFields(values)) => {
+				let mut document = HashMap::with_capacity(fields.len());
+				for ((name, field), value) in Schema::sorted_entries(fields).into_iter().zip(values)
+				{
+					document.insert(name.to_string(), Self::decode(t, field, value)?);
+				}
+				Document::Struct(document)
+			}
+			(Schema::Enum(variants), StoredDocument::Enum(index, value)) => {
+				let (name, variant) = Schema::sorted_entries(variants)[usize::from(*index)];
+				Document::Struct(HashMap::from([(
+					name.to_string(),
+					Self::decode(t, variant, value)?,
+				)]))
+			}
+			_ => unreachable!("Stored documents are read for their schema!"),
+		};
+		Ok(document)
+	}
+
+	fn hashable(value: Value) -> HashableValue {
+		match value {
+			Value::String(string) => HashableValue::String(string),
+			Value::Bool(bool) => HashableValue::Bool(bool),
+			Value::Int(int) => HashableValue::Int(int),
+			Value::Uint(uint) => HashableValue::Uint(uint),
+			Value::Float(..) => unreachable!("Map keys can't be floats!"),
+		}
+	}
+
+	/// Frees everything the document stores out of line.
+	fn free(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		stored: &StoredDocument,
+		hints: &mut PageHints,
+	) -> Result<(), DatabaseError> {
+		match (schema, stored) {
+			(_, StoredDocument::Value(StoredValue::String(pointer))) => {
+				Self::free_value(t, *pointer, hints)?;
+			}
+			(Schema::Option(inner), StoredDocument::Option(Some(value))) => {
+				Self::free(t, inner, value, hints)?;
+			}
+			(Schema::List(items), StoredDocument::List(pointer)) => {
+				let buf = Self::load(t, *pointer)?;
+				let mut items_reader = ItemsReader::new(&buf, StoredDocument::size(items))?;
+				while let Some(item) = items_reader.next(items)? {
+					Self::free(t, items, &item, hints)?;
+				}
+				Self::free_value(t, *pointer, hints)?;
+			}
+			(Schema::Map(key, values), StoredDocument::List(pointer)) => {
+				let key = Schema::Primitive((*key).into());
+				let buf = Self::load(t, *pointer)?;
+				let mut items_reader = ItemsReader::for_map(&buf, &key, values)?;
+				while let Some((stored_key, value)) = items_reader.next_entry(&key, values)? {
+					Self::free(t, &key, &stored_key, hints)?;
+					Self::free(t, values, &value, hints)?;
+				}
+				Self::free_value(t, *pointer, hints)?;
+			}
+			(Schema::Struct(fields), StoredDocument::StructFields(values)) => {
+				for ((_, field), value) in Schema::sorted_entries(fields).into_iter().zip(values) {
+					Self::free(t, field, value, hints)?;
+				}
+			}
+			(Schema::Enum(variants), StoredDocument::Enum(index, value)) => {
+				let (_, variant) = Schema::sorted_entries(variants)[usize::from(*index)];
+				Self::free(t, variant, value, hints)?;
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Reads a value that is stored out of line.
+	fn load(t: &impl PageSource, pointer: DbPointer) -> Result<Vec<u8>, DatabaseError> {
+		if Self::is_overflow(t, pointer.page_address())? {
+			return Overflow::read(t, pointer.page_address());
+		}
+		SlottedPage::new(t.get_page(pointer.page_address())?)?
+			.get_record(pointer.index().into())?
+			.ok_or(DatabaseError::InvalidRecordIndex)
+	}
+
+	/// Frees a value that is stored out of line, and its slotted page once
+	/// that is empty. Otherwise, the page becomes the heap hint, so that the
+	/// space is reused.
+	fn free_value(
+		t: &mut impl TransactionApi,
+		pointer: DbPointer,
+		hints: &mut PageHints,
+	) -> Result<(), DatabaseError> {
+		let page_address = pointer.page_address();
+		if Self::is_overflow(t, page_address)? {
+			return Overflow::free(t, page_address);
+		}
+		let mut page = SlottedPage::new(t.get_page_mut(page_address)?)?;
+		page.delete(pointer.index().into())?;
+		if page.get_num_slots()? == 0 {
+			mem::drop(page);
+			PageAllocator::free(t, page_address)?;
+			// A freed page may be handed out for anything, so it must not be
+			// written to as a slotted page
+			if hints.heap == Some(page_address) {
+				hints.heap = None;
+			}
+		} else {
+			hints.heap = Some(page_address);
+		}
+		Ok(())
+	}
+
+	fn is_overflow(t: &impl PageSource, page_address: PageAddress) -> Result<bool, DatabaseError> {
+		match SlottedPage::new(t.get_page(page_address)?) {
+			Ok(..) => Ok(false),
+			Err(DatabaseError::UnexpectedPageKind {
+				received: PageKind::Overflow,
+				..
+			}) => Ok(true),
+			Err(err) => Err(err),
+		}
+	}
+}
+
+/// Lays out documents, and stores their out-of-line values. The values are
+/// packed into the slotted page that the last one was put on, across
+/// documents, until it is full.
+struct Encoder<'t, T> {
+	t: &'t mut T,
+	/// The slotted page that the last value was put on
+	heap: Option<PageAddress>,
+	/// Every value that was stored so far
+	values: Vec<DbPointer>,
+}
+
+impl<T: TransactionApi> Encoder<'_, T> {
+	fn encode(
+		&mut self,
+		schema: &Schema,
+		document: &Document,
+	) -> Result<StoredDocument, DatabaseError> {
+		let stored = match (schema, document) {
+			(Schema::Empty, _) => StoredDocument::Empty,
+			(Schema::Primitive(..), Document::Value(value)) => {
+				let value = match value {
+					Value::String(string) => StoredValue::String(self.store(string.as_bytes())?),
+					Value::Bool(bool) => StoredValue::Bool(*bool),
+					Value::Int(int) => StoredValue::Int(*int),
+					Value::Uint(uint) => StoredValue::Uint(*uint),
+					Value::Float(float) => StoredValue::Float(*float),
+				};
+				StoredDocument::Value(value)
+			}
+			(Schema::Option(..), Document::Nil) => StoredDocument::Option(None),
+			(Schema::Option(inner), document) => {
+				StoredDocument::Option(Some(Box::new(self.encode(inner, document)?)))
+			}
+			(Schema::List(items), Document::List(documents)) => {
+				let mut buf = Self::items_header(documents.len())?;
+				for document in documents {
+					self.encode(items, document)?.write(items, &mut buf);
+				}
+				StoredDocument::List(self.store(&buf)?)
+			}
+			(Schema::Map(key, values), Document::Map(entries)) => {
+				let key = Schema::Primitive((*key).into());
+				let mut buf = Self::items_header(entries.len())?;
+				for (key_value, value) in entries {
+					let key_document = Document::Value(key_value.clone().into());
+					self.encode(&key, &key_document)?.write(&key, &mut buf);
+					self.encode(values, value)?.write(values, &mut buf);
+				}
+				StoredDocument::List(self.store(&buf)?)
+			}
+			(Schema::Struct(fields), Document::Struct(values)) => {
+				let mut stored = Vec::with_capacity(fields.len());
+				for (name, field) in Schema::sorted_entries(fields) {
+					let value = values.get(name).unwrap_or(&Document::Nil);
+					stored.push(self.encode(field, value)?);
+				}
+				StoredDocument::StructFields(stored)
+			}
+			(Schema::Enum(variants), Document::Struct(values)) if values.len() == 1 => {
+				let (name, value) = values.iter().next().unwrap();
+				let sorted_variants = Schema::sorted_entries(variants);
+				let Some(index) = sorted_variants
+					.iter()
+					.position(|(variant, _)| variant == name)
+				else {
+					return Err(ValidationReport::from(SchemaError::unknown_variant()).into());
+				};
+				let stored = self.encode(sorted_variants[index].1, value)?;
+				let index = index
+					.try_into()
+					.expect("Enums must have at most 2^16 variants!");
+				StoredDocument::Enum(index, Box::new(stored))
+			}
+			// Documents are validated before they are encoded
+			(schema, document) => {
+				let error = SchemaError::new(schema.clone(), document.kind());
+				return Err(ValidationReport::from(error).into());
+			}
+		};
+		Ok(stored)
+	}
+
+	fn items_header(len: usize) -> Result<Vec<u8>, DatabaseError> {
+		let Ok(len) = u32::try_from(len) else {
+			return Err(DatabaseError::PageFormat(format!(
+				"Length {len} doesn't fit into 32 bits"
+			)));
+		};
+		Ok(len.to_le_bytes().to_vec())
+	}
+
+	/// Stores a value out of line, and returns a pointer to it.
+	fn store(&mut self, buf: &[u8]) -> Result<DbPointer, DatabaseError> {
+		let page_size = self.t.get_page(PageAllocator::META_PAGE_ADDRESS)?.size();
+		let pointer = if buf.len() > SlottedPage::<()>::max_record_length(page_size) {
+			DbPointer::new(Overflow::write(self.t, buf)?, 0)
+		} else {
+			self.store_in_heap(buf)?
+		};
+		self.values.push(pointer);
+		Ok(pointer)
+	}
+
+	fn store_in_heap(&mut self, buf: &[u8]) -> Result<DbPointer, DatabaseError> {
+		if let Some(page_address) = self.heap {
+			let mut page = SlottedPage::new(self.t.get_page_mut(page_address)?)?;
+			match page.insert(buf) {
+				Ok(index) => return Ok(Codec::pointer(page_address, index)),
+				Err(DatabaseError::PageFull) => {}
+				Err(err) => return Err(err),
+			}
+		}
+		let page_address = PageAllocator::alloc(self.t)?;
+		let mut page = SlottedPage::new_unchecked(self.t.get_page_mut(page_address)?);
+		page.init()?;
+		let index = page.insert(buf)?;
+		self.heap = Some(page_address);
+		Ok(Codec::pointer(page_address, index))
+	}
+}
+
+/// Reads the items of a list or the entries of a map, which are stored as a
+/// 32-bit length followed by the items. As all items of a schema have the
+/// same size, the length has to match the size of the items exactly.
+struct ItemsReader<'a> {
+	reader: Reader<'a>,
+	/// The number of items that are left
+	remaining: usize,
+}
+
+impl<'a> ItemsReader<'a> {
+	fn new(buf: &'a [u8], item_size: usize) -> Result<Self, DatabaseError> {
+		let mut reader = Reader::new(buf);
+		let len: usize = u32::from_le_bytes(reader.take()?)
+			.try_into()
+			.expect("Lengths must fit into usize!");
+		let size = buf.len() - mem::size_of::<u32>();
+		if len.checked_mul(item_size) != Some(size) {
+			return Err(DatabaseError::PageFormat(format!(
+				"Expected {len} items of {item_size} bytes, but found {size} bytes"
+			)));
+		}
+		Ok(Self {
+			reader,
+			remaining: len,
+		})
+	}
+
+	fn for_map(buf: &'a [u8], key: &Schema, values: &Schema) -> Result<Self, DatabaseError> {
+		Self::new(
+			buf,
+			StoredDocument::size(key) + StoredDocument::size(values),
+		)
+	}
+
+	/// The number of items that are left.
+	fn len(&self) -> usize {
+		self.remaining
+	}
+
+	fn next(&mut self, schema: &Schema) -> Result<Option<StoredDocument>, DatabaseError> {
+		if self.remaining == 0 {
+			return Ok(None);
+		}
+		self.remaining -= 1;
+		StoredDocument::read(schema, &mut self.reader).map(Some)
+	}
+
+	fn next_entry(
+		&mut self,
+		key: &Schema,
+		value: &Schema,
+	) -> Result<Option<(StoredDocument, StoredDocument)>, DatabaseError> {
+		if self.remaining == 0 {
+			return Ok(None);
+		}
+		self.remaining -= 1;
+		let key = StoredDocument::read(key, &mut self.reader)?;
+		let value = StoredDocument::read(value, &mut self.reader)?;
+		Ok(Some((key, value)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{num::NonZeroU16, time::Duration};
+
+	use crate::{
+		consts::DEFAULT_PAGE_SIZE,
+		doc_store::document::{HashablePrimitive, Primitive},
+		page_store::{PageStorage, PageStorageApi, PageStorageConfig, StorageError},
+	};
+
+	use super::*;
+
+	fn string(value: &str) -> Document {
+		Document::Value(Value::String(value.to_string()))
+	}
+
+	fn fields<const N: usize>(fields: [(&str, Schema); N]) -> HashMap<String, Box<Schema>> {
+		fields
+			.into_iter()
+			.map(|(name, schema)| (name.to_string(), Box::new(schema)))
+			.collect()
+	}
+
+	fn schema() -> Schema {
+		Schema::Struct(fields([
+			("name", Schema::Primitive(Primitive::String)),
+			("count", Schema::Primitive(Primitive::Uint)),
+			(
+				"nickname",
+				Schema::Option(Box::new(Schema::Primitive(Primitive::String))),
+			),
+			(
+				"tags",
+				Schema::List(Box::new(Schema::Primitive(Primitive::String))),
+			),
+			(
+				"scores",
+				Schema::Map(
+					HashablePrimitive::String,
+					Box::new(Schema::Primitive(Primitive::Float)),
+				),
+			),
+			(
+				"state",
+				Schema::Enum(fields([
+					("active", Schema::Empty),
+					("moved", Schema::Primitive(Primitive::String)),
+				])),
+			),
+		]))
+	}
+
+	fn document(name: &str) -> Document {
+		Document::Struct(HashMap::from([
+			("name".to_string(), string(name)),
+			("count".to_string(), Document::Value(Value::Uint(3))),
+			("nickname".to_string(), Document::Nil),
+			(
+				"tags".to_string(),
+				Document::List(vec![string("a"), string(""), string(&"b".repeat(100_000))]),
+			),
+			(
+				"scores".to_string(),
+				Document::Map(HashMap::from([(
+					HashableValue::String("x".to_string()),
+					Document::Value(Value::Float(0.5)),
+				)])),
+			),
+			(
+				"state".to_string(),
+				Document::Struct(HashMap::from([("moved".to_string(), string("away"))])),
+			),
+		]))
+	}
+
+	#[test]
+	fn insert_update_and_delete_documents() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let schema = schema();
+
+		let mut hints = PageHints::default();
+
+		// when
+		let first = Codec::insert(&mut t, &schema, &document("first"), &mut hints).unwrap();
+		let second = Codec::insert(&mut t, &schema, &document("second"), &mut hints).unwrap();
+		let updated =
+			Codec::update(&mut t, &schema, first, &document("updated"), &mut hints).unwrap();
+		let deleted = Codec::delete(&mut t, &schema, second, &mut hints).unwrap();
+		let deleted_again = Codec::delete(&mut t, &schema, second, &mut hints).unwrap();
+		let third = Codec::insert(&mut t, &schema, &document("third"), &mut hints).unwrap();
+
+		// then
+		assert!(updated);
+		assert!(deleted);
+		assert!(!deleted_again);
+		assert_eq!(second.page_address(), first.page_address());
+		// the record of the deleted document is reused
+		assert_eq!(third, second);
+		assert_eq!(
+			Codec::get(&t, &schema, first).unwrap(),
+			Some(document("updated"))
+		);
+		assert_eq!(
+			Codec::get(&t, &schema, third).unwrap(),
+			Some(document("third"))
+		);
+		assert_eq!(
+			Codec::get(&t, &schema, DbPointer::new(first.page_address(), 2)).unwrap(),
+			None
+		);
+		t.commit().unwrap();
+	}
+
+	#[test]
+	fn free_out_of_line_values() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let schema = schema();
+		let mut hints = PageHints::default();
+		let pointer = Codec::insert(&mut t, &schema, &document("first"), &mut hints).unwrap();
+		let unused_page = PageAllocator::alloc(&mut t).unwrap();
+
+		// when
+		Codec::delete(&mut t, &schema, pointer, &mut hints).unwrap();
+		let emptied_hints = hints;
+		let reinserted = Codec::insert(&mut t, &schema, &document("second"), &mut hints).unwrap();
+
+		// then
+		// the slotted page was freed, so it is no longer the heap hint
+		assert_eq!(emptied_hints.heap, None);
+		// the slotted and overflow pages of the deleted document were reused
+		assert_eq!(reinserted, pointer);
+		let next_page = PageAllocator::alloc(&mut t).unwrap();
+		assert_eq!(next_page.segment_num, unused_page.segment_num);
+		assert_eq!(next_page.page_num.get(), unused_page.page_num.get() + 1);
+	}
+
+	fn first_free(t: &impl PageSource, page_address: PageAddress) -> usize {
+		RecordsPage::new(t.get_page(page_address).unwrap())
+			.unwrap()
+			.get_first_free()
+			.unwrap()
+	}
+
+	#[test]
+	fn reuse_deleted_records_from_the_first_free_one() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let schema = Schema::Primitive(Primitive::Uint);
+		let mut hints = PageHints::default();
+		let pointers: Vec<_> = (0..4)
+			.map(|i| {
+				Codec::insert(
+					&mut t,
+					&schema,
+					&Document::Value(Value::Uint(i)),
+					&mut hints,
+				)
+				.unwrap()
+			})
+			.collect();
+		let page_address = pointers[0].page_address();
+		let initial_first_free = first_free(&t, page_address);
+
+		// when
+		Codec::delete(&mut t, &schema, pointers[2], &mut hints).unwrap();
+		Codec::delete(&mut t, &schema, pointers[0], &mut hints).unwrap();
+		let deleted_first_free = first_free(&t, page_address);
+		let reinserted: Vec<_> = (4..7)
+			.map(|i| {
+				Codec::insert(
+					&mut t,
+					&schema,
+					&Document::Value(Value::Uint(i)),
+					&mut hints,
+				)
+				.unwrap()
+			})
+			.collect();
+
+		// then
+		assert_eq!(initial_first_free, 4);
+		assert_eq!(deleted_first_free, 0);
+		let indices: Vec<_> = reinserted.iter().map(|pointer| pointer.index()).collect();
+		assert_eq!(indices, vec![0, 2, 4]);
+		assert_eq!(first_free(&t, page_address), 5);
+	}
+
+	#[test]
+	fn keep_document_when_update_fails() {
+		// given
+		let mut config = PageStorageConfig::default();
+		config.page_cache.page_cache_size = 16 * DEFAULT_PAGE_SIZE;
+		config.page_cache.eviction_timeout = Duration::from_millis(10);
+		let storage = PageStorage::create_in_memory(&config).unwrap();
+		let schema = schema();
+		let mut hints = PageHints::default();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let pointer = Codec::insert(&mut t, &schema, &document("first"), &mut hints).unwrap();
+		t.commit().unwrap();
+		storage.flush_sync().unwrap();
+		// the new tags need more pages than the cache holds
+		let mut too_large = document("updated");
+		let Document::Struct(values) = &mut too_large else {
+			unreachable!()
+		};
+		values.insert(
+			"tags".to_string(),
+			Document::List(vec![string(&"c".repeat(32 * DEFAULT_PAGE_SIZE))]),
+		);
+
+		// when
+		let mut t = storage.transaction().unwrap();
+		let result = Codec::update(&mut t, &schema, pointer, &too_large, &mut hints);
+		t.commit().unwrap();
+		storage.flush_sync().unwrap();
+
+		// then
+		assert!(matches!(
+			result,
+			Err(DatabaseError::Storage(StorageError::CacheExhausted))
+		));
+		let t = storage.transaction().unwrap();
+		assert_eq!(
+			Codec::get(&t, &schema, pointer).unwrap(),
+			Some(document("first"))
+		);
+	}
+
+	#[test]
+	fn report_schema_mismatches_with_path() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let schema = schema();
+		let mut document = document("first");
+		let Document::Struct(values) = &mut document else {
+			unreachable!()
+		};
+		values.insert(
+			"tags".to_string(),
+			Document::List(vec![string("a"), Document::Value(Value::Int(1))]),
+		);
+
+		// when
+		let result = Codec::insert(&mut t, &schema, &document, &mut PageHints::default());
+
+		// then
+		let Err(DatabaseError::Validation(report)) = result else {
+			panic!("Expected a schema error, but got {result:?}");
+		};
+		assert_eq!(
+			report.to_string(),
+			"The document doesn't match its schema: .tags[1]: expected string, found int"
+		);
+	}
+
+	fn string_pointer(t: &impl PageSource, schema: &Schema, pointer: DbPointer) -> DbPointer {
+		let Some(StoredDocument::Value(StoredValue::String(pointer))) =
+			Codec::read_stored(t, schema, pointer).unwrap()
+		else {
+			panic!("Expected a stored string");
+		};
+		pointer
+	}
+
+	#[test]
+	fn share_heap_pages_between_documents() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let schema = Schema::Primitive(Primitive::String);
+		let mut hints = PageHints::default();
+
+		// when
+		let first = Codec::insert(&mut t, &schema, &string("first"), &mut hints).unwrap();
+		let second = Codec::insert(&mut t, &schema, &string("second"), &mut hints).unwrap();
+		let first_value = string_pointer(&t, &schema, first);
+		let second_value = string_pointer(&t, &schema, second);
+		Codec::delete(&mut t, &schema, first, &mut hints).unwrap();
+		let after_first_delete = hints;
+		Codec::delete(&mut t, &schema, second, &mut hints).unwrap();
+
+		// then
+		assert_eq!(first.page_address(), second.page_address());
+		assert_eq!(first_value.page_address(), second_value.page_address());
+		assert_eq!(
+			after_first_delete,
+			PageHints {
+				records: Some(first.page_address()),
+				heap: Some(first_value.page_address()),
+			}
+		);
+		// the slotted page was freed with its last value
+		assert_eq!(hints.heap, None);
+		assert_eq!(hints.records, Some(second.page_address()));
+	}
+
+	#[test]
+	fn reject_item_counts_that_dont_match_the_header() {
+		// given
+		let schema = Schema::Primitive(Primitive::Uint);
+		let item_size = StoredDocument::size(&schema);
+		let mut too_few = 3u32.to_le_bytes().to_vec();
+		too_few.extend_from_slice(&[0; 16]);
+		let mut too_many = 1u32.to_le_bytes().to_vec();
+		too_many.extend_from_slice(&[0; 16]);
+		let mut partial = 2u32.to_le_bytes().to_vec();
+		partial.extend_from_slice(&[0; 12]);
+		let mut valid = 2u32.to_le_bytes().to_vec();
+		valid.extend_from_slice(&[0; 16]);
+
+		// when
+		let results = [&too_few, &too_many, &partial].map(|buf| ItemsReader::new(buf, item_size));
+		let mut reader = ItemsReader::new(&valid, item_size).unwrap();
+
+		// then
+		for result in results {
+			assert!(matches!(result, Err(DatabaseError::PageFormat(..))));
+		}
+		assert_eq!(reader.len(), 2);
+		assert!(reader.next(&schema).unwrap().is_some());
+		assert!(reader.next(&schema).unwrap().is_some());
+		assert!(reader.next(&schema).unwrap().is_none());
+	}
+}
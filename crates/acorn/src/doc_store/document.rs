@@ -8,6 +8,7 @@ use std::{
 enum SchemaAccessStep {
 	Index(usize),
 	Entry(String),
+	Key(String),
 }
 
 impl fmt::Display for SchemaAccessStep {
@@ -15,46 +16,116 @@ impl fmt::Display for SchemaAccessStep {
 		match self {
 			Self::Index(index) => write!(f, "[{index}]"),
 			Self::Entry(key) => write!(f, ".{key}"),
+			Self::Key(key) => write!(f, "[{key}]"),
 		}
 	}
 }
 
+/// A single way in which a document doesn't match a schema
 #[derive(Debug)]
 pub(crate) struct SchemaError {
 	access_stack: Vec<SchemaAccessStep>,
-	expected: Schema,
-	received: Primitive,
+	kind: SchemaErrorKind,
+}
+
+#[derive(Debug)]
+enum SchemaErrorKind {
+	Mismatch {
+		expected: Schema,
+		received: DocumentKind,
+	},
+	UnknownField,
+	UnknownVariant,
 }
 
 impl SchemaError {
-	fn new(expected: Schema, received: Primitive) -> Self {
+	pub(crate) fn new(expected: Schema, received: DocumentKind) -> Self {
+		Self::with_kind(SchemaErrorKind::Mismatch { expected, received })
+	}
+
+	pub(crate) fn unknown_field() -> Self {
+		Self::with_kind(SchemaErrorKind::UnknownField)
+	}
+
+	pub(crate) fn unknown_variant() -> Self {
+		Self::with_kind(SchemaErrorKind::UnknownVariant)
+	}
+
+	fn with_kind(kind: SchemaErrorKind) -> Self {
 		Self {
-			expected,
-			received,
 			access_stack: Vec::new(),
+			kind,
 		}
 	}
 
 	fn push_access_step(&mut self, step: SchemaAccessStep) {
 		self.access_stack.push(step)
 	}
+
+	fn at(mut self, step: SchemaAccessStep) -> Self {
+		self.push_access_step(step);
+		self
+	}
 }
 
 impl fmt::Display for SchemaError {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "Schema expected {} at ", self.expected)?;
+		if self.access_stack.is_empty() {
+			write!(f, ".")?;
+		}
 		for step in self.access_stack.iter().rev() {
 			write!(f, "{step}")?;
 		}
-		write!(f, ", but found {}", self.received)?;
-		Ok(())
+		match &self.kind {
+			SchemaErrorKind::Mismatch { expected, received } => {
+				write!(f, ": expected {expected}, found {received}")
+			}
+			SchemaErrorKind::UnknownField => write!(f, ": unknown field"),
+			SchemaErrorKind::UnknownVariant => write!(f, ": unknown variant"),
+		}
 	}
 }
 
 impl Error for SchemaError {}
 
+/// Every way in which a document doesn't match a schema, in a stable order
+#[derive(Debug)]
+pub(crate) struct ValidationReport {
+	errors: Vec<SchemaError>,
+}
+
+impl ValidationReport {
+	pub fn errors(&self) -> &[SchemaError] {
+		&self.errors
+	}
+}
+
+impl From<SchemaError> for ValidationReport {
+	fn from(value: SchemaError) -> Self {
+		Self {
+			errors: vec![value],
+		}
+	}
+}
+
+impl fmt::Display for ValidationReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "The document doesn't match its schema: ")?;
+		for (i, error) in self.errors.iter().enumerate() {
+			if i > 0 {
+				write!(f, "; ")?;
+			}
+			write!(f, "{error}")?;
+		}
+		Ok(())
+	}
+}
+
+impl Error for ValidationReport {}
+
+/// A primitive value in a document
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Value {
+pub enum Value {
 	String(String),
 	Bool(bool),
 	Int(i64),
@@ -63,7 +134,7 @@ pub(crate) enum Value {
 }
 
 impl Value {
-	pub fn primitive(&self) -> Primitive {
+	pub(crate) fn primitive(&self) -> Primitive {
 		match self {
 			Self::String(..) => Primitive::String,
 			Self::Bool(..) => Primitive::Bool,
@@ -74,14 +145,37 @@ impl Value {
 	}
 }
 
+/// A primitive value that can be used as the key of a map
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub(crate) enum HashableValue {
+pub enum HashableValue {
 	String(String),
 	Bool(bool),
 	Int(i64),
 	Uint(u64),
 }
 
+impl HashableValue {
+	pub(crate) fn primitive(&self) -> HashablePrimitive {
+		match self {
+			Self::String(..) => HashablePrimitive::String,
+			Self::Bool(..) => HashablePrimitive::Bool,
+			Self::Int(..) => HashablePrimitive::Int,
+			Self::Uint(..) => HashablePrimitive::Uint,
+		}
+	}
+}
+
+impl fmt::Display for HashableValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::String(string) => write!(f, "{string:?}"),
+			Self::Bool(bool) => write!(f, "{bool}"),
+			Self::Int(int) => write!(f, "{int}"),
+			Self::Uint(uint) => write!(f, "{uint}"),
+		}
+	}
+}
+
 impl From<HashableValue> for Value {
 	fn from(value: HashableValue) -> Self {
 		match value {
@@ -93,8 +187,9 @@ impl From<HashableValue> for Value {
 	}
 }
 
+/// A tree of values, which is what the database stores
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum Document {
+pub enum Document {
 	Nil,
 	Value(Value),
 	List(Vec<Document>),
@@ -102,8 +197,45 @@ pub(crate) enum Document {
 	Struct(HashMap<String, Document>),
 }
 
+impl Document {
+	pub(crate) fn kind(&self) -> DocumentKind {
+		match self {
+			Self::Nil => DocumentKind::Nil,
+			Self::Value(value) => DocumentKind::Primitive(value.primitive()),
+			Self::List(..) => DocumentKind::List,
+			Self::Map(..) => DocumentKind::Map,
+			Self::Struct(..) => DocumentKind::Struct,
+		}
+	}
+}
+
+/// What was found in a document where the schema expected something else
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum Primitive {
+pub(crate) enum DocumentKind {
+	Missing,
+	Nil,
+	Primitive(Primitive),
+	List,
+	Map,
+	Struct,
+}
+
+impl fmt::Display for DocumentKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Missing => write!(f, "nothing"),
+			Self::Nil => write!(f, "nil"),
+			Self::Primitive(primitive) => write!(f, "{primitive}"),
+			Self::List => write!(f, "list"),
+			Self::Map => write!(f, "map"),
+			Self::Struct => write!(f, "struct"),
+		}
+	}
+}
+
+/// The type of a primitive value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
 	String,
 	Bool,
 	Int,
@@ -123,8 +255,9 @@ impl fmt::Display for Primitive {
 	}
 }
 
+/// The type of a primitive value that can be used as the key of a map
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum HashablePrimitive {
+pub enum HashablePrimitive {
 	String,
 	Bool,
 	Int,
@@ -148,14 +281,19 @@ impl fmt::Display for HashablePrimitive {
 	}
 }
 
+/// The shape that every document of a database has
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) enum Schema {
+pub enum Schema {
+	/// Holds nothing, which is written as `Nil`
 	Empty,
 	Primitive(Primitive),
+	/// Either `Nil` or a value of the inner schema
 	Option(Box<Schema>),
 	List(Box<Schema>),
 	Map(HashablePrimitive, Box<Schema>),
+	/// Named fields, which may be left out of a document if they are optional
 	Struct(HashMap<String, Box<Schema>>),
+	/// One of the named variants, written as a struct with a single field
 	Enum(HashMap<String, Box<Schema>>),
 }
 
@@ -170,7 +308,7 @@ impl fmt::Display for Schema {
 			Self::Struct(entries) => {
 				write!(f, "{{ ")?;
 
-				for (i, (key, schema)) in entries.iter().enumerate() {
+				for (i, (key, schema)) in Self::sorted_entries(entries).into_iter().enumerate() {
 					write!(f, "{key}: {schema}")?;
 					if i < entries.len() - 1 {
 						write!(f, ", ")?;
@@ -180,8 +318,8 @@ impl fmt::Display for Schema {
 				Ok(())
 			}
 			Self::Enum(variants) => {
-				for (i, (name, schema)) in variants.iter().enumerate() {
-					if **schema == Schema::Empty {
+				for (i, (name, schema)) in Self::sorted_entries(variants).into_iter().enumerate() {
+					if *schema == Schema::Empty {
 						write!(f, "{name}")?;
 					} else {
 						write!(f, "{name}({schema})")?;
@@ -195,3 +333,274 @@ impl fmt::Display for Schema {
 		}
 	}
 }
+
+impl Schema {
+	/// Checks that the document matches the schema, and reports every mismatch
+	/// otherwise.
+	///
+	/// Enum values are structs with a single field named after the variant,
+	/// and variants without a value hold `Nil`. A missing struct field is
+	/// treated like `Nil`, so optional fields may be left out.
+	pub(crate) fn validate(&self, document: &Document) -> Result<(), ValidationReport> {
+		let errors = self.errors(document);
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(ValidationReport { errors })
+		}
+	}
+
+	fn errors(&self, document: &Document) -> Vec<SchemaError> {
+		let mut errors = Vec::new();
+		match (self, document) {
+			(Self::Empty, Document::Nil) | (Self::Option(..), Document::Nil) => {}
+			(Self::Primitive(primitive), Document::Value(value))
+				if value.primitive() == *primitive => {}
+			(Self::Option(inner), document) => {
+				errors = inner.errors(document);
+				// A mismatch of the value itself is reported against the option
+				for error in &mut errors {
+					if let (true, SchemaErrorKind::Mismatch { expected, .. }) =
+						(error.access_stack.is_empty(), &mut error.kind)
+					{
+						*expected = self.clone();
+					}
+				}
+			}
+			(Self::List(items), Document::List(documents)) => {
+				for (index, document) in documents.iter().enumerate() {
+					Self::nested(
+						&mut errors,
+						items.errors(document),
+						SchemaAccessStep::Index(index),
+					);
+				}
+			}
+			(Self::Map(key_primitive, values), Document::Map(entries)) => {
+				let mut entries: Vec<_> = entries
+					.iter()
+					.map(|(key, value)| (key.to_string(), key, value))
+					.collect();
+				entries.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+				for (name, key, value) in entries {
+					let mut entry_errors = Vec::new();
+					if key.primitive() != *key_primitive {
+						entry_errors.push(SchemaError::new(
+							Self::Primitive((*key_primitive).into()),
+							DocumentKind::Primitive(key.primitive().into()),
+						));
+					}
+					entry_errors.extend(values.errors(value));
+					Self::nested(&mut errors, entry_errors, SchemaAccessStep::Key(name));
+				}
+			}
+			(Self::Struct(fields), Document::Struct(values)) => {
+				for (name, schema) in Self::sorted_entries(fields) {
+					let field_errors = match values.get(name) {
+						Some(value) => schema.errors(value),
+						None if matches!(schema, Self::Empty | Self::Option(..)) => Vec::new(),
+						None => vec![SchemaError::new(schema.clone(), DocumentKind::Missing)],
+					};
+					Self::nested(
+						&mut errors,
+						field_errors,
+						SchemaAccessStep::Entry(name.to_string()),
+					);
+				}
+				let mut unknown: Vec<_> = values
+					.keys()
+					.filter(|name| !fields.contains_key(*name))
+					.collect();
+				unknown.sort();
+				for name in unknown {
+					errors.push(
+						SchemaError::unknown_field().at(SchemaAccessStep::Entry(name.clone())),
+					);
+				}
+			}
+			(Self::Enum(variants), Document::Struct(values)) if values.len() == 1 => {
+				let (name, value) = values.iter().next().unwrap();
+				let variant_errors = match variants.get(name) {
+					Some(schema) => schema.errors(value),
+					None => vec![SchemaError::unknown_variant()],
+				};
+				Self::nested(
+					&mut errors,
+					variant_errors,
+					SchemaAccessStep::Entry(name.clone()),
+				);
+			}
+			(schema, document) => errors.push(SchemaError::new(schema.clone(), document.kind())),
+		}
+		errors
+	}
+
+	fn nested(errors: &mut Vec<SchemaError>, nested: Vec<SchemaError>, step: SchemaAccessStep) {
+		errors.extend(nested.into_iter().map(|error| error.at(step.clone())));
+	}
+
+	/// The fields of a struct or the variants of an enum in a stable order.
+	pub(crate) fn sorted_entries(entries: &HashMap<String, Box<Schema>>) -> Vec<(&str, &Schema)> {
+		let mut entries: Vec<_> = entries
+			.iter()
+			.map(|(name, schema)| (name.as_str(), schema.as_ref()))
+			.collect();
+		entries.sort_by_key(|(name, _)| *name);
+		entries
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entries(
+		entries: impl IntoIterator<Item = (&'static str, Schema)>,
+	) -> HashMap<String, Box<Schema>> {
+		entries
+			.into_iter()
+			.map(|(name, schema)| (name.to_string(), Box::new(schema)))
+			.collect()
+	}
+
+	fn fields(fields: impl IntoIterator<Item = (&'static str, Document)>) -> Document {
+		Document::Struct(
+			fields
+				.into_iter()
+				.map(|(name, document)| (name.to_string(), document))
+				.collect(),
+		)
+	}
+
+	fn value(value: Value) -> Document {
+		Document::Value(value)
+	}
+
+	fn string(value: &str) -> Document {
+		Document::Value(Value::String(value.to_string()))
+	}
+
+	fn order_schema() -> Schema {
+		let item = Schema::Struct(entries([
+			("name", Schema::Primitive(Primitive::String)),
+			("price", Schema::Primitive(Primitive::Float)),
+		]));
+		Schema::Struct(entries([
+			("id", Schema::Primitive(Primitive::Uint)),
+			(
+				"note",
+				Schema::Option(Box::new(Schema::Primitive(Primitive::String))),
+			),
+			("items", Schema::List(Box::new(item))),
+			(
+				"discounts",
+				Schema::Map(
+					HashablePrimitive::String,
+					Box::new(Schema::Primitive(Primitive::Float)),
+				),
+			),
+			(
+				"status",
+				Schema::Enum(entries([
+					("open", Schema::Empty),
+					("shipped", Schema::Primitive(Primitive::String)),
+				])),
+			),
+		]))
+	}
+
+	fn item(name: &str, price: Document) -> Document {
+		fields([("name", string(name)), ("price", price)])
+	}
+
+	#[test]
+	fn validate_matching_document() {
+		// given
+		let order = fields([
+			("id", value(Value::Uint(1))),
+			(
+				"items",
+				Document::List(vec![item("acorn", value(Value::Float(0.5)))]),
+			),
+			(
+				"discounts",
+				Document::Map(HashMap::from([(
+					HashableValue::String("autumn".to_string()),
+					value(Value::Float(0.1)),
+				)])),
+			),
+			("status", fields([("open", Document::Nil)])),
+		]);
+
+		// when
+		let result = order_schema().validate(&order);
+
+		// then
+		assert!(result.is_ok(), "{result:?}");
+	}
+
+	#[test]
+	fn report_every_violation() {
+		// given
+		let order = fields([
+			("note", value(Value::Int(3))),
+			(
+				"items",
+				Document::List(vec![
+					item("acorn", value(Value::Float(0.5))),
+					item("oak", string("free")),
+					fields([("name", string("leaf")), ("color", string("red"))]),
+				]),
+			),
+			(
+				"discounts",
+				Document::Map(HashMap::from([
+					(HashableValue::Int(1), value(Value::Float(0.1))),
+					(HashableValue::String("autumn".to_string()), Document::Nil),
+				])),
+			),
+			("status", fields([("lost", Document::Nil)])),
+			("extra", Document::Nil),
+		]);
+
+		// when
+		let result = order_schema().validate(&order);
+
+		// then
+		let errors: Vec<_> = result
+			.unwrap_err()
+			.errors()
+			.iter()
+			.map(ToString::to_string)
+			.collect();
+		assert_eq!(
+			errors,
+			[
+				".discounts[\"autumn\"]: expected float, found nil",
+				".discounts[1]: expected string, found int",
+				".id: expected uint, found nothing",
+				".items[1].price: expected float, found string",
+				".items[2].price: expected float, found nothing",
+				".items[2].color: unknown field",
+				".note: expected ?string, found int",
+				".status.lost: unknown variant",
+				".extra: unknown field",
+			]
+		);
+	}
+
+	#[test]
+	fn report_mismatch_of_whole_document() {
+		// given
+		let schema = Schema::List(Box::new(Schema::Primitive(Primitive::Bool)));
+
+		// when
+		let result = schema.validate(&fields([]));
+
+		// then
+		assert_eq!(
+			result.unwrap_err().to_string(),
+			"The document doesn't match its schema: .: expected [bool], found struct"
+		);
+	}
+}
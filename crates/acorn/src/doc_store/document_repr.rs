@@ -1,17 +1,23 @@
-use std::{convert::Infallible, mem, num::NonZero};
+use std::{collections::HashMap, convert::Infallible, mem, num::NonZero};
 
-use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+use zerocopy::{
+	little_endian::{U16, U32},
+	FromBytes, FromZeros, Immutable, IntoBytes,
+};
 
 use crate::{page_store::PageAddress, repr::Repr};
 
-use super::{DatabaseError, DbPointer};
+use super::{
+	document::{HashablePrimitive, Primitive, Schema},
+	DatabaseError, DbPointer,
+};
 
 #[derive(Debug, Immutable, IntoBytes, FromBytes)]
 #[repr(C)]
 struct DbPointerRepr {
-	segment_num: u32,
-	page_num: u16,
-	index: u16,
+	segment_num: U32,
+	page_num: U16,
+	index: U16,
 }
 
 impl From<Option<DbPointer>> for DbPointerRepr {
@@ -20,19 +26,19 @@ impl From<Option<DbPointer>> for DbPointerRepr {
 			return Self::new_zeroed();
 		};
 		Self {
-			segment_num: value.segment_num,
-			page_num: value.page_num.get(),
-			index: value.index,
+			segment_num: value.segment_num.into(),
+			page_num: value.page_num.get().into(),
+			index: value.index.into(),
 		}
 	}
 }
 
 impl From<DbPointerRepr> for Option<DbPointer> {
 	fn from(value: DbPointerRepr) -> Self {
-		let page_num = NonZero::new(value.page_num)?;
+		let page_num = NonZero::new(value.page_num.get())?;
 		Some(DbPointer::new(
-			PageAddress::new(value.segment_num, page_num),
-			value.index,
+			PageAddress::new(value.segment_num.get(), page_num),
+			value.index.get(),
 		))
 	}
 }
@@ -74,19 +80,344 @@ pub(crate) enum StoredValue {
 
 impl StoredValue {
 	pub fn size(&self) -> usize {
+		Self::size_of(self.primitive())
+	}
+
+	pub fn size_of(primitive: Primitive) -> usize {
+		match primitive {
+			Primitive::String => mem::size_of::<DbPointerRepr>(),
+			Primitive::Bool => mem::size_of::<bool>(),
+			Primitive::Int => mem::size_of::<i64>(),
+			Primitive::Uint => mem::size_of::<u64>(),
+			Primitive::Float => mem::size_of::<f64>(),
+		}
+	}
+
+	fn primitive(&self) -> Primitive {
+		match self {
+			Self::String(..) => Primitive::String,
+			Self::Bool(..) => Primitive::Bool,
+			Self::Int(..) => Primitive::Int,
+			Self::Uint(..) => Primitive::Uint,
+			Self::Float(..) => Primitive::Float,
+		}
+	}
+
+	fn write(&self, buf: &mut Vec<u8>) {
 		match self {
-			Self::String(..) => mem::size_of::<DbPointerRepr>(),
-			Self::Bool(..) => mem::size_of::<bool>(),
-			Self::Int(..) => mem::size_of::<i64>(),
-			Self::Uint(..) => mem::size_of::<u64>(),
-			Self::Float(..) => mem::size_of::<f64>(),
+			Self::String(pointer) => buf.extend(DbPointerRepr::from(*pointer).as_bytes()),
+			Self::Bool(bool) => buf.push(u8::from(*bool)),
+			Self::Int(int) => buf.extend(int.to_le_bytes()),
+			Self::Uint(uint) => buf.extend(uint.to_le_bytes()),
+			Self::Float(float) => buf.extend(float.to_le_bytes()),
 		}
 	}
+
+	fn read(primitive: Primitive, reader: &mut Reader) -> Result<Self, DatabaseError> {
+		let value = match primitive {
+			Primitive::String => Self::String(reader.pointer()?),
+			Primitive::Bool => {
+				let [bool] = reader.take()?;
+				Self::Bool(bool != 0)
+			}
+			Primitive::Int => Self::Int(i64::from_le_bytes(reader.take()?)),
+			Primitive::Uint => Self::Uint(u64::from_le_bytes(reader.take()?)),
+			Primitive::Float => Self::Float(f64::from_le_bytes(reader.take()?)),
+		};
+		Ok(value)
+	}
 }
 
+/// A document laid out for a schema. Every document of a schema takes the same
+/// number of bytes; strings and lists are stored elsewhere, and only pointed
+/// to. Maps are stored like lists of key-value pairs.
 pub(crate) enum StoredDocument {
+	Empty,
 	Value(StoredValue),
 	Option(Option<Box<StoredDocument>>),
 	List(DbPointer),
 	StructFields(Vec<StoredDocument>),
+	/// The index of the variant among the sorted variant names, and its value
+	Enum(u16, Box<StoredDocument>),
+}
+
+impl StoredDocument {
+	/// The number of bytes that a document of the schema takes.
+	pub fn size(schema: &Schema) -> usize {
+		match schema {
+			Schema::Empty => 0,
+			Schema::Primitive(primitive) => StoredValue::size_of(*primitive),
+			Schema::Option(inner) => 1 + Self::size(inner),
+			Schema::List(..) | Schema::Map(..) => mem::size_of::<DbPointerRepr>(),
+			Schema::Struct(fields) => fields.values().map(|field| Self::size(field)).sum(),
+			Schema::Enum(variants) => {
+				let max_variant_size = variants
+					.values()
+					.map(|variant| Self::size(variant))
+					.max()
+					.unwrap_or(0);
+				mem::size_of::<u16>() + max_variant_size
+			}
+		}
+	}
+
+	/// Appends the layout of the document to `buf`. The document must have been
+	/// created for the schema.
+	pub fn write(&self, schema: &Schema, buf: &mut Vec<u8>) {
+		let start = buf.len();
+		match (self, schema) {
+			(Self::Empty, _) => {}
+			(Self::Value(value), _) => value.write(buf),
+			(Self::Option(value), Schema::Option(inner)) => {
+				buf.push(u8::from(value.is_some()));
+				if let Some(value) = value {
+					value.write(inner, buf);
+				}
+			}
+			(Self::List(pointer), _) => buf.extend(DbPointerRepr::from(*pointer).as_bytes()),
+			(Self::StructFields(values), Schema::Struct(fields)) => {
+				for (value, (_, field)) in values.iter().zip(Schema::sorted_entries(fields)) {
+					value.write(field, buf);
+				}
+			}
+			(Self::Enum(index, value), Schema::Enum(variants)) => {
+				buf.extend(index.to_le_bytes());
+				value.write(Schema::sorted_entries(variants)[usize::from(*index)].1, buf);
+			}
+			_ => unreachable!("Stored document doesn't match its schema!"),
+		}
+		// Options and enums are padded to the size of their largest value
+		buf.resize(start + Self::size(schema), 0);
+	}
+
+	/// Reads a document of the schema from the start of `reader`.
+	pub fn read(schema: &Schema, reader: &mut Reader) -> Result<Self, DatabaseError> {
+		let size = Self::size(schema);
+		let mut inner = Reader::new(reader.take_slice(size)?);
+		let document = match schema {
+			Schema::Empty => Self::Empty,
+			Schema::Primitive(primitive) => Self::Value(StoredValue::read(*primitive, &mut inner)?),
+			Schema::Option(schema) => {
+				let [is_some] = inner.take()?;
+				if is_some == 0 {
+					Self::Option(None)
+				} else {
+					Self::Option(Some(Box::new(Self::read(schema, &mut inner)?)))
+				}
+			}
+			Schema::List(..) | Schema::Map(..) => Self::List(inner.pointer()?),
+			Schema::Struct(fields) => {
+				let mut values = Vec::with_capacity(fields.len());
+				for (_, field) in Schema::sorted_entries(fields) {
+					values.push(Self::read(field, &mut inner)?);
+				}
+				Self::StructFields(values)
+			}
+			Schema::Enum(variants) => {
+				let index = u16::from_le_bytes(inner.take()?);
+				let Some((_, variant)) = Schema::sorted_entries(variants)
+					.get(usize::from(index))
+					.copied()
+				else {
+					return Err(DatabaseError::PageFormat(format!(
+						"Unknown enum variant {index}"
+					)));
+				};
+				Self::Enum(index, Box::new(Self::read(variant, &mut inner)?))
+			}
+		};
+		Ok(document)
+	}
+}
+
+/// A schema laid out to be stored with the database. Every node is a tag,
+/// followed by its primitive or inner schemas. Struct fields and enum variants
+/// are prefixed with their number, and written sorted by name, each with its
+/// length-prefixed name.
+pub(crate) struct StoredSchema;
+
+impl StoredSchema {
+	const EMPTY: u8 = 0;
+	const PRIMITIVE: u8 = 1;
+	const OPTION: u8 = 2;
+	const LIST: u8 = 3;
+	const MAP: u8 = 4;
+	const STRUCT: u8 = 5;
+	const ENUM: u8 = 6;
+
+	pub fn encode(schema: &Schema) -> Vec<u8> {
+		let mut buf = Vec::new();
+		Self::write(schema, &mut buf);
+		buf
+	}
+
+	pub fn decode(buf: &[u8]) -> Result<Schema, DatabaseError> {
+		let mut reader = Reader::new(buf);
+		let schema = Self::read(&mut reader)?;
+		if !reader.is_empty() {
+			return Err(DatabaseError::PageFormat(
+				"Found trailing bytes after the stored schema".to_string(),
+			));
+		}
+		Ok(schema)
+	}
+
+	fn write(schema: &Schema, buf: &mut Vec<u8>) {
+		match schema {
+			Schema::Empty => buf.push(Self::EMPTY),
+			Schema::Primitive(primitive) => {
+				buf.push(Self::PRIMITIVE);
+				buf.push(Self::primitive_tag(*primitive));
+			}
+			Schema::Option(inner) => {
+				buf.push(Self::OPTION);
+				Self::write(inner, buf);
+			}
+			Schema::List(items) => {
+				buf.push(Self::LIST);
+				Self::write(items, buf);
+			}
+			Schema::Map(key, values) => {
+				buf.push(Self::MAP);
+				buf.push(Self::primitive_tag((*key).into()));
+				Self::write(values, buf);
+			}
+			Schema::Struct(fields) => {
+				buf.push(Self::STRUCT);
+				Self::write_entries(fields, buf);
+			}
+			Schema::Enum(variants) => {
+				buf.push(Self::ENUM);
+				Self::write_entries(variants, buf);
+			}
+		}
+	}
+
+	fn write_entries(entries: &HashMap<String, Box<Schema>>, buf: &mut Vec<u8>) {
+		buf.extend(Self::length(entries.len()).to_le_bytes());
+		for (name, schema) in Schema::sorted_entries(entries) {
+			buf.extend(Self::length(name.len()).to_le_bytes());
+			buf.extend(name.as_bytes());
+			Self::write(schema, buf);
+		}
+	}
+
+	fn length(length: usize) -> u32 {
+		length
+			.try_into()
+			.expect("Schema names and entries must fit into 32 bits!")
+	}
+
+	fn primitive_tag(primitive: Primitive) -> u8 {
+		match primitive {
+			Primitive::String => 0,
+			Primitive::Bool => 1,
+			Primitive::Int => 2,
+			Primitive::Uint => 3,
+			Primitive::Float => 4,
+		}
+	}
+
+	fn read(reader: &mut Reader) -> Result<Schema, DatabaseError> {
+		let [tag] = reader.take()?;
+		let schema = match tag {
+			Self::EMPTY => Schema::Empty,
+			Self::PRIMITIVE => Schema::Primitive(Self::read_primitive(reader)?),
+			Self::OPTION => Schema::Option(Box::new(Self::read(reader)?)),
+			Self::LIST => Schema::List(Box::new(Self::read(reader)?)),
+			Self::MAP => {
+				let key = match Self::read_primitive(reader)? {
+					Primitive::String => HashablePrimitive::String,
+					Primitive::Bool => HashablePrimitive::Bool,
+					Primitive::Int => HashablePrimitive::Int,
+					Primitive::Uint => HashablePrimitive::Uint,
+					Primitive::Float => {
+						return Err(DatabaseError::PageFormat(
+							"Map keys can't be floats".to_string(),
+						))
+					}
+				};
+				Schema::Map(key, Box::new(Self::read(reader)?))
+			}
+			Self::STRUCT => Schema::Struct(Self::read_entries(reader)?),
+			Self::ENUM => Schema::Enum(Self::read_entries(reader)?),
+			tag => {
+				return Err(DatabaseError::PageFormat(format!(
+					"Unknown schema tag {tag}"
+				)))
+			}
+		};
+		Ok(schema)
+	}
+
+	fn read_primitive(reader: &mut Reader) -> Result<Primitive, DatabaseError> {
+		let [tag] = reader.take()?;
+		let primitive = match tag {
+			0 => Primitive::String,
+			1 => Primitive::Bool,
+			2 => Primitive::Int,
+			3 => Primitive::Uint,
+			4 => Primitive::Float,
+			tag => {
+				return Err(DatabaseError::PageFormat(format!(
+					"Unknown primitive tag {tag}"
+				)))
+			}
+		};
+		Ok(primitive)
+	}
+
+	fn read_entries(reader: &mut Reader) -> Result<HashMap<String, Box<Schema>>, DatabaseError> {
+		let num_entries = u32::from_le_bytes(reader.take()?);
+		let mut entries = HashMap::new();
+		for _ in 0..num_entries {
+			let name_length = u32::from_le_bytes(reader.take()?)
+				.try_into()
+				.expect("Lengths must fit into usize!");
+			let name = String::from_utf8(reader.take_slice(name_length)?.to_vec())?;
+			entries.insert(name, Box::new(Self::read(reader)?));
+		}
+		Ok(entries)
+	}
+}
+
+/// Reads values from the front of a buffer.
+pub(crate) struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	pub fn new(buf: &'a [u8]) -> Self {
+		Self(buf)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn take<const N: usize>(&mut self) -> Result<[u8; N], DatabaseError> {
+		let Some((bytes, rest)) = self.0.split_first_chunk::<N>() else {
+			return Err(Self::unexpected_end());
+		};
+		self.0 = rest;
+		Ok(*bytes)
+	}
+
+	pub fn take_slice(&mut self, len: usize) -> Result<&'a [u8], DatabaseError> {
+		if len > self.0.len() {
+			return Err(Self::unexpected_end());
+		}
+		let (bytes, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Ok(bytes)
+	}
+
+	fn pointer(&mut self) -> Result<DbPointer, DatabaseError> {
+		let repr =
+			DbPointerRepr::read_from_bytes(&self.take::<{ mem::size_of::<DbPointerRepr>() }>()?)
+				.expect("Buffer must have the size of a DB pointer!");
+		repr.try_into()
+	}
+
+	fn unexpected_end() -> DatabaseError {
+		DatabaseError::PageFormat("Unexpected end of a stored document".to_string())
+	}
 }
@@ -1,13 +1,26 @@
-use std::{convert::Infallible, num::NonZero, string::FromUtf8Error};
+use std::{
+	convert::Infallible,
+	mem,
+	num::{NonZero, NonZeroU16},
+	string::FromUtf8Error,
+};
 
-use document::SchemaError;
-use pages::PageKind;
+use codec::{Codec, PageHints};
+use document::ValidationReport;
+use document_repr::StoredSchema;
+use overflow::Overflow;
+use page_alloc::PageAllocator;
+use pages::{MetaPage, PageKind};
 use thiserror::Error;
 
-use crate::page_store::{PageAddress, StorageError};
+use crate::page_store::{PageAddress, PageSource, StorageError, TransactionApi};
 
+pub use document::{Document, HashablePrimitive, HashableValue, Primitive, Schema, Value};
+
+mod codec;
 mod document;
 mod document_repr;
+mod overflow;
 mod page_alloc;
 mod pages;
 
@@ -31,11 +44,17 @@ pub(crate) enum DatabaseError {
 	#[error("Tried to access a record at an index that's out of bounds")]
 	InvalidRecordIndex,
 
+	#[error("There isn't enough free space on the page for the record")]
+	PageFull,
+
+	#[error("The document takes {size} bytes, but at most {max_size} fit into a page")]
+	DocumentTooLarge { size: usize, max_size: usize },
+
 	#[error(transparent)]
 	StringEncoding(#[from] FromUtf8Error),
 
 	#[error(transparent)]
-	Schema(#[from] SchemaError),
+	Validation(#[from] ValidationReport),
 
 	#[error(transparent)]
 	Storage(#[from] StorageError),
@@ -47,7 +66,7 @@ impl From<Infallible> for DatabaseError {
 	}
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct DbPointer {
 	segment_num: u32,
 	page_num: NonZero<u16>,
@@ -73,3 +92,278 @@ impl DbPointer {
 		self.index
 	}
 }
+
+/// Stores documents that match a schema through the [`Codec`], so that the
+/// records of many documents share a page, and so do their out-of-line values.
+/// The schema is stored when the store is initialized, and the pages that the
+/// next documents are put on are kept in the meta page.
+pub(crate) struct DocStore;
+
+impl DocStore {
+	pub fn init(
+		t: &mut impl TransactionApi,
+		last_page_num: NonZeroU16,
+		schema: &Schema,
+	) -> Result<(), DatabaseError> {
+		PageAllocator::init(t, last_page_num)?;
+		let schema_head = Overflow::write(t, &StoredSchema::encode(schema))?;
+		let mut meta_page = MetaPage::new(t.get_page_mut(PageAllocator::META_PAGE_ADDRESS)?)?;
+		meta_page.set_schema_head(Some(schema_head))?;
+		meta_page.set_records_hint(None)?;
+		meta_page.set_heap_hint(None)
+	}
+
+	/// Reads the schema that the store was initialized with.
+	pub fn schema(t: &impl PageSource) -> Result<Schema, DatabaseError> {
+		let meta_page = MetaPage::new(t.get_page(PageAllocator::META_PAGE_ADDRESS)?)?;
+		let Some(schema_head) = meta_page.get_schema_head()? else {
+			return Err(DatabaseError::PageFormat(
+				"The database has no schema".to_string(),
+			));
+		};
+		StoredSchema::decode(&Overflow::read(t, schema_head)?)
+	}
+
+	pub fn insert(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		document: &Document,
+	) -> Result<DbPointer, DatabaseError> {
+		Self::with_hints(t, |t, hints| Codec::insert(t, schema, document, hints))
+	}
+
+	/// Reads the document at the pointer, or returns `None` if there is none.
+	pub fn get(
+		t: &impl PageSource,
+		schema: &Schema,
+		pointer: DbPointer,
+	) -> Result<Option<Document>, DatabaseError> {
+		Codec::get(t, schema, pointer)
+	}
+
+	/// Replaces the document at the pointer. Returns `false` if there is none.
+	pub fn update(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		pointer: DbPointer,
+		document: &Document,
+	) -> Result<bool, DatabaseError> {
+		Self::with_hints(t, |t, hints| {
+			Codec::update(t, schema, pointer, document, hints)
+		})
+	}
+
+	/// Deletes the document at the pointer. Returns `false` if there is none.
+	pub fn delete(
+		t: &mut impl TransactionApi,
+		schema: &Schema,
+		pointer: DbPointer,
+	) -> Result<bool, DatabaseError> {
+		Self::with_hints(t, |t, hints| Codec::delete(t, schema, pointer, hints))
+	}
+
+	/// Runs the operation with the page hints of the meta page, and stores them
+	/// back if they changed. They are stored even if the operation fails, as it
+	/// may have freed the pages they pointed to.
+	fn with_hints<T: TransactionApi, R>(
+		t: &mut T,
+		operation: impl FnOnce(&mut T, &mut PageHints) -> Result<R, DatabaseError>,
+	) -> Result<R, DatabaseError> {
+		let meta_page = MetaPage::new(t.get_page(PageAllocator::META_PAGE_ADDRESS)?)?;
+		let initial_hints = PageHints {
+			records: meta_page.get_records_hint()?,
+			heap: meta_page.get_heap_hint()?,
+		};
+		mem::drop(meta_page);
+
+		let mut hints = initial_hints;
+		let result = operation(t, &mut hints);
+		if hints != initial_hints {
+			let mut meta_page = MetaPage::new(t.get_page_mut(PageAllocator::META_PAGE_ADDRESS)?)?;
+			meta_page.set_records_hint(hints.records)?;
+			meta_page.set_heap_hint(hints.heap)?;
+		}
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use crate::page_store::{PageStorage, PageStorageApi};
+
+	use super::*;
+
+	fn schema() -> Schema {
+		Schema::Struct(HashMap::from([
+			(
+				"name".to_string(),
+				Box::new(Schema::Primitive(Primitive::String)),
+			),
+			(
+				"tags".to_string(),
+				Box::new(Schema::List(Box::new(Schema::Primitive(Primitive::String)))),
+			),
+		]))
+	}
+
+	fn document(name: &str) -> Document {
+		Document::Struct(HashMap::from([
+			(
+				"name".to_string(),
+				Document::Value(Value::String(name.to_string())),
+			),
+			("tags".to_string(), Document::List(Vec::new())),
+		]))
+	}
+
+	#[test]
+	fn insert_update_and_delete_documents() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		let schema = schema();
+		DocStore::init(&mut t, NonZeroU16::MAX, &schema).unwrap();
+
+		// when
+		let first = DocStore::insert(&mut t, &schema, &document("first")).unwrap();
+		let second = DocStore::insert(&mut t, &schema, &document("second")).unwrap();
+		let updated = DocStore::update(&mut t, &schema, first, &document("updated")).unwrap();
+		let deleted = DocStore::delete(&mut t, &schema, second).unwrap();
+		let deleted_again = DocStore::delete(&mut t, &schema, second).unwrap();
+		let third = DocStore::insert(&mut t, &schema, &document("third")).unwrap();
+
+		// then
+		assert!(updated);
+		assert!(deleted);
+		assert!(!deleted_again);
+		// the documents share a records page
+		assert_ne!(first, second);
+		assert_eq!(first.page_address(), second.page_address());
+		// the record of the deleted document is reused
+		assert_eq!(third, second);
+		assert_eq!(
+			DocStore::get(&t, &schema, first).unwrap(),
+			Some(document("updated"))
+		);
+		assert_eq!(
+			DocStore::get(&t, &schema, third).unwrap(),
+			Some(document("third"))
+		);
+		assert_eq!(
+			DocStore::get(&t, &schema, DbPointer::new(first.page_address(), 2)).unwrap(),
+			None
+		);
+		assert_eq!(
+			DocStore::get(
+				&t,
+				&schema,
+				DbPointer::new(PageAllocator::META_PAGE_ADDRESS, 0)
+			)
+			.unwrap(),
+			None
+		);
+		t.commit().unwrap();
+	}
+
+	#[test]
+	fn store_small_documents_on_few_pages() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		let schema = schema();
+		DocStore::init(&mut t, NonZeroU16::MAX, &schema).unwrap();
+		let first_page = PageAllocator::alloc(&mut t).unwrap();
+		PageAllocator::free(&mut t, first_page).unwrap();
+
+		// when
+		for i in 0..100 {
+			DocStore::insert(&mut t, &schema, &document(&format!("document {i}"))).unwrap();
+		}
+
+		// then
+		// one records page and one slotted page were allocated
+		let next_page = PageAllocator::alloc(&mut t).unwrap();
+		assert_eq!(next_page.segment_num, first_page.segment_num);
+		assert_eq!(next_page.page_num.get(), first_page.page_num.get() + 2);
+	}
+
+	#[test]
+	fn read_stored_schema() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		let schema = Schema::Struct(HashMap::from([
+			("name".to_string(), Box::new(schema())),
+			(
+				"scores".to_string(),
+				Box::new(Schema::Map(
+					HashablePrimitive::Int,
+					Box::new(Schema::Option(Box::new(Schema::Primitive(
+						Primitive::Float,
+					)))),
+				)),
+			),
+			(
+				"state".to_string(),
+				Box::new(Schema::Enum(HashMap::from([
+					("active".to_string(), Box::new(Schema::Empty)),
+					(
+						"moved".to_string(),
+						Box::new(Schema::Primitive(Primitive::Bool)),
+					),
+				]))),
+			),
+		]));
+
+		// when
+		DocStore::init(&mut t, NonZeroU16::MAX, &schema).unwrap();
+
+		// then
+		assert_eq!(DocStore::schema(&t).unwrap(), schema);
+		let mut encoded = StoredSchema::encode(&schema);
+		assert!(matches!(
+			StoredSchema::decode(&encoded[..encoded.len() - 1]),
+			Err(DatabaseError::PageFormat(..))
+		));
+		encoded.push(0);
+		assert!(matches!(
+			StoredSchema::decode(&encoded),
+			Err(DatabaseError::PageFormat(..))
+		));
+	}
+
+	#[test]
+	fn try_insert_too_large_document() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		let schema = Schema::Struct(
+			(0..10_000)
+				.map(|i| {
+					(
+						format!("field {i}"),
+						Box::new(Schema::Primitive(Primitive::Uint)),
+					)
+				})
+				.collect(),
+		);
+		DocStore::init(&mut t, NonZeroU16::MAX, &schema).unwrap();
+		let document = Document::Struct(
+			(0..10_000)
+				.map(|i| (format!("field {i}"), Document::Value(Value::Uint(i))))
+				.collect(),
+		);
+
+		// when
+		let result = DocStore::insert(&mut t, &schema, &document);
+
+		// then
+		assert!(matches!(
+			result,
+			Err(DatabaseError::DocumentTooLarge { .. })
+		));
+		t.undo().unwrap();
+	}
+}
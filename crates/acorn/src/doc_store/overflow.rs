@@ -0,0 +1,412 @@
+use crate::page_store::{PageAddress, PageSource, ReadPage, TransactionApi};
+
+use super::{page_alloc::PageAllocator, pages::OverflowPage, DatabaseError};
+
+/// Stores values that don't fit into a single page as chains of overflow pages.
+/// A value is referenced by the address of the first page of its chain.
+pub(super) struct Overflow;
+
+impl Overflow {
+	/// Stores `data` in a new chain, and returns the address of its first page.
+	/// If that fails, the pages that were chained so far are freed again.
+	pub fn write(t: &mut impl TransactionApi, data: &[u8]) -> Result<PageAddress, DatabaseError> {
+		let mut writer = Self::writer(t)?;
+		if let Err(err) = writer.write(data) {
+			let head = writer.head;
+			Self::free(writer.t, head)?;
+			return Err(err);
+		}
+		writer.finish()
+	}
+
+	/// Starts a new chain that is written piece by piece.
+	pub fn writer<T: TransactionApi>(t: &mut T) -> Result<OverflowWriter<'_, T>, DatabaseError> {
+		OverflowWriter::new(t)
+	}
+
+	/// Reads the whole value stored in the chain starting at `head`.
+	pub fn read(t: &impl PageSource, head: PageAddress) -> Result<Vec<u8>, DatabaseError> {
+		let mut buf = vec![0; Self::len(t, head)?];
+		let length = Self::read_range(t, head, 0, &mut buf)?;
+		debug_assert_eq!(length, buf.len());
+		Ok(buf)
+	}
+
+	pub fn reader<T: PageSource>(
+		t: &T,
+		head: PageAddress,
+	) -> Result<OverflowReader<'_, T>, DatabaseError> {
+		OverflowReader::new(t, head)
+	}
+
+	/// Reads the bytes of the value starting at `offset` into `buf`, and returns
+	/// how many were read, which is less than the length of `buf` if the value
+	/// ends first.
+	pub fn read_range(
+		t: &impl PageSource,
+		head: PageAddress,
+		offset: usize,
+		buf: &mut [u8],
+	) -> Result<usize, DatabaseError> {
+		let mut reader = Self::reader(t, head)?;
+		if reader.skip(offset)? < offset {
+			return Ok(0);
+		}
+		let mut length = 0;
+		while length < buf.len() {
+			let read = reader.read(&mut buf[length..])?;
+			if read == 0 {
+				break;
+			}
+			length += read;
+		}
+		Ok(length)
+	}
+
+	/// The length of the value in bytes.
+	pub fn len(t: &impl PageSource, head: PageAddress) -> Result<usize, DatabaseError> {
+		let max_num_pages = PageAllocator::num_allocated_pages(t)?;
+		let mut length = 0;
+		let mut num_pages = 0;
+		let mut page_address = Some(head);
+		while let Some(current) = page_address {
+			num_pages += 1;
+			Self::check_num_pages(head, num_pages, max_num_pages)?;
+			let page = OverflowPage::new(t.get_page(current)?)?;
+			length += page.get_length()?;
+			page_address = page.get_next_page_address()?;
+		}
+		Ok(length)
+	}
+
+	/// Frees every page of the chain starting at `head`. The whole chain is
+	/// read before any page is freed, so that a cyclic one is left untouched.
+	pub fn free(t: &mut impl TransactionApi, head: PageAddress) -> Result<(), DatabaseError> {
+		let max_num_pages = PageAllocator::num_allocated_pages(t)?;
+		let mut pages = Vec::new();
+		let mut page_address = Some(head);
+		while let Some(current) = page_address {
+			pages.push(current);
+			Self::check_num_pages(head, pages.len(), max_num_pages)?;
+			page_address = OverflowPage::new(t.get_page(current)?)?.get_next_page_address()?;
+		}
+		for page_address in pages {
+			PageAllocator::free(t, page_address)?;
+		}
+		Ok(())
+	}
+
+	/// A chain can't have more pages than were ever allocated, so a longer one
+	/// must be cyclic.
+	fn check_num_pages(
+		head: PageAddress,
+		num_pages: usize,
+		max_num_pages: usize,
+	) -> Result<(), DatabaseError> {
+		if num_pages > max_num_pages {
+			return Err(DatabaseError::PageFormat(format!(
+				"The overflow chain at {head} has more pages than the {max_num_pages} that were allocated, so it is cyclic"
+			)));
+		}
+		Ok(())
+	}
+}
+
+/// Writes a value into a new chain, allocating pages as they fill up.
+///
+/// The value is only complete once [`OverflowWriter::finish`] was called; if
+/// the writer is dropped before, the transaction should be aborted.
+pub(super) struct OverflowWriter<'t, T> {
+	t: &'t mut T,
+	head: PageAddress,
+	page_address: PageAddress,
+	/// The number of bytes written to the current page
+	page_length: usize,
+	capacity: usize,
+}
+
+impl<'t, T: TransactionApi> OverflowWriter<'t, T> {
+	fn new(t: &'t mut T) -> Result<Self, DatabaseError> {
+		let head = PageAllocator::alloc(t)?;
+		let page = t.get_page_mut(head)?;
+		let capacity = OverflowPage::<()>::capacity(page.size());
+		OverflowPage::new_unchecked(page).init()?;
+		Ok(Self {
+			t,
+			head,
+			page_address: head,
+			page_length: 0,
+			capacity,
+		})
+	}
+
+	/// Appends `buf` to the value.
+	pub fn write(&mut self, mut buf: &[u8]) -> Result<(), DatabaseError> {
+		while !buf.is_empty() {
+			if self.page_length == self.capacity {
+				self.next_page()?;
+			}
+			let length = buf.len().min(self.capacity - self.page_length);
+			let mut page = OverflowPage::new_unchecked(self.t.get_page_mut(self.page_address)?);
+			page.write_data(self.page_length, &buf[..length])?;
+			self.page_length += length;
+			buf = &buf[length..];
+		}
+		Ok(())
+	}
+
+	/// Completes the value, and returns the address of the first page of its
+	/// chain.
+	pub fn finish(self) -> Result<PageAddress, DatabaseError> {
+		let mut page = OverflowPage::new_unchecked(self.t.get_page_mut(self.page_address)?);
+		page.set_length(self.page_length)?;
+		Ok(self.head)
+	}
+
+	fn next_page(&mut self) -> Result<(), DatabaseError> {
+		let next_page_address = PageAllocator::alloc(self.t)?;
+		OverflowPage::new_unchecked(self.t.get_page_mut(next_page_address)?).init()?;
+
+		let mut page = OverflowPage::new_unchecked(self.t.get_page_mut(self.page_address)?);
+		page.set_length(self.page_length)?;
+		page.set_next_page_address(Some(next_page_address))?;
+
+		self.page_address = next_page_address;
+		self.page_length = 0;
+		Ok(())
+	}
+}
+
+/// Reads a value from a chain front to back.
+pub(super) struct OverflowReader<'t, T> {
+	t: &'t T,
+	head: PageAddress,
+	page_address: Option<PageAddress>,
+	/// The position within the current page
+	offset: usize,
+	/// The number of pages of the chain that were visited so far
+	num_pages: usize,
+	max_num_pages: usize,
+}
+
+impl<'t, T: PageSource> OverflowReader<'t, T> {
+	fn new(t: &'t T, head: PageAddress) -> Result<Self, DatabaseError> {
+		Ok(Self {
+			t,
+			head,
+			page_address: Some(head),
+			offset: 0,
+			num_pages: 1,
+			max_num_pages: PageAllocator::num_allocated_pages(t)?,
+		})
+	}
+
+	fn next_page(&mut self, next_page_address: Option<PageAddress>) -> Result<(), DatabaseError> {
+		self.num_pages += 1;
+		Overflow::check_num_pages(self.head, self.num_pages, self.max_num_pages)?;
+		self.page_address = next_page_address;
+		self.offset = 0;
+		Ok(())
+	}
+
+	/// Reads the next bytes of the value into `buf`, and returns how many were
+	/// read. Like [`std::io::Read::read`], this returns 0 only at the end of
+	/// the value or if `buf` is empty.
+	pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, DatabaseError> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		while let Some(page_address) = self.page_address {
+			let page = OverflowPage::new(self.t.get_page(page_address)?)?;
+			let page_length = page.get_length()?;
+			if self.offset < page_length {
+				let length = buf.len().min(page_length - self.offset);
+				page.read_data(self.offset, &mut buf[..length])?;
+				self.offset += length;
+				return Ok(length);
+			}
+			let next_page_address = page.get_next_page_address()?;
+			self.next_page(next_page_address)?;
+		}
+		Ok(0)
+	}
+
+	/// Skips up to `length` bytes of the value without reading them, and returns
+	/// how many were skipped.
+	pub fn skip(&mut self, length: usize) -> Result<usize, DatabaseError> {
+		let mut skipped = 0;
+		while let Some(page_address) = self.page_address {
+			let page = OverflowPage::new(self.t.get_page(page_address)?)?;
+			let remaining = page.get_length()?.saturating_sub(self.offset);
+			if skipped + remaining >= length {
+				self.offset += length - skipped;
+				return Ok(length);
+			}
+			skipped += remaining;
+			let next_page_address = page.get_next_page_address()?;
+			self.next_page(next_page_address)?;
+		}
+		Ok(skipped)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashSet, num::NonZeroU16};
+
+	use crate::page_store::{PageStorage, PageStorageApi};
+
+	use super::*;
+
+	fn data(length: usize) -> Vec<u8> {
+		(0..length).map(|i| (i % 251) as u8).collect()
+	}
+
+	fn capacity(t: &impl PageSource) -> usize {
+		let page_size = t.get_page(PageAllocator::META_PAGE_ADDRESS).unwrap().size();
+		OverflowPage::<()>::capacity(page_size)
+	}
+
+	#[test]
+	fn write_and_read_chain() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let data = data(3 * capacity(&t) + 100);
+
+		// when
+		let mut writer = Overflow::writer(&mut t).unwrap();
+		for chunk in data.chunks(1000) {
+			writer.write(chunk).unwrap();
+		}
+		let head = writer.finish().unwrap();
+		let empty = Overflow::write(&mut t, &[]).unwrap();
+
+		// then
+		assert_eq!(Overflow::len(&t, head).unwrap(), data.len());
+		assert_eq!(Overflow::read(&t, head).unwrap(), data);
+		let mut reader = Overflow::reader(&t, head).unwrap();
+		let mut read = Vec::new();
+		let mut buf = [0; 777];
+		loop {
+			let length = reader.read(&mut buf).unwrap();
+			if length == 0 {
+				break;
+			}
+			read.extend_from_slice(&buf[..length]);
+		}
+		assert_eq!(read, data);
+		assert_eq!(Overflow::len(&t, empty).unwrap(), 0);
+		assert_eq!(Overflow::read(&t, empty).unwrap(), Vec::<u8>::new());
+		t.commit().unwrap();
+	}
+
+	#[test]
+	fn read_ranges() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let capacity = capacity(&t);
+		let data = data(2 * capacity + 10);
+		let head = Overflow::write(&mut t, &data).unwrap();
+
+		// when
+		let mut across_pages = vec![0; 20];
+		let across_pages_length =
+			Overflow::read_range(&t, head, capacity - 10, &mut across_pages).unwrap();
+		let mut at_end = vec![0; 20];
+		let at_end_length = Overflow::read_range(&t, head, data.len() - 5, &mut at_end).unwrap();
+		let mut past_end = vec![0; 20];
+		let past_end_length =
+			Overflow::read_range(&t, head, data.len() + 1, &mut past_end).unwrap();
+
+		// then
+		assert_eq!(across_pages_length, 20);
+		assert_eq!(across_pages, data[capacity - 10..capacity + 10]);
+		assert_eq!(at_end_length, 5);
+		assert_eq!(at_end[..5], data[data.len() - 5..]);
+		assert_eq!(past_end_length, 0);
+	}
+
+	#[test]
+	fn free_chain() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let data = data(2 * capacity(&t) + 1);
+		let head = Overflow::write(&mut t, &data).unwrap();
+		let mut chain = HashSet::new();
+		let mut page_address = Some(head);
+		while let Some(current) = page_address {
+			chain.insert(current);
+			page_address = OverflowPage::new(t.get_page(current).unwrap())
+				.unwrap()
+				.get_next_page_address()
+				.unwrap();
+		}
+
+		// when
+		Overflow::free(&mut t, head).unwrap();
+
+		// then
+		assert_eq!(chain.len(), 3);
+		assert!(matches!(
+			Overflow::read(&t, head),
+			Err(DatabaseError::UnexpectedPageKind { .. })
+		));
+		// the pages of the chain are reused before new ones are allocated
+		let reused: HashSet<_> = (0..3)
+			.map(|_| PageAllocator::alloc(&mut t).unwrap())
+			.collect();
+		assert_eq!(reused, chain);
+	}
+
+	#[test]
+	fn reject_cyclic_chain() {
+		// given
+		let storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+		let mut t = storage.transaction().unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::MAX).unwrap();
+		let data = data(2 * capacity(&t) + 1);
+		let head = Overflow::write(&mut t, &data).unwrap();
+		let second = OverflowPage::new(t.get_page(head).unwrap())
+			.unwrap()
+			.get_next_page_address()
+			.unwrap()
+			.unwrap();
+		let last = OverflowPage::new(t.get_page(second).unwrap())
+			.unwrap()
+			.get_next_page_address()
+			.unwrap()
+			.unwrap();
+		OverflowPage::new(t.get_page_mut(last).unwrap())
+			.unwrap()
+			.set_next_page_address(Some(second))
+			.unwrap();
+
+		// when
+		let len = Overflow::len(&t, head);
+		let mut reader = Overflow::reader(&t, head).unwrap();
+		let mut buf = vec![0; data.len()];
+		let read = loop {
+			match reader.read(&mut buf) {
+				Ok(0) => break Ok(0),
+				Ok(..) => {}
+				Err(err) => break Err(err),
+			}
+		};
+		let freed = Overflow::free(&mut t, head);
+
+		// then
+		assert!(matches!(len, Err(DatabaseError::PageFormat(..))));
+		assert!(matches!(read, Err(DatabaseError::PageFormat(..))));
+		assert!(matches!(freed, Err(DatabaseError::PageFormat(..))));
+		// no page of the chain was freed
+		let next_page = PageAllocator::alloc(&mut t).unwrap();
+		assert!(![head, second, last].contains(&next_page));
+		assert!(OverflowPage::new(t.get_page(head).unwrap()).is_ok());
+	}
+}
@@ -1,20 +1,31 @@
-use std::{mem, num::NonZero};
+use std::{
+	mem,
+	num::{NonZero, NonZeroU16},
+};
 
-use crate::page_store::{PageAddress, TransactionApi};
+use crate::page_store::{PageAddress, PageSource, TransactionApi};
 
 use super::{
 	pages::{FreelistPage, MetaPage},
 	DatabaseError,
 };
 
-struct PageAllocator;
+pub(super) struct PageAllocator;
 
 impl PageAllocator {
-	const META_PAGE_ADDRESS: PageAddress = PageAddress::new_unwrap(0, 1);
+	pub const META_PAGE_ADDRESS: PageAddress = PageAddress::new_unwrap(0, 1);
 
-	pub fn init(t: &mut impl TransactionApi) -> Result<(), DatabaseError> {
+	/// Sets up the allocator, which hands out pages up to `last_page_num` in
+	/// each segment, before it moves on to the next one.
+	pub fn init(
+		t: &mut impl TransactionApi,
+		last_page_num: NonZeroU16,
+	) -> Result<(), DatabaseError> {
 		let mut meta_page = MetaPage::new_unchecked(t.get_page_mut(Self::META_PAGE_ADDRESS)?);
-		meta_page.init(Self::page_address_after(Self::META_PAGE_ADDRESS))?;
+		meta_page.init(
+			Self::page_address_after(Self::META_PAGE_ADDRESS, last_page_num),
+			last_page_num,
+		)?;
 		Ok(())
 	}
 
@@ -61,6 +72,17 @@ impl PageAllocator {
 		Ok(())
 	}
 
+	/// The number of pages that were handed out so far, including the meta page
+	/// and pages that were freed again.
+	pub fn num_allocated_pages(t: &impl PageSource) -> Result<usize, DatabaseError> {
+		let meta_page = MetaPage::new(t.get_page(Self::META_PAGE_ADDRESS)?)?;
+		let next_page_address = meta_page.get_next_page_address()?;
+		let last_page_num = usize::from(meta_page.get_last_page_num()?.get());
+		let segment_num = usize::try_from(next_page_address.segment_num)
+			.expect("Segment numbers must fit into usize!");
+		Ok(segment_num * last_page_num + usize::from(next_page_address.page_num.get()) - 1)
+	}
+
 	fn next_free_page(t: &mut impl TransactionApi) -> Result<Option<PageAddress>, DatabaseError> {
 		let Some(freelist_head_id) = Self::meta_page(t)?.get_freelist_head()? else {
 			return Ok(None);
@@ -80,12 +102,13 @@ impl PageAllocator {
 	fn next_uninit_page(t: &mut impl TransactionApi) -> Result<PageAddress, DatabaseError> {
 		let mut meta_page = Self::meta_page_mut(t)?;
 		let page_address = meta_page.get_next_page_address()?;
-		meta_page.set_next_page_address(Self::page_address_after(page_address))?;
+		let last_page_num = meta_page.get_last_page_num()?;
+		meta_page.set_next_page_address(Self::page_address_after(page_address, last_page_num))?;
 		Ok(page_address)
 	}
 
-	fn page_address_after(page_address: PageAddress) -> PageAddress {
-		if page_address.page_num.get() == u16::MAX {
+	fn page_address_after(page_address: PageAddress, last_page_num: NonZeroU16) -> PageAddress {
+		if page_address.page_num >= last_page_num {
 			PageAddress::new(
 				page_address
 					.segment_num
@@ -116,6 +139,7 @@ impl PageAllocator {
 mod tests {
 	use crate::{
 		doc_store::pages::PageKind,
+		files::segment::DEFAULT_PAGE_BODY_SIZE,
 		page_store::{test_helpers::page_address, MockPage, MockPageMut, MockTransactionApi},
 	};
 	use mockall::{predicate::*, Sequence};
@@ -144,17 +168,21 @@ mod tests {
 					.with(
 						eq(7),
 						eq([
-							0_u32.to_ne_bytes().as_slice(),
-							2_u16.to_ne_bytes().as_slice(),
+							0_u32.to_le_bytes().as_slice(),
+							2_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
 					.returning(|_, _| Ok(()));
+				page.expect_write()
+					.once()
+					.with(eq(13), eq(0x1000_u16.to_le_bytes()))
+					.returning(|_, _| Ok(()));
 				Ok(page)
 			});
 
 		// when
-		PageAllocator::init(&mut t).unwrap();
+		PageAllocator::init(&mut t, NonZeroU16::new(0x1000).unwrap()).unwrap();
 	}
 
 	#[test]
@@ -185,8 +213,8 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x24_u32.to_ne_bytes().as_slice(),
-								0x25_u16.to_ne_bytes().as_slice(),
+								0x24_u32.to_le_bytes().as_slice(),
+								0x25_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
@@ -202,6 +230,7 @@ mod tests {
 			.with(eq(page_address!(0x24, 0x25)))
 			.returning(|_| {
 				let mut page = MockPageMut::new();
+				page.expect_size().return_const(DEFAULT_PAGE_BODY_SIZE);
 				// - check the page type
 				page.expect_read()
 					.once()
@@ -215,7 +244,7 @@ mod tests {
 					.once()
 					.with(eq(7), always())
 					.returning(|_, buf| {
-						buf.copy_from_slice(&60_u16.to_ne_bytes());
+						buf.copy_from_slice(&60_u16.to_le_bytes());
 						Ok(())
 					});
 				// - read the last item of the freelist head page (None)
@@ -233,8 +262,8 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x69_u32.to_ne_bytes().as_slice(),
-								0x420_u16.to_ne_bytes().as_slice(),
+								0x69_u32.to_le_bytes().as_slice(),
+								0x420_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
@@ -244,7 +273,7 @@ mod tests {
 				//   skipped)
 				page.expect_write()
 					.once()
-					.with(eq(7), eq(58_u16.to_ne_bytes()))
+					.with(eq(7), eq(58_u16.to_le_bytes()))
 					.returning(|_, _| Ok(()));
 				Ok(page)
 			});
@@ -284,8 +313,8 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x24_u32.to_ne_bytes().as_slice(),
-								0x25_u16.to_ne_bytes().as_slice(),
+								0x24_u32.to_le_bytes().as_slice(),
+								0x25_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
@@ -314,7 +343,7 @@ mod tests {
 					.once()
 					.with(eq(7), always())
 					.returning(|_, buf| {
-						buf.copy_from_slice(&0_u16.to_ne_bytes());
+						buf.copy_from_slice(&0_u16.to_le_bytes());
 						Ok(())
 					});
 				// - read the next page ID in the freelist (60:70)
@@ -324,8 +353,8 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x60_u32.to_ne_bytes().as_slice(),
-								0x70_u16.to_ne_bytes().as_slice(),
+								0x60_u32.to_le_bytes().as_slice(),
+								0x70_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
@@ -355,8 +384,8 @@ mod tests {
 					.with(
 						eq(1),
 						eq([
-							0x60_u32.to_ne_bytes().as_slice(),
-							0x70_u16.to_ne_bytes().as_slice(),
+							0x60_u32.to_le_bytes().as_slice(),
+							0x70_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -425,21 +454,29 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x2000_u32.to_ne_bytes().as_slice(),
-								0x3_u16.to_ne_bytes().as_slice(),
+								0x2000_u32.to_le_bytes().as_slice(),
+								0x3_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
 						Ok(())
 					});
+				// - read the last page number of a segment (ffff)
+				page.expect_read()
+					.once()
+					.with(eq(13), always())
+					.returning(|_, buf| {
+						buf.copy_from_slice(&0xffff_u16.to_le_bytes());
+						Ok(())
+					});
 				// - increment the next unititialized page ID to 2000:4
 				page.expect_write()
 					.once()
 					.with(
 						eq(7),
 						eq([
-							0x2000_u32.to_ne_bytes().as_slice(),
-							0x4_u16.to_ne_bytes().as_slice(),
+							0x2000_u32.to_le_bytes().as_slice(),
+							0x4_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -501,28 +538,36 @@ mod tests {
 						buf.copy_from_slice(&[PageKind::FreelistMeta as u8]);
 						Ok(())
 					});
-				// - read the next uninitialized page ID (2000:ffff)
+				// - read the next uninitialized page ID (2000:1000)
 				page.expect_read()
 					.once()
 					.with(eq(7), always())
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x2000_u32.to_ne_bytes().as_slice(),
-								0xffff_u16.to_ne_bytes().as_slice(),
+								0x2000_u32.to_le_bytes().as_slice(),
+								0x1000_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
 						Ok(())
 					});
+				// - read the last page number of a segment (1000)
+				page.expect_read()
+					.once()
+					.with(eq(13), always())
+					.returning(|_, buf| {
+						buf.copy_from_slice(&0x1000_u16.to_le_bytes());
+						Ok(())
+					});
 				// - increment the next unititialized page ID to 2001:1
 				page.expect_write()
 					.once()
 					.with(
 						eq(7),
 						eq([
-							0x2001_u32.to_ne_bytes().as_slice(),
-							0x1_u16.to_ne_bytes().as_slice(),
+							0x2001_u32.to_le_bytes().as_slice(),
+							0x1_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -534,7 +579,7 @@ mod tests {
 		let page_address = PageAllocator::alloc(&mut t).unwrap();
 
 		// then
-		assert_eq!(page_address, page_address!(0x2000, 0xffff));
+		assert_eq!(page_address, page_address!(0x2000, 0x1000));
 	}
 
 	#[test]
@@ -565,8 +610,8 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x2000_u32.to_ne_bytes().as_slice(),
-								0x1_u16.to_ne_bytes().as_slice(),
+								0x2000_u32.to_le_bytes().as_slice(),
+								0x1_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
@@ -582,6 +627,7 @@ mod tests {
 			.with(eq(page_address!(0x2000, 0x1)))
 			.returning(|_| {
 				let mut page = MockPageMut::new();
+				page.expect_size().return_const(DEFAULT_PAGE_BODY_SIZE);
 				// - check the page type
 				page.expect_read()
 					.once()
@@ -594,7 +640,7 @@ mod tests {
 				page.expect_read()
 					.with(eq(7), always())
 					.returning(|_, buf| {
-						buf.copy_from_slice(&2_u16.to_ne_bytes());
+						buf.copy_from_slice(&2_u16.to_le_bytes());
 						Ok(())
 					});
 
@@ -604,8 +650,8 @@ mod tests {
 					.with(
 						eq(21),
 						eq([
-							0x69_u32.to_ne_bytes().as_slice(),
-							0x420_u16.to_ne_bytes().as_slice(),
+							0x69_u32.to_le_bytes().as_slice(),
+							0x420_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -614,7 +660,7 @@ mod tests {
 				// - increment the page length
 				page.expect_write()
 					.once()
-					.with(eq(7), eq(3_u16.to_ne_bytes()))
+					.with(eq(7), eq(3_u16.to_le_bytes()))
 					.returning(|_, _| Ok(()));
 				Ok(page)
 			});
@@ -702,8 +748,8 @@ mod tests {
 					.with(
 						eq(1),
 						eq([
-							0x69_u32.to_ne_bytes().as_slice(),
-							0x420_u16.to_ne_bytes().as_slice(),
+							0x69_u32.to_le_bytes().as_slice(),
+							0x420_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -743,8 +789,8 @@ mod tests {
 					.returning(|_, buf| {
 						buf.copy_from_slice(
 							&[
-								0x2000_u32.to_ne_bytes().as_slice(),
-								0x1_u16.to_ne_bytes().as_slice(),
+								0x2000_u32.to_le_bytes().as_slice(),
+								0x1_u16.to_le_bytes().as_slice(),
 							]
 							.concat(),
 						);
@@ -760,6 +806,7 @@ mod tests {
 			.with(eq(page_address!(0x2000, 0x1)))
 			.returning(|_| {
 				let mut page = MockPageMut::new();
+				page.expect_size().return_const(DEFAULT_PAGE_BODY_SIZE);
 				// - set the page type
 				page.expect_read()
 					.once()
@@ -773,7 +820,10 @@ mod tests {
 					.once()
 					.with(eq(7), always())
 					.returning(|_, buf| {
-						buf.copy_from_slice(&(FreelistPage::<()>::NUM_SLOTS as u16).to_ne_bytes());
+						buf.copy_from_slice(
+							&(FreelistPage::<()>::num_slots(DEFAULT_PAGE_BODY_SIZE) as u16)
+								.to_le_bytes(),
+						);
 						Ok(())
 					});
 				Ok(page)
@@ -802,8 +852,8 @@ mod tests {
 					.with(
 						eq(1),
 						eq([
-							0x2000_u32.to_ne_bytes().as_slice(),
-							0x1_u16.to_ne_bytes().as_slice(),
+							0x2000_u32.to_le_bytes().as_slice(),
+							0x1_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -838,8 +888,8 @@ mod tests {
 					.with(
 						eq(1),
 						eq([
-							0x69_u32.to_ne_bytes().as_slice(),
-							0x420_u16.to_ne_bytes().as_slice(),
+							0x69_u32.to_le_bytes().as_slice(),
+							0x420_u16.to_le_bytes().as_slice(),
 						]
 						.concat()),
 					)
@@ -1,12 +1,15 @@
 use std::{
+	cmp::Reverse,
 	mem::{self, offset_of},
 	num::NonZeroU16,
 };
 
-use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+use zerocopy::{
+	little_endian::{U16, U32},
+	FromBytes, FromZeros, Immutable, IntoBytes,
+};
 
 use crate::{
-	files::segment::PAGE_BODY_SIZE,
 	page_store::{PageAddress, ReadPage, WritePage},
 	repr::Repr,
 };
@@ -19,6 +22,8 @@ pub(crate) enum PageKind {
 	FreelistMeta = 0,
 	FreelistBlock = 1,
 	Records = 2,
+	Slotted = 3,
+	Overflow = 4,
 }
 
 impl PageKind {
@@ -27,6 +32,8 @@ impl PageKind {
 			0 => Some(PageKind::FreelistMeta),
 			1 => Some(PageKind::FreelistBlock),
 			2 => Some(PageKind::Records),
+			3 => Some(PageKind::Slotted),
+			4 => Some(PageKind::Overflow),
 			_ => None,
 		}
 	}
@@ -68,28 +75,28 @@ impl Repr<PageHeader> for PageHeaderRepr {
 #[derive(Debug, Immutable, IntoBytes, FromBytes)]
 #[repr(C, packed)]
 struct PageAddressRepr {
-	segment_num: u32,
-	page_num: u16,
+	segment_num: U32,
+	page_num: U16,
 }
 
 impl TryFrom<PageAddressRepr> for PageAddress {
 	type Error = DatabaseError;
 
 	fn try_from(value: PageAddressRepr) -> Result<Self, DatabaseError> {
-		let Some(page_num) = NonZeroU16::new(value.page_num) else {
+		let Some(page_num) = NonZeroU16::new(value.page_num.get()) else {
 			return Err(DatabaseError::PageFormat(
 				"Found invalid page number '0'!".to_string(),
 			));
 		};
-		Ok(PageAddress::new(value.segment_num, page_num))
+		Ok(PageAddress::new(value.segment_num.get(), page_num))
 	}
 }
 
 impl From<PageAddress> for PageAddressRepr {
 	fn from(value: PageAddress) -> Self {
 		Self {
-			segment_num: value.segment_num,
-			page_num: value.page_num.get(),
+			segment_num: value.segment_num.into(),
+			page_num: value.page_num.get().into(),
 		}
 	}
 }
@@ -97,8 +104,8 @@ impl From<PageAddress> for PageAddressRepr {
 impl From<PageAddressRepr> for Option<PageAddress> {
 	fn from(value: PageAddressRepr) -> Self {
 		Some(PageAddress::new(
-			value.segment_num,
-			NonZeroU16::new(value.page_num)?,
+			value.segment_num.get(),
+			NonZeroU16::new(value.page_num.get())?,
 		))
 	}
 }
@@ -112,6 +119,23 @@ impl From<Option<PageAddress>> for PageAddressRepr {
 	}
 }
 
+/// A length or offset within a page.
+#[derive(Debug, Immutable, IntoBytes, FromBytes)]
+#[repr(transparent)]
+struct PageSizeRepr(U16);
+
+impl From<u16> for PageSizeRepr {
+	fn from(value: u16) -> Self {
+		Self(value.into())
+	}
+}
+
+impl From<PageSizeRepr> for usize {
+	fn from(value: PageSizeRepr) -> Self {
+		value.0.get().into()
+	}
+}
+
 macro_rules! read_section {
 	($page:expr, $page_repr:ident.$field:ident, $field_repr:ty) => {{
 		let mut repr = <$field_repr as FromZeros>::new_zeroed();
@@ -141,7 +165,7 @@ macro_rules! read_array_section {
 	($page:expr, $page_repr:ident.$field:ident, $field_repr:ident, $index:expr) => {{
 		let mut repr = <$field_repr as FromZeros>::new_zeroed();
 		let index = mem::offset_of!($page_repr, $field) + mem::size_of::<$field_repr>() * $index;
-		if index + mem::size_of::<$field_repr>() > PAGE_BODY_SIZE {
+		if index + mem::size_of::<$field_repr>() > ReadPage::size(&$page) {
 			Err(DatabaseError::PageOutOfBounds)
 		} else {
 			ReadPage::read(
@@ -159,7 +183,7 @@ macro_rules! write_array_section {
 	($page:expr, $page_repr:ident.$field:ident, $field_repr:ident, $index:expr, $value:expr) => {{
 		let repr: $field_repr = $value.into();
 		let index = mem::offset_of!($page_repr, $field) + mem::size_of::<$field_repr>() * $index;
-		if index + mem::size_of::<$field_repr>() > PAGE_BODY_SIZE {
+		if index + mem::size_of::<$field_repr>() > ReadPage::size(&$page) {
 			Ok(())
 		} else {
 			WritePage::write(
@@ -177,6 +201,14 @@ struct MetaPageFormat {
 	header: PageHeaderRepr,
 	freelist_head: PageAddressRepr,
 	next_page_address: PageAddressRepr,
+	last_page_num: U16,
+	/// The first page of the chain that holds the schema of the documents
+	schema_head: PageAddressRepr,
+	/// The records page that the next document is put on if it has room
+	records_hint: PageAddressRepr,
+	/// The slotted page that the next out-of-line value is put on if it has
+	/// room
+	heap_hint: PageAddressRepr,
 }
 
 pub(super) struct MetaPage<P>(P);
@@ -206,10 +238,33 @@ impl<P: ReadPage> MetaPage<P> {
 	pub fn get_next_page_address(&self) -> Result<PageAddress, DatabaseError> {
 		read_section!(self.0, MetaPageFormat.next_page_address, PageAddressRepr)
 	}
+
+	pub fn get_last_page_num(&self) -> Result<NonZeroU16, DatabaseError> {
+		let last_page_num: u16 = read_section!(self.0, MetaPageFormat.last_page_num, U16)?;
+		NonZeroU16::new(last_page_num).ok_or_else(|| {
+			DatabaseError::PageFormat("Found invalid last page number '0'!".to_string())
+		})
+	}
+
+	pub fn get_schema_head(&self) -> Result<Option<PageAddress>, DatabaseError> {
+		read_section!(self.0, MetaPageFormat.schema_head, PageAddressRepr)
+	}
+
+	pub fn get_records_hint(&self) -> Result<Option<PageAddress>, DatabaseError> {
+		read_section!(self.0, MetaPageFormat.records_hint, PageAddressRepr)
+	}
+
+	pub fn get_heap_hint(&self) -> Result<Option<PageAddress>, DatabaseError> {
+		read_section!(self.0, MetaPageFormat.heap_hint, PageAddressRepr)
+	}
 }
 
 impl<P: WritePage> MetaPage<P> {
-	pub fn init(&mut self, next_page_address: PageAddress) -> Result<(), DatabaseError> {
+	pub fn init(
+		&mut self,
+		next_page_address: PageAddress,
+		last_page_num: NonZeroU16,
+	) -> Result<(), DatabaseError> {
 		write_section!(
 			self.0,
 			MetaPageFormat.header,
@@ -220,6 +275,12 @@ impl<P: WritePage> MetaPage<P> {
 		)?;
 		self.set_freelist_head(None)?;
 		self.set_next_page_address(next_page_address)?;
+		write_section!(
+			self.0,
+			MetaPageFormat.last_page_num,
+			U16,
+			last_page_num.get()
+		)?;
 		Ok(())
 	}
 
@@ -235,25 +296,34 @@ impl<P: WritePage> MetaPage<P> {
 			value
 		)
 	}
+
+	pub fn set_schema_head(&mut self, value: Option<PageAddress>) -> Result<(), DatabaseError> {
+		write_section!(self.0, MetaPageFormat.schema_head, PageAddressRepr, value)
+	}
+
+	pub fn set_records_hint(&mut self, value: Option<PageAddress>) -> Result<(), DatabaseError> {
+		write_section!(self.0, MetaPageFormat.records_hint, PageAddressRepr, value)
+	}
+
+	pub fn set_heap_hint(&mut self, value: Option<PageAddress>) -> Result<(), DatabaseError> {
+		write_section!(self.0, MetaPageFormat.heap_hint, PageAddressRepr, value)
+	}
 }
 
 #[repr(C, packed)]
 struct FreelistPageFormat {
 	header: PageHeaderRepr,
 	next_page_address: PageAddressRepr,
-	length: u16,
+	length: PageSizeRepr,
 	items: [PageAddressRepr; 0],
 }
 
-impl FreelistPageFormat {
-	const NUM_SLOTS: usize =
-		(PAGE_BODY_SIZE - offset_of!(FreelistPageFormat, items)) / size_of::<PageAddressRepr>();
-}
-
 pub(super) struct FreelistPage<P>(P);
 
 impl<P> FreelistPage<P> {
-	pub const NUM_SLOTS: usize = FreelistPageFormat::NUM_SLOTS;
+	pub const fn num_slots(page_size: usize) -> usize {
+		(page_size - offset_of!(FreelistPageFormat, items)) / size_of::<PageAddressRepr>()
+	}
 
 	pub fn new_unchecked(page: P) -> Self {
 		Self(page)
@@ -281,11 +351,11 @@ impl<P: ReadPage> FreelistPage<P> {
 	}
 
 	pub fn get_length(&self) -> Result<usize, DatabaseError> {
-		read_section!(self.0, FreelistPageFormat.length, u16)
+		read_section!(self.0, FreelistPageFormat.length, PageSizeRepr)
 	}
 
 	pub fn is_full(&self) -> Result<bool, DatabaseError> {
-		Ok(self.get_length()? >= FreelistPageFormat::NUM_SLOTS)
+		Ok(self.get_length()? >= Self::num_slots(self.0.size()))
 	}
 
 	pub fn get_item(&self, index: usize) -> Result<Option<PageAddress>, DatabaseError> {
@@ -322,9 +392,11 @@ impl<P: WritePage> FreelistPage<P> {
 
 	fn set_length(&mut self, value: usize) -> Result<(), DatabaseError> {
 		let repr = u16::try_from(value).expect("Freelist page length must be 16-bit!");
-		write_section!(self.0, FreelistPageFormat.length, u16, repr)
+		write_section!(self.0, FreelistPageFormat.length, PageSizeRepr, repr)
 	}
+}
 
+impl<P: ReadPage + WritePage> FreelistPage<P> {
 	fn set_item(&mut self, index: usize, value: Option<PageAddress>) -> Result<(), DatabaseError> {
 		write_array_section!(
 			self.0,
@@ -334,9 +406,7 @@ impl<P: WritePage> FreelistPage<P> {
 			value
 		)
 	}
-}
 
-impl<P: ReadPage + WritePage> FreelistPage<P> {
 	pub fn push_item(&mut self, value: PageAddress) -> Result<(), DatabaseError> {
 		let index = self.get_length()?;
 		self.set_item(index, Some(value))?;
@@ -362,15 +432,23 @@ impl<P: ReadPage + WritePage> FreelistPage<P> {
 #[repr(C, packed)]
 struct RecordsPageFormat {
 	header: PageHeaderRepr,
-	record_length: u16,
-	num_records: u16,
-	offsets: [u16; 0],
+	record_length: PageSizeRepr,
+	num_records: PageSizeRepr,
+	/// The lowest index that may hold a free record; all records below it are
+	/// in use. What makes a record free is up to the user of the page.
+	first_free: PageSizeRepr,
+	offsets: [PageSizeRepr; 0],
 	// records themselves stacked end-to-front afterwards
 }
 
 pub(super) struct RecordsPage<P>(P);
 
 impl<P> RecordsPage<P> {
+	/// The length of the largest record that fits into an empty page.
+	pub const fn max_record_length(page_size: usize) -> usize {
+		page_size - offset_of!(RecordsPageFormat, offsets) - size_of::<PageSizeRepr>()
+	}
+
 	pub fn new_unchecked(page: P) -> Self {
 		Self(page)
 	}
@@ -389,17 +467,21 @@ impl<P: ReadPage> RecordsPage<P> {
 	}
 
 	pub fn get_record_length(&self) -> Result<usize, DatabaseError> {
-		read_section!(self.0, RecordsPageFormat.record_length, u16)
+		read_section!(self.0, RecordsPageFormat.record_length, PageSizeRepr)
 	}
 
 	pub fn get_num_records(&self) -> Result<usize, DatabaseError> {
-		read_section!(self.0, RecordsPageFormat.num_records, u16)
+		read_section!(self.0, RecordsPageFormat.num_records, PageSizeRepr)
+	}
+
+	pub fn get_first_free(&self) -> Result<usize, DatabaseError> {
+		read_section!(self.0, RecordsPageFormat.first_free, PageSizeRepr)
 	}
 
 	pub fn get_record(&self, index: usize, buf: &mut [u8]) -> Result<(), DatabaseError> {
 		let len = self.get_record_length()?;
 		let offset = self.get_offset_at(index)?;
-		if offset + len > PAGE_BODY_SIZE {
+		if offset + len > self.0.size() {
 			return Err(DatabaseError::PageOutOfBounds);
 		}
 		self.0.read(offset, &mut buf[0..len])?;
@@ -410,36 +492,750 @@ impl<P: ReadPage> RecordsPage<P> {
 		if index >= self.get_num_records()? {
 			return Err(DatabaseError::InvalidRecordIndex);
 		}
-		read_array_section!(self.0, RecordsPageFormat.offsets, u16, index)
+		read_array_section!(self.0, RecordsPageFormat.offsets, PageSizeRepr, index)
 	}
 
 	fn get_first_record_offset(&self) -> Result<usize, DatabaseError> {
-		let offset =
-			offset_of!(RecordsPageFormat, offsets) + self.get_num_records()? * size_of::<u16>();
+		let offset = offset_of!(RecordsPageFormat, offsets)
+			+ self.get_num_records()? * size_of::<PageSizeRepr>();
 		Ok(offset)
 	}
 }
+
+impl<P: WritePage> RecordsPage<P> {
+	pub fn init(&mut self, record_length: usize) -> Result<(), DatabaseError> {
+		write_section!(
+			self.0,
+			RecordsPageFormat.header,
+			PageHeaderRepr,
+			PageHeader {
+				kind: PageKind::Records
+			}
+		)?;
+		self.set_record_length(record_length)?;
+		self.set_num_records(0)?;
+		self.set_first_free(0)?;
+		Ok(())
+	}
+
+	/// Removes all records from the page.
+	pub fn clear(&mut self) -> Result<(), DatabaseError> {
+		self.set_num_records(0)?;
+		self.set_first_free(0)
+	}
+
+	pub fn set_first_free(&mut self, value: usize) -> Result<(), DatabaseError> {
+		let repr: u16 = value.try_into().expect("Record index must be 16-bit!");
+		write_section!(self.0, RecordsPageFormat.first_free, PageSizeRepr, repr)
+	}
+
+	fn set_record_length(&mut self, length: usize) -> Result<(), DatabaseError> {
+		let repr: u16 = length.try_into().expect("Record length must be 16-bit!");
+		write_section!(self.0, RecordsPageFormat.record_length, PageSizeRepr, repr)
+	}
+
+	fn set_num_records(&mut self, value: usize) -> Result<(), DatabaseError> {
+		let repr: u16 = value.try_into().expect("Number of records must be 16-bit!");
+		write_section!(self.0, RecordsPageFormat.num_records, PageSizeRepr, repr)
+	}
+}
+
 impl<P: ReadPage + WritePage> RecordsPage<P> {
+	/// Appends a record below the previous one, and returns its index. Fails
+	/// with [`DatabaseError::PageOutOfBounds`] if the page is full.
+	pub fn push_record(&mut self, buf: &[u8]) -> Result<usize, DatabaseError> {
+		let len = self.get_record_length()?;
+		let index = self.get_num_records()?;
+		let end = if index == 0 {
+			self.0.size()
+		} else {
+			self.get_offset_at(index - 1)?
+		};
+		let offsets_end = self.get_first_record_offset()? + size_of::<PageSizeRepr>();
+		let Some(offset) = end.checked_sub(len).filter(|offset| *offset >= offsets_end) else {
+			return Err(DatabaseError::PageOutOfBounds);
+		};
+		self.set_num_records(index + 1)?;
+		self.set_offset_at(index, offset)?;
+		self.set_record(index, buf)?;
+		Ok(index)
+	}
+
 	pub fn set_record(&mut self, index: usize, buf: &[u8]) -> Result<(), DatabaseError> {
 		let len = self.get_record_length()?;
 		let offset = self.get_offset_at(index)?;
-		if offset + len > PAGE_BODY_SIZE {
+		if offset + len > self.0.size() {
 			return Err(DatabaseError::PageOutOfBounds);
 		}
 		self.0.write(offset, &buf[0..len])?;
 		Ok(())
 	}
 
-	fn set_record_length(&mut self, length: usize) -> Result<(), DatabaseError> {
-		let repr: u16 = length.try_into().expect("Record length must be 16-bit!");
-		write_section!(self.0, RecordsPageFormat.record_length, u16, repr)
-	}
-
 	fn set_offset_at(&mut self, index: usize, offset: usize) -> Result<(), DatabaseError> {
 		if index >= self.get_num_records()? {
 			return Err(DatabaseError::InvalidRecordIndex);
 		}
 		let repr: u16 = offset.try_into().expect("Record offset must be 16-bit!");
-		write_array_section!(self.0, RecordsPageFormat.offsets, u16, index, repr)
+		write_array_section!(self.0, RecordsPageFormat.offsets, PageSizeRepr, index, repr)
+	}
+}
+
+/// Where a record lies on a slotted page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+	offset: usize,
+	length: usize,
+}
+
+/// A slot of a slotted page. Records never start at offset 0, which marks a
+/// tombstone.
+#[derive(Debug, Immutable, IntoBytes, FromBytes)]
+#[repr(C, packed)]
+struct SlotRepr {
+	offset: U16,
+	length: U16,
+}
+
+impl From<SlotRepr> for Option<Slot> {
+	fn from(value: SlotRepr) -> Self {
+		if value.offset.get() == 0 {
+			return None;
+		}
+		Some(Slot {
+			offset: value.offset.get().into(),
+			length: value.length.get().into(),
+		})
+	}
+}
+
+impl From<Option<Slot>> for SlotRepr {
+	fn from(value: Option<Slot>) -> Self {
+		let Some(slot) = value else {
+			return Self::new_zeroed();
+		};
+		Self {
+			offset: u16::try_from(slot.offset)
+				.expect("Record offset must be 16-bit!")
+				.into(),
+			length: u16::try_from(slot.length)
+				.expect("Record length must be 16-bit!")
+				.into(),
+		}
+	}
+}
+
+#[repr(C, packed)]
+struct SlottedPageFormat {
+	header: PageHeaderRepr,
+	num_slots: PageSizeRepr,
+	/// The offset of the lowest record
+	records_start: PageSizeRepr,
+	slots: [SlotRepr; 0],
+	// records themselves stacked end-to-front afterwards, in any order
+}
+
+/// A page of variable-length records. The slot directory grows from the front
+/// of the page, and the records grow from the back. A record keeps the index
+/// of its slot until it is deleted, which leaves a tombstone that the next
+/// insert reuses. Space freed by deleting or shrinking records is reclaimed by
+/// compacting the page once the gap between slots and records is too small.
+pub(super) struct SlottedPage<P>(P);
+
+impl<P> SlottedPage<P> {
+	const SLOTS_OFFSET: usize = offset_of!(SlottedPageFormat, slots);
+	const SLOT_SIZE: usize = size_of::<SlotRepr>();
+
+	/// The length of the largest record that fits into an empty page.
+	pub const fn max_record_length(page_size: usize) -> usize {
+		page_size - Self::SLOTS_OFFSET - Self::SLOT_SIZE
+	}
+
+	pub fn new_unchecked(page: P) -> Self {
+		Self(page)
+	}
+
+	fn slots_end(num_slots: usize) -> usize {
+		Self::SLOTS_OFFSET + num_slots * Self::SLOT_SIZE
+	}
+}
+
+impl<P: ReadPage> SlottedPage<P> {
+	pub fn new(page: P) -> Result<Self, DatabaseError> {
+		let header: PageHeader = read_section!(page, SlottedPageFormat.header, PageHeaderRepr)?;
+		if header.kind != PageKind::Slotted {
+			return Err(DatabaseError::UnexpectedPageKind {
+				expected: PageKind::Slotted,
+				received: header.kind,
+			});
+		}
+		Ok(Self::new_unchecked(page))
+	}
+
+	/// The number of slots, including tombstones.
+	pub fn get_num_slots(&self) -> Result<usize, DatabaseError> {
+		read_section!(self.0, SlottedPageFormat.num_slots, PageSizeRepr)
+	}
+
+	/// Reads the record in the given slot, or returns `None` if it was deleted.
+	pub fn get_record(&self, index: usize) -> Result<Option<Vec<u8>>, DatabaseError> {
+		let Some(slot) = self.get_slot(index)? else {
+			return Ok(None);
+		};
+		let mut buf = vec![0; slot.length];
+		self.0.read(slot.offset, &mut buf)?;
+		Ok(Some(buf))
+	}
+
+	/// The number of bytes that are free for new records once the page is
+	/// compacted. Inserting a record also takes a slot, unless a tombstone can
+	/// be reused.
+	pub fn free_space(&self) -> Result<usize, DatabaseError> {
+		let num_slots = self.get_num_slots()?;
+		let mut used = Self::slots_end(num_slots);
+		for index in 0..num_slots {
+			if let Some(slot) = self.get_slot(index)? {
+				used += slot.length;
+			}
+		}
+		Ok(self.0.size().saturating_sub(used))
+	}
+
+	fn get_records_start(&self) -> Result<usize, DatabaseError> {
+		read_section!(self.0, SlottedPageFormat.records_start, PageSizeRepr)
+	}
+
+	/// The number of free bytes between the slots and the records
+	fn contiguous_free_space(&self) -> Result<usize, DatabaseError> {
+		let slots_end = Self::slots_end(self.get_num_slots()?);
+		Ok(self.get_records_start()?.saturating_sub(slots_end))
+	}
+
+	fn get_slot(&self, index: usize) -> Result<Option<Slot>, DatabaseError> {
+		if index >= self.get_num_slots()? {
+			return Err(DatabaseError::InvalidRecordIndex);
+		}
+		let slot: Option<Slot> =
+			read_array_section!(self.0, SlottedPageFormat.slots, SlotRepr, index)?;
+		if let Some(slot) = slot {
+			if slot.offset + slot.length > self.0.size() {
+				return Err(DatabaseError::PageOutOfBounds);
+			}
+		}
+		Ok(slot)
+	}
+
+	fn first_tombstone(&self) -> Result<Option<usize>, DatabaseError> {
+		for index in 0..self.get_num_slots()? {
+			if self.get_slot(index)?.is_none() {
+				return Ok(Some(index));
+			}
+		}
+		Ok(None)
+	}
+}
+
+impl<P: WritePage> SlottedPage<P> {
+	fn set_num_slots(&mut self, value: usize) -> Result<(), DatabaseError> {
+		let repr: u16 = value.try_into().expect("Number of slots must be 16-bit!");
+		write_section!(self.0, SlottedPageFormat.num_slots, PageSizeRepr, repr)
+	}
+
+	fn set_records_start(&mut self, value: usize) -> Result<(), DatabaseError> {
+		let repr: u16 = value.try_into().expect("Record offset must be 16-bit!");
+		write_section!(self.0, SlottedPageFormat.records_start, PageSizeRepr, repr)
+	}
+}
+
+impl<P: ReadPage + WritePage> SlottedPage<P> {
+	pub fn init(&mut self) -> Result<(), DatabaseError> {
+		write_section!(
+			self.0,
+			SlottedPageFormat.header,
+			PageHeaderRepr,
+			PageHeader {
+				kind: PageKind::Slotted
+			}
+		)?;
+		self.set_num_slots(0)?;
+		self.set_records_start(self.0.size())?;
+		Ok(())
+	}
+
+	/// Stores a record, and returns the index of its slot. Fails with
+	/// [`DatabaseError::PageFull`] if there isn't enough free space.
+	pub fn insert(&mut self, buf: &[u8]) -> Result<usize, DatabaseError> {
+		let num_slots = self.get_num_slots()?;
+		let (index, needed) = match self.first_tombstone()? {
+			Some(index) => (index, buf.len()),
+			None => (num_slots, buf.len() + Self::SLOT_SIZE),
+		};
+		if self.free_space()? < needed {
+			return Err(DatabaseError::PageFull);
+		}
+		if self.contiguous_free_space()? < needed {
+			self.compact()?;
+		}
+		// A new slot is only added after compacting, since it may still hold the
+		// bytes of an old record.
+		if index == num_slots {
+			self.set_num_slots(num_slots + 1)?;
+		}
+		let offset = self.alloc(buf.len())?;
+		self.0.write(offset, buf)?;
+		self.set_slot(
+			index,
+			Some(Slot {
+				offset,
+				length: buf.len(),
+			}),
+		)?;
+		Ok(index)
+	}
+
+	/// Replaces the record in the given slot, in place if the new record isn't
+	/// longer. Fails with [`DatabaseError::PageFull`] if there isn't enough free
+	/// space, in which case the old record is kept.
+	pub fn update(&mut self, index: usize, buf: &[u8]) -> Result<(), DatabaseError> {
+		let Some(slot) = self.get_slot(index)? else {
+			return Err(DatabaseError::InvalidRecordIndex);
+		};
+		if buf.len() <= slot.length {
+			self.0.write(slot.offset, buf)?;
+			return self.set_slot(
+				index,
+				Some(Slot {
+					offset: slot.offset,
+					length: buf.len(),
+				}),
+			);
+		}
+
+		if self.free_space()? + slot.length < buf.len() {
+			return Err(DatabaseError::PageFull);
+		}
+		if self.contiguous_free_space()? < buf.len() {
+			// The old record is dropped, so that compacting reclaims its space.
+			self.set_slot(index, None)?;
+			self.compact()?;
+		}
+		let offset = self.alloc(buf.len())?;
+		self.0.write(offset, buf)?;
+		self.set_slot(
+			index,
+			Some(Slot {
+				offset,
+				length: buf.len(),
+			}),
+		)
+	}
+
+	/// Deletes the record in the given slot, which leaves a tombstone. Trailing
+	/// tombstones are removed.
+	pub fn delete(&mut self, index: usize) -> Result<(), DatabaseError> {
+		let Some(slot) = self.get_slot(index)? else {
+			return Err(DatabaseError::InvalidRecordIndex);
+		};
+		if slot.offset == self.get_records_start()? {
+			self.set_records_start(slot.offset + slot.length)?;
+		}
+		self.set_slot(index, None)?;
+
+		let mut num_slots = self.get_num_slots()?;
+		while num_slots > 0 && self.get_slot(num_slots - 1)?.is_none() {
+			num_slots -= 1;
+		}
+		self.set_num_slots(num_slots)
+	}
+
+	/// Moves all records to the back of the page, so that the free space lies
+	/// between the slots and the records.
+	pub fn compact(&mut self) -> Result<(), DatabaseError> {
+		let mut slots: Vec<(usize, Slot)> = Vec::new();
+		for index in 0..self.get_num_slots()? {
+			if let Some(slot) = self.get_slot(index)? {
+				slots.push((index, slot));
+			}
+		}
+		// Records that are already packed at the back keep their place.
+		slots.sort_by_key(|(_, slot)| Reverse(slot.offset));
+		let mut records: Vec<(usize, Slot, Vec<u8>)> = Vec::with_capacity(slots.len());
+		for (index, slot) in slots {
+			let mut buf = vec![0; slot.length];
+			self.0.read(slot.offset, &mut buf)?;
+			records.push((index, slot, buf));
+		}
+
+		let mut records_start = self.0.size();
+		for (index, slot, buf) in records {
+			records_start -= slot.length;
+			if slot.offset != records_start {
+				self.0.write(records_start, &buf)?;
+				self.set_slot(
+					index,
+					Some(Slot {
+						offset: records_start,
+						length: slot.length,
+					}),
+				)?;
+			}
+		}
+		self.set_records_start(records_start)
+	}
+
+	/// Reserves space for a record below the lowest one.
+	fn alloc(&mut self, length: usize) -> Result<usize, DatabaseError> {
+		let records_start = self.get_records_start()?;
+		let slots_end = Self::slots_end(self.get_num_slots()?);
+		let Some(offset) = records_start
+			.checked_sub(length)
+			.filter(|offset| *offset >= slots_end)
+		else {
+			return Err(DatabaseError::PageFull);
+		};
+		self.set_records_start(offset)?;
+		Ok(offset)
+	}
+
+	fn set_slot(&mut self, index: usize, value: Option<Slot>) -> Result<(), DatabaseError> {
+		if index >= self.get_num_slots()? {
+			return Err(DatabaseError::InvalidRecordIndex);
+		}
+		write_array_section!(self.0, SlottedPageFormat.slots, SlotRepr, index, value)
+	}
+}
+
+#[repr(C, packed)]
+struct OverflowPageFormat {
+	header: PageHeaderRepr,
+	next_page_address: PageAddressRepr,
+	/// The number of bytes of the value that are stored on this page
+	length: PageSizeRepr,
+	data: [u8; 0],
+}
+
+/// A page in a chain of pages that stores a value that doesn't fit into a
+/// single page. Every page but the last one is full.
+pub(super) struct OverflowPage<P>(P);
+
+impl<P> OverflowPage<P> {
+	/// The number of bytes of the value that fit into a page.
+	pub const fn capacity(page_size: usize) -> usize {
+		page_size - offset_of!(OverflowPageFormat, data)
+	}
+
+	pub fn new_unchecked(page: P) -> Self {
+		Self(page)
+	}
+}
+
+impl<P: ReadPage> OverflowPage<P> {
+	pub fn new(page: P) -> Result<Self, DatabaseError> {
+		let header: PageHeader = read_section!(page, OverflowPageFormat.header, PageHeaderRepr)?;
+		if header.kind != PageKind::Overflow {
+			return Err(DatabaseError::UnexpectedPageKind {
+				expected: PageKind::Overflow,
+				received: header.kind,
+			});
+		}
+		Ok(Self::new_unchecked(page))
+	}
+
+	pub fn get_next_page_address(&self) -> Result<Option<PageAddress>, DatabaseError> {
+		read_section!(
+			self.0,
+			OverflowPageFormat.next_page_address,
+			PageAddressRepr
+		)
+	}
+
+	pub fn get_length(&self) -> Result<usize, DatabaseError> {
+		let length: usize = read_section!(self.0, OverflowPageFormat.length, PageSizeRepr)?;
+		if length > Self::capacity(self.0.size()) {
+			return Err(DatabaseError::PageOutOfBounds);
+		}
+		Ok(length)
+	}
+
+	/// Reads data stored on this page, starting at `offset` within the data.
+	pub fn read_data(&self, offset: usize, buf: &mut [u8]) -> Result<(), DatabaseError> {
+		if offset + buf.len() > Self::capacity(self.0.size()) {
+			return Err(DatabaseError::PageOutOfBounds);
+		}
+		self.0
+			.read(offset_of!(OverflowPageFormat, data) + offset, buf)?;
+		Ok(())
+	}
+}
+
+impl<P: WritePage> OverflowPage<P> {
+	pub fn init(&mut self) -> Result<(), DatabaseError> {
+		write_section!(
+			self.0,
+			OverflowPageFormat.header,
+			PageHeaderRepr,
+			PageHeader {
+				kind: PageKind::Overflow
+			}
+		)?;
+		self.set_next_page_address(None)?;
+		self.set_length(0)?;
+		Ok(())
+	}
+
+	pub fn set_next_page_address(
+		&mut self,
+		value: Option<PageAddress>,
+	) -> Result<(), DatabaseError> {
+		write_section!(
+			self.0,
+			OverflowPageFormat.next_page_address,
+			PageAddressRepr,
+			value
+		)
+	}
+
+	pub fn set_length(&mut self, value: usize) -> Result<(), DatabaseError> {
+		let repr = u16::try_from(value).expect("Overflow page length must be 16-bit!");
+		write_section!(self.0, OverflowPageFormat.length, PageSizeRepr, repr)
+	}
+}
+
+impl<P: ReadPage + WritePage> OverflowPage<P> {
+	pub fn write_data(&mut self, offset: usize, buf: &[u8]) -> Result<(), DatabaseError> {
+		if offset + buf.len() > Self::capacity(self.0.size()) {
+			return Err(DatabaseError::PageOutOfBounds);
+		}
+		self.0
+			.write(offset_of!(OverflowPageFormat, data) + offset, buf)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::page_store::StorageError;
+
+	use super::*;
+
+	const PAGE_SIZE: usize = 128;
+	const FREE_SPACE: usize = PAGE_SIZE - 5;
+
+	struct BufPage(Vec<u8>);
+
+	impl ReadPage for BufPage {
+		fn size(&self) -> usize {
+			self.0.len()
+		}
+
+		fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+			buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+			Ok(())
+		}
+	}
+
+	impl WritePage for BufPage {
+		fn write(&mut self, offset: usize, buf: &[u8]) -> Result<(), StorageError> {
+			self.0[offset..offset + buf.len()].copy_from_slice(buf);
+			Ok(())
+		}
+	}
+
+	fn slotted_page() -> SlottedPage<BufPage> {
+		let mut page = SlottedPage::new_unchecked(BufPage(vec![0xff; PAGE_SIZE]));
+		page.init().unwrap();
+		page
+	}
+
+	#[test]
+	fn insert_and_get_records() {
+		// given
+		let mut page = slotted_page();
+
+		// when
+		let first = page.insert(&[1; 10]).unwrap();
+		let second = page.insert(&[2; 30]).unwrap();
+		let empty = page.insert(&[]).unwrap();
+
+		// then
+		assert_eq!((first, second, empty), (0, 1, 2));
+		assert_eq!(page.get_num_slots().unwrap(), 3);
+		assert_eq!(page.get_record(first).unwrap(), Some(vec![1; 10]));
+		assert_eq!(page.get_record(second).unwrap(), Some(vec![2; 30]));
+		assert_eq!(page.get_record(empty).unwrap(), Some(vec![]));
+		assert_eq!(page.free_space().unwrap(), FREE_SPACE - 3 * 4 - 40);
+		assert!(matches!(
+			page.get_record(3),
+			Err(DatabaseError::InvalidRecordIndex)
+		));
+		let page = SlottedPage::new(page.0).unwrap();
+		assert_eq!(page.get_record(second).unwrap(), Some(vec![2; 30]));
+	}
+
+	#[test]
+	fn reject_other_page_kinds() {
+		// given
+		let mut page = BufPage(vec![0; PAGE_SIZE]);
+		page.0[0] = PageKind::Records as u8;
+
+		// when
+		let result = SlottedPage::new(page);
+
+		// then
+		assert!(matches!(
+			result,
+			Err(DatabaseError::UnexpectedPageKind {
+				expected: PageKind::Slotted,
+				received: PageKind::Records
+			})
+		));
+	}
+
+	#[test]
+	fn delete_records_and_reuse_tombstones() {
+		// given
+		let mut page = slotted_page();
+		for i in 0..4 {
+			page.insert(&[i; 8]).unwrap();
+		}
+
+		// when
+		page.delete(1).unwrap();
+		let num_slots_after_delete = page.get_num_slots().unwrap();
+		let deleted = page.get_record(1).unwrap();
+		let reused = page.insert(&[9; 12]).unwrap();
+
+		// then
+		assert_eq!(num_slots_after_delete, 4);
+		assert_eq!(deleted, None);
+		assert_eq!(reused, 1);
+		assert_eq!(page.get_record(1).unwrap(), Some(vec![9; 12]));
+		assert_eq!(page.get_record(2).unwrap(), Some(vec![2; 8]));
+		assert!(matches!(
+			page.delete(5),
+			Err(DatabaseError::InvalidRecordIndex)
+		));
+	}
+
+	#[test]
+	fn remove_trailing_tombstones() {
+		// given
+		let mut page = slotted_page();
+		for i in 0..4 {
+			page.insert(&[i; 8]).unwrap();
+		}
+		page.delete(2).unwrap();
+
+		// when
+		page.delete(3).unwrap();
+
+		// then
+		assert_eq!(page.get_num_slots().unwrap(), 2);
+		assert_eq!(page.free_space().unwrap(), FREE_SPACE - 2 * (4 + 8));
+		assert!(matches!(
+			page.delete(1).and_then(|()| page.delete(1)),
+			Err(DatabaseError::InvalidRecordIndex)
+		));
+	}
+
+	#[test]
+	fn update_records() {
+		// given
+		let mut page = slotted_page();
+		let first = page.insert(&[1; 10]).unwrap();
+		let second = page.insert(&[2; 10]).unwrap();
+
+		// when
+		page.update(first, &[3; 4]).unwrap();
+		page.update(second, &[4; 20]).unwrap();
+
+		// then
+		assert_eq!(page.get_record(first).unwrap(), Some(vec![3; 4]));
+		assert_eq!(page.get_record(second).unwrap(), Some(vec![4; 20]));
+		assert_eq!(page.get_num_slots().unwrap(), 2);
+		assert_eq!(page.free_space().unwrap(), FREE_SPACE - 2 * 4 - 24);
+	}
+
+	#[test]
+	fn compact_fragmented_page() {
+		// given
+		let mut page = slotted_page();
+		let records: Vec<usize> = (0..5).map(|i| page.insert(&[i; 20]).unwrap()).collect();
+		page.delete(records[1]).unwrap();
+		page.delete(records[3]).unwrap();
+		page.update(records[2], &[7; 5]).unwrap();
+		let free_space = page.free_space().unwrap();
+
+		// when
+		// needs more than the contiguous free space, but less than the total
+		let inserted = page.insert(&[8; 50]).unwrap();
+
+		// then
+		assert_eq!(inserted, records[1]);
+		assert_eq!(page.free_space().unwrap(), free_space - 50);
+		assert_eq!(page.get_record(records[0]).unwrap(), Some(vec![0; 20]));
+		assert_eq!(page.get_record(records[2]).unwrap(), Some(vec![7; 5]));
+		assert_eq!(page.get_record(records[3]).unwrap(), None);
+		assert_eq!(page.get_record(records[4]).unwrap(), Some(vec![4; 20]));
+		assert_eq!(page.get_record(inserted).unwrap(), Some(vec![8; 50]));
+	}
+
+	#[test]
+	fn grow_record_by_compacting() {
+		// given
+		let mut page = slotted_page();
+		let first = page.insert(&[1; 40]).unwrap();
+		let second = page.insert(&[2; 40]).unwrap();
+		page.update(first, &[3; 10]).unwrap();
+
+		// when
+		page.update(second, &[4; 60]).unwrap();
+
+		// then
+		assert_eq!(page.get_record(first).unwrap(), Some(vec![3; 10]));
+		assert_eq!(page.get_record(second).unwrap(), Some(vec![4; 60]));
+		assert_eq!(page.free_space().unwrap(), FREE_SPACE - 2 * 4 - 70);
+	}
+
+	#[test]
+	fn try_insert_into_full_page() {
+		// given
+		let mut page = slotted_page();
+		let first = page.insert(&[1; 60]).unwrap();
+		page.insert(&[2; 40]).unwrap();
+		let free_space = page.free_space().unwrap();
+
+		// when
+		let insert_result = page.insert(&vec![3; free_space - 3]);
+		let update_result = page.update(first, &[4; 90]);
+
+		// then
+		assert!(matches!(insert_result, Err(DatabaseError::PageFull)));
+		assert!(matches!(update_result, Err(DatabaseError::PageFull)));
+		assert_eq!(page.get_record(first).unwrap(), Some(vec![1; 60]));
+		assert_eq!(page.free_space().unwrap(), free_space);
+		page.insert(&vec![3; free_space - 4]).unwrap();
+		assert_eq!(page.free_space().unwrap(), 0);
+	}
+
+	#[test]
+	fn write_overflow_page_data() {
+		// given
+		let mut page = OverflowPage::new_unchecked(BufPage(vec![0xff; PAGE_SIZE]));
+		page.init().unwrap();
+		let capacity = OverflowPage::<()>::capacity(PAGE_SIZE);
+
+		// when
+		page.write_data(capacity - 4, &[1; 4]).unwrap();
+		page.set_length(capacity).unwrap();
+		let out_of_bounds = page.write_data(capacity - 3, &[2; 4]);
+
+		// then
+		assert!(matches!(out_of_bounds, Err(DatabaseError::PageOutOfBounds)));
+		let page = OverflowPage::new(page.0).unwrap();
+		assert_eq!(page.get_length().unwrap(), capacity);
+		assert_eq!(page.get_next_page_address().unwrap(), None);
+		let mut buf = [0; 4];
+		page.read_data(capacity - 4, &mut buf).unwrap();
+		assert_eq!(buf, [1; 4]);
 	}
 }
@@ -0,0 +1,86 @@
+use super::{
+	utils::{CRC16, CRC32},
+	FileError,
+};
+
+/// The algorithm used to protect page and WAL item integrity. This is a
+/// per-database setting that is recorded in the header of every file that uses
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ChecksumAlgorithm {
+	/// CRC-16/IBM-SDLC. This is what pages used before the algorithm was
+	/// configurable, and is too weak for large pages.
+	Crc16 = 0,
+
+	/// CRC-32/ISO-HDLC. This is what WAL items used before the algorithm was
+	/// configurable.
+	Crc32 = 1,
+
+	/// CRC-32C (Castagnoli), which is hardware-accelerated on most platforms.
+	Crc32c = 2,
+
+	/// The 64-bit variant of xxHash.
+	Xxh64 = 3,
+}
+
+impl ChecksumAlgorithm {
+	pub(crate) fn checksum(self, data: &[u8]) -> u64 {
+		match self {
+			Self::Crc16 => CRC16.checksum(data).into(),
+			Self::Crc32 => CRC32.checksum(data).into(),
+			Self::Crc32c => crc32c::crc32c(data).into(),
+			Self::Xxh64 => xxhash_rust::xxh64::xxh64(data, 0),
+		}
+	}
+
+	/// Reads the algorithm from the byte it is stored as in file headers.
+	pub(crate) fn from_byte(value: u8) -> Result<Self, FileError> {
+		match value {
+			0 => Ok(Self::Crc16),
+			1 => Ok(Self::Crc32),
+			2 => Ok(Self::Crc32c),
+			3 => Ok(Self::Xxh64),
+			_ => Err(FileError::Corrupted(format!(
+				"Unknown checksum algorithm {value}"
+			))),
+		}
+	}
+
+	/// Computes a checksum that fits into 32 bits. For 64-bit algorithms, the
+	/// two halves of the checksum are folded together.
+	pub(crate) fn checksum_32(self, data: &[u8]) -> u32 {
+		let checksum = self.checksum(data);
+		u32::try_from((checksum ^ (checksum >> 32)) & 0xffff_ffff)
+			.expect("Masked checksum must fit into 32 bits!")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn checksum_check_values() {
+		// given
+		let data = b"123456789";
+
+		// then
+		assert_eq!(ChecksumAlgorithm::Crc16.checksum(data), 0x906e);
+		assert_eq!(ChecksumAlgorithm::Crc32.checksum(data), 0xcbf4_3926);
+		assert_eq!(ChecksumAlgorithm::Crc32c.checksum(data), 0xe306_9283);
+	}
+
+	#[test]
+	fn fold_64_bit_checksum() {
+		// given
+		let data = b"123456789";
+		let checksum = ChecksumAlgorithm::Xxh64.checksum(data);
+
+		// when
+		let folded = ChecksumAlgorithm::Xxh64.checksum_32(data);
+
+		// then
+		assert_eq!(folded, (checksum as u32) ^ ((checksum >> 32) as u32));
+	}
+}
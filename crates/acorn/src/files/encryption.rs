@@ -0,0 +1,477 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use chacha20poly1305::{
+	aead::{AeadInPlace, KeyInit},
+	XChaCha20Poly1305, XNonce,
+};
+use uuid::Uuid;
+
+use super::{
+	segment::{
+		SegmentFile, SegmentFileApi, SegmentOp, SegmentReadOp, SegmentStats, SegmentWriteOp,
+	},
+	FileError, PageAddress, WalIndex,
+};
+
+pub(crate) const KEY_SIZE: usize = 32;
+pub(crate) const NONCE_SIZE: usize = 24;
+pub(crate) const TAG_SIZE: usize = 16;
+
+/// A 256-bit key for XChaCha20-Poly1305.
+pub type EncryptionKey = [u8; KEY_SIZE];
+pub(crate) type Nonce = [u8; NONCE_SIZE];
+pub(crate) type Tag = [u8; TAG_SIZE];
+
+/// Supplies the key of an encrypted database. Keys are never stored in the
+/// database folder itself.
+pub trait KeyProvider {
+	/// Returns the key of the database with the given id, or `None` if it is
+	/// unknown.
+	fn key(&self, database_id: Uuid) -> Option<EncryptionKey>;
+}
+
+/// An authenticated cipher. Encryption happens in place, and the
+/// authentication tag is stored separately.
+pub(crate) trait Cipher: Send + Sync {
+	fn encrypt(&self, nonce: &Nonce, buf: &mut [u8]) -> Tag;
+
+	/// Fails with `FileError::Tampered` if the tag doesn't match.
+	fn decrypt(&self, nonce: &Nonce, buf: &mut [u8], tag: &Tag) -> Result<(), FileError>;
+}
+
+pub(crate) struct XChaCha20Poly1305Cipher(XChaCha20Poly1305);
+
+impl XChaCha20Poly1305Cipher {
+	pub fn new(key: &EncryptionKey) -> Self {
+		Self(XChaCha20Poly1305::new(key.into()))
+	}
+}
+
+impl Cipher for XChaCha20Poly1305Cipher {
+	fn encrypt(&self, nonce: &Nonce, buf: &mut [u8]) -> Tag {
+		self.0
+			.encrypt_in_place_detached(XNonce::from_slice(nonce), &[], buf)
+			.expect("Encrypted buffer exceeded the maximum message size")
+			.into()
+	}
+
+	fn decrypt(&self, nonce: &Nonce, buf: &mut [u8], tag: &Tag) -> Result<(), FileError> {
+		self.0
+			.decrypt_in_place_detached(XNonce::from_slice(nonce), &[], buf, tag.into())
+			.map_err(|_| FileError::Tampered)
+	}
+}
+
+#[repr(u8)]
+enum NonceDomain {
+	KeyCheck = 0,
+	Page = 1,
+	WalItem = 2,
+}
+
+fn nonce(domain: NonceDomain, page_address: Option<PageAddress>, wal_index: WalIndex) -> Nonce {
+	let mut nonce = [0; NONCE_SIZE];
+	nonce[0] = domain as u8;
+	if let Some(page_address) = page_address {
+		nonce[2..4].copy_from_slice(&page_address.page_num.get().to_le_bytes());
+		nonce[4..8].copy_from_slice(&page_address.segment_num.to_le_bytes());
+	}
+	nonce[8..16].copy_from_slice(&wal_index.generation.to_le_bytes());
+	nonce[16..24].copy_from_slice(&wal_index.offset.get().to_le_bytes());
+	nonce
+}
+
+/// The nonce for a page body. The WAL index of the last change to the page
+/// determines the page's contents, so the same nonce is never used for
+/// different data.
+fn page_nonce(page_address: PageAddress, wal_index: WalIndex) -> Nonce {
+	nonce(NonceDomain::Page, Some(page_address), wal_index)
+}
+
+fn wal_item_nonce(wal_index: WalIndex) -> Nonce {
+	nonce(NonceDomain::WalItem, None, wal_index)
+}
+
+/// Computes a value from which it can be verified that a key is the one the
+/// database was created with, without revealing anything about the key.
+pub(crate) fn key_check(cipher: &dyn Cipher) -> Tag {
+	let nonce = nonce(
+		NonceDomain::KeyCheck,
+		None,
+		WalIndex::new(0, NonZeroU64::MIN),
+	);
+	cipher.encrypt(&nonce, &mut [])
+}
+
+/// Encrypts the bodies of pages before they are written to a segment file, and
+/// decrypts them after they are read. The authentication tag is stored at the
+/// end of the page body, so the usable body is `TAG_SIZE` bytes smaller than
+/// that of the underlying segment file.
+///
+/// If no cipher is set, pages are passed through unchanged.
+pub(crate) struct EncryptedSegmentFile<S: SegmentFileApi = SegmentFile> {
+	inner: S,
+	segment_num: u32,
+	cipher: Option<Arc<dyn Cipher>>,
+}
+
+impl<S: SegmentFileApi> EncryptedSegmentFile<S> {
+	pub fn new(inner: S, segment_num: u32, cipher: Option<Arc<dyn Cipher>>) -> Self {
+		Self {
+			inner,
+			segment_num,
+			cipher,
+		}
+	}
+
+	fn encrypt_page(cipher: &dyn Cipher, nonce: &Nonce, plaintext: &[u8]) -> Vec<u8> {
+		let mut buf = Vec::with_capacity(plaintext.len() + TAG_SIZE);
+		buf.extend_from_slice(plaintext);
+		let tag = cipher.encrypt(nonce, &mut buf);
+		buf.extend_from_slice(&tag);
+		buf
+	}
+
+	fn decrypt_page(
+		&self,
+		cipher: &dyn Cipher,
+		op: &mut SegmentReadOp,
+		buf: &mut [u8],
+	) -> Result<(), FileError> {
+		let Some(wal_index) = *op.wal_index else {
+			op.buf.fill(0);
+			return Ok(());
+		};
+		let page_address = PageAddress::new(self.segment_num, op.page_num);
+		let (body, tag) = buf.split_at_mut(op.buf.len());
+		let tag: &Tag = (&*tag).try_into().unwrap();
+		cipher.decrypt(&page_nonce(page_address, wal_index), body, tag)?;
+		op.buf.copy_from_slice(body);
+		Ok(())
+	}
+}
+
+impl<S: SegmentFileApi> SegmentFileApi for EncryptedSegmentFile<S> {
+	fn read(&self, mut op: SegmentReadOp) -> Result<(), FileError> {
+		let Some(cipher) = &self.cipher else {
+			return self.inner.read(op);
+		};
+
+		let mut buf = vec![0; op.buf.len() + TAG_SIZE];
+		self.inner.read(SegmentReadOp {
+			page_num: op.page_num,
+			wal_index: &mut *op.wal_index,
+			buf: &mut buf,
+		})?;
+		self.decrypt_page(cipher.as_ref(), &mut op, &mut buf)
+	}
+
+	fn write(&self, op: SegmentWriteOp) -> Result<(), FileError> {
+		let Some(cipher) = &self.cipher else {
+			return self.inner.write(op);
+		};
+
+		let page_address = PageAddress::new(self.segment_num, op.page_num);
+		let nonce = page_nonce(page_address, op.wal_index);
+		let buf = Self::encrypt_page(cipher.as_ref(), &nonce, op.buf);
+		self.inner.write(SegmentWriteOp {
+			page_num: op.page_num,
+			wal_index: op.wal_index,
+			buf: &buf,
+		})
+	}
+
+	fn batch(&self, ops: &mut [SegmentOp]) -> Result<(), FileError> {
+		let Some(cipher) = &self.cipher else {
+			return self.inner.batch(ops);
+		};
+
+		let mut buffers: Vec<Vec<u8>> = Vec::with_capacity(ops.len());
+		for op in ops.iter() {
+			match op {
+				SegmentOp::Read(read_op) => buffers.push(vec![0; read_op.buf.len() + TAG_SIZE]),
+				SegmentOp::Write(write_op) => {
+					let page_address = PageAddress::new(self.segment_num, write_op.page_num);
+					let nonce = page_nonce(page_address, write_op.wal_index);
+					buffers.push(Self::encrypt_page(cipher.as_ref(), &nonce, write_op.buf));
+				}
+			}
+		}
+
+		let mut wal_indices: Vec<Option<WalIndex>> = vec![None; ops.len()];
+		let mut inner_ops: Vec<SegmentOp> = ops
+			.iter()
+			.zip(buffers.iter_mut())
+			.zip(wal_indices.iter_mut())
+			.map(|((op, buf), wal_index)| match op {
+				SegmentOp::Read(read_op) => SegmentOp::Read(SegmentReadOp {
+					page_num: read_op.page_num,
+					wal_index,
+					buf,
+				}),
+				SegmentOp::Write(write_op) => SegmentOp::Write(SegmentWriteOp {
+					page_num: write_op.page_num,
+					wal_index: write_op.wal_index,
+					buf,
+				}),
+			})
+			.collect();
+		self.inner.batch(&mut inner_ops)?;
+		drop(inner_ops);
+
+		for ((op, buf), wal_index) in ops.iter_mut().zip(buffers.iter_mut()).zip(wal_indices) {
+			if let SegmentOp::Read(read_op) = op {
+				*read_op.wal_index = wal_index;
+				self.decrypt_page(cipher.as_ref(), read_op, buf)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn sync(&self) -> Result<(), FileError> {
+		self.inner.sync()
+	}
+
+	fn stats(&self) -> SegmentStats {
+		self.inner.stats()
+	}
+}
+
+/// Encrypts the bodies of the items of a single WAL file. The authentication
+/// tag is appended to the item body.
+#[derive(Clone)]
+pub(crate) struct WalCipher {
+	cipher: Arc<dyn Cipher>,
+	generation: u64,
+}
+
+impl WalCipher {
+	pub fn new(cipher: Arc<dyn Cipher>, generation: u64) -> Self {
+		Self { cipher, generation }
+	}
+
+	pub fn encrypt_item(&self, offset: NonZeroU64, body: &mut Vec<u8>) {
+		let nonce = wal_item_nonce(WalIndex::new(self.generation, offset));
+		let tag = self.cipher.encrypt(&nonce, body);
+		body.extend_from_slice(&tag);
+	}
+
+	/// Decrypts the item body in place, and returns the length of the
+	/// plaintext.
+	pub fn decrypt_item(&self, offset: NonZeroU64, body: &mut [u8]) -> Result<usize, FileError> {
+		let Some(len) = body.len().checked_sub(TAG_SIZE) else {
+			return Err(FileError::Corrupted(
+				"Encrypted WAL item is too short".to_string(),
+			));
+		};
+		let nonce = wal_item_nonce(WalIndex::new(self.generation, offset));
+		let (data, tag) = body.split_at_mut(len);
+		self.cipher
+			.decrypt(&nonce, data, (&*tag).try_into().unwrap())?;
+		Ok(len)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		files::{
+			segment::{MockSegmentFileApi, DEFAULT_PAGE_BODY_SIZE},
+			test_helpers::{wal_index, TestCipher},
+		},
+		utils::test_helpers::non_zero,
+	};
+
+	use super::*;
+
+	const BODY_SIZE: usize = DEFAULT_PAGE_BODY_SIZE - TAG_SIZE;
+
+	#[test]
+	fn encrypt_and_decrypt_with_xchacha20poly1305() {
+		// given
+		let cipher = XChaCha20Poly1305Cipher::new(&[25; KEY_SIZE]);
+		let nonce = [69; NONCE_SIZE];
+		let mut buf = *b"Hello, world!";
+
+		// when
+		let tag = cipher.encrypt(&nonce, &mut buf);
+		let ciphertext = buf;
+		cipher.decrypt(&nonce, &mut buf, &tag).unwrap();
+
+		// then
+		assert_ne!(&ciphertext, b"Hello, world!");
+		assert_eq!(&buf, b"Hello, world!");
+	}
+
+	#[test]
+	fn try_decrypt_tampered_data_with_xchacha20poly1305() {
+		// given
+		let cipher = XChaCha20Poly1305Cipher::new(&[25; KEY_SIZE]);
+		let nonce = [69; NONCE_SIZE];
+		let mut buf = *b"Hello, world!";
+		let tag = cipher.encrypt(&nonce, &mut buf);
+		buf[3] ^= 1;
+
+		// when
+		let result = cipher.decrypt(&nonce, &mut buf, &tag);
+
+		// then
+		assert!(matches!(result, Err(FileError::Tampered)));
+	}
+
+	#[test]
+	fn write_encrypted_page() {
+		// given
+		let cipher = Arc::new(TestCipher);
+		let expected = EncryptedSegmentFile::<MockSegmentFileApi>::encrypt_page(
+			cipher.as_ref(),
+			&page_nonce(PageAddress::new(420, non_zero!(69)), wal_index!(25, 24)),
+			&[1; BODY_SIZE],
+		);
+		let mut inner = MockSegmentFileApi::new();
+		inner
+			.expect_write()
+			.withf(move |op| {
+				op.page_num == non_zero!(69)
+					&& op.wal_index == wal_index!(25, 24)
+					&& op.buf == expected
+			})
+			.once()
+			.returning(|_| Ok(()));
+		let segment = EncryptedSegmentFile::new(inner, 420, Some(cipher));
+
+		// then
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(69),
+				wal_index: wal_index!(25, 24),
+				buf: &[1; BODY_SIZE],
+			})
+			.unwrap();
+	}
+
+	#[test]
+	fn read_encrypted_page() {
+		// given
+		let cipher = Arc::new(TestCipher);
+		let page = EncryptedSegmentFile::<MockSegmentFileApi>::encrypt_page(
+			cipher.as_ref(),
+			&page_nonce(PageAddress::new(420, non_zero!(69)), wal_index!(25, 24)),
+			&[1; BODY_SIZE],
+		);
+		let mut inner = MockSegmentFileApi::new();
+		inner
+			.expect_read()
+			.once()
+			.returning(move |op: SegmentReadOp| {
+				*op.wal_index = Some(wal_index!(25, 24));
+				op.buf.copy_from_slice(&page);
+				Ok(())
+			});
+		let segment = EncryptedSegmentFile::new(inner, 420, Some(cipher));
+
+		// when
+		let mut buf = [0; BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(69),
+				wal_index: &mut wal_index,
+				buf: &mut buf,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(25, 24)));
+		assert_eq!(buf, [1; BODY_SIZE]);
+	}
+
+	#[test]
+	fn try_read_page_moved_to_different_address() {
+		// given
+		let cipher = Arc::new(TestCipher);
+		let page = EncryptedSegmentFile::<MockSegmentFileApi>::encrypt_page(
+			cipher.as_ref(),
+			&page_nonce(PageAddress::new(420, non_zero!(68)), wal_index!(25, 24)),
+			&[1; BODY_SIZE],
+		);
+		let mut inner = MockSegmentFileApi::new();
+		inner
+			.expect_read()
+			.once()
+			.returning(move |op: SegmentReadOp| {
+				*op.wal_index = Some(wal_index!(25, 24));
+				op.buf.copy_from_slice(&page);
+				Ok(())
+			});
+		let segment = EncryptedSegmentFile::new(inner, 420, Some(cipher));
+
+		// when
+		let mut buf = [0; BODY_SIZE];
+		let mut wal_index = None;
+		let result = segment.read(SegmentReadOp {
+			page_num: non_zero!(69),
+			wal_index: &mut wal_index,
+			buf: &mut buf,
+		});
+
+		// then
+		assert!(matches!(result, Err(FileError::Tampered)));
+	}
+
+	#[test]
+	fn read_uninitialized_encrypted_page() {
+		// given
+		let mut inner = MockSegmentFileApi::new();
+		inner.expect_read().once().returning(|op: SegmentReadOp| {
+			*op.wal_index = None;
+			op.buf.fill(0);
+			Ok(())
+		});
+		let segment = EncryptedSegmentFile::new(inner, 420, Some(Arc::new(TestCipher)));
+
+		// when
+		let mut buf = [25; BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(69),
+				wal_index: &mut wal_index,
+				buf: &mut buf,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, None);
+		assert_eq!(buf, [0; BODY_SIZE]);
+	}
+
+	#[test]
+	fn encrypt_and_decrypt_wal_item() {
+		// given
+		let cipher = WalCipher::new(Arc::new(TestCipher), 25);
+		let mut body = vec![1, 2, 3, 4];
+
+		// when
+		cipher.encrypt_item(non_zero!(24), &mut body);
+		let len = cipher.decrypt_item(non_zero!(24), &mut body).unwrap();
+
+		// then
+		assert_eq!(body.len(), 4 + TAG_SIZE);
+		assert_eq!(&body[..len], &[1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn try_decrypt_wal_item_at_wrong_offset() {
+		// given
+		let cipher = WalCipher::new(Arc::new(TestCipher), 25);
+		let mut body = vec![1, 2, 3, 4];
+		cipher.encrypt_item(non_zero!(24), &mut body);
+
+		// when
+		let result = cipher.decrypt_item(non_zero!(25), &mut body);
+
+		// then
+		assert!(matches!(result, Err(FileError::Tampered)));
+	}
+}
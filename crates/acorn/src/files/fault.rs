@@ -0,0 +1,652 @@
+//! Wrappers around the files of a database folder that inject IO errors, short
+//! and torn writes, and power losses, for testing that the database recovers
+//! from them.
+//!
+//! On a power loss, whatever was synced is kept, and every sector written since
+//! then may or may not have reached the disk. Creating a file is treated as
+//! durable, as if the file and its directory had been synced.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	fs::{self, File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	mem,
+	num::NonZeroU16,
+	ops::Range,
+	os::unix::fs::FileExt,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use super::{
+	encryption::EncryptedSegmentFile,
+	segment::{SegmentFileApi, SegmentOp, SegmentReadOp, SegmentStats, SegmentWriteOp},
+	wal::{WalFile, WalStorage},
+	DatabaseFolder, DatabaseFolderApi, FileError,
+};
+
+/// The unit in which writes reach the disk
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+	/// Nothing is written, and the operation fails with `EIO`.
+	Eio,
+	/// Only the first sectors are written before the operation fails with
+	/// `EIO`.
+	ShortWrite,
+	/// A random subset of the sectors is written before the operation fails
+	/// with `EIO`.
+	TornWrite,
+}
+
+const FAULTS: [Fault; 3] = [Fault::Eio, Fault::ShortWrite, Fault::TornWrite];
+
+fn eio() -> io::Error {
+	io::Error::from_raw_os_error(libc::EIO)
+}
+
+/// Splits the range of a write at `offset` into the parts that fall into
+/// different sectors. The parts are relative to `offset`.
+fn sectors(offset: u64, len: usize) -> Vec<Range<usize>> {
+	let mut parts = Vec::new();
+	let mut start: usize = 0;
+	while start < len {
+		let sector_end = (offset + start as u64) / SECTOR_SIZE * SECTOR_SIZE + SECTOR_SIZE;
+		let end = usize::min(len, (sector_end - offset) as usize);
+		parts.push(start..end);
+		start = end;
+	}
+	parts
+}
+
+fn read_range(file: &File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+	// Anything past the end of the file reads as zeros.
+	let mut buf = vec![0; len];
+	let mut pos: usize = 0;
+	while pos < len {
+		match file.read_at(&mut buf[pos..], offset + pos as u64)? {
+			0 => break,
+			num_read => pos += num_read,
+		}
+	}
+	Ok(buf)
+}
+
+struct Disk {
+	rng: fastrand::Rng,
+	/// The contents of every file opened since the power was turned on, as of
+	/// when it was last synced
+	durable: HashMap<PathBuf, Vec<u8>>,
+	/// The faults to inject, by the number of the operation they affect
+	faults: BTreeMap<u64, Fault>,
+	/// The number of operations so far that modified or synced a file
+	num_ops: u64,
+	powered_off: bool,
+}
+
+impl Disk {
+	fn check_power(&self) -> io::Result<()> {
+		if self.powered_off {
+			return Err(eio());
+		}
+		Ok(())
+	}
+
+	/// Counts an operation that modifies or syncs a file, and returns the fault
+	/// to inject into it, if any.
+	fn next_op(&mut self) -> io::Result<Option<Fault>> {
+		self.check_power()?;
+		let fault = self.faults.remove(&self.num_ops);
+		self.num_ops += 1;
+		Ok(fault)
+	}
+
+	/// Records the contents of a file that are on disk when it is first opened.
+	fn track(&mut self, path: &Path) -> io::Result<()> {
+		if !self.durable.contains_key(path) {
+			self.sync(path)?;
+		}
+		Ok(())
+	}
+
+	fn sync(&mut self, path: &Path) -> io::Result<()> {
+		self.durable.insert(path.to_path_buf(), fs::read(path)?);
+		Ok(())
+	}
+
+	/// Decides which of the sectors of a write that is interrupted by `fault`
+	/// reach the file.
+	fn written_sectors(&mut self, fault: Fault, num_sectors: usize) -> Vec<bool> {
+		match fault {
+			Fault::Eio => vec![false; num_sectors],
+			Fault::ShortWrite => {
+				let num_written = self.rng.usize(0..num_sectors.max(1));
+				(0..num_sectors).map(|i| i < num_written).collect()
+			}
+			Fault::TornWrite => (0..num_sectors).map(|_| self.rng.bool()).collect(),
+		}
+	}
+
+	/// Decides what is left of each file after a power loss.
+	fn lose_power(&mut self) -> io::Result<()> {
+		self.powered_off = true;
+		for (path, synced) in mem::take(&mut self.durable) {
+			let current = match fs::read(&path) {
+				Ok(current) => current,
+				Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+				Err(err) => return Err(err),
+			};
+			let len = if self.rng.bool() {
+				synced.len()
+			} else {
+				current.len()
+			};
+			let mut contents = vec![0; len];
+			for (i, sector) in contents.chunks_mut(SECTOR_SIZE as usize).enumerate() {
+				let source = if self.rng.bool() { &synced } else { &current };
+				let source = source.get(i * SECTOR_SIZE as usize..).unwrap_or_default();
+				let num_bytes = usize::min(sector.len(), source.len());
+				sector[..num_bytes].copy_from_slice(&source[..num_bytes]);
+			}
+			fs::write(&path, contents)?;
+		}
+		Ok(())
+	}
+}
+
+/// A database folder whose files can fail and lose power. The wrapped folder
+/// must be writable.
+pub(crate) struct FaultyFolder {
+	folder: Arc<DatabaseFolder>,
+	disk: Arc<Mutex<Disk>>,
+}
+
+impl FaultyFolder {
+	/// Wraps `folder`, whose files are considered durable as they are. `seed`
+	/// determines all random decisions.
+	pub fn new(folder: Arc<DatabaseFolder>, seed: u64) -> Self {
+		Self {
+			folder,
+			disk: Arc::new(Mutex::new(Disk {
+				rng: fastrand::Rng::with_seed(seed),
+				durable: HashMap::new(),
+				faults: BTreeMap::new(),
+				num_ops: 0,
+				powered_off: false,
+			})),
+		}
+	}
+
+	/// Makes the operation that modifies or syncs a file fail with `fault`
+	/// after `num_ops` other such operations.
+	pub fn inject(&self, num_ops: u64, fault: Fault) {
+		let mut disk = self.disk.lock();
+		let op = disk.num_ops + num_ops;
+		disk.faults.insert(op, fault);
+	}
+
+	/// Injects a random fault into one of the next `num_ops` operations that
+	/// modify or sync a file.
+	pub fn inject_random(&self, num_ops: u64) {
+		let mut disk = self.disk.lock();
+		let op = disk.num_ops + disk.rng.u64(0..num_ops);
+		let fault = FAULTS[disk.rng.usize(0..FAULTS.len())];
+		disk.faults.insert(op, fault);
+	}
+
+	/// Removes all faults that haven't been injected yet.
+	pub fn clear_faults(&self) {
+		self.disk.lock().faults.clear();
+	}
+
+	/// Cuts the power. Every file keeps what was synced, and a random selection
+	/// of the sectors written since. Afterwards, all operations on the files
+	/// fail; the folder has to be wrapped again to simulate a restart.
+	pub fn power_loss(&self) -> io::Result<()> {
+		self.disk.lock().lose_power()
+	}
+
+	fn open_file(&self, path: &Path) -> io::Result<FaultyFile> {
+		self.disk.lock().track(path)?;
+		Ok(FaultyFile {
+			file: OpenOptions::new().read(true).write(true).open(path)?,
+			path: path.to_path_buf(),
+			disk: Arc::clone(&self.disk),
+		})
+	}
+
+	fn create_file(&self, path: &Path) -> io::Result<FaultyFile> {
+		Ok(FaultyFile {
+			file: OpenOptions::new()
+				.create(true)
+				.truncate(true)
+				.read(true)
+				.write(true)
+				.open(path)?,
+			path: path.to_path_buf(),
+			disk: Arc::clone(&self.disk),
+		})
+	}
+}
+
+impl DatabaseFolderApi for FaultyFolder {
+	type SegmentFile = FaultySegmentFile;
+	type WalFile = WalFile<FaultyFile>;
+	type IterWalFiles = std::vec::IntoIter<Result<(u64, Self::WalFile), FileError>>;
+
+	fn open_segment_file(&self, segment_num: u32) -> Result<Self::SegmentFile, FileError> {
+		self.disk.lock().check_power()?;
+		let path = self.folder.segment_file_path(segment_num)?;
+		let inner = self.folder.open_segment_file(segment_num)?;
+		self.disk.lock().track(&path)?;
+		Ok(FaultySegmentFile {
+			inner,
+			file: OpenOptions::new().read(true).write(true).open(&path)?,
+			path,
+			page_size: self.folder.segment_config.page_size,
+			disk: Arc::clone(&self.disk),
+		})
+	}
+
+	fn open_wal_file(&self, generation: u64) -> Result<Self::WalFile, FileError> {
+		self.disk.lock().check_power()?;
+		let path = self.folder.wal_file_path(generation)?;
+		let cipher = self.folder.wal_cipher(generation);
+		if !path.exists() {
+			// Like `WalFile::create_file`, the header is only moved into place
+			// once it is durable
+			let tmp_path = path.with_extension(WalFile::TMP_EXTENSION);
+			WalFile::create(
+				self.create_file(&tmp_path)?,
+				self.folder.segment_config.checksum,
+				self.folder.wal_cipher(generation),
+			)?;
+			let mut disk = self.disk.lock();
+			if disk.next_op()?.is_some() {
+				// The failed rename leaves nothing behind that later opens of the
+				// folder could see
+				disk.durable.remove(&tmp_path);
+				fs::remove_file(&tmp_path)?;
+				return Err(eio().into());
+			}
+			mem::drop(disk);
+			fs::rename(&tmp_path, &path)?;
+		}
+		WalFile::open(self.open_file(&path)?, cipher)
+	}
+
+	fn delete_wal_file(&self, generation: u64) -> Result<(), FileError> {
+		self.disk.lock().next_op()?;
+		self.folder.delete_wal_file(generation)?;
+		let path = self.folder.wal_file_path(generation)?;
+		self.disk.lock().durable.remove(&path);
+		Ok(())
+	}
+
+	fn iter_wal_files(&self) -> Result<Self::IterWalFiles, FileError> {
+		self.disk.lock().check_power()?;
+		let mut wal_files = Vec::new();
+		for entry in fs::read_dir(self.folder.wal_dir()?)? {
+			let entry = entry?;
+			let path = entry.path();
+			if !path.is_file() || path.extension() == Some(WalFile::TMP_EXTENSION.as_ref()) {
+				continue;
+			}
+			let Ok(generation) = entry.file_name().to_string_lossy().parse::<u64>() else {
+				return Err(FileError::UnexpectedFile(entry.file_name()));
+			};
+			wal_files.push(
+				self.open_wal_file(generation)
+					.map(|file| (generation, file)),
+			);
+		}
+		Ok(wal_files.into_iter())
+	}
+
+	fn clear_wal_files(&self) -> Result<(), FileError> {
+		self.disk.lock().next_op()?;
+		self.folder.clear_wal_files()?;
+		let wal_dir = self.folder.wal_dir()?;
+		self.disk
+			.lock()
+			.durable
+			.retain(|path, _| !path.starts_with(&wal_dir));
+		Ok(())
+	}
+
+	fn page_body_size(&self) -> usize {
+		self.folder.page_body_size()
+	}
+
+	fn is_read_only(&self) -> bool {
+		self.folder.is_read_only()
+	}
+
+	fn was_clean_shutdown(&self) -> bool {
+		// Every reboot follows a power loss.
+		false
+	}
+
+	fn mark_clean_shutdown(&self) -> Result<(), FileError> {
+		self.disk.lock().next_op()?;
+		self.folder.mark_clean_shutdown()
+	}
+}
+
+/// A segment file that can fail and lose power. Faults are injected by writing
+/// the pages, and then restoring the sectors that shouldn't have been written.
+pub(crate) struct FaultySegmentFile<S: SegmentFileApi = EncryptedSegmentFile> {
+	inner: S,
+	/// The same file, for reading and restoring the raw pages
+	file: File,
+	path: PathBuf,
+	page_size: usize,
+	disk: Arc<Mutex<Disk>>,
+}
+
+impl<S: SegmentFileApi> FaultySegmentFile<S> {
+	fn write_pages(
+		&self,
+		page_nums: &[NonZeroU16],
+		write: impl FnOnce() -> Result<(), FileError>,
+	) -> Result<(), FileError> {
+		let mut disk = self.disk.lock();
+		let Some(fault) = disk.next_op()? else {
+			return write();
+		};
+		if fault == Fault::Eio {
+			return Err(eio().into());
+		}
+
+		let mut pages: Vec<(u64, Vec<u8>)> = Vec::with_capacity(page_nums.len());
+		for page_num in page_nums {
+			let offset = page_num.get() as u64 * self.page_size as u64;
+			pages.push((offset, read_range(&self.file, offset, self.page_size)?));
+		}
+		write()?;
+
+		let sectors_per_page = self.page_size / SECTOR_SIZE as usize;
+		let written = disk.written_sectors(fault, pages.len() * sectors_per_page);
+		for (i, written) in written.into_iter().enumerate() {
+			if written {
+				continue;
+			}
+			let (offset, old_page) = &pages[i / sectors_per_page];
+			let start = (i % sectors_per_page) * SECTOR_SIZE as usize;
+			let old_sector = &old_page[start..start + SECTOR_SIZE as usize];
+			self.file.write_all_at(old_sector, offset + start as u64)?;
+		}
+		Err(eio().into())
+	}
+}
+
+impl<S: SegmentFileApi> SegmentFileApi for FaultySegmentFile<S> {
+	fn read(&self, op: SegmentReadOp) -> Result<(), FileError> {
+		let disk = self.disk.lock();
+		disk.check_power()?;
+		self.inner.read(op)
+	}
+
+	fn write(&self, op: SegmentWriteOp) -> Result<(), FileError> {
+		self.write_pages(&[op.page_num], || self.inner.write(op))
+	}
+
+	fn batch(&self, ops: &mut [SegmentOp]) -> Result<(), FileError> {
+		let page_nums: Vec<NonZeroU16> = ops
+			.iter()
+			.filter_map(|op| match op {
+				SegmentOp::Write(write_op) => Some(write_op.page_num),
+				SegmentOp::Read(..) => None,
+			})
+			.collect();
+		if page_nums.is_empty() {
+			let disk = self.disk.lock();
+			disk.check_power()?;
+			return self.inner.batch(ops);
+		}
+		self.write_pages(&page_nums, || self.inner.batch(ops))
+	}
+
+	fn sync(&self) -> Result<(), FileError> {
+		let mut disk = self.disk.lock();
+		if disk.next_op()?.is_some() {
+			return Err(eio().into());
+		}
+		self.inner.sync()?;
+		disk.sync(&self.path)?;
+		Ok(())
+	}
+
+	fn stats(&self) -> SegmentStats {
+		self.inner.stats()
+	}
+}
+
+/// A WAL file that can fail and lose power.
+pub(crate) struct FaultyFile {
+	file: File,
+	path: PathBuf,
+	disk: Arc<Mutex<Disk>>,
+}
+
+impl Read for FaultyFile {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.disk.lock().check_power()?;
+		self.file.read(buf)
+	}
+}
+
+impl Seek for FaultyFile {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.file.seek(pos)
+	}
+}
+
+impl Write for FaultyFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut disk = self.disk.lock();
+		let Some(fault) = disk.next_op()? else {
+			return self.file.write(buf);
+		};
+		let offset = self.file.stream_position()?;
+		let sectors = sectors(offset, buf.len());
+		let written = disk.written_sectors(fault, sectors.len());
+		for (range, written) in sectors.into_iter().zip(written) {
+			if written {
+				self.file
+					.write_all_at(&buf[range.clone()], offset + range.start as u64)?;
+			}
+		}
+		Err(eio())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl WalStorage for FaultyFile {
+	fn set_len(&mut self, len: u64) -> io::Result<()> {
+		if self.disk.lock().next_op()?.is_some() {
+			return Err(eio());
+		}
+		self.file.set_len(len)
+	}
+
+	fn sync_data(&mut self) -> io::Result<()> {
+		let mut disk = self.disk.lock();
+		if disk.next_op()?.is_some() {
+			return Err(eio());
+		}
+		self.file.sync_data()?;
+		disk.sync(&self.path)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tempfile::tempdir;
+
+	use crate::files::{
+		segment::SegmentConfig,
+		test_helpers::wal_index,
+		wal::{Item, TransactionData, WalFileApi},
+	};
+
+	use super::*;
+
+	fn commit_item(transaction_id: u64) -> Item<'static> {
+		Item::Commit(TransactionData {
+			transaction_id,
+			prev_transaction_item: None,
+		})
+	}
+
+	fn transaction_ids(file: &mut WalFile<FaultyFile>) -> Vec<u64> {
+		file.iter_items()
+			.unwrap()
+			.map(|result| match result.unwrap().1 {
+				Item::Commit(data) => data.transaction_id,
+				item => panic!("Unexpected item {item:?}"),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn keep_synced_items_after_power_loss() {
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), SegmentConfig::default()).unwrap(),
+		);
+
+		for seed in 0..16 {
+			// given
+			let faulty = FaultyFolder::new(Arc::clone(&folder), seed);
+			faulty.clear_wal_files().unwrap();
+			let mut file = faulty.open_wal_file(0).unwrap();
+			file.push_item(commit_item(1)).unwrap();
+			file.push_item(commit_item(2)).unwrap();
+			file.sync().unwrap();
+			file.push_item(commit_item(3)).unwrap();
+			file.push_item(commit_item(4)).unwrap();
+
+			// when
+			faulty.power_loss().unwrap();
+			let faulty = FaultyFolder::new(Arc::clone(&folder), seed);
+			let mut file = faulty.open_wal_file(0).unwrap();
+
+			// then
+			let ids = transaction_ids(&mut file);
+			assert!([&[1, 2][..], &[1, 2, 3], &[1, 2, 3, 4]].contains(&&ids[..]));
+		}
+	}
+
+	#[test]
+	fn remove_tmp_file_after_failed_rename() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), SegmentConfig::default()).unwrap(),
+		);
+		let faulty = FaultyFolder::new(Arc::clone(&folder), 0);
+		let ops_before = faulty.disk.lock().num_ops;
+		faulty.open_wal_file(0).unwrap();
+		let num_ops = faulty.disk.lock().num_ops - ops_before;
+		// the rename is the last operation of creating a WAL file
+		faulty.inject(num_ops - 1, Fault::Eio);
+
+		// when
+		let result = faulty.open_wal_file(1);
+
+		// then
+		assert!(matches!(result, Err(FileError::Io(err)) if err.raw_os_error() == Some(libc::EIO)));
+		let tmp_path = folder
+			.wal_file_path(1)
+			.unwrap()
+			.with_extension(WalFile::TMP_EXTENSION);
+		assert!(!tmp_path.exists());
+		assert!(!faulty.disk.lock().durable.contains_key(&tmp_path));
+		assert!(faulty.open_wal_file(1).is_ok());
+	}
+
+	#[test]
+	fn inject_scripted_faults() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), SegmentConfig::default()).unwrap(),
+		);
+		let faulty = FaultyFolder::new(folder, 0);
+		let mut file = faulty.open_wal_file(0).unwrap();
+		faulty.inject(1, Fault::ShortWrite);
+
+		// when
+		let first = file.push_item(commit_item(1));
+		let second = file.push_item(commit_item(2));
+		let retry = file.sync();
+
+		// then
+		assert!(first.is_ok());
+		assert!(matches!(second, Err(FileError::Io(err)) if err.raw_os_error() == Some(libc::EIO)));
+		assert!(retry.is_ok());
+		assert_eq!(transaction_ids(&mut file), vec![1, 2]);
+	}
+
+	#[test]
+	fn tear_page_writes() {
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), SegmentConfig::default()).unwrap(),
+		);
+		let body_size = folder.page_body_size();
+
+		let mut num_torn = 0;
+		for seed in 0..16 {
+			// given
+			let faulty = FaultyFolder::new(Arc::clone(&folder), seed);
+			let segment = faulty.open_segment_file(seed as u32).unwrap();
+			let page_num = NonZeroU16::new(1).unwrap();
+			segment
+				.write(SegmentWriteOp {
+					page_num,
+					wal_index: wal_index!(0, 1),
+					buf: &vec![1; body_size],
+				})
+				.unwrap();
+			faulty.inject(0, Fault::TornWrite);
+
+			// when
+			let result = segment.write(SegmentWriteOp {
+				page_num,
+				wal_index: wal_index!(0, 2),
+				buf: &vec![2; body_size],
+			});
+			let mut buf = vec![0; body_size];
+			let read_result = segment.read(SegmentReadOp {
+				page_num,
+				wal_index: &mut None,
+				buf: &mut buf,
+			});
+
+			// then
+			assert!(result.is_err());
+			match read_result {
+				Ok(()) => assert!(buf == vec![1; body_size] || buf == vec![2; body_size]),
+				Err(FileError::ChecksumMismatch) => num_torn += 1,
+				Err(err) => panic!("Unexpected error {err}"),
+			}
+		}
+		assert!(num_torn > 0);
+	}
+
+	#[test]
+	fn split_writes_into_sectors() {
+		assert_eq!(sectors(0, 0), vec![]);
+		assert_eq!(sectors(500, 20), vec![0..12, 12..20]);
+		assert_eq!(sectors(512, 1024), vec![0..512, 512..1024]);
+		assert_eq!(sectors(1000, 600), vec![0..24, 24..536, 536..600]);
+	}
+}
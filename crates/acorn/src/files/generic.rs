@@ -10,7 +10,7 @@ pub(super) struct GenericHeaderRepr {
 	magic: [u8; 4],
 	byte_order: u8,
 	file_type: u8,
-	content_offset: u16,
+	content_offset: [u8; 2],
 	version: u8,
 }
 
@@ -19,6 +19,7 @@ pub(super) struct GenericHeaderRepr {
 pub(crate) enum FileType {
 	Wal = 0,
 	Segment = 1,
+	Manifest = 2,
 }
 
 impl TryFrom<u8> for FileType {
@@ -28,21 +29,69 @@ impl TryFrom<u8> for FileType {
 		match value {
 			0 => Ok(Self::Wal),
 			1 => Ok(Self::Segment),
+			2 => Ok(Self::Manifest),
 			_ => Err(FileError::Corrupted(format!("Unknown file type {value}"))),
 		}
 	}
 }
 
-#[cfg(target_endian = "big")]
-const NATIVE_BYTE_ORDER: u8 = 0;
+/// The byte order of all integers in a file, including the content offset of
+/// the generic header itself.
+///
+/// New files are always little-endian, so that they can be moved between
+/// platforms. Older versions used the native byte order of the platform, so
+/// big-endian files may still be encountered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum ByteOrder {
+	Big = 0,
+	Little = 1,
+}
+
+impl ByteOrder {
+	/// The byte order used for newly created files
+	pub const PORTABLE: Self = Self::Little;
+}
 
-#[cfg(target_endian = "little")]
-const NATIVE_BYTE_ORDER: u8 = 1;
+impl TryFrom<u8> for ByteOrder {
+	type Error = FileError;
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(Self::Big),
+			1 => Ok(Self::Little),
+			_ => Err(FileError::Corrupted(format!("Unknown byte order {value}"))),
+		}
+	}
+}
+
+/// Evaluates `$body` with `$O` defined as the zerocopy byte order type that
+/// corresponds to `$byte_order`, so that reprs generic over their byte order
+/// can be used with a byte order only known at runtime.
+macro_rules! with_byte_order {
+	($byte_order:expr, $O:ident => $body:expr) => {
+		match $byte_order {
+			$crate::files::generic::ByteOrder::Little => {
+				#[allow(clippy::upper_case_acronyms)]
+				type $O = ::zerocopy::LittleEndian;
+				$body
+			}
+			$crate::files::generic::ByteOrder::Big => {
+				#[allow(clippy::upper_case_acronyms)]
+				type $O = ::zerocopy::BigEndian;
+				$body
+			}
+		}
+	};
+}
+
+pub(crate) use with_byte_order;
 
 const MAGIC: [u8; 4] = *b"ACRN";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(super) struct GenericHeader {
+	pub byte_order: ByteOrder,
 	pub file_type: FileType,
 	pub content_offset: u16,
 	pub version: u8,
@@ -50,11 +99,15 @@ pub(super) struct GenericHeader {
 
 impl From<GenericHeader> for GenericHeaderRepr {
 	fn from(value: GenericHeader) -> Self {
+		let content_offset = match value.byte_order {
+			ByteOrder::Big => value.content_offset.to_be_bytes(),
+			ByteOrder::Little => value.content_offset.to_le_bytes(),
+		};
 		Self {
 			magic: MAGIC,
-			byte_order: NATIVE_BYTE_ORDER,
+			byte_order: value.byte_order as u8,
 			file_type: value.file_type as u8,
-			content_offset: value.content_offset,
+			content_offset,
 			version: value.version,
 		}
 	}
@@ -67,12 +120,15 @@ impl TryFrom<GenericHeaderRepr> for GenericHeader {
 		if value.magic != MAGIC {
 			return Err(FileError::MissingMagic);
 		}
-		if value.byte_order != NATIVE_BYTE_ORDER {
-			return Err(FileError::ByteOrderMismatch);
-		}
+		let byte_order = ByteOrder::try_from(value.byte_order)?;
+		let content_offset = match byte_order {
+			ByteOrder::Big => u16::from_be_bytes(value.content_offset),
+			ByteOrder::Little => u16::from_le_bytes(value.content_offset),
+		};
 		Ok(Self {
+			byte_order,
 			file_type: value.file_type.try_into()?,
-			content_offset: value.content_offset,
+			content_offset,
 			version: value.version,
 		})
 	}
@@ -90,14 +146,35 @@ mod tests {
 	fn verify_header() {
 		let header_repr = GenericHeaderRepr {
 			magic: *b"ACRN",
-			byte_order: NATIVE_BYTE_ORDER,
+			byte_order: 1,
+			file_type: FileType::Wal as u8,
+			content_offset: [69, 0],
+			version: 1,
+		};
+		assert_eq!(
+			GenericHeader::try_from(header_repr).unwrap(),
+			GenericHeader {
+				byte_order: ByteOrder::Little,
+				file_type: FileType::Wal,
+				content_offset: 69,
+				version: 1
+			}
+		);
+	}
+
+	#[test]
+	fn verify_big_endian_header() {
+		let header_repr = GenericHeaderRepr {
+			magic: *b"ACRN",
+			byte_order: 0,
 			file_type: FileType::Wal as u8,
-			content_offset: 69,
+			content_offset: [0, 69],
 			version: 1,
 		};
 		assert_eq!(
 			GenericHeader::try_from(header_repr).unwrap(),
 			GenericHeader {
+				byte_order: ByteOrder::Big,
 				file_type: FileType::Wal,
 				content_offset: 69,
 				version: 1
@@ -109,9 +186,9 @@ mod tests {
 	fn try_verify_header_with_missing_magic() {
 		let header_repr = GenericHeaderRepr {
 			magic: *b"KEKW",
-			byte_order: NATIVE_BYTE_ORDER,
+			byte_order: 1,
 			file_type: FileType::Wal as u8,
-			content_offset: 69,
+			content_offset: [69, 0],
 			version: 1,
 		};
 		let err = GenericHeader::try_from(header_repr).unwrap_err();
@@ -119,18 +196,18 @@ mod tests {
 	}
 
 	#[test]
-	fn try_verify_header_with_byte_order_mismatch() {
+	fn try_verify_header_with_unknown_byte_order() {
 		let header_repr = GenericHeaderRepr {
 			magic: *b"ACRN",
-			byte_order: !NATIVE_BYTE_ORDER,
+			byte_order: 2,
 			file_type: FileType::Wal as u8,
-			content_offset: 69,
+			content_offset: [69, 0],
 			version: 1,
 		};
 		let err = GenericHeader::try_from(header_repr).unwrap_err();
 		assert_eq!(
 			err.to_string(),
-			"The file was created on a platform with a different byte order and cannot be opened"
+			"The file is corrupted: Unknown byte order 2"
 		);
 	}
 }
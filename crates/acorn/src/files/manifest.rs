@@ -0,0 +1,331 @@
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{Seek, SeekFrom},
+	path::Path,
+};
+
+use uuid::Uuid;
+use zerocopy::{
+	byteorder::{self, LittleEndian, U16, U32, U64},
+	FromBytes, Immutable, IntoBytes,
+};
+
+use super::{
+	checksum::ChecksumAlgorithm,
+	encryption::{Tag, TAG_SIZE},
+	generic::{with_byte_order, ByteOrder, FileType, GenericHeader, GenericHeaderRepr},
+	segment::{validate_page_size, validate_segment_sizes, SegmentConfig},
+	utils::CRC32,
+	FileError,
+};
+use crate::repr::{IoRepr, Repr};
+
+pub(super) const FORMAT_VERSION: u8 = 1;
+
+/// The on-disk format version of the database as a whole, as opposed to the
+/// version of the manifest file itself.
+pub(crate) const DATABASE_FORMAT_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Manifest {
+	pub id: Uuid,
+	pub format_version: u16,
+	pub segment_config: SegmentConfig,
+	pub clean_shutdown: bool,
+
+	/// For encrypted databases, a value that allows verifying the key. See
+	/// `encryption::key_check`.
+	pub key_check: Option<Tag>,
+}
+
+impl Manifest {
+	/// Creates the manifest of a new database. The maximum segment size is
+	/// resolved here, so that it is recorded explicitly.
+	pub fn new(segment_config: SegmentConfig) -> Self {
+		Self {
+			id: Uuid::new_v4(),
+			format_version: DATABASE_FORMAT_VERSION,
+			segment_config: SegmentConfig {
+				max_segment_size: Some(segment_config.max_segment_size()),
+				..segment_config
+			},
+			clean_shutdown: false,
+			key_check: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
+#[repr(C, packed)]
+struct ManifestRepr<O: byteorder::ByteOrder = LittleEndian> {
+	id: [u8; 16],
+	format_version: U16<O>,
+	page_size: U32<O>,
+	max_segment_size: U64<O>,
+	extent_size: U64<O>,
+	checksum: u8,
+	clean_shutdown: u8,
+	encrypted: u8,
+	key_check: [u8; TAG_SIZE],
+	compress_pages: u8,
+	crc: U32<O>,
+}
+
+impl<O: byteorder::ByteOrder> ManifestRepr<O> {
+	fn checksum(&self) -> u32 {
+		CRC32.checksum(&self.as_bytes()[..Self::SIZE - size_of::<u32>()])
+	}
+}
+
+impl<O: byteorder::ByteOrder> From<Manifest> for ManifestRepr<O> {
+	fn from(value: Manifest) -> Self {
+		let mut repr = Self {
+			id: value.id.into_bytes(),
+			format_version: value.format_version.into(),
+			page_size: u32::try_from(value.segment_config.page_size)
+				.expect("Page size must be 32-bit!")
+				.into(),
+			max_segment_size: (value.segment_config.max_segment_size() as u64).into(),
+			extent_size: (value.segment_config.extent_size as u64).into(),
+			checksum: value.segment_config.checksum as u8,
+			clean_shutdown: value.clean_shutdown as u8,
+			encrypted: value.key_check.is_some() as u8,
+			key_check: value.key_check.unwrap_or_default(),
+			compress_pages: value.segment_config.compress_pages as u8,
+			crc: 0.into(),
+		};
+		repr.crc = repr.checksum().into();
+		repr
+	}
+}
+
+impl<O: byteorder::ByteOrder> TryFrom<ManifestRepr<O>> for Manifest {
+	type Error = FileError;
+
+	fn try_from(value: ManifestRepr<O>) -> Result<Self, Self::Error> {
+		if value.checksum() != value.crc.get() {
+			return Err(FileError::ChecksumMismatch);
+		}
+		let page_size = size_from_disk("page size", value.page_size.get().into())?;
+		validate_page_size(page_size)?;
+		let segment_config = SegmentConfig {
+			page_size,
+			max_segment_size: Some(size_from_disk(
+				"maximum segment size",
+				value.max_segment_size.get(),
+			)?),
+			extent_size: size_from_disk("extent size", value.extent_size.get())?,
+			checksum: ChecksumAlgorithm::from_byte(value.checksum)?,
+			compress_pages: value.compress_pages != 0,
+		};
+		validate_segment_sizes(&segment_config)
+			.map_err(|err| FileError::Corrupted(err.to_string()))?;
+		Ok(Self {
+			id: Uuid::from_bytes(value.id),
+			format_version: value.format_version.get(),
+			segment_config,
+			clean_shutdown: value.clean_shutdown != 0,
+			key_check: (value.encrypted != 0).then_some(value.key_check),
+		})
+	}
+}
+
+impl<O: byteorder::ByteOrder> Repr<Manifest> for ManifestRepr<O> {
+	type Error = FileError;
+}
+
+fn size_from_disk(name: &str, value: u64) -> Result<usize, FileError> {
+	usize::try_from(value)
+		.map_err(|_| FileError::Corrupted(format!("The {name} {value} exceeds the address space")))
+}
+
+pub(crate) fn read_manifest(path: impl AsRef<Path>) -> Result<Manifest, FileError> {
+	let mut file = File::open(path)?;
+
+	let header = GenericHeaderRepr::deserialize(&mut file)?;
+	if header.file_type != FileType::Manifest {
+		return Err(FileError::WrongFileType(header.file_type));
+	}
+
+	file.seek(SeekFrom::Start(header.content_offset.into()))?;
+	with_byte_order!(header.byte_order, O => match header.version {
+		FORMAT_VERSION => ManifestRepr::<O>::deserialize(&mut file),
+		version => Err(FileError::IncompatibleVersion(header.file_type, version)),
+	})
+}
+
+/// Writes the manifest to a temporary file first, and then moves it into
+/// place, so that a crash can never leave behind a partially written manifest.
+pub(crate) fn write_manifest(path: impl AsRef<Path>, manifest: Manifest) -> Result<(), FileError> {
+	let path = path.as_ref();
+	let tmp_path = path.with_extension("tmp");
+
+	let mut file = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.open(&tmp_path)?;
+	GenericHeaderRepr::serialize(
+		GenericHeader {
+			byte_order: ByteOrder::PORTABLE,
+			file_type: FileType::Manifest,
+			content_offset: u16::try_from(GenericHeaderRepr::SIZE).unwrap(),
+			version: FORMAT_VERSION,
+		},
+		&mut file,
+	)?;
+	<ManifestRepr>::serialize(manifest, &mut file)?;
+	file.sync_all()?;
+
+	fs::rename(&tmp_path, path)?;
+	if let Some(parent) = path.parent() {
+		File::open(parent)?.sync_all()?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Write;
+
+	use zerocopy::BigEndian;
+
+	use crate::utils::units::{GIB, KIB};
+
+	use super::*;
+
+	#[test]
+	fn write_and_read_manifest() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let manifest = Manifest {
+			id: Uuid::from_u128(0x6942_0000_0000_0000_0000_0000_0000_0025),
+			format_version: DATABASE_FORMAT_VERSION,
+			segment_config: SegmentConfig {
+				page_size: 8 * KIB,
+				max_segment_size: Some((8 * KIB) << 16),
+				..Default::default()
+			},
+			clean_shutdown: true,
+			key_check: None,
+		};
+
+		// when
+		write_manifest(tempdir.path().join("MANIFEST"), manifest.clone()).unwrap();
+		let received = read_manifest(tempdir.path().join("MANIFEST")).unwrap();
+
+		// then
+		assert_eq!(received, manifest);
+		assert!(!tempdir.path().join("MANIFEST.tmp").exists());
+	}
+
+	#[test]
+	fn write_and_read_encrypted_manifest() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let manifest = Manifest {
+			key_check: Some([25; TAG_SIZE]),
+			..Manifest::new(SegmentConfig::default())
+		};
+
+		// when
+		write_manifest(tempdir.path().join("MANIFEST"), manifest.clone()).unwrap();
+		let received = read_manifest(tempdir.path().join("MANIFEST")).unwrap();
+
+		// then
+		assert_eq!(received, manifest);
+	}
+
+	#[test]
+	fn write_and_read_compressed_manifest() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let manifest = Manifest::new(SegmentConfig {
+			compress_pages: true,
+			..Default::default()
+		});
+
+		// when
+		write_manifest(tempdir.path().join("MANIFEST"), manifest.clone()).unwrap();
+		let received = read_manifest(tempdir.path().join("MANIFEST")).unwrap();
+
+		// then
+		assert_eq!(received, manifest);
+	}
+
+	#[test]
+	fn derive_max_segment_size_from_page_size() {
+		// when
+		let manifest = Manifest::new(SegmentConfig {
+			page_size: 64 * KIB,
+			..Default::default()
+		});
+
+		// then
+		assert_eq!(manifest.segment_config.max_segment_size, Some(4 * GIB));
+	}
+
+	#[test]
+	fn read_big_endian_manifest() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let manifest = Manifest::new(SegmentConfig::default());
+		let mut file = File::create(tempdir.path().join("MANIFEST")).unwrap();
+		GenericHeaderRepr::serialize(
+			GenericHeader {
+				byte_order: ByteOrder::Big,
+				file_type: FileType::Manifest,
+				content_offset: u16::try_from(GenericHeaderRepr::SIZE).unwrap(),
+				version: FORMAT_VERSION,
+			},
+			&mut file,
+		)
+		.unwrap();
+		ManifestRepr::<BigEndian>::serialize(manifest.clone(), &mut file).unwrap();
+
+		// when
+		let received = read_manifest(tempdir.path().join("MANIFEST")).unwrap();
+
+		// then
+		assert_eq!(received, manifest);
+	}
+
+	#[test]
+	fn try_read_manifest_with_invalid_segment_size() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let mut manifest = Manifest::new(SegmentConfig::default());
+		manifest.segment_config.max_segment_size = Some(manifest.segment_config.page_size * 3 + 1);
+		write_manifest(tempdir.path().join("MANIFEST"), manifest).unwrap();
+
+		// when
+		let result = read_manifest(tempdir.path().join("MANIFEST"));
+
+		// then
+		assert!(matches!(result, Err(FileError::Corrupted(..))));
+	}
+
+	#[test]
+	fn try_read_corrupted_manifest() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		write_manifest(
+			tempdir.path().join("MANIFEST"),
+			Manifest::new(SegmentConfig::default()),
+		)
+		.unwrap();
+		let mut file = OpenOptions::new()
+			.write(true)
+			.open(tempdir.path().join("MANIFEST"))
+			.unwrap();
+		file.seek(SeekFrom::Start(GenericHeaderRepr::SIZE as u64 + 4))
+			.unwrap();
+		file.write_all(&[0xff; 4]).unwrap();
+
+		// when
+		let result = read_manifest(tempdir.path().join("MANIFEST"));
+
+		// then
+		assert!(matches!(result, Err(FileError::ChecksumMismatch)));
+	}
+}
@@ -0,0 +1,441 @@
+//! A database folder that keeps all of its files in memory, for tests and for
+//! databases that don't have to outlive the process.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	io::{self, Read, Seek, SeekFrom, Write},
+	num::NonZeroU16,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::{
+	segment::{
+		SegmentConfig, SegmentFileApi, SegmentOp, SegmentReadOp, SegmentStats, SegmentWriteOp,
+	},
+	wal::{WalFile, WalStorage},
+	DatabaseFolderApi, FileError, WalIndex,
+};
+
+/// A database folder whose segments and WAL generations only exist in memory.
+/// Files stay around until the folder is dropped, so they can be opened again
+/// like the files of a [`super::DatabaseFolder`].
+///
+/// The pages of in-memory segments are stored as they are, so the checksum
+/// algorithm of the segment config only determines the size of page bodies,
+/// and pages are never compressed.
+pub(crate) struct MemoryFolder {
+	segment_config: SegmentConfig,
+	segments: Mutex<HashMap<u32, Arc<RwLock<SegmentPages>>>>,
+	wal_files: Mutex<BTreeMap<u64, MemoryFile>>,
+	clean_shutdown: AtomicBool,
+}
+
+impl MemoryFolder {
+	pub fn new(segment_config: SegmentConfig) -> Self {
+		Self {
+			segment_config,
+			segments: Mutex::new(HashMap::new()),
+			wal_files: Mutex::new(BTreeMap::new()),
+			clean_shutdown: AtomicBool::new(false),
+		}
+	}
+
+	/// Whether the database was marked as cleanly shut down.
+	pub fn is_clean_shutdown(&self) -> bool {
+		self.clean_shutdown.load(Ordering::Acquire)
+	}
+}
+
+impl DatabaseFolderApi for MemoryFolder {
+	type SegmentFile = MemorySegmentFile;
+	type WalFile = WalFile<MemoryFile>;
+	type IterWalFiles = std::vec::IntoIter<Result<(u64, Self::WalFile), FileError>>;
+
+	fn open_segment_file(&self, segment_num: u32) -> Result<Self::SegmentFile, FileError> {
+		let pages = Arc::clone(self.segments.lock().entry(segment_num).or_default());
+		Ok(MemorySegmentFile {
+			pages,
+			page_size: self.segment_config.page_size,
+			max_size: self.segment_config.max_segment_size(),
+			page_body_size: self.page_body_size(),
+			stats: Mutex::new(SegmentStats::default()),
+		})
+	}
+
+	fn open_wal_file(&self, generation: u64) -> Result<Self::WalFile, FileError> {
+		let mut wal_files = self.wal_files.lock();
+		if let Some(file) = wal_files.get(&generation) {
+			return WalFile::open(file.reopen(), None);
+		}
+		let file = MemoryFile::default();
+		wal_files.insert(generation, file.reopen());
+		WalFile::create(file, self.segment_config.checksum, None)
+	}
+
+	fn delete_wal_file(&self, generation: u64) -> Result<(), FileError> {
+		if self.wal_files.lock().remove(&generation).is_none() {
+			return Err(io::Error::from(io::ErrorKind::NotFound).into());
+		}
+		Ok(())
+	}
+
+	fn iter_wal_files(&self) -> Result<Self::IterWalFiles, FileError> {
+		let wal_files = self.wal_files.lock();
+		let files: Vec<Result<(u64, Self::WalFile), FileError>> = wal_files
+			.iter()
+			.map(|(generation, file)| {
+				WalFile::open(file.reopen(), None).map(|file| (*generation, file))
+			})
+			.collect();
+		Ok(files.into_iter())
+	}
+
+	fn clear_wal_files(&self) -> Result<(), FileError> {
+		self.wal_files.lock().clear();
+		Ok(())
+	}
+
+	fn page_body_size(&self) -> usize {
+		self.segment_config.page_body_size()
+	}
+
+	fn is_read_only(&self) -> bool {
+		false
+	}
+
+	fn was_clean_shutdown(&self) -> bool {
+		// The folder is shared by every storage opened on it, so there is no open
+		// that could reset the flag. Recovering is always safe.
+		false
+	}
+
+	fn mark_clean_shutdown(&self) -> Result<(), FileError> {
+		self.clean_shutdown.store(true, Ordering::Release);
+		Ok(())
+	}
+}
+
+/// A page that was written to an in-memory segment
+struct StoredPage {
+	wal_index: WalIndex,
+	body: Box<[u8]>,
+}
+
+/// The pages of an in-memory segment, indexed by page number. Pages that were
+/// never written are `None`.
+#[derive(Default)]
+struct SegmentPages(Vec<Option<StoredPage>>);
+
+impl SegmentPages {
+	fn read(&self, op: &mut SegmentReadOp) {
+		match self.0.get(usize::from(op.page_num.get())) {
+			Some(Some(page)) => {
+				op.buf.copy_from_slice(&page.body);
+				*op.wal_index = Some(page.wal_index);
+			}
+			_ => {
+				op.buf.fill(0);
+				*op.wal_index = None;
+			}
+		}
+	}
+
+	fn write(&mut self, op: &SegmentWriteOp) {
+		let index = usize::from(op.page_num.get());
+		if self.0.len() <= index {
+			self.0.resize_with(index + 1, || None);
+		}
+		self.0[index] = Some(StoredPage {
+			wal_index: op.wal_index,
+			body: op.buf.into(),
+		});
+	}
+}
+
+pub(crate) struct MemorySegmentFile {
+	pages: Arc<RwLock<SegmentPages>>,
+	page_size: usize,
+	max_size: usize,
+	page_body_size: usize,
+	stats: Mutex<SegmentStats>,
+}
+
+impl MemorySegmentFile {
+	fn check_bounds(&self, page_num: NonZeroU16) -> Result<(), FileError> {
+		if (usize::from(page_num.get()) + 1) * self.page_size > self.max_size {
+			return Err(FileError::PageOutOfRange(page_num.get()));
+		}
+		Ok(())
+	}
+
+	fn record_writes(&self, num_pages: usize) {
+		let num_bytes = (num_pages * self.page_body_size) as u64;
+		let mut stats = self.stats.lock();
+		stats.pages_written += num_pages as u64;
+		stats.body_bytes_written += num_bytes;
+		stats.body_bytes_stored += num_bytes;
+		stats.write_calls += 1;
+	}
+}
+
+impl SegmentFileApi for MemorySegmentFile {
+	fn read(&self, mut op: SegmentReadOp) -> Result<(), FileError> {
+		debug_assert_eq!(op.buf.len(), self.page_body_size);
+		self.check_bounds(op.page_num)?;
+		self.pages.read().read(&mut op);
+		Ok(())
+	}
+
+	fn write(&self, op: SegmentWriteOp) -> Result<(), FileError> {
+		debug_assert_eq!(op.buf.len(), self.page_body_size);
+		self.check_bounds(op.page_num)?;
+		self.pages.write().write(&op);
+		self.record_writes(1);
+		Ok(())
+	}
+
+	fn batch(&self, ops: &mut [SegmentOp]) -> Result<(), FileError> {
+		for op in ops.iter() {
+			match op {
+				SegmentOp::Read(read_op) => self.check_bounds(read_op.page_num)?,
+				SegmentOp::Write(write_op) => self.check_bounds(write_op.page_num)?,
+			}
+		}
+
+		let mut pages = self.pages.write();
+		let mut num_written: usize = 0;
+		for op in ops.iter_mut() {
+			match op {
+				SegmentOp::Read(read_op) => pages.read(read_op),
+				SegmentOp::Write(write_op) => {
+					pages.write(write_op);
+					num_written += 1;
+				}
+			}
+		}
+		if num_written > 0 {
+			self.record_writes(num_written);
+		}
+		Ok(())
+	}
+
+	fn sync(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn stats(&self) -> SegmentStats {
+		*self.stats.lock()
+	}
+}
+
+/// An in-memory file. Handles to the same file share its contents, but each
+/// has its own position.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryFile {
+	data: Arc<Mutex<Vec<u8>>>,
+	pos: u64,
+}
+
+impl MemoryFile {
+	/// Returns another handle to the file, positioned at the start.
+	fn reopen(&self) -> Self {
+		Self {
+			data: Arc::clone(&self.data),
+			pos: 0,
+		}
+	}
+}
+
+impl Read for MemoryFile {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let data = self.data.lock();
+		let start = usize::try_from(self.pos)
+			.unwrap_or(usize::MAX)
+			.min(data.len());
+		let num_read = usize::min(buf.len(), data.len() - start);
+		buf[..num_read].copy_from_slice(&data[start..start + num_read]);
+		self.pos += num_read as u64;
+		Ok(num_read)
+	}
+}
+
+impl Write for MemoryFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut data = self.data.lock();
+		let Some(end) = usize::try_from(self.pos)
+			.ok()
+			.and_then(|start| start.checked_add(buf.len()))
+		else {
+			return Err(io::ErrorKind::FileTooLarge.into());
+		};
+		if data.len() < end {
+			data.resize(end, 0);
+		}
+		data[end - buf.len()..end].copy_from_slice(buf);
+		self.pos = end as u64;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Seek for MemoryFile {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		let (base, offset) = match pos {
+			SeekFrom::Start(offset) => {
+				self.pos = offset;
+				return Ok(offset);
+			}
+			SeekFrom::End(offset) => (self.data.lock().len() as u64, offset),
+			SeekFrom::Current(offset) => (self.pos, offset),
+		};
+		let Some(pos) = base.checked_add_signed(offset) else {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"invalid seek to a negative or overflowing position",
+			));
+		};
+		self.pos = pos;
+		Ok(pos)
+	}
+}
+
+impl WalStorage for MemoryFile {
+	fn set_len(&mut self, len: u64) -> io::Result<()> {
+		let len = usize::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::FileTooLarge))?;
+		self.data.lock().resize(len, 0);
+		Ok(())
+	}
+
+	fn sync_data(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::files::{
+		test_helpers::wal_index,
+		wal::{Item, TransactionData, WalFileApi},
+	};
+
+	use super::*;
+
+	#[test]
+	fn keep_pages_across_opens() {
+		// given
+		let folder = MemoryFolder::new(SegmentConfig::default());
+		let body_size = folder.page_body_size();
+		let page_num = NonZeroU16::new(2).unwrap();
+		folder
+			.open_segment_file(1)
+			.unwrap()
+			.write(SegmentWriteOp {
+				page_num,
+				wal_index: wal_index!(0, 69),
+				buf: &vec![25; body_size],
+			})
+			.unwrap();
+
+		// when
+		let segment = folder.open_segment_file(1).unwrap();
+		let mut buf = vec![0; body_size];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num,
+				wal_index: &mut wal_index,
+				buf: &mut buf,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(buf, vec![25; body_size]);
+		assert_eq!(wal_index, Some(wal_index!(0, 69)));
+	}
+
+	#[test]
+	fn read_unwritten_page() {
+		// given
+		let folder = MemoryFolder::new(SegmentConfig::default());
+		let segment = folder.open_segment_file(0).unwrap();
+		let mut buf = vec![1; folder.page_body_size()];
+		let mut wal_index = Some(wal_index!(0, 1));
+
+		// when
+		segment
+			.read(SegmentReadOp {
+				page_num: NonZeroU16::new(5).unwrap(),
+				wal_index: &mut wal_index,
+				buf: &mut buf,
+			})
+			.unwrap();
+
+		// then
+		assert!(buf.iter().all(|byte| *byte == 0));
+		assert_eq!(wal_index, None);
+	}
+
+	#[test]
+	fn reject_pages_out_of_range() {
+		// given
+		let folder = MemoryFolder::new(SegmentConfig {
+			max_segment_size: Some(8 * SegmentConfig::default().page_size),
+			..Default::default()
+		});
+		let segment = folder.open_segment_file(0).unwrap();
+
+		// when
+		let result = segment.write(SegmentWriteOp {
+			page_num: NonZeroU16::new(8).unwrap(),
+			wal_index: wal_index!(0, 1),
+			buf: &vec![0; folder.page_body_size()],
+		});
+
+		// then
+		assert!(matches!(result, Err(FileError::PageOutOfRange(8))));
+	}
+
+	#[test]
+	fn reopen_wal_generations() {
+		// given
+		let folder = MemoryFolder::new(SegmentConfig::default());
+		let mut file = folder.open_wal_file(3).unwrap();
+		file.push_item(Item::Commit(TransactionData {
+			transaction_id: 25,
+			prev_transaction_item: None,
+		}))
+		.unwrap();
+		file.flush().unwrap();
+		folder.open_wal_file(4).unwrap();
+		folder.delete_wal_file(4).unwrap();
+
+		// when
+		let mut files: Vec<(u64, WalFile<MemoryFile>)> =
+			Result::from_iter(folder.iter_wal_files().unwrap()).unwrap();
+
+		// then
+		assert_eq!(files.len(), 1);
+		let (generation, file) = &mut files[0];
+		assert_eq!(*generation, 3);
+		let items: Vec<Item> = file
+			.iter_items()
+			.unwrap()
+			.map(|result| result.unwrap().1)
+			.collect();
+		assert_eq!(
+			items,
+			vec![Item::Commit(TransactionData {
+				transaction_id: 25,
+				prev_transaction_item: None,
+			})]
+		);
+	}
+}
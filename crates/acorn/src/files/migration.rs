@@ -0,0 +1,578 @@
+use std::{
+	fs::{self, File},
+	path::{Path, PathBuf},
+};
+
+use super::{
+	generic::{ByteOrder, FileType, GenericHeaderRepr},
+	manifest::{self, read_manifest},
+	segment::{self, SegmentFile},
+	wal, DatabaseFolder, FileError, LockMode,
+};
+use crate::repr::IoRepr;
+
+/// Upgrades a whole file of the given type from one format version to another.
+/// Migrations may skip versions, as long as `to_version` is newer than
+/// `from_version`.
+#[derive(Debug)]
+pub(crate) struct FileMigration {
+	pub file_type: FileType,
+	pub from_version: u8,
+	pub to_version: u8,
+	pub migrate: fn(&Path) -> Result<(), FileError>,
+}
+
+/// Upgrades a single page from one page format version to another, in place.
+///
+/// Migrations are applied to pages as they are stored, including the page
+/// header, which has to be rewritten as well. The body of a page may still be
+/// compressed or encrypted at that point.
+#[derive(Debug)]
+pub(crate) struct PageMigration {
+	pub from_version: u8,
+	pub to_version: u8,
+	pub migrate: fn(&mut [u8], ByteOrder) -> Result<(), FileError>,
+}
+
+/// The set of migrations available to upgrade files and pages of older format
+/// versions.
+#[derive(Debug)]
+pub(crate) struct MigrationRegistry {
+	pub file_migrations: &'static [FileMigration],
+	pub page_migrations: &'static [PageMigration],
+}
+
+/// All migrations known to this version of acorn. Whenever a format version is
+/// bumped, a migration from the previous version should be registered here.
+///
+/// WAL files have no migrations, since pages refer to WAL items by their
+/// offset, which rewriting a WAL file could change. Older WAL files are instead
+/// read as they are until they are checkpointed and deleted.
+pub(crate) static MIGRATIONS: MigrationRegistry = MigrationRegistry {
+	file_migrations: &[
+		FileMigration {
+			file_type: FileType::Segment,
+			from_version: segment::LEGACY_FORMAT_VERSION,
+			to_version: segment::FORMAT_VERSION,
+			migrate: segment::upgrade_segment_header,
+		},
+		FileMigration {
+			file_type: FileType::Segment,
+			from_version: segment::CRC16_FORMAT_VERSION,
+			to_version: segment::FORMAT_VERSION,
+			migrate: segment::upgrade_segment_header,
+		},
+	],
+	page_migrations: &[],
+};
+
+impl MigrationRegistry {
+	fn file_migration_path(
+		&self,
+		file_type: FileType,
+		from_version: u8,
+		to_version: u8,
+	) -> Result<Vec<&FileMigration>, FileError> {
+		let migrations = self
+			.file_migrations
+			.iter()
+			.filter(|migration| migration.file_type == file_type);
+		find_path(
+			migrations,
+			|migration| (migration.from_version, migration.to_version),
+			from_version,
+			to_version,
+		)
+		.ok_or(FileError::IncompatibleVersion(file_type, from_version))
+	}
+
+	fn page_migration_path(
+		&self,
+		from_version: u8,
+		to_version: u8,
+	) -> Result<Vec<&PageMigration>, FileError> {
+		find_path(
+			self.page_migrations.iter(),
+			|migration| (migration.from_version, migration.to_version),
+			from_version,
+			to_version,
+		)
+		.ok_or(FileError::IncompatiblePageVersion(from_version))
+	}
+
+	/// Makes sure that pages of `from_version` can be upgraded to `to_version`.
+	pub fn check_page_migration(&self, from_version: u8, to_version: u8) -> Result<(), FileError> {
+		self.page_migration_path(from_version, to_version)?;
+		Ok(())
+	}
+
+	/// Upgrades a raw page from `from_version` to `to_version`. Fails with
+	/// `FileError::IncompatiblePageVersion` if no migrations lead there.
+	pub fn migrate_page(
+		&self,
+		page: &mut [u8],
+		from_version: u8,
+		to_version: u8,
+		byte_order: ByteOrder,
+	) -> Result<(), FileError> {
+		for migration in self.page_migration_path(from_version, to_version)? {
+			(migration.migrate)(page, byte_order)?;
+		}
+		Ok(())
+	}
+}
+
+/// Finds a chain of migrations that leads from `from_version` to `to_version`,
+/// always taking the first registered migration for each version.
+fn find_path<'a, M>(
+	migrations: impl Iterator<Item = &'a M> + Clone,
+	versions: impl Fn(&M) -> (u8, u8),
+	from_version: u8,
+	to_version: u8,
+) -> Option<Vec<&'a M>> {
+	let mut path = Vec::new();
+	let mut version = from_version;
+	while version != to_version {
+		let migration = migrations.clone().find(|migration| {
+			let (from, to) = versions(migration);
+			from == version && from < to && to <= to_version
+		})?;
+		version = versions(migration).1;
+		path.push(migration);
+	}
+	Some(path)
+}
+
+/// What a migration did, or would do in a dry run, to a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileMigrationReport {
+	pub path: PathBuf,
+	pub file_type: FileType,
+	pub from_version: u8,
+	pub to_version: u8,
+	/// The number of pages upgraded to the current page format
+	pub num_pages: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MigrationReport {
+	pub dry_run: bool,
+	/// Every file that needed to be migrated. Files that already were in the
+	/// current format are not listed.
+	pub files: Vec<FileMigrationReport>,
+}
+
+fn current_version(file_type: FileType) -> u8 {
+	match file_type {
+		FileType::Wal => wal::FORMAT_VERSION,
+		FileType::Segment => segment::FORMAT_VERSION,
+		FileType::Manifest => manifest::FORMAT_VERSION,
+	}
+}
+
+/// Upgrades a single file to the current format version of its file type. If
+/// `dry_run` is set, the file is only checked.
+///
+/// Returns `None` if the file already was in the current format.
+pub(super) fn migrate_file(
+	path: &Path,
+	migrations: &MigrationRegistry,
+	dry_run: bool,
+) -> Result<Option<FileMigrationReport>, FileError> {
+	let header = GenericHeaderRepr::deserialize(File::open(path)?)?;
+	let to_version = current_version(header.file_type);
+	if header.version == to_version {
+		return Ok(None);
+	}
+
+	let path_migrations =
+		migrations.file_migration_path(header.file_type, header.version, to_version)?;
+	if !dry_run {
+		for migration in path_migrations {
+			(migration.migrate)(path)?;
+		}
+	}
+	Ok(Some(FileMigrationReport {
+		path: path.to_path_buf(),
+		file_type: header.file_type,
+		from_version: header.version,
+		to_version,
+		num_pages: 0,
+	}))
+}
+
+/// Upgrades all files of the database at `path` to the current format, using
+/// the given migrations. This must be done while the database is closed.
+///
+/// If `dry_run` is set, nothing is changed; the report then lists what would
+/// have been migrated.
+pub(super) fn migrate(
+	path: &Path,
+	migrations: &MigrationRegistry,
+	dry_run: bool,
+) -> Result<MigrationReport, FileError> {
+	if !path.is_dir() {
+		return Err(FileError::MissingManifest(path.to_path_buf()));
+	}
+	let lock_mode = if dry_run {
+		LockMode::Shared
+	} else {
+		LockMode::Exclusive
+	};
+	let _lock = DatabaseFolder::lock(path, lock_mode)?;
+
+	let manifest_path = path.join(DatabaseFolder::MANIFEST_FILE_NAME);
+	if !manifest_path.exists() {
+		return Err(FileError::MissingManifest(path.to_path_buf()));
+	}
+	let mut files: Vec<FileMigrationReport> = Vec::new();
+	files.extend(migrate_file(&manifest_path, migrations, dry_run)?);
+
+	let manifest = read_manifest(&manifest_path)?;
+	if manifest.format_version != manifest::DATABASE_FORMAT_VERSION {
+		return Err(FileError::IncompatibleDatabaseVersion(
+			manifest.format_version,
+		));
+	}
+
+	let segments_dir = path.join(DatabaseFolder::SEGMENTS_DIR_NAME);
+	if segments_dir.exists() {
+		let mut segment_paths: Vec<(u32, PathBuf)> = Vec::new();
+		for entry in fs::read_dir(segments_dir)? {
+			let entry = entry?;
+			let Ok(segment_num) = entry.file_name().to_string_lossy().parse() else {
+				return Err(FileError::UnexpectedFile(entry.file_name()));
+			};
+			segment_paths.push((segment_num, entry.path()));
+		}
+		segment_paths.sort();
+
+		for (_, segment_path) in segment_paths {
+			let report = migrate_file(&segment_path, migrations, dry_run)?;
+			let segment = if dry_run {
+				SegmentFile::open_file_read_only(&segment_path, &manifest.segment_config)?
+			} else {
+				SegmentFile::open_file(&segment_path, &manifest.segment_config)?
+			};
+			let num_pages = segment.upgrade_pages(migrations, dry_run)?;
+			match report {
+				Some(report) => files.push(FileMigrationReport {
+					num_pages,
+					..report
+				}),
+				None if num_pages > 0 => files.push(FileMigrationReport {
+					path: segment_path,
+					file_type: FileType::Segment,
+					from_version: segment::FORMAT_VERSION,
+					to_version: segment::FORMAT_VERSION,
+					num_pages,
+				}),
+				None => (),
+			}
+		}
+	}
+
+	Ok(MigrationReport { dry_run, files })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{borrow::Cow, num::NonZeroU16};
+
+	use pretty_assertions::assert_buf_eq;
+
+	use crate::{
+		files::{
+			checksum::ChecksumAlgorithm,
+			segment::{SegmentConfig, SegmentFileApi, SegmentReadOp},
+			test_helpers::{page_address, wal_index},
+			wal::{Item, TransactionData, WalFileApi, WriteData},
+			DatabaseFolderApi,
+		},
+		utils::{test_helpers::non_zero, units::KIB},
+	};
+
+	use super::*;
+
+	// The fixtures were written by earlier versions of acorn:
+	// - `segment_v1` is a segment that contains one page, which refers to WAL
+	//   generation 0.
+	// - `segment_v2` is a segment with 16 KiB pages that contains two pages,
+	//   which refer to WAL generation 1.
+	// - `wal_v1` is a WAL that contains a write to page 1 of segment 0, and the
+	//   commit of its transaction.
+	const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+	fn fixture_config() -> SegmentConfig {
+		SegmentConfig {
+			page_size: 32 * KIB,
+			checksum: ChecksumAlgorithm::Crc16,
+			..Default::default()
+		}
+	}
+
+	fn fixture_page_body(page_body_size: usize) -> Vec<u8> {
+		(0..page_body_size).map(|i| (i % 251) as u8).collect()
+	}
+
+	/// Creates a database at `path` whose only segment is the fixture segment.
+	fn create_fixture_database(path: &Path) {
+		DatabaseFolder::create(path.to_path_buf(), fixture_config()).unwrap();
+		fs::create_dir_all(path.join("segments")).unwrap();
+		fs::copy(
+			Path::new(FIXTURES_DIR).join("segment_v1"),
+			path.join("segments/0"),
+		)
+		.unwrap();
+	}
+
+	fn assert_fixture_segment(segment: &impl SegmentFileApi, config: &SegmentConfig) {
+		let mut wal_index = None;
+		let mut buf = vec![0; config.page_body_size()];
+		segment
+			.read(SegmentReadOp {
+				page_num: NonZeroU16::new(1).unwrap(),
+				wal_index: &mut wal_index,
+				buf: &mut buf,
+			})
+			.unwrap();
+		assert_eq!(wal_index, Some(wal_index!(0, 25)));
+		assert_buf_eq!(buf, fixture_page_body(config.page_body_size()));
+	}
+
+	fn v2_fixture_config() -> SegmentConfig {
+		SegmentConfig {
+			page_size: 16 * KIB,
+			checksum: ChecksumAlgorithm::Crc16,
+			..Default::default()
+		}
+	}
+
+	fn assert_v2_fixture_segment(segment: &impl SegmentFileApi, config: &SegmentConfig) {
+		for (page_num, wal_offset) in [(1, 25), (2, 100)] {
+			let mut wal_index = None;
+			let mut buf = vec![0; config.page_body_size()];
+			segment
+				.read(SegmentReadOp {
+					page_num: NonZeroU16::new(page_num).unwrap(),
+					wal_index: &mut wal_index,
+					buf: &mut buf,
+				})
+				.unwrap();
+			assert_eq!(wal_index, Some(wal_index!(1, wal_offset)));
+			let expected_body: Vec<u8> = (0..config.page_body_size())
+				.map(|i| ((i + usize::from(page_num)) % 251) as u8)
+				.collect();
+			assert_buf_eq!(buf, expected_body);
+		}
+	}
+
+	fn file_version(path: impl AsRef<Path>) -> u8 {
+		GenericHeaderRepr::deserialize(File::open(path).unwrap())
+			.unwrap()
+			.version
+	}
+
+	#[test]
+	fn dry_run_migration() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_fixture_database(tempdir.path());
+		let segment_before = fs::read(tempdir.path().join("segments/0")).unwrap();
+
+		// when
+		let report = migrate(tempdir.path(), &MIGRATIONS, true).unwrap();
+
+		// then
+		assert_eq!(
+			report,
+			MigrationReport {
+				dry_run: true,
+				files: vec![FileMigrationReport {
+					path: tempdir.path().join("segments/0"),
+					file_type: FileType::Segment,
+					from_version: 1,
+					to_version: segment::FORMAT_VERSION,
+					num_pages: 0,
+				}]
+			}
+		);
+		assert_buf_eq!(
+			fs::read(tempdir.path().join("segments/0")).unwrap(),
+			segment_before
+		);
+	}
+
+	#[test]
+	fn migrate_database() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_fixture_database(tempdir.path());
+
+		// when
+		let report = migrate(tempdir.path(), &MIGRATIONS, false).unwrap();
+
+		// then
+		assert!(!report.dry_run);
+		assert_eq!(report.files.len(), 1);
+		assert_eq!(
+			file_version(tempdir.path().join("segments/0")),
+			segment::FORMAT_VERSION
+		);
+		let folder = DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap();
+		assert_fixture_segment(&folder.open_segment_file(0).unwrap(), &fixture_config());
+	}
+
+	#[test]
+	fn migrate_current_database() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_fixture_database(tempdir.path());
+		migrate(tempdir.path(), &MIGRATIONS, false).unwrap();
+
+		// when
+		let report = migrate(tempdir.path(), &MIGRATIONS, false).unwrap();
+
+		// then
+		assert_eq!(report.files, vec![]);
+	}
+
+	#[test]
+	fn migrate_v1_segment_file() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let path = tempdir.path().join("0");
+		fs::copy(Path::new(FIXTURES_DIR).join("segment_v1"), &path).unwrap();
+
+		// when
+		let report = migrate_file(&path, &MIGRATIONS, false).unwrap();
+
+		// then
+		assert_eq!(
+			report,
+			Some(FileMigrationReport {
+				path: path.clone(),
+				file_type: FileType::Segment,
+				from_version: 1,
+				to_version: segment::FORMAT_VERSION,
+				num_pages: 0,
+			})
+		);
+		assert_eq!(file_version(&path), segment::FORMAT_VERSION);
+		let config = fixture_config();
+		assert_fixture_segment(&SegmentFile::open_file(&path, &config).unwrap(), &config);
+	}
+
+	#[test]
+	fn migrate_v2_segment_file() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let path = tempdir.path().join("0");
+		fs::copy(Path::new(FIXTURES_DIR).join("segment_v2"), &path).unwrap();
+
+		// when
+		let report = migrate_file(&path, &MIGRATIONS, false).unwrap();
+
+		// then
+		assert_eq!(
+			report,
+			Some(FileMigrationReport {
+				path: path.clone(),
+				file_type: FileType::Segment,
+				from_version: segment::CRC16_FORMAT_VERSION,
+				to_version: segment::FORMAT_VERSION,
+				num_pages: 0,
+			})
+		);
+		assert_eq!(file_version(&path), segment::FORMAT_VERSION);
+		let config = v2_fixture_config();
+		assert_v2_fixture_segment(&SegmentFile::open_file(&path, &config).unwrap(), &config);
+	}
+
+	#[test]
+	fn read_v1_wal_file_after_migration() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_fixture_database(tempdir.path());
+		fs::create_dir_all(tempdir.path().join("wal")).unwrap();
+		fs::copy(
+			Path::new(FIXTURES_DIR).join("wal_v1"),
+			tempdir.path().join("wal/0"),
+		)
+		.unwrap();
+
+		// when
+		let report = migrate(tempdir.path(), &MIGRATIONS, false).unwrap();
+
+		// then
+		assert_eq!(report.files.len(), 1);
+		assert_eq!(file_version(tempdir.path().join("wal/0")), 1);
+		let folder = DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap();
+		let mut wal_file = folder.open_wal_file(0).unwrap();
+		let items: Vec<_> = wal_file
+			.iter_items()
+			.unwrap()
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(
+			items,
+			vec![
+				(
+					non_zero!(9),
+					Item::Write(WriteData {
+						transaction_data: TransactionData {
+							transaction_id: 7,
+							prev_transaction_item: None,
+						},
+						page_address: page_address!(0, 1),
+						offset: 100,
+						from: Some(Cow::Owned(vec![0; 4])),
+						to: Cow::Owned(vec![1, 2, 3, 4]),
+					})
+				),
+				(
+					non_zero!(75),
+					Item::Commit(TransactionData {
+						transaction_id: 7,
+						prev_transaction_item: Some(wal_index!(0, 9)),
+					})
+				),
+			]
+		);
+	}
+
+	#[test]
+	fn try_migrate_without_migration_path() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_fixture_database(tempdir.path());
+		let migrations = MigrationRegistry {
+			file_migrations: &[],
+			page_migrations: &[],
+		};
+
+		// when
+		let result = migrate(tempdir.path(), &migrations, true);
+
+		// then
+		assert!(matches!(
+			result,
+			Err(FileError::IncompatibleVersion(FileType::Segment, 1))
+		));
+	}
+
+	#[test]
+	fn try_migrate_open_database() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_fixture_database(tempdir.path());
+		migrate(tempdir.path(), &MIGRATIONS, false).unwrap();
+		let _folder = DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap();
+
+		// when
+		let result = migrate(tempdir.path(), &MIGRATIONS, false);
+
+		// then
+		assert!(matches!(result, Err(FileError::Locked(..))));
+	}
+}
@@ -2,29 +2,49 @@ use std::{
 	convert::Infallible,
 	ffi::OsString,
 	fmt,
-	fs::{self, ReadDir},
+	fs::{self, File, ReadDir},
 	io,
 	num::{NonZero, NonZeroU16, NonZeroU64},
-	path::PathBuf,
+	path::{Path, PathBuf},
+	sync::Arc,
 };
 
 #[cfg(feature = "io_uring")]
 use io_uring::squeue::PushError;
+use log::warn;
+use parking_lot::Mutex;
 use thiserror::Error;
+use uuid::Uuid;
 
 #[cfg(test)]
 use mockall::automock;
 
 use self::{
+	checksum::ChecksumAlgorithm,
+	encryption::{
+		key_check, Cipher, EncryptedSegmentFile, KeyProvider, WalCipher, XChaCha20Poly1305Cipher,
+		TAG_SIZE,
+	},
 	generic::FileType,
-	segment::{SegmentFile, SegmentFileApi},
+	manifest::{read_manifest, write_manifest, Manifest},
+	migration::{MigrationReport, MIGRATIONS},
+	segment::{
+		validate_page_size, validate_segment_sizes, SegmentConfig, SegmentFile, SegmentFileApi,
+	},
 	wal::{WalFile, WalFileApi},
 };
 
 #[cfg(test)]
 use self::{segment::MockSegmentFileApi, wal::MockWalFileApi};
 
+pub(crate) mod checksum;
+pub(crate) mod encryption;
+#[cfg(test)]
+pub(crate) mod fault;
 pub(super) mod generic;
+pub(crate) mod manifest;
+pub(crate) mod memory;
+pub(crate) mod migration;
 pub(crate) mod segment;
 pub(super) mod utils;
 pub(crate) mod wal;
@@ -34,9 +54,6 @@ pub(crate) enum FileError {
 	#[error("The file is not an acorn database file")]
 	MissingMagic,
 
-	#[error("The file was created on a platform with a different byte order and cannot be opened")]
-	ByteOrderMismatch,
-
 	#[error("The file is corrupted: {0}")]
 	Corrupted(String),
 
@@ -46,9 +63,48 @@ pub(crate) enum FileError {
 	#[error("Incompatible version of {0:?} file: {1}")]
 	IncompatibleVersion(FileType, u8),
 
+	#[error("Incompatible database format version: {0}")]
+	IncompatibleDatabaseVersion(u16),
+
 	#[error("Incompatible page version: {0}")]
 	IncompatiblePageVersion(u8),
 
+	#[error("Page {0} lies beyond the maximum segment size")]
+	PageOutOfRange(u16),
+
+	#[error("Invalid page size {0}; must be a power of two between 4 KiB and 64 KiB")]
+	InvalidPageSize(usize),
+
+	#[error("Invalid maximum segment size {0}; must be a multiple of the page size between 2 and 65536 pages")]
+	InvalidSegmentSize(usize),
+
+	#[error("Invalid extent size {0}; must be a non-zero multiple of the page size")]
+	InvalidExtentSize(usize),
+
+	#[error("Expected page size {expected}, but the segment uses {found}")]
+	PageSizeMismatch { expected: usize, found: usize },
+
+	#[error("Expected checksum algorithm {expected:?}, but the file uses {found:?}")]
+	ChecksumAlgorithmMismatch {
+		expected: ChecksumAlgorithm,
+		found: ChecksumAlgorithm,
+	},
+
+	#[error("Authentication failed; the data has been tampered with")]
+	Tampered,
+
+	#[error("The database is encrypted, but no key was provided")]
+	MissingKey,
+
+	#[error("The provided key is not the key of the database")]
+	InvalidKey,
+
+	#[error("A key was provided, but the database is not encrypted")]
+	NotEncrypted,
+
+	#[error("Pages of encrypted databases can't be compressed")]
+	CompressedEncryption,
+
 	#[error("Unexpected end of file")]
 	UnexpectedEof,
 
@@ -58,6 +114,21 @@ pub(crate) enum FileError {
 	#[error("Unexpected file in database folder: {}", _0.to_string_lossy())]
 	UnexpectedFile(OsString),
 
+	#[error("No database exists at {}", _0.display())]
+	MissingManifest(PathBuf),
+
+	#[error("A database already exists at {}", _0.display())]
+	AlreadyExists(PathBuf),
+
+	#[error("The database at {} is already in use by another process", _0.display())]
+	Locked(PathBuf),
+
+	#[error("The database was opened in read-only mode")]
+	ReadOnly,
+
+	#[error("Segment {0} does not exist")]
+	MissingSegment(u32),
+
 	#[error("Too many concurrent IO operations!")]
 	TooManyConcurrent,
 
@@ -99,7 +170,9 @@ pub(crate) struct TransactionState {
 	pub last_index: WalIndex,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Page addresses are ordered by segment and then by page number, which is the
+/// order in which the pages are laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct PageAddress {
 	pub segment_num: u32,
 	pub page_num: NonZeroU16,
@@ -141,21 +214,296 @@ impl WalIndex {
 	}
 }
 
+/// How [`DatabaseFolder::lock`] locks a folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockMode {
+	/// Held by the single handle that may change the folder.
+	Exclusive,
+	/// Held by any number of handles that only read the folder.
+	Shared,
+}
+
 pub(crate) struct DatabaseFolder {
 	path: PathBuf,
+	segment_config: SegmentConfig,
+	manifest: Mutex<Manifest>,
+	was_clean_shutdown: bool,
+	read_only: bool,
+	cipher: Option<Arc<dyn Cipher>>,
+	// Holds the advisory lock on the folder for as long as it is open
+	_lock: File,
 }
 
 impl DatabaseFolder {
 	const SEGMENTS_DIR_NAME: &'static str = "segments";
 	const WAL_DIR_NAME: &'static str = "wal";
+	const MANIFEST_FILE_NAME: &'static str = "MANIFEST";
+	#[cfg(not(unix))]
+	const LOCK_FILE_NAME: &'static str = "LOCK";
+
+	pub fn create(path: PathBuf, segment_config: SegmentConfig) -> Result<Self, FileError> {
+		Self::create_impl(path, segment_config, None)
+	}
+
+	/// Creates a database whose pages and WAL items are encrypted with the key
+	/// supplied by `key_provider`. Encrypted pages can't be compressed, so
+	/// `segment_config.compress_pages` must not be set.
+	pub fn create_encrypted(
+		path: PathBuf,
+		segment_config: SegmentConfig,
+		key_provider: &dyn KeyProvider,
+	) -> Result<Self, FileError> {
+		Self::create_impl(path, segment_config, Some(key_provider))
+	}
+
+	fn create_impl(
+		path: PathBuf,
+		segment_config: SegmentConfig,
+		key_provider: Option<&dyn KeyProvider>,
+	) -> Result<Self, FileError> {
+		validate_page_size(segment_config.page_size)?;
+		validate_segment_sizes(&segment_config)?;
+		if key_provider.is_some() && segment_config.compress_pages {
+			return Err(FileError::CompressedEncryption);
+		}
+		fs::create_dir_all(&path)?;
+		let lock = Self::lock(&path, LockMode::Exclusive)?;
+
+		let manifest_path = path.join(Self::MANIFEST_FILE_NAME);
+		if manifest_path.exists() {
+			return Err(FileError::AlreadyExists(path));
+		}
+		let mut manifest = Manifest::new(segment_config);
+		let cipher = match key_provider {
+			Some(key_provider) => {
+				let key = key_provider.key(manifest.id).ok_or(FileError::MissingKey)?;
+				let cipher: Arc<dyn Cipher> = Arc::new(XChaCha20Poly1305Cipher::new(&key));
+				manifest.key_check = Some(key_check(cipher.as_ref()));
+				Some(cipher)
+			}
+			None => None,
+		};
+		write_manifest(&manifest_path, manifest.clone())?;
+
+		Ok(Self {
+			path,
+			segment_config: manifest.segment_config.clone(),
+			manifest: Mutex::new(manifest),
+			was_clean_shutdown: true,
+			read_only: false,
+			cipher,
+			_lock: lock,
+		})
+	}
+
+	pub fn open(path: PathBuf) -> Result<Self, FileError> {
+		Self::open_impl(path, None)
+	}
+
+	pub fn open_encrypted(
+		path: PathBuf,
+		key_provider: &dyn KeyProvider,
+	) -> Result<Self, FileError> {
+		Self::open_impl(path, Some(key_provider))
+	}
+
+	fn open_impl(path: PathBuf, key_provider: Option<&dyn KeyProvider>) -> Result<Self, FileError> {
+		let (lock, mut manifest) = Self::open_manifest(&path, LockMode::Exclusive)?;
+		let cipher = Self::load_cipher(&manifest, key_provider)?;
+		let manifest_path = path.join(Self::MANIFEST_FILE_NAME);
+
+		let was_clean_shutdown = manifest.clean_shutdown;
+		if !was_clean_shutdown {
+			warn!("Database at {} was not shut down cleanly", path.display());
+		}
+
+		// The flag is cleared for as long as the database is open, so that a crash
+		// will be detected the next time it is opened.
+		manifest.clean_shutdown = false;
+		write_manifest(&manifest_path, manifest.clone())?;
+
+		Ok(Self {
+			path,
+			segment_config: manifest.segment_config.clone(),
+			manifest: Mutex::new(manifest),
+			was_clean_shutdown,
+			read_only: false,
+			cipher,
+			_lock: lock,
+		})
+	}
+
+	/// Opens the database without write access. Missing files are never created,
+	/// and any attempt to modify the folder fails with `FileError::ReadOnly`.
+	///
+	/// Only a shared lock is taken, so any number of read-only handles may
+	/// coexist, but not alongside a writer.
+	pub fn open_read_only(path: PathBuf) -> Result<Self, FileError> {
+		Self::open_read_only_impl(path, None)
+	}
+
+	pub fn open_read_only_encrypted(
+		path: PathBuf,
+		key_provider: &dyn KeyProvider,
+	) -> Result<Self, FileError> {
+		Self::open_read_only_impl(path, Some(key_provider))
+	}
+
+	fn open_read_only_impl(
+		path: PathBuf,
+		key_provider: Option<&dyn KeyProvider>,
+	) -> Result<Self, FileError> {
+		let (lock, manifest) = Self::open_manifest(&path, LockMode::Shared)?;
+		let cipher = Self::load_cipher(&manifest, key_provider)?;
+		Ok(Self {
+			path,
+			segment_config: manifest.segment_config.clone(),
+			was_clean_shutdown: manifest.clean_shutdown,
+			manifest: Mutex::new(manifest),
+			read_only: true,
+			cipher,
+			_lock: lock,
+		})
+	}
 
-	pub fn open(path: PathBuf) -> Self {
-		Self { path }
+	/// Upgrades all files of the database at `path` to the current format
+	/// versions. The database must not be open while it is migrated.
+	///
+	/// If `dry_run` is set, nothing is changed, and the report only lists the
+	/// files that would be migrated.
+	pub fn migrate(path: PathBuf, dry_run: bool) -> Result<MigrationReport, FileError> {
+		migration::migrate(&path, &MIGRATIONS, dry_run)
+	}
+
+	fn load_cipher(
+		manifest: &Manifest,
+		key_provider: Option<&dyn KeyProvider>,
+	) -> Result<Option<Arc<dyn Cipher>>, FileError> {
+		match (manifest.key_check, key_provider) {
+			(None, None) => Ok(None),
+			(None, Some(..)) => Err(FileError::NotEncrypted),
+			(Some(..), None) => Err(FileError::MissingKey),
+			(Some(expected_key_check), Some(key_provider)) => {
+				let key = key_provider.key(manifest.id).ok_or(FileError::MissingKey)?;
+				let cipher = XChaCha20Poly1305Cipher::new(&key);
+				if key_check(&cipher) != expected_key_check {
+					return Err(FileError::InvalidKey);
+				}
+				Ok(Some(Arc::new(cipher)))
+			}
+		}
+	}
+
+	fn open_manifest(path: &Path, lock_mode: LockMode) -> Result<(File, Manifest), FileError> {
+		if !path.is_dir() {
+			return Err(FileError::MissingManifest(path.to_path_buf()));
+		}
+		let lock = Self::lock(path, lock_mode)?;
+
+		let manifest_path = path.join(Self::MANIFEST_FILE_NAME);
+		if !manifest_path.exists() {
+			return Err(FileError::MissingManifest(path.to_path_buf()));
+		}
+		let manifest = read_manifest(&manifest_path)?;
+		if manifest.format_version != manifest::DATABASE_FORMAT_VERSION {
+			return Err(FileError::IncompatibleDatabaseVersion(
+				manifest.format_version,
+			));
+		}
+		Ok((lock, manifest))
+	}
+
+	#[cfg(unix)]
+	fn lock(path: &Path, mode: LockMode) -> Result<File, FileError> {
+		use std::os::fd::AsRawFd;
+
+		let operation = match mode {
+			LockMode::Exclusive => libc::LOCK_EX,
+			LockMode::Shared => libc::LOCK_SH,
+		};
+		let file = File::open(path)?;
+		// Safety: the file descriptor is guaranteed to be valid for the lifetime of
+		// `file`.
+		if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } != 0 {
+			let error = io::Error::last_os_error();
+			if error.kind() == io::ErrorKind::WouldBlock {
+				return Err(FileError::Locked(path.to_path_buf()));
+			}
+			return Err(error.into());
+		}
+		Ok(file)
+	}
+
+	// Directories can't be opened as files everywhere, so a lock file inside
+	// the folder is locked instead.
+	#[cfg(not(unix))]
+	fn lock(path: &Path, mode: LockMode) -> Result<File, FileError> {
+		let file = fs::OpenOptions::new()
+			.create(true)
+			.truncate(false)
+			.write(true)
+			.open(path.join(Self::LOCK_FILE_NAME))?;
+		let result = match mode {
+			LockMode::Exclusive => file.try_lock(),
+			LockMode::Shared => file.try_lock_shared(),
+		};
+		match result {
+			Ok(()) => Ok(file),
+			Err(fs::TryLockError::WouldBlock) => Err(FileError::Locked(path.to_path_buf())),
+			Err(fs::TryLockError::Error(error)) => Err(error.into()),
+		}
+	}
+
+	pub fn id(&self) -> Uuid {
+		self.manifest.lock().id
+	}
+
+	pub fn segment_config(&self) -> &SegmentConfig {
+		&self.segment_config
+	}
+
+	/// The size of the page bodies available to the page cache. This is smaller
+	/// than the segment's page body size for encrypted databases, since the
+	/// authentication tag is stored in the page body.
+	pub fn page_body_size(&self) -> usize {
+		let page_body_size = self.segment_config.page_body_size();
+		if self.cipher.is_some() {
+			page_body_size - TAG_SIZE
+		} else {
+			page_body_size
+		}
+	}
+
+	pub fn is_encrypted(&self) -> bool {
+		self.cipher.is_some()
+	}
+
+	/// Whether the database was shut down cleanly before it was opened. If not,
+	/// it needs to be recovered from the WAL.
+	pub fn was_clean_shutdown(&self) -> bool {
+		self.was_clean_shutdown
+	}
+
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
+	/// Records in the manifest that the database was shut down cleanly. This
+	/// should only be called once all changes have been flushed.
+	pub fn mark_clean_shutdown(&self) -> Result<(), FileError> {
+		if self.read_only {
+			return Err(FileError::ReadOnly);
+		}
+		let mut manifest = self.manifest.lock();
+		manifest.clean_shutdown = true;
+		write_manifest(self.path.join(Self::MANIFEST_FILE_NAME), manifest.clone())
 	}
 
 	fn segments_dir(&self) -> Result<PathBuf, FileError> {
 		let path = self.path.join(Self::SEGMENTS_DIR_NAME);
-		fs::create_dir_all(&path)?;
+		if !self.read_only {
+			fs::create_dir_all(&path)?;
+		}
 		Ok(path)
 	}
 
@@ -165,13 +513,21 @@ impl DatabaseFolder {
 
 	fn wal_dir(&self) -> Result<PathBuf, FileError> {
 		let path = self.path.join(Self::WAL_DIR_NAME);
-		fs::create_dir_all(&path)?;
+		if !self.read_only {
+			fs::create_dir_all(&path)?;
+		}
 		Ok(path)
 	}
 
 	fn wal_file_path(&self, generation: u64) -> Result<PathBuf, FileError> {
 		self.wal_dir().map(|p| p.join(generation.to_string()))
 	}
+
+	fn wal_cipher(&self, generation: u64) -> Option<WalCipher> {
+		self.cipher
+			.as_ref()
+			.map(|cipher| WalCipher::new(Arc::clone(cipher), generation))
+	}
 }
 
 #[cfg_attr(test, automock(
@@ -189,67 +545,145 @@ pub(crate) trait DatabaseFolderApi {
 	fn delete_wal_file(&self, generation: u64) -> Result<(), FileError>;
 	fn iter_wal_files(&self) -> Result<Self::IterWalFiles, FileError>;
 	fn clear_wal_files(&self) -> Result<(), FileError>;
+
+	fn page_body_size(&self) -> usize;
+	fn is_read_only(&self) -> bool;
+	/// Whether the database was shut down cleanly before it was opened, so that
+	/// recovery can be skipped.
+	fn was_clean_shutdown(&self) -> bool;
+	fn mark_clean_shutdown(&self) -> Result<(), FileError>;
 }
 
 impl DatabaseFolderApi for DatabaseFolder {
-	type SegmentFile = SegmentFile;
+	type SegmentFile = EncryptedSegmentFile;
 	type WalFile = WalFile;
 	type IterWalFiles = IterWalFiles;
 
 	fn open_segment_file(&self, segment_num: u32) -> Result<Self::SegmentFile, FileError> {
 		let path = self.segment_file_path(segment_num)?;
-		if path.exists() {
-			SegmentFile::open_file(path)
+		let segment_file = if self.read_only {
+			if !path.exists() {
+				return Err(FileError::MissingSegment(segment_num));
+			}
+			SegmentFile::open_file_read_only(path, &self.segment_config)?
+		} else if path.exists() {
+			SegmentFile::open_file(path, &self.segment_config)?
 		} else {
-			SegmentFile::create_file(path)
-		}
+			SegmentFile::create_file(path, &self.segment_config)?
+		};
+		Ok(EncryptedSegmentFile::new(
+			segment_file,
+			segment_num,
+			self.cipher.clone(),
+		))
 	}
 
 	fn open_wal_file(&self, generation: u64) -> Result<Self::WalFile, FileError> {
 		let path = self.wal_file_path(generation)?;
-		if path.exists() {
-			WalFile::open_file(path)
+		let cipher = self.wal_cipher(generation);
+		if self.read_only {
+			if !path.exists() {
+				return Err(FileError::ReadOnly);
+			}
+			WalFile::open_file_read_only(path, cipher)
+		} else if path.exists() {
+			WalFile::open_file(path, cipher)
 		} else {
-			WalFile::create_file(path)
+			WalFile::create_file(path, self.segment_config.checksum, cipher)
 		}
 	}
 
 	fn delete_wal_file(&self, generation: u64) -> Result<(), FileError> {
+		if self.read_only {
+			return Err(FileError::ReadOnly);
+		}
 		let path = self.wal_file_path(generation)?;
 		fs::remove_file(path)?;
 		Ok(())
 	}
 
 	fn clear_wal_files(&self) -> Result<(), FileError> {
+		if self.read_only {
+			return Err(FileError::ReadOnly);
+		}
 		fs::remove_dir_all(self.wal_dir()?)?;
 		Ok(())
 	}
 
 	fn iter_wal_files(&self) -> Result<Self::IterWalFiles, FileError> {
-		Ok(IterWalFiles(fs::read_dir(self.wal_dir()?)?))
+		let wal_dir = self.wal_dir()?;
+		if self.read_only && !wal_dir.exists() {
+			return Ok(IterWalFiles::empty());
+		}
+		Ok(IterWalFiles {
+			read_dir: Some(fs::read_dir(wal_dir)?),
+			read_only: self.read_only,
+			cipher: self.cipher.clone(),
+		})
+	}
+
+	fn page_body_size(&self) -> usize {
+		DatabaseFolder::page_body_size(self)
+	}
+
+	fn is_read_only(&self) -> bool {
+		DatabaseFolder::is_read_only(self)
+	}
+
+	fn was_clean_shutdown(&self) -> bool {
+		DatabaseFolder::was_clean_shutdown(self)
+	}
+
+	fn mark_clean_shutdown(&self) -> Result<(), FileError> {
+		DatabaseFolder::mark_clean_shutdown(self)
 	}
 }
 
-pub(crate) struct IterWalFiles(ReadDir);
+pub(crate) struct IterWalFiles {
+	read_dir: Option<ReadDir>,
+	read_only: bool,
+	cipher: Option<Arc<dyn Cipher>>,
+}
+
+impl IterWalFiles {
+	fn empty() -> Self {
+		Self {
+			read_dir: None,
+			read_only: true,
+			cipher: None,
+		}
+	}
+}
 
 impl Iterator for IterWalFiles {
 	type Item = Result<(u64, WalFile), FileError>;
 
 	fn next(&mut self) -> Option<Self::Item> {
-		for entry_result in &mut self.0 {
+		let read_dir = self.read_dir.as_mut()?;
+		for entry_result in read_dir {
 			let entry = match entry_result {
 				Ok(entry) => entry,
 				Err(error) => return Some(Err(error.into())),
 			};
-			if entry.path().is_file() {
-				let file = match WalFile::open_file(entry.path()) {
-					Ok(file) => file,
-					Err(error) => return Some(Err(error)),
-				};
+			let path = entry.path();
+			if path.is_file() && path.extension() != Some(WalFile::TMP_EXTENSION.as_ref()) {
 				let Ok(generation): Result<u64, _> = entry.file_name().to_string_lossy().parse()
 				else {
 					return Some(Err(FileError::UnexpectedFile(entry.file_name())));
 				};
+				let cipher = self
+					.cipher
+					.as_ref()
+					.map(|cipher| WalCipher::new(Arc::clone(cipher), generation));
+				let file = if self.read_only {
+					WalFile::open_file_read_only(path, cipher)
+				} else {
+					WalFile::open_file(path, cipher)
+				};
+				let file = match file {
+					Ok(file) => file,
+					Err(error) => return Some(Err(error)),
+				};
 
 				return Some(Ok((generation, file)));
 			}
@@ -274,4 +708,316 @@ pub(crate) mod test_helpers {
 		};
 	}
 	pub(crate) use wal_index;
+
+	use super::{
+		encryption::{Cipher, EncryptionKey, KeyProvider, Nonce, Tag, NONCE_SIZE, TAG_SIZE},
+		utils::CRC32,
+		FileError,
+	};
+
+	pub(crate) struct StaticKeyProvider(pub EncryptionKey);
+
+	impl KeyProvider for StaticKeyProvider {
+		fn key(&self, _database_id: uuid::Uuid) -> Option<EncryptionKey> {
+			Some(self.0)
+		}
+	}
+
+	/// A deterministic stand-in for a real cipher. It provides no secrecy at all,
+	/// but detects any change to the data or the nonce.
+	pub(crate) struct TestCipher;
+
+	impl TestCipher {
+		fn tag(nonce: &Nonce, buf: &[u8]) -> Tag {
+			let mut digest = CRC32.digest();
+			digest.update(nonce);
+			digest.update(buf);
+			let mut tag = [0; TAG_SIZE];
+			tag[0..4].copy_from_slice(&digest.finalize().to_ne_bytes());
+			tag
+		}
+
+		fn apply_keystream(nonce: &Nonce, buf: &mut [u8]) {
+			for (i, byte) in buf.iter_mut().enumerate() {
+				*byte ^= nonce[i % NONCE_SIZE] ^ 0xa5;
+			}
+		}
+	}
+
+	impl Cipher for TestCipher {
+		fn encrypt(&self, nonce: &Nonce, buf: &mut [u8]) -> Tag {
+			Self::apply_keystream(nonce, buf);
+			Self::tag(nonce, buf)
+		}
+
+		fn decrypt(&self, nonce: &Nonce, buf: &mut [u8], tag: &Tag) -> Result<(), FileError> {
+			if Self::tag(nonce, buf) != *tag {
+				return Err(FileError::Tampered);
+			}
+			Self::apply_keystream(nonce, buf);
+			Ok(())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::mem;
+
+	use crate::utils::units::KIB;
+
+	use super::{test_helpers::StaticKeyProvider, *};
+
+	#[test]
+	fn create_and_open_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment_config = SegmentConfig {
+			page_size: 8 * KIB,
+			..Default::default()
+		};
+		let folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), segment_config.clone()).unwrap();
+		let id = folder.id();
+		mem::drop(folder);
+
+		// when
+		let folder = DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap();
+
+		// then
+		assert_eq!(folder.id(), id);
+		assert_eq!(
+			folder.segment_config(),
+			&SegmentConfig {
+				max_segment_size: Some((8 * KIB) << 16),
+				..segment_config
+			}
+		);
+	}
+
+	#[test]
+	fn try_create_database_folder_with_invalid_segment_size() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment_config = SegmentConfig {
+			page_size: 8 * KIB,
+			max_segment_size: Some(((8 * KIB) << 16) + 8 * KIB),
+			..Default::default()
+		};
+
+		// when
+		let result = DatabaseFolder::create(tempdir.path().to_path_buf(), segment_config);
+
+		// then
+		assert!(matches!(result, Err(FileError::InvalidSegmentSize(..))));
+		assert!(!tempdir.path().join("MANIFEST").exists());
+	}
+
+	#[test]
+	fn try_create_existing_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		mem::drop(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
+
+		// when
+		let result = DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default());
+
+		// then
+		assert!(matches!(result, Err(FileError::AlreadyExists(..))));
+	}
+
+	#[test]
+	fn try_open_missing_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+
+		// when
+		let result = DatabaseFolder::open(tempdir.path().to_path_buf());
+
+		// then
+		assert!(matches!(result, Err(FileError::MissingManifest(..))));
+	}
+
+	#[test]
+	fn try_open_locked_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let _folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap();
+
+		// when
+		let result = DatabaseFolder::open(tempdir.path().to_path_buf());
+
+		// then
+		assert!(matches!(result, Err(FileError::Locked(..))));
+	}
+
+	#[test]
+	fn detect_unclean_shutdown() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		mem::drop(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
+
+		// when
+		let folder = DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap();
+
+		// then
+		assert!(!folder.was_clean_shutdown());
+	}
+
+	#[test]
+	fn detect_clean_shutdown() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap();
+		folder.mark_clean_shutdown().unwrap();
+		mem::drop(folder);
+
+		// when
+		let folder = DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap();
+
+		// then
+		assert!(folder.was_clean_shutdown());
+	}
+
+	#[test]
+	fn open_read_only_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap();
+		folder.mark_clean_shutdown().unwrap();
+		mem::drop(folder);
+		let manifest_before = fs::read(tempdir.path().join("MANIFEST")).unwrap();
+
+		// when
+		let folder_1 = DatabaseFolder::open_read_only(tempdir.path().to_path_buf()).unwrap();
+		let folder_2 = DatabaseFolder::open_read_only(tempdir.path().to_path_buf()).unwrap();
+
+		// then
+		assert!(folder_1.is_read_only());
+		assert!(folder_2.was_clean_shutdown());
+		assert_eq!(
+			fs::read(tempdir.path().join("MANIFEST")).unwrap(),
+			manifest_before
+		);
+		assert!(matches!(
+			folder_1.open_segment_file(69),
+			Err(FileError::MissingSegment(69))
+		));
+		assert!(matches!(
+			folder_1.clear_wal_files(),
+			Err(FileError::ReadOnly)
+		));
+		assert!(!tempdir.path().join("segments").exists());
+	}
+
+	#[test]
+	fn try_open_read_only_database_folder_while_open() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let _folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap();
+
+		// when
+		let result = DatabaseFolder::open_read_only(tempdir.path().to_path_buf());
+
+		// then
+		assert!(matches!(result, Err(FileError::Locked(..))));
+	}
+
+	#[test]
+	fn create_and_open_encrypted_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let key_provider = StaticKeyProvider([25; 32]);
+		mem::drop(
+			DatabaseFolder::create_encrypted(
+				tempdir.path().to_path_buf(),
+				Default::default(),
+				&key_provider,
+			)
+			.unwrap(),
+		);
+
+		// when
+		let folder =
+			DatabaseFolder::open_encrypted(tempdir.path().to_path_buf(), &key_provider).unwrap();
+
+		// then
+		assert!(folder.is_encrypted());
+		assert_eq!(
+			folder.page_body_size(),
+			folder.segment_config().page_body_size() - encryption::TAG_SIZE
+		);
+	}
+
+	#[test]
+	fn try_create_compressed_encrypted_database_folder() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment_config = SegmentConfig {
+			compress_pages: true,
+			..Default::default()
+		};
+
+		// when
+		let result = DatabaseFolder::create_encrypted(
+			tempdir.path().to_path_buf(),
+			segment_config,
+			&StaticKeyProvider([25; 32]),
+		);
+
+		// then
+		assert!(matches!(result, Err(FileError::CompressedEncryption)));
+		assert!(!tempdir.path().join("MANIFEST").exists());
+	}
+
+	#[test]
+	fn try_open_encrypted_database_folder_with_wrong_key() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		mem::drop(
+			DatabaseFolder::create_encrypted(
+				tempdir.path().to_path_buf(),
+				Default::default(),
+				&StaticKeyProvider([25; 32]),
+			)
+			.unwrap(),
+		);
+
+		// when
+		let result = DatabaseFolder::open_encrypted(
+			tempdir.path().to_path_buf(),
+			&StaticKeyProvider([69; 32]),
+		);
+
+		// then
+		assert!(matches!(result, Err(FileError::InvalidKey)));
+	}
+
+	#[test]
+	fn try_open_encrypted_database_folder_without_key() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		mem::drop(
+			DatabaseFolder::create_encrypted(
+				tempdir.path().to_path_buf(),
+				Default::default(),
+				&StaticKeyProvider([25; 32]),
+			)
+			.unwrap(),
+		);
+
+		// when
+		let result = DatabaseFolder::open(tempdir.path().to_path_buf());
+
+		// then
+		assert!(matches!(result, Err(FileError::MissingKey)));
+	}
 }
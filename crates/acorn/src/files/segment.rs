@@ -1,9 +1,10 @@
 use std::{
 	fs::{File, OpenOptions},
-	io::{Seek, SeekFrom},
+	io::{self, Seek, SeekFrom},
 	num::{NonZeroU16, NonZeroU64},
 	os::{self},
 	path::Path,
+	sync::atomic::{AtomicU64, Ordering},
 };
 
 #[cfg(feature = "io_uring")]
@@ -11,28 +12,211 @@ use io_uring::{cqueue, opcode, squeue, types};
 
 #[cfg(test)]
 use mockall::automock;
-use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+use parking_lot::Mutex;
+use zerocopy::{
+	byteorder::{self, LittleEndian, U16, U32, U64},
+	FromBytes, FromZeros, Immutable, IntoBytes,
+};
 
 use super::{
-	generic::{GenericHeader, GenericHeaderRepr},
+	checksum::ChecksumAlgorithm,
+	generic::{with_byte_order, ByteOrder, GenericHeader, GenericHeaderRepr},
+	migration::{MigrationRegistry, MIGRATIONS},
 	FileError, WalIndex,
 };
 use crate::{
-	consts::PAGE_SIZE,
-	files::{generic::FileType, utils::CRC16},
+	consts::{
+		DEFAULT_CHECKSUM_ALGORITHM, DEFAULT_PAGE_SIZE, DEFAULT_SEGMENT_EXTENT_SIZE, MAX_PAGE_SIZE,
+		MIN_PAGE_SIZE,
+	},
+	files::generic::FileType,
 	repr::{IoRepr, Repr},
+	utils::units::KIB,
 };
 
 const FORMAT_VERSION_UNINIT: u8 = 0;
-const FORMAT_VERSION: u8 = 1;
+pub(super) const FORMAT_VERSION: u8 = 3;
+
+/// Version 1 segments have no segment header, and always use 32 KiB pages.
+pub(super) const LEGACY_FORMAT_VERSION: u8 = 1;
+const LEGACY_PAGE_SIZE: usize = 32 * KIB;
+
+/// The maximum number of adjacent pages that are merged into a single
+/// vectored write.
+#[cfg(not(feature = "io_uring"))]
+const MAX_WRITE_RUN_LEN: usize = 64;
+
+/// Version 2 segments don't record a checksum algorithm, and always use CRC16.
+pub(super) const CRC16_FORMAT_VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SegmentConfig {
+	/// The size of a page, including its header. Must be a power of two between
+	/// `MIN_PAGE_SIZE` and `MAX_PAGE_SIZE`.
+	pub page_size: usize,
+
+	/// The size up to which a segment file may grow. Pages beyond this size
+	/// cannot be stored in the segment. Since page numbers are 16-bit, this is
+	/// capped at `page_size << 16`, which is also what `None` stands for.
+	pub max_segment_size: Option<usize>,
+
+	/// The amount by which a segment file is extended when a page is written
+	/// past its current end.
+	pub extent_size: usize,
+
+	/// The algorithm used for page checksums. This also determines the size of
+	/// the page header.
+	pub checksum: ChecksumAlgorithm,
+
+	/// Whether page bodies are compressed before they are written. Pages are
+	/// only stored compressed if that actually saves space. Encrypted databases
+	/// can't use compression, since their page bodies are already ciphertext
+	/// at this point.
+	pub compress_pages: bool,
+}
+
+impl Default for SegmentConfig {
+	fn default() -> Self {
+		Self {
+			page_size: DEFAULT_PAGE_SIZE,
+			max_segment_size: None,
+			extent_size: DEFAULT_SEGMENT_EXTENT_SIZE,
+			checksum: DEFAULT_CHECKSUM_ALGORITHM,
+			compress_pages: false,
+		}
+	}
+}
+
+impl SegmentConfig {
+	#[inline]
+	pub fn page_body_size(&self) -> usize {
+		page_body_size(self.page_size, self.checksum)
+	}
+
+	/// The size up to which a segment file may grow, which defaults to the
+	/// largest size that 16-bit page numbers can address.
+	#[inline]
+	pub fn max_segment_size(&self) -> usize {
+		self.max_segment_size.unwrap_or(self.page_size << 16)
+	}
+
+	/// The number of the last page that fits into a segment. The config must
+	/// have been validated with `validate_segment_sizes`.
+	pub fn last_page_num(&self) -> NonZeroU16 {
+		u16::try_from(self.max_segment_size() / self.page_size - 1)
+			.ok()
+			.and_then(NonZeroU16::new)
+			.expect("Segments must hold between 2 and 65536 pages!")
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SegmentHeader {
+	page_size: usize,
+	checksum: ChecksumAlgorithm,
+}
+
+#[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
+#[repr(C, packed)]
+struct SegmentHeaderRepr<O: byteorder::ByteOrder = LittleEndian> {
+	page_size: U32<O>,
+	checksum: u8,
+}
+
+impl<O: byteorder::ByteOrder> From<SegmentHeader> for SegmentHeaderRepr<O> {
+	fn from(value: SegmentHeader) -> Self {
+		Self {
+			page_size: u32::try_from(value.page_size)
+				.expect("Page size must be 32-bit!")
+				.into(),
+			checksum: value.checksum as u8,
+		}
+	}
+}
+
+impl<O: byteorder::ByteOrder> TryFrom<SegmentHeaderRepr<O>> for SegmentHeader {
+	type Error = FileError;
+
+	fn try_from(value: SegmentHeaderRepr<O>) -> Result<Self, Self::Error> {
+		let page_size = value.page_size.get() as usize;
+		validate_page_size(page_size)?;
+		Ok(Self {
+			page_size,
+			checksum: ChecksumAlgorithm::from_byte(value.checksum)?,
+		})
+	}
+}
+
+impl<O: byteorder::ByteOrder> Repr<SegmentHeader> for SegmentHeaderRepr<O> {
+	type Error = FileError;
+}
+
+/// The segment header of version 2 segments.
+#[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
+#[repr(C, packed)]
+struct Crc16SegmentHeaderRepr<O: byteorder::ByteOrder = LittleEndian> {
+	page_size: U32<O>,
+}
+
+impl<O: byteorder::ByteOrder> From<SegmentHeader> for Crc16SegmentHeaderRepr<O> {
+	fn from(value: SegmentHeader) -> Self {
+		debug_assert_eq!(value.checksum, ChecksumAlgorithm::Crc16);
+		Self {
+			page_size: u32::try_from(value.page_size)
+				.expect("Page size must be 32-bit!")
+				.into(),
+		}
+	}
+}
+
+impl<O: byteorder::ByteOrder> TryFrom<Crc16SegmentHeaderRepr<O>> for SegmentHeader {
+	type Error = FileError;
+
+	fn try_from(value: Crc16SegmentHeaderRepr<O>) -> Result<Self, Self::Error> {
+		let page_size = value.page_size.get() as usize;
+		validate_page_size(page_size)?;
+		Ok(Self {
+			page_size,
+			checksum: ChecksumAlgorithm::Crc16,
+		})
+	}
+}
+
+impl<O: byteorder::ByteOrder> Repr<SegmentHeader> for Crc16SegmentHeaderRepr<O> {
+	type Error = FileError;
+}
+
+pub(crate) fn validate_page_size(page_size: usize) -> Result<(), FileError> {
+	if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+		return Err(FileError::InvalidPageSize(page_size));
+	}
+	Ok(())
+}
 
-// 2 GiB when PAGE_SIZE = 32 KiB
-const SEGMENT_SIZE: usize = PAGE_SIZE << 16;
+/// Makes sure that segments consist of whole pages, no more than 16-bit page
+/// numbers can address, and that they also grow by whole pages.
+pub(crate) fn validate_segment_sizes(config: &SegmentConfig) -> Result<(), FileError> {
+	let page_size = config.page_size;
+	let max_segment_size = config.max_segment_size();
+	// The first page holds the segment header, so there must be room for at
+	// least one more
+	if max_segment_size < 2 * page_size
+		|| !max_segment_size.is_multiple_of(page_size)
+		|| max_segment_size > page_size << 16
+	{
+		return Err(FileError::InvalidSegmentSize(max_segment_size));
+	}
+	if config.extent_size == 0 || !config.extent_size.is_multiple_of(page_size) {
+		return Err(FileError::InvalidExtentSize(config.extent_size));
+	}
+	Ok(())
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct InitPageHeader {
 	wal_index: WalIndex,
-	crc: u16,
+	checksum: u64,
+	compressed: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -43,66 +227,312 @@ enum PageHeader {
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
 #[repr(C, packed)]
-struct PageHeaderRepr {
-	wal_generation: u64,
-	wal_offset: u64,
-	crc: u16,
+struct PageHeaderRepr<O: byteorder::ByteOrder = LittleEndian> {
+	wal_generation: U64<O>,
+	wal_offset: U64<O>,
+	checksum: U64<O>,
+	format_version: u8,
+}
+impl<O: byteorder::ByteOrder> Repr<PageHeader> for PageHeaderRepr<O> {
+	type Error = FileError;
+}
+
+const PAGE_FORMAT_VERSION: u8 = 2;
+
+/// Set in the format version byte of the page header if the page body is
+/// compressed. Using a bit of the existing byte keeps the header, and with it
+/// the page body, the same size.
+const PAGE_FLAG_COMPRESSED: u8 = 0b1000_0000;
+
+#[inline]
+fn page_format_byte(format_version: u8, header: &InitPageHeader) -> u8 {
+	if header.compressed {
+		format_version | PAGE_FLAG_COMPRESSED
+	} else {
+		format_version
+	}
+}
+
+impl<O: byteorder::ByteOrder> From<PageHeader> for PageHeaderRepr<O> {
+	fn from(value: PageHeader) -> Self {
+		match value {
+			PageHeader::Uninit => Self::new_zeroed(),
+			PageHeader::Init(header) => Self {
+				wal_generation: header.wal_index.generation.into(),
+				wal_offset: header.wal_index.offset.get().into(),
+				checksum: header.checksum.into(),
+				format_version: page_format_byte(PAGE_FORMAT_VERSION, &header),
+			},
+		}
+	}
+}
+
+impl<O: byteorder::ByteOrder> TryFrom<PageHeaderRepr<O>> for PageHeader {
+	type Error = FileError;
+
+	fn try_from(value: PageHeaderRepr<O>) -> Result<Self, Self::Error> {
+		init_page_header(
+			value.format_version,
+			PAGE_FORMAT_VERSION,
+			value.wal_generation.get(),
+			value.wal_offset.get(),
+			value.checksum.get(),
+		)
+	}
+}
+
+/// The page header used with CRC16 checksums, which only has room for a 16-bit
+/// checksum.
+#[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
+#[repr(C, packed)]
+struct Crc16PageHeaderRepr<O: byteorder::ByteOrder = LittleEndian> {
+	wal_generation: U64<O>,
+	wal_offset: U64<O>,
+	crc: U16<O>,
 	format_version: u8,
 }
-impl Repr<PageHeader> for PageHeaderRepr {
+impl<O: byteorder::ByteOrder> Repr<PageHeader> for Crc16PageHeaderRepr<O> {
 	type Error = FileError;
 }
 
-const PAGE_FORMAT_VERSION: u8 = 1;
+const CRC16_PAGE_FORMAT_VERSION: u8 = 1;
 
-impl From<PageHeader> for PageHeaderRepr {
+impl<O: byteorder::ByteOrder> From<PageHeader> for Crc16PageHeaderRepr<O> {
 	fn from(value: PageHeader) -> Self {
 		match value {
 			PageHeader::Uninit => Self::new_zeroed(),
 			PageHeader::Init(header) => Self {
-				wal_generation: header.wal_index.generation,
-				wal_offset: header.wal_index.offset.get(),
-				crc: header.crc,
-				format_version: PAGE_FORMAT_VERSION,
+				wal_generation: header.wal_index.generation.into(),
+				wal_offset: header.wal_index.offset.get().into(),
+				crc: u16::try_from(header.checksum)
+					.expect("CRC16 checksum must be 16-bit!")
+					.into(),
+				format_version: page_format_byte(CRC16_PAGE_FORMAT_VERSION, &header),
 			},
 		}
 	}
 }
 
-impl TryFrom<PageHeaderRepr> for PageHeader {
+impl<O: byteorder::ByteOrder> TryFrom<Crc16PageHeaderRepr<O>> for PageHeader {
 	type Error = FileError;
 
-	fn try_from(value: PageHeaderRepr) -> Result<Self, Self::Error> {
-		if value.format_version == FORMAT_VERSION_UNINIT {
-			return Ok(Self::Uninit);
+	fn try_from(value: Crc16PageHeaderRepr<O>) -> Result<Self, Self::Error> {
+		init_page_header(
+			value.format_version,
+			CRC16_PAGE_FORMAT_VERSION,
+			value.wal_generation.get(),
+			value.wal_offset.get(),
+			value.crc.get().into(),
+		)
+	}
+}
+
+fn init_page_header(
+	format_version: u8,
+	expected_format_version: u8,
+	wal_generation: u64,
+	wal_offset: u64,
+	checksum: u64,
+) -> Result<PageHeader, FileError> {
+	if format_version == FORMAT_VERSION_UNINIT {
+		return Ok(PageHeader::Uninit);
+	}
+
+	let compressed = format_version & PAGE_FLAG_COMPRESSED != 0;
+	let format_version = format_version & !PAGE_FLAG_COMPRESSED;
+	if format_version != expected_format_version {
+		return Err(FileError::IncompatiblePageVersion(format_version));
+	}
+	let Some(wal_offset) = NonZeroU64::new(wal_offset) else {
+		return Err(FileError::Corrupted(
+			"Found invalid WAL offset '0'".to_string(),
+		));
+	};
+	Ok(PageHeader::Init(InitPageHeader {
+		wal_index: WalIndex::new(wal_generation, wal_offset),
+		checksum,
+		compressed,
+	}))
+}
+
+/// Compressed page bodies start with the length of the compressed data.
+const COMPRESSED_LEN_SIZE: usize = size_of::<u16>();
+
+/// Holes are only punched in whole blocks of this size, since smaller holes
+/// wouldn't free any space on typical file systems.
+const HOLE_PUNCH_ALIGNMENT: u64 = 4 * KIB as u64;
+
+/// Compresses `body` into `buf`, which must be the same size as `body`. Returns
+/// the number of bytes used, or `None` if compression wouldn't save space.
+fn compress_page_body(body: &[u8], buf: &mut [u8], byte_order: ByteOrder) -> Option<usize> {
+	let compressed = lz4_flex::block::compress(body);
+	let len = COMPRESSED_LEN_SIZE + compressed.len();
+	if len >= buf.len() {
+		return None;
+	}
+	let compressed_len = u16::try_from(compressed.len()).ok()?;
+	let compressed_len = match byte_order {
+		ByteOrder::Big => compressed_len.to_be_bytes(),
+		ByteOrder::Little => compressed_len.to_le_bytes(),
+	};
+	buf[0..COMPRESSED_LEN_SIZE].copy_from_slice(&compressed_len);
+	buf[COMPRESSED_LEN_SIZE..len].copy_from_slice(&compressed);
+	Some(len)
+}
+
+/// Returns the part of a compressed page body that contains data.
+fn compressed_page_data(buf: &[u8], byte_order: ByteOrder) -> Result<&[u8], FileError> {
+	let len_bytes = buf[0..COMPRESSED_LEN_SIZE].try_into().unwrap();
+	let len = match byte_order {
+		ByteOrder::Big => u16::from_be_bytes(len_bytes),
+		ByteOrder::Little => u16::from_le_bytes(len_bytes),
+	} as usize;
+	buf.get(0..COMPRESSED_LEN_SIZE + len)
+		.ok_or_else(|| FileError::Corrupted(format!("Invalid compressed page length {len}")))
+}
+
+fn decompress_page_body(data: &[u8], buf: &mut [u8]) -> Result<(), FileError> {
+	match lz4_flex::block::decompress_into(&data[COMPRESSED_LEN_SIZE..], buf) {
+		Ok(len) if len == buf.len() => Ok(()),
+		_ => Err(FileError::Corrupted(
+			"Compressed page could not be decompressed".to_string(),
+		)),
+	}
+}
+
+/// Everything that determines how pages of a segment file are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PageFormat {
+	checksum: ChecksumAlgorithm,
+	byte_order: ByteOrder,
+}
+
+/// The page format version that pages are written in. Pages of older versions
+/// are upgraded when they are read, if a migration is registered for them.
+#[inline]
+pub(super) const fn page_format_version(checksum: ChecksumAlgorithm) -> u8 {
+	match checksum {
+		ChecksumAlgorithm::Crc16 => CRC16_PAGE_FORMAT_VERSION,
+		_ => PAGE_FORMAT_VERSION,
+	}
+}
+
+/// Returns the format version of a raw page, which is always stored in the last
+/// byte of the page header.
+#[inline]
+fn raw_page_format_version(page: &[u8], checksum: ChecksumAlgorithm) -> u8 {
+	page[page_header_size(checksum) - 1] & !PAGE_FLAG_COMPRESSED
+}
+
+#[inline]
+const fn page_header_size(checksum: ChecksumAlgorithm) -> usize {
+	match checksum {
+		ChecksumAlgorithm::Crc16 => <Crc16PageHeaderRepr>::SIZE,
+		_ => <PageHeaderRepr>::SIZE,
+	}
+}
+
+fn read_page_header(format: PageFormat, buf: &[u8]) -> Result<PageHeader, FileError> {
+	with_byte_order!(format.byte_order, O => match format.checksum {
+		ChecksumAlgorithm::Crc16 => Crc16PageHeaderRepr::<O>::from_bytes(buf),
+		_ => PageHeaderRepr::<O>::from_bytes(buf),
+	})
+}
+
+fn write_page_header(format: PageFormat, header: PageHeader, buf: &mut [u8]) {
+	with_byte_order!(format.byte_order, O => match format.checksum {
+		ChecksumAlgorithm::Crc16 => {
+			buf.copy_from_slice(Crc16PageHeaderRepr::<O>::from(header).as_bytes())
 		}
+		_ => buf.copy_from_slice(PageHeaderRepr::<O>::from(header).as_bytes()),
+	})
+}
+
+#[inline]
+pub(crate) const fn page_body_size(page_size: usize, checksum: ChecksumAlgorithm) -> usize {
+	page_size - page_header_size(checksum)
+}
+
+pub(crate) const DEFAULT_PAGE_BODY_SIZE: usize =
+	page_body_size(DEFAULT_PAGE_SIZE, DEFAULT_CHECKSUM_ALGORITHM);
+
+/// Statistics about the pages written to a segment file since it was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SegmentStats {
+	pub pages_written: u64,
+	pub compressed_pages_written: u64,
+	/// The total size of the written page bodies before compression
+	pub body_bytes_written: u64,
+	/// The total size of the written page bodies as they were stored
+	pub body_bytes_stored: u64,
+	/// The number of writes issued to the file. This is lower than
+	/// `pages_written` if adjacent pages were merged into vectored writes.
+	pub write_calls: u64,
+}
 
-		if value.format_version != PAGE_FORMAT_VERSION {
-			return Err(FileError::IncompatiblePageVersion(value.format_version));
+impl SegmentStats {
+	/// The ratio between the original and the stored size of the written page
+	/// bodies. This is 1.0 if nothing was compressed.
+	pub fn compression_ratio(&self) -> f64 {
+		if self.body_bytes_stored == 0 {
+			return 1.0;
 		}
-		let Some(wal_offset) = NonZeroU64::new(value.wal_offset) else {
-			return Err(FileError::Corrupted(
-				"Found invalid WAL offset '0'".to_string(),
-			));
-		};
-		Ok(Self::Init(InitPageHeader {
-			wal_index: WalIndex::new(value.wal_generation, wal_offset),
-			crc: value.crc,
-		}))
+		self.body_bytes_written as f64 / self.body_bytes_stored as f64
 	}
 }
 
-pub(crate) const PAGE_BODY_SIZE: usize = PAGE_SIZE - PageHeaderRepr::SIZE;
+#[derive(Debug, Default)]
+struct AtomicSegmentStats {
+	pages_written: AtomicU64,
+	compressed_pages_written: AtomicU64,
+	body_bytes_written: AtomicU64,
+	body_bytes_stored: AtomicU64,
+	write_calls: AtomicU64,
+}
+
+impl AtomicSegmentStats {
+	fn record(&self, op: &RawWriteOp) {
+		self.pages_written.fetch_add(1, Ordering::Relaxed);
+		if op.compressed {
+			self.compressed_pages_written
+				.fetch_add(1, Ordering::Relaxed);
+		}
+		self.body_bytes_written
+			.fetch_add(op.body_len as u64, Ordering::Relaxed);
+		self.body_bytes_stored
+			.fetch_add(op.stored_body_len as u64, Ordering::Relaxed);
+	}
+
+	fn get(&self) -> SegmentStats {
+		SegmentStats {
+			pages_written: self.pages_written.load(Ordering::Relaxed),
+			compressed_pages_written: self.compressed_pages_written.load(Ordering::Relaxed),
+			body_bytes_written: self.body_bytes_written.load(Ordering::Relaxed),
+			body_bytes_stored: self.body_bytes_stored.load(Ordering::Relaxed),
+			write_calls: self.write_calls.load(Ordering::Relaxed),
+		}
+	}
+}
 
 pub(crate) struct SegmentFile {
 	file: File,
+	page_size: usize,
+	format: PageFormat,
+	compress_pages: bool,
+	stats: AtomicSegmentStats,
+	len: AtomicU64,
+	grow_lock: Mutex<()>,
+	max_size: u64,
+	extent_size: u64,
+	read_only: bool,
+	migrations: &'static MigrationRegistry,
 }
 
 const READ_OP_ID: u64 = 1;
 const WRITE_OP_ID: u64 = 2;
 
 impl SegmentFile {
-	pub fn create_file(path: impl AsRef<Path>) -> Result<Self, FileError> {
+	pub fn create_file(path: impl AsRef<Path>, config: &SegmentConfig) -> Result<Self, FileError> {
 		let mut file = OpenOptions::new()
 			.create(true)
 			.truncate(true)
@@ -110,57 +540,248 @@ impl SegmentFile {
 			.write(true)
 			.open(path)?;
 
+		validate_page_size(config.page_size)?;
+
 		let header = GenericHeader {
+			byte_order: ByteOrder::PORTABLE,
 			file_type: FileType::Segment,
-			content_offset: u16::try_from(PAGE_SIZE).unwrap(),
+			content_offset: u16::try_from(GenericHeaderRepr::SIZE).unwrap(),
 			version: FORMAT_VERSION,
 		};
 		GenericHeaderRepr::serialize(header, &mut file)?;
+		<SegmentHeaderRepr>::serialize(
+			SegmentHeader {
+				page_size: config.page_size,
+				checksum: config.checksum,
+			},
+			&mut file,
+		)?;
 
-		file.set_len(SEGMENT_SIZE as u64)?;
+		// Only the header page is allocated up front; the rest of the segment is
+		// allocated in extents as pages are written.
+		let len = config.page_size as u64;
+		file.set_len(len)?;
 
-		Ok(Self { file })
+		Ok(Self::new(file, len, config, ByteOrder::PORTABLE, false))
 	}
 
-	pub fn open_file(path: impl AsRef<Path>) -> Result<Self, FileError> {
-		let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+	pub fn open_file(path: impl AsRef<Path>, config: &SegmentConfig) -> Result<Self, FileError> {
+		let file = OpenOptions::new().read(true).write(true).open(path)?;
+		Self::open(file, config, false)
+	}
 
-		file.seek(SeekFrom::Start(0))?;
-		let header = GenericHeaderRepr::deserialize(&mut file)?;
+	pub fn open_file_read_only(
+		path: impl AsRef<Path>,
+		config: &SegmentConfig,
+	) -> Result<Self, FileError> {
+		let file = OpenOptions::new().read(true).open(path)?;
+		Self::open(file, config, true)
+	}
 
-		if header.file_type != FileType::Segment {
-			return Err(FileError::WrongFileType(header.file_type));
+	fn open(mut file: File, config: &SegmentConfig, read_only: bool) -> Result<Self, FileError> {
+		let (header, segment_header) = read_segment_header(&mut file)?;
+		let SegmentHeader {
+			page_size,
+			checksum,
+		} = segment_header;
+		if page_size != config.page_size {
+			return Err(FileError::PageSizeMismatch {
+				expected: config.page_size,
+				found: page_size,
+			});
 		}
-		if header.version != FORMAT_VERSION {
-			return Err(FileError::IncompatibleVersion(
-				header.file_type,
-				FORMAT_VERSION,
+		if checksum != config.checksum {
+			return Err(FileError::ChecksumAlgorithmMismatch {
+				expected: config.checksum,
+				found: checksum,
+			});
+		}
+
+		// Segment files are allocated lazily, so anything shorter than the maximum
+		// size is fine as long as the header page is intact. Pages past the end of
+		// the file read as uninitialized.
+		let len = file.metadata()?.len();
+		if len < page_size as u64 {
+			return Err(FileError::Corrupted(
+				"Storage segment has been truncated".to_string(),
 			));
 		}
-		if header.content_offset as usize != PAGE_SIZE {
+		if len > config.max_segment_size() as u64 {
 			return Err(FileError::Corrupted(format!(
-				"Expected content offset {PAGE_SIZE}, but found {}",
-				header.content_offset
+				"Storage segment has size {len}, which exceeds the maximum segment size of {}",
+				config.max_segment_size()
 			)));
 		}
-		if file.metadata()?.len() != SEGMENT_SIZE as u64 {
-			return Err(FileError::Corrupted(
-				"Storage segment has been truncated".to_string(),
-			));
+
+		Ok(Self::new(file, len, config, header.byte_order, read_only))
+	}
+
+	fn new(
+		file: File,
+		len: u64,
+		config: &SegmentConfig,
+		byte_order: ByteOrder,
+		read_only: bool,
+	) -> Self {
+		let page_size = config.page_size as u64;
+		Self {
+			file,
+			page_size: config.page_size,
+			format: PageFormat {
+				checksum: config.checksum,
+				byte_order,
+			},
+			compress_pages: config.compress_pages,
+			stats: AtomicSegmentStats::default(),
+			len: AtomicU64::new(len),
+			grow_lock: Mutex::new(()),
+			max_size: u64::min(config.max_segment_size() as u64, page_size << 16),
+			extent_size: u64::max(config.extent_size as u64, page_size),
+			read_only,
+			migrations: &MIGRATIONS,
+		}
+	}
+
+	/// Upgrades a raw page that was read from disk to the current page format, if
+	/// it was written in an older one. The upgraded page is only stored the next
+	/// time the page is written.
+	fn upgrade_page(&self, page: &mut [u8]) -> Result<(), FileError> {
+		let version = raw_page_format_version(page, self.format.checksum);
+		let current_version = page_format_version(self.format.checksum);
+		if version == FORMAT_VERSION_UNINIT || version == current_version {
+			return Ok(());
+		}
+		self.migrations
+			.migrate_page(page, version, current_version, self.format.byte_order)
+	}
+
+	/// Upgrades all pages of the segment that were written in an older page
+	/// format, and returns how many there were. If `dry_run` is set, the pages
+	/// are only counted.
+	pub fn upgrade_pages(
+		&self,
+		migrations: &MigrationRegistry,
+		dry_run: bool,
+	) -> Result<usize, FileError> {
+		if self.read_only && !dry_run {
+			return Err(FileError::ReadOnly);
+		}
+
+		let current_version = page_format_version(self.format.checksum);
+		let num_pages = self.len.load(Ordering::Acquire) / self.page_size as u64;
+		let mut page_buf = vec![0; self.page_size];
+		let mut num_upgraded = 0;
+		for page_num in 1..num_pages {
+			let offset = page_num * self.page_size as u64;
+			self.read_exact_at(&mut RawReadOp::new(offset, &mut page_buf, self.format))?;
+			let version = raw_page_format_version(&page_buf, self.format.checksum);
+			if version == FORMAT_VERSION_UNINIT || version == current_version {
+				continue;
+			}
+			if dry_run {
+				migrations.check_page_migration(version, current_version)?;
+			} else {
+				migrations.migrate_page(
+					&mut page_buf,
+					version,
+					current_version,
+					self.format.byte_order,
+				)?;
+				os::unix::fs::FileExt::write_all_at(&self.file, &page_buf, offset)?;
+			}
+			num_upgraded += 1;
+		}
+		if !dry_run {
+			self.file.sync_data()?;
+		}
+		Ok(num_upgraded)
+	}
+
+	#[inline]
+	fn page_offset(&self, page_num: NonZeroU16) -> u64 {
+		page_num.get() as u64 * self.page_size as u64
+	}
+
+	fn check_bounds(&self, page_num: NonZeroU16) -> Result<(), FileError> {
+		if self.page_offset(page_num) + self.page_size as u64 > self.max_size {
+			return Err(FileError::PageOutOfRange(page_num.get()));
+		}
+		Ok(())
+	}
+
+	/// Makes sure that the file is at least `min_len` bytes long, extending it by
+	/// whole extents if it isn't.
+	fn ensure_len(&self, min_len: u64) -> Result<(), FileError> {
+		if self.len.load(Ordering::Acquire) >= min_len {
+			return Ok(());
 		}
 
-		Ok(Self { file })
+		let _guard = self.grow_lock.lock();
+		let len = self.len.load(Ordering::Acquire);
+		if len >= min_len {
+			return Ok(());
+		}
+
+		let new_len = u64::min(min_len.next_multiple_of(self.extent_size), self.max_size);
+		allocate(&self.file, len, new_len)?;
+		self.len.store(new_len, Ordering::Release);
+		Ok(())
 	}
 
 	#[cfg(unix)]
 	fn read_exact_at(&self, op: &mut RawReadOp) -> Result<(), FileError> {
-		os::unix::fs::FileExt::read_exact_at(&self.file, op.buf, op.offset)?;
+		let mut num_read: usize = 0;
+		while num_read < op.buf.len() {
+			match os::unix::fs::FileExt::read_at(
+				&self.file,
+				&mut op.buf[num_read..],
+				op.offset + num_read as u64,
+			) {
+				Ok(0) => break,
+				Ok(n) => num_read += n,
+				Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+				Err(err) => return Err(err.into()),
+			}
+		}
+		// Everything past the end of the file has never been written to.
+		op.buf[num_read..].fill(0);
 		Ok(())
 	}
 
 	#[cfg(unix)]
 	fn write_all_at(&self, op: &RawWriteOp) -> Result<(), FileError> {
+		self.ensure_len(op.offset + self.page_size as u64)?;
 		os::unix::fs::FileExt::write_all_at(&self.file, op.buf, op.offset)?;
+		self.stats.write_calls.fetch_add(1, Ordering::Relaxed);
+		self.complete_write(op)
+	}
+
+	/// Writes a run of pages that directly follow each other in the file with a
+	/// single vectored write.
+	#[cfg(all(unix, not(feature = "io_uring")))]
+	fn write_run(&self, run: &[&RawWriteOp]) -> Result<(), FileError> {
+		let (Some(first), Some(last)) = (run.first(), run.last()) else {
+			return Ok(());
+		};
+		self.ensure_len(last.offset + self.page_size as u64)?;
+		let mut bufs: Vec<io::IoSlice> = run.iter().map(|op| io::IoSlice::new(op.buf)).collect();
+		write_all_vectored_at(&self.file, &mut bufs, first.offset)?;
+		self.stats.write_calls.fetch_add(1, Ordering::Relaxed);
+		for op in run {
+			self.complete_write(op)?;
+		}
+		Ok(())
+	}
+
+	/// Frees the unused space at the end of the page if only part of it was
+	/// written, and records the write in the segment statistics.
+	fn complete_write(&self, op: &RawWriteOp) -> Result<(), FileError> {
+		let hole_start = (op.offset + op.buf.len() as u64).next_multiple_of(HOLE_PUNCH_ALIGNMENT);
+		let hole_end = op.offset + self.page_size as u64;
+		if hole_start < hole_end {
+			punch_hole(&self.file, hole_start, hole_end - hole_start)?;
+		}
+		self.stats.record(op);
 		Ok(())
 	}
 
@@ -177,8 +798,14 @@ impl SegmentFile {
 
 		let mut ring = IoUring::new(queue_size)?;
 
+		for op in ops.iter() {
+			if let RawIoOp::Write(write_op) = op {
+				self.ensure_len(write_op.offset + self.page_size as u64)?;
+			}
+		}
+
 		let mut queue = ring.submission();
-		for op in ops {
+		for op in ops.iter_mut() {
 			let entry = op.as_cqueue_entry(&self.file);
 			unsafe { queue.push(&entry) }?;
 		}
@@ -201,67 +828,287 @@ impl SegmentFile {
 			}
 		}
 
+		for op in ops.iter() {
+			if let RawIoOp::Write(write_op) = op {
+				self.stats.write_calls.fetch_add(1, Ordering::Relaxed);
+				self.complete_write(write_op)?;
+			}
+		}
+
 		Ok(())
 	}
 
+	/// Executes the operations in order. Consecutive writes to adjacent pages are
+	/// merged into vectored writes, so callers should sort their writes by page
+	/// number.
 	#[cfg(not(feature = "io_uring"))]
 	fn exec_batch(&self, ops: &mut [RawIoOp]) -> Result<(), FileError> {
-		for op in ops {
+		let mut run: Vec<&RawWriteOp> = Vec::new();
+		for op in ops.iter_mut() {
 			match op {
-				RawIoOp::Read(read_op) => self.read_exact_at(read_op)?,
-				RawIoOp::Write(write_op) => self.write_all_at(write_op)?,
+				RawIoOp::Read(read_op) => {
+					self.write_run(&run)?;
+					run.clear();
+					self.read_exact_at(read_op)?;
+				}
+				RawIoOp::Write(write_op) => {
+					// A page can only be followed directly by the next one if it fills its
+					// whole slot, which compressed pages don't.
+					let adjacent = run
+						.last()
+						.is_some_and(|last| last.offset + last.buf.len() as u64 == write_op.offset);
+					if !adjacent || run.len() >= MAX_WRITE_RUN_LEN {
+						self.write_run(&run)?;
+						run.clear();
+					}
+					run.push(write_op);
+				}
 			}
 		}
-		Ok(())
+		self.write_run(&run)
 	}
 
 	#[cfg(not(unix))]
 	compile_error!("Functionality not implemented on this platform!");
 }
 
-#[inline]
-fn get_page_offset(page_num: NonZeroU16) -> u64 {
-	page_num.get() as u64 * PAGE_SIZE as u64
+/// Reads the generic header and the segment header of a segment file of any
+/// supported version.
+fn read_segment_header(file: &mut File) -> Result<(GenericHeader, SegmentHeader), FileError> {
+	file.seek(SeekFrom::Start(0))?;
+	let header = GenericHeaderRepr::deserialize(&mut *file)?;
+
+	if header.file_type != FileType::Segment {
+		return Err(FileError::WrongFileType(header.file_type));
+	}
+
+	let segment_header = match header.version {
+		LEGACY_FORMAT_VERSION => {
+			if header.content_offset as usize != LEGACY_PAGE_SIZE {
+				return Err(FileError::Corrupted(format!(
+					"Expected content offset {LEGACY_PAGE_SIZE}, but found {}",
+					header.content_offset
+				)));
+			}
+			SegmentHeader {
+				page_size: LEGACY_PAGE_SIZE,
+				checksum: ChecksumAlgorithm::Crc16,
+			}
+		}
+		CRC16_FORMAT_VERSION => {
+			file.seek(SeekFrom::Start(header.content_offset.into()))?;
+			with_byte_order!(header.byte_order, O => {
+				Crc16SegmentHeaderRepr::<O>::deserialize(&mut *file)?
+			})
+		}
+		FORMAT_VERSION => {
+			file.seek(SeekFrom::Start(header.content_offset.into()))?;
+			with_byte_order!(header.byte_order, O => {
+				SegmentHeaderRepr::<O>::deserialize(&mut *file)?
+			})
+		}
+		version => return Err(FileError::IncompatibleVersion(header.file_type, version)),
+	};
+	Ok((header, segment_header))
 }
 
-#[derive(Debug)]
-struct RawReadOp<'a> {
-	offset: u64,
-	buf: &'a mut [u8],
+/// Rewrites the header of a segment file of an older version in the current
+/// format. The pages themselves are left untouched, since their format doesn't
+/// depend on the segment version.
+pub(super) fn upgrade_segment_header(path: &Path) -> Result<(), FileError> {
+	let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+	let (header, segment_header) = read_segment_header(&mut file)?;
+
+	file.seek(SeekFrom::Start(0))?;
+	GenericHeaderRepr::serialize(
+		GenericHeader {
+			byte_order: header.byte_order,
+			file_type: FileType::Segment,
+			content_offset: u16::try_from(GenericHeaderRepr::SIZE).unwrap(),
+			version: FORMAT_VERSION,
+		},
+		&mut file,
+	)?;
+	with_byte_order!(header.byte_order, O => {
+		SegmentHeaderRepr::<O>::serialize(segment_header, &mut file)?
+	});
+	file.sync_all()?;
+	Ok(())
 }
 
-impl<'a> RawReadOp<'a> {
-	fn new(op: &SegmentReadOp, buf: &'a mut [u8]) -> Self {
-		debug_assert_eq!(buf.len(), PAGE_SIZE);
-		Self {
-			offset: get_page_offset(op.page_num),
-			buf,
-		}
+/// Extends `file` from `len` to `new_len` bytes, preferring to actually reserve
+/// the space on disk where that is supported.
+#[cfg(target_os = "linux")]
+fn allocate(file: &File, len: u64, new_len: u64) -> Result<(), FileError> {
+	use std::os::fd::AsRawFd;
+
+	let (Ok(offset), Ok(size)) = (i64::try_from(len), i64::try_from(new_len - len)) else {
+		return Err(FileError::Unexpected);
+	};
+
+	// Safety: the file descriptor is valid for the lifetime of `file`.
+	if unsafe { libc::fallocate(file.as_raw_fd(), 0, offset, size) } == 0 {
+		return Ok(());
 	}
 
-	fn complete(&self, op: &mut SegmentReadOp) -> Result<(), FileError> {
-		debug_assert_eq!(op.buf.len(), PAGE_BODY_SIZE);
+	let error = io::Error::last_os_error();
+	if error.raw_os_error() != Some(libc::EOPNOTSUPP) {
+		return Err(error.into());
+	}
 
-		let header = PageHeaderRepr::from_bytes(&self.buf[0..PageHeaderRepr::SIZE])?;
-		let PageHeader::Init(header) = header else {
-			op.buf.fill(0);
-			*op.wal_index = None;
-			return Ok(());
-		};
+	// The file system doesn't support fallocate, so we settle for a sparse
+	// extension.
+	file.set_len(new_len)?;
+	Ok(())
+}
 
-		let body = &self.buf[PageHeaderRepr::SIZE..];
+#[cfg(not(target_os = "linux"))]
+fn allocate(file: &File, _len: u64, new_len: u64) -> Result<(), FileError> {
+	file.set_len(new_len)?;
+	Ok(())
+}
 
-		let crc = CRC16.checksum(body);
-		if header.crc != crc {
-			return Err(FileError::ChecksumMismatch);
+/// Writes all of `bufs` to `file` at `offset`, like `write_all_at` does for a
+/// single buffer.
+#[cfg(all(target_os = "linux", not(feature = "io_uring")))]
+fn write_all_vectored_at(
+	file: &File,
+	mut bufs: &mut [io::IoSlice],
+	mut offset: u64,
+) -> Result<(), FileError> {
+	use std::os::fd::AsRawFd;
+
+	while !bufs.is_empty() {
+		let (Ok(file_offset), Ok(num_bufs)) = (i64::try_from(offset), i32::try_from(bufs.len()))
+		else {
+			return Err(FileError::Unexpected);
+		};
+
+		// Safety: `IoSlice` is ABI compatible with `iovec` on Unix, and the file
+		// descriptor is valid for the lifetime of `file`.
+		let result = unsafe {
+			libc::pwritev(
+				file.as_raw_fd(),
+				bufs.as_ptr().cast(),
+				num_bufs,
+				file_offset,
+			)
+		};
+		let Ok(num_written) = usize::try_from(result) else {
+			let error = io::Error::last_os_error();
+			if error.kind() == io::ErrorKind::Interrupted {
+				continue;
+			}
+			return Err(error.into());
+		};
+		if num_written == 0 {
+			return Err(io::Error::from(io::ErrorKind::WriteZero).into());
 		}
 
-		*op.wal_index = Some(header.wal_index);
-		op.buf.copy_from_slice(body);
-		Ok(())
+		offset += num_written as u64;
+		io::IoSlice::advance_slices(&mut bufs, num_written);
 	}
+	Ok(())
+}
 
-	#[cfg(feature = "io_uring")]
+#[cfg(all(unix, not(target_os = "linux"), not(feature = "io_uring")))]
+fn write_all_vectored_at(
+	file: &File,
+	bufs: &mut [io::IoSlice],
+	offset: u64,
+) -> Result<(), FileError> {
+	let mut offset = offset;
+	for buf in bufs.iter() {
+		os::unix::fs::FileExt::write_all_at(file, buf, offset)?;
+		offset += buf.len() as u64;
+	}
+	Ok(())
+}
+
+/// Deallocates the given range of `file`, which then reads as zeros. This is
+/// only an optimization, so file systems that don't support it are ignored.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64, len: u64) -> Result<(), FileError> {
+	use std::os::fd::AsRawFd;
+
+	let (Ok(offset), Ok(len)) = (i64::try_from(offset), i64::try_from(len)) else {
+		return Err(FileError::Unexpected);
+	};
+
+	// Safety: the file descriptor is valid for the lifetime of `file`.
+	let result = unsafe {
+		libc::fallocate(
+			file.as_raw_fd(),
+			libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+			offset,
+			len,
+		)
+	};
+	if result == 0 {
+		return Ok(());
+	}
+
+	let error = io::Error::last_os_error();
+	if error.raw_os_error() != Some(libc::EOPNOTSUPP) {
+		return Err(error.into());
+	}
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &File, _offset: u64, _len: u64) -> Result<(), FileError> {
+	Ok(())
+}
+
+#[derive(Debug)]
+struct RawReadOp<'a> {
+	offset: u64,
+	buf: &'a mut [u8],
+	format: PageFormat,
+}
+
+impl<'a> RawReadOp<'a> {
+	fn new(offset: u64, buf: &'a mut [u8], format: PageFormat) -> Self {
+		Self {
+			offset,
+			buf,
+			format,
+		}
+	}
+
+	fn complete(&self, op: &mut SegmentReadOp) -> Result<(), FileError> {
+		let checksum = self.format.checksum;
+		debug_assert_eq!(op.buf.len(), page_body_size(self.buf.len(), checksum));
+
+		let header_size = page_header_size(checksum);
+		let header = read_page_header(self.format, &self.buf[0..header_size])?;
+		let PageHeader::Init(header) = header else {
+			op.buf.fill(0);
+			*op.wal_index = None;
+			return Ok(());
+		};
+
+		let body = &self.buf[header_size..];
+		let stored_body = if header.compressed {
+			compressed_page_data(body, self.format.byte_order)?
+		} else {
+			body
+		};
+
+		if header.checksum != checksum.checksum(stored_body) {
+			return Err(FileError::ChecksumMismatch);
+		}
+
+		if header.compressed {
+			decompress_page_body(stored_body, op.buf)?;
+		} else {
+			op.buf.copy_from_slice(body);
+		}
+		*op.wal_index = Some(header.wal_index);
+		Ok(())
+	}
+
+	#[cfg(feature = "io_uring")]
 	fn as_opcode(&mut self, fd: &File) -> opcode::Read {
 		use std::os::fd::AsRawFd;
 
@@ -278,25 +1125,50 @@ impl<'a> RawReadOp<'a> {
 struct RawWriteOp<'a> {
 	offset: u64,
 	buf: &'a [u8],
+	compressed: bool,
+	body_len: usize,
+	stored_body_len: usize,
 }
 
 impl<'a> RawWriteOp<'a> {
-	fn new(op: &SegmentWriteOp, buf: &'a mut [u8]) -> Self {
-		debug_assert_eq!(op.buf.len(), PAGE_BODY_SIZE);
-		debug_assert_eq!(buf.len(), PAGE_SIZE);
+	fn new(
+		op: &SegmentWriteOp,
+		offset: u64,
+		buf: &'a mut [u8],
+		format: PageFormat,
+		compress: bool,
+	) -> Self {
+		let checksum = format.checksum;
+		debug_assert_eq!(op.buf.len(), page_body_size(buf.len(), checksum));
+
+		let header_size = page_header_size(checksum);
+		let body_buf = &mut buf[header_size..];
+		let compressed_len = if compress {
+			compress_page_body(op.buf, body_buf, format.byte_order)
+		} else {
+			None
+		};
+		let stored_body_len = match compressed_len {
+			Some(len) => len,
+			None => {
+				body_buf.copy_from_slice(op.buf);
+				op.buf.len()
+			}
+		};
 
-		let crc = CRC16.checksum(op.buf);
 		let header = PageHeader::Init(InitPageHeader {
 			wal_index: op.wal_index,
-			crc,
+			checksum: checksum.checksum(&body_buf[0..stored_body_len]),
+			compressed: compressed_len.is_some(),
 		});
-
-		buf[0..PageHeaderRepr::SIZE].copy_from_slice(PageHeaderRepr::from(header).as_bytes());
-		buf[PageHeaderRepr::SIZE..].copy_from_slice(op.buf);
+		write_page_header(format, header, &mut buf[0..header_size]);
 
 		Self {
-			offset: get_page_offset(op.page_num),
-			buf,
+			offset,
+			buf: &buf[0..header_size + stored_body_len],
+			compressed: compressed_len.is_some(),
+			body_len: op.buf.len(),
+			stored_body_len,
 		}
 	}
 
@@ -320,10 +1192,18 @@ enum RawIoOp<'a> {
 }
 
 impl<'a> RawIoOp<'a> {
-	fn new(op: &SegmentOp, buf: &'a mut [u8]) -> Self {
+	fn new(
+		op: &SegmentOp,
+		offset: u64,
+		buf: &'a mut [u8],
+		format: PageFormat,
+		compress: bool,
+	) -> Self {
 		match op {
-			SegmentOp::Read(read_op) => Self::Read(RawReadOp::new(read_op, buf)),
-			SegmentOp::Write(write_op) => Self::Write(RawWriteOp::new(write_op, buf)),
+			SegmentOp::Read(..) => Self::Read(RawReadOp::new(offset, buf, format)),
+			SegmentOp::Write(write_op) => {
+				Self::Write(RawWriteOp::new(write_op, offset, buf, format, compress))
+			}
 		}
 	}
 
@@ -372,43 +1252,99 @@ pub(crate) trait SegmentFileApi {
 	fn read<'a>(&self, op: SegmentReadOp<'a>) -> Result<(), FileError>;
 	fn write<'a>(&self, op: SegmentWriteOp<'a>) -> Result<(), FileError>;
 	fn batch<'a>(&self, ops: &mut [SegmentOp<'a>]) -> Result<(), FileError>;
+	/// Makes all pages written so far durable.
+	fn sync(&self) -> Result<(), FileError>;
+	fn stats(&self) -> SegmentStats;
 }
 
 impl SegmentFileApi for SegmentFile {
 	fn read(&self, mut op: SegmentReadOp) -> Result<(), FileError> {
-		debug_assert_eq!(op.buf.len(), PAGE_BODY_SIZE);
+		debug_assert_eq!(
+			op.buf.len(),
+			page_body_size(self.page_size, self.format.checksum)
+		);
+		self.check_bounds(op.page_num)?;
 
-		let mut page_buf = [0; PAGE_SIZE];
-		let mut raw_op = RawReadOp::new(&op, &mut page_buf);
+		let mut page_buf = vec![0; self.page_size];
+		let mut raw_op = RawReadOp::new(self.page_offset(op.page_num), &mut page_buf, self.format);
 		self.read_exact_at(&mut raw_op)?;
+		self.upgrade_page(raw_op.buf)?;
 		raw_op.complete(&mut op)
 	}
 
 	fn write(&self, op: SegmentWriteOp) -> Result<(), FileError> {
-		debug_assert_eq!(op.buf.len(), PAGE_BODY_SIZE);
-
-		let mut page_buf = [0; PAGE_SIZE];
-		let raw_op = RawWriteOp::new(&op, &mut page_buf);
+		debug_assert_eq!(
+			op.buf.len(),
+			page_body_size(self.page_size, self.format.checksum)
+		);
+		if self.read_only {
+			return Err(FileError::ReadOnly);
+		}
+		self.check_bounds(op.page_num)?;
+
+		let mut page_buf = vec![0; self.page_size];
+		let raw_op = RawWriteOp::new(
+			&op,
+			self.page_offset(op.page_num),
+			&mut page_buf,
+			self.format,
+			self.compress_pages,
+		);
 		self.write_all_at(&raw_op)?;
 
 		Ok(())
 	}
 
 	fn batch(&self, ops: &mut [SegmentOp]) -> Result<(), FileError> {
-		let mut buffers = vec![[0; PAGE_SIZE]; ops.len()];
+		for op in ops.iter() {
+			match op {
+				SegmentOp::Read(read_op) => self.check_bounds(read_op.page_num)?,
+				SegmentOp::Write(..) if self.read_only => return Err(FileError::ReadOnly),
+				SegmentOp::Write(write_op) => self.check_bounds(write_op.page_num)?,
+			}
+		}
+
+		let mut buffers = vec![0; ops.len() * self.page_size];
 		let mut raw_ops: Vec<RawIoOp> = Vec::with_capacity(ops.len());
-		for (op, buf) in ops.iter().zip(buffers.iter_mut()) {
-			raw_ops.push(RawIoOp::new(op, buf));
+		for (op, buf) in ops.iter().zip(buffers.chunks_exact_mut(self.page_size)) {
+			let page_num = match op {
+				SegmentOp::Read(read_op) => read_op.page_num,
+				SegmentOp::Write(write_op) => write_op.page_num,
+			};
+			raw_ops.push(RawIoOp::new(
+				op,
+				self.page_offset(page_num),
+				buf,
+				self.format,
+				self.compress_pages,
+			));
 		}
 
 		self.exec_batch(&mut raw_ops)?;
 
+		for raw_op in raw_ops.iter_mut() {
+			if let RawIoOp::Read(read_op) = raw_op {
+				self.upgrade_page(read_op.buf)?;
+			}
+		}
 		for (raw_op, op) in raw_ops.iter().zip(ops.iter_mut()) {
 			raw_op.complete(op)?
 		}
 
 		Ok(())
 	}
+
+	fn sync(&self) -> Result<(), FileError> {
+		if self.read_only {
+			return Err(FileError::ReadOnly);
+		}
+		self.file.sync_data()?;
+		Ok(())
+	}
+
+	fn stats(&self) -> SegmentStats {
+		self.stats.get()
+	}
 }
 
 #[cfg(test)]
@@ -422,83 +1358,484 @@ mod tests {
 		utils::test_helpers::non_zero,
 	};
 
+	use zerocopy::BigEndian;
+
+	use crate::files::migration::PageMigration;
+
 	use super::*;
 
+	const CRC16_PAGE_BODY_SIZE: usize = page_body_size(DEFAULT_PAGE_SIZE, ChecksumAlgorithm::Crc16);
+
+	fn segment_file_start(page_size: usize) -> Vec<u8> {
+		[
+			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Little,
+				file_type: FileType::Segment,
+				content_offset: GenericHeaderRepr::SIZE as u16,
+				version: FORMAT_VERSION,
+			})
+			.as_bytes(),
+			<SegmentHeaderRepr>::from(SegmentHeader {
+				page_size,
+				checksum: DEFAULT_CHECKSUM_ALGORITHM,
+			})
+			.as_bytes(),
+		]
+		.concat()
+	}
+
+	fn crc16_segment_file_start(page_size: usize) -> Vec<u8> {
+		[
+			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Little,
+				file_type: FileType::Segment,
+				content_offset: GenericHeaderRepr::SIZE as u16,
+				version: CRC16_FORMAT_VERSION,
+			})
+			.as_bytes(),
+			<Crc16SegmentHeaderRepr>::from(SegmentHeader {
+				page_size,
+				checksum: ChecksumAlgorithm::Crc16,
+			})
+			.as_bytes(),
+		]
+		.concat()
+	}
+
+	fn legacy_segment_file_start() -> Vec<u8> {
+		GenericHeaderRepr::from(GenericHeader {
+			byte_order: ByteOrder::Little,
+			file_type: FileType::Segment,
+			content_offset: LEGACY_PAGE_SIZE as u16,
+			version: LEGACY_FORMAT_VERSION,
+		})
+		.as_bytes()
+		.to_vec()
+	}
+
 	#[test]
 	fn create_segment_file() {
 		// given
 		let tempdir = tempfile::tempdir().unwrap();
 
 		// when
-		SegmentFile::create_file(tempdir.path().join("0")).unwrap();
+		SegmentFile::create_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
 
 		// then
-		let expected: Vec<u8> = GenericHeaderRepr::from(GenericHeader {
-			file_type: FileType::Segment,
-			content_offset: PAGE_SIZE as u16,
-			version: FORMAT_VERSION,
-		})
-		.as_bytes()
-		.to_vec();
+		let expected = segment_file_start(DEFAULT_PAGE_SIZE);
 
 		let mut file = File::open(tempdir.path().join("0")).unwrap();
-		let received: &mut [u8] = &mut [0; GenericHeaderRepr::SIZE];
+		let received: &mut [u8] = &mut [0; GenericHeaderRepr::SIZE + <SegmentHeaderRepr>::SIZE];
 		file.read_exact(received).unwrap();
 
 		assert_buf_eq!(received, expected);
+		assert_eq!(file.metadata().unwrap().len(), DEFAULT_PAGE_SIZE as u64);
+	}
+
+	#[test]
+	fn create_segment_file_with_page_size() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			page_size: 8 * KIB,
+			..Default::default()
+		};
+
+		// when
+		SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+
+		// then
+		let expected = segment_file_start(8 * KIB);
+
+		let mut file = File::open(tempdir.path().join("0")).unwrap();
+		let received: &mut [u8] = &mut [0; GenericHeaderRepr::SIZE + <SegmentHeaderRepr>::SIZE];
+		file.read_exact(received).unwrap();
+
+		assert_buf_eq!(received, expected);
+		assert_eq!(file.metadata().unwrap().len(), 8 * KIB as u64);
+	}
+
+	#[test]
+	fn try_create_segment_file_with_invalid_page_size() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			page_size: 3 * KIB,
+			..Default::default()
+		};
+
+		// when
+		let result = SegmentFile::create_file(tempdir.path().join("0"), &config);
+
+		// then
+		assert!(matches!(result, Err(FileError::InvalidPageSize(3072))));
 	}
 
 	#[test]
 	fn open_segment_file() {
 		// given
 		let tempdir = tempfile::tempdir().unwrap();
-		let file_start: Vec<u8> = GenericHeaderRepr::from(GenericHeader {
-			file_type: FileType::Segment,
-			content_offset: PAGE_SIZE as u16,
-			version: FORMAT_VERSION,
-		})
-		.as_bytes()
-		.to_vec();
+		let file_start = segment_file_start(DEFAULT_PAGE_SIZE);
+		let mut file = File::create(tempdir.path().join("0")).unwrap();
+		file.set_len((DEFAULT_PAGE_SIZE << 16) as u64).unwrap();
+		file.write_all(&file_start).unwrap();
+
+		// then
+		SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+	}
+
+	#[test]
+	fn try_open_segment_file_with_different_page_size() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let file_start = segment_file_start(16 * KIB);
+		let mut file = File::create(tempdir.path().join("0")).unwrap();
+		file.set_len(16 * KIB as u64).unwrap();
+		file.write_all(&file_start).unwrap();
+
+		// when
+		let result = SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default());
+
+		// then
+		assert!(matches!(
+			result,
+			Err(FileError::PageSizeMismatch {
+				expected: DEFAULT_PAGE_SIZE,
+				found: 16384
+			})
+		));
+	}
+
+	#[test]
+	fn open_legacy_segment_file() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let file_start = legacy_segment_file_start();
+		let mut file = File::create(tempdir.path().join("0")).unwrap();
+		file.set_len(2 * DEFAULT_PAGE_SIZE as u64).unwrap();
+		file.write_all(&file_start).unwrap();
+		let config = SegmentConfig {
+			checksum: ChecksumAlgorithm::Crc16,
+			..Default::default()
+		};
+
+		// when
+		let segment = SegmentFile::open_file(tempdir.path().join("0"), &config).unwrap();
+		let mut data = [25; CRC16_PAGE_BODY_SIZE];
+		let mut wal_index = Some(wal_index!(69, 420));
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(5),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, None);
+		assert_eq!(data, [0; CRC16_PAGE_BODY_SIZE]);
+	}
+
+	#[test]
+	fn read_from_crc16_page() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let mut file = File::create(tempdir.path().join("0")).unwrap();
+		file.set_len(4 * DEFAULT_PAGE_SIZE as u64).unwrap();
+		file.write_all(&crc16_segment_file_start(DEFAULT_PAGE_SIZE))
+			.unwrap();
+		file.seek(SeekFrom::Start(3 * DEFAULT_PAGE_SIZE as u64))
+			.unwrap();
+		file.write_all(
+			Crc16PageHeaderRepr::<LittleEndian> {
+				wal_generation: 69.into(),
+				wal_offset: 420.into(),
+				crc: 0x0c78.into(),
+				format_version: 1,
+			}
+			.as_bytes(),
+		)
+		.unwrap();
+		file.write_all(&[3; CRC16_PAGE_BODY_SIZE]).unwrap();
+		let config = SegmentConfig {
+			checksum: ChecksumAlgorithm::Crc16,
+			..Default::default()
+		};
+
+		// when
+		let segment = SegmentFile::open_file(tempdir.path().join("0"), &config).unwrap();
+		let mut data = [0; CRC16_PAGE_BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(3),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(69, 420)));
+		assert_eq!(data, [3; CRC16_PAGE_BODY_SIZE]);
+	}
+
+	#[test]
+	fn read_and_write_big_endian_segment_file() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let mut file = File::create(tempdir.path().join("0")).unwrap();
+		file.set_len(5 * DEFAULT_PAGE_SIZE as u64).unwrap();
+		file.write_all(
+			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Big,
+				file_type: FileType::Segment,
+				content_offset: GenericHeaderRepr::SIZE as u16,
+				version: FORMAT_VERSION,
+			})
+			.as_bytes(),
+		)
+		.unwrap();
+		file.write_all(
+			SegmentHeaderRepr::<BigEndian>::from(SegmentHeader {
+				page_size: DEFAULT_PAGE_SIZE,
+				checksum: DEFAULT_CHECKSUM_ALGORITHM,
+			})
+			.as_bytes(),
+		)
+		.unwrap();
+		file.seek(SeekFrom::Start(3 * DEFAULT_PAGE_SIZE as u64))
+			.unwrap();
+		file.write_all(
+			PageHeaderRepr::<BigEndian> {
+				wal_generation: 69.into(),
+				wal_offset: 420.into(),
+				checksum: 0x882a_b20b.into(),
+				format_version: 2,
+			}
+			.as_bytes(),
+		)
+		.unwrap();
+		file.write_all(&[3; DEFAULT_PAGE_BODY_SIZE]).unwrap();
+
+		// when
+		let segment =
+			SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(3),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(4),
+				wal_index: wal_index!(69, 421),
+				buf: &[4; DEFAULT_PAGE_BODY_SIZE],
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(69, 420)));
+		assert_eq!(data, [3; DEFAULT_PAGE_BODY_SIZE]);
+
+		let mut file = File::open(tempdir.path().join("0")).unwrap();
+		file.seek(SeekFrom::Start(4 * DEFAULT_PAGE_SIZE as u64))
+			.unwrap();
+		let mut header = [0; <PageHeaderRepr>::SIZE];
+		file.read_exact(&mut header).unwrap();
+		let header = PageHeaderRepr::<BigEndian>::from_bytes(&header).unwrap();
+		assert!(matches!(
+			header,
+			PageHeader::Init(InitPageHeader { wal_index, .. }) if wal_index == wal_index!(69, 421)
+		));
+	}
+
+	#[test]
+	fn try_open_segment_file_with_different_checksum_algorithm() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
 		let mut file = File::create(tempdir.path().join("0")).unwrap();
-		file.set_len(SEGMENT_SIZE as u64).unwrap();
+		file.set_len(DEFAULT_PAGE_SIZE as u64).unwrap();
+		file.write_all(&crc16_segment_file_start(DEFAULT_PAGE_SIZE))
+			.unwrap();
+
+		// when
+		let result = SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default());
+
+		// then
+		assert!(matches!(
+			result,
+			Err(FileError::ChecksumAlgorithmMismatch {
+				expected: DEFAULT_CHECKSUM_ALGORITHM,
+				found: ChecksumAlgorithm::Crc16
+			})
+		));
+	}
+
+	#[test]
+	fn try_read_corrupted_page() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment =
+			SegmentFile::create_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(2),
+				wal_index: wal_index!(69, 420),
+				buf: &[25; DEFAULT_PAGE_BODY_SIZE],
+			})
+			.unwrap();
+		let file = OpenOptions::new()
+			.write(true)
+			.open(tempdir.path().join("0"))
+			.unwrap();
+		os::unix::fs::FileExt::write_all_at(&file, &[0xff; 4], 3 * DEFAULT_PAGE_SIZE as u64 - 4)
+			.unwrap();
+
+		// when
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index = None;
+		let result = segment.read(SegmentReadOp {
+			page_num: non_zero!(2),
+			wal_index: &mut wal_index,
+			buf: &mut data,
+		});
+
+		// then
+		assert!(matches!(result, Err(FileError::ChecksumMismatch)));
+	}
+
+	#[test]
+	fn try_open_oversized_segment_file() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let file_start = segment_file_start(DEFAULT_PAGE_SIZE);
+		let mut file = File::create(tempdir.path().join("0")).unwrap();
+		file.set_len(8 * DEFAULT_PAGE_SIZE as u64).unwrap();
 		file.write_all(&file_start).unwrap();
 
+		// when
+		let result = SegmentFile::open_file(
+			tempdir.path().join("0"),
+			&SegmentConfig {
+				max_segment_size: Some(4 * DEFAULT_PAGE_SIZE),
+				..Default::default()
+			},
+		);
+
+		// then
+		assert!(matches!(result, Err(FileError::Corrupted(..))));
+	}
+
+	#[test]
+	fn write_extends_file_by_extent() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment = SegmentFile::create_file(
+			tempdir.path().join("0"),
+			&SegmentConfig {
+				extent_size: 4 * DEFAULT_PAGE_SIZE,
+				..Default::default()
+			},
+		)
+		.unwrap();
+
+		// when
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(5),
+				wal_index: wal_index!(69, 420),
+				buf: &[3; DEFAULT_PAGE_BODY_SIZE],
+			})
+			.unwrap();
+
+		// then
+		let file = File::open(tempdir.path().join("0")).unwrap();
+		assert_eq!(file.metadata().unwrap().len(), 8 * DEFAULT_PAGE_SIZE as u64);
+	}
+
+	#[test]
+	fn try_write_past_max_segment_size() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment = SegmentFile::create_file(
+			tempdir.path().join("0"),
+			&SegmentConfig {
+				max_segment_size: Some(4 * DEFAULT_PAGE_SIZE),
+				extent_size: 4 * DEFAULT_PAGE_SIZE,
+				..Default::default()
+			},
+		)
+		.unwrap();
+
+		// when
+		let result = segment.write(SegmentWriteOp {
+			page_num: non_zero!(4),
+			wal_index: wal_index!(69, 420),
+			buf: &[3; DEFAULT_PAGE_BODY_SIZE],
+		});
+
 		// then
-		SegmentFile::open_file(tempdir.path().join("0")).unwrap();
+		assert!(matches!(result, Err(FileError::PageOutOfRange(4))));
+	}
+
+	#[test]
+	fn try_write_to_read_only_segment_file() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		SegmentFile::create_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+		let segment =
+			SegmentFile::open_file_read_only(tempdir.path().join("0"), &SegmentConfig::default())
+				.unwrap();
+
+		// when
+		let result = segment.write(SegmentWriteOp {
+			page_num: non_zero!(3),
+			wal_index: wal_index!(69, 420),
+			buf: &[3; DEFAULT_PAGE_BODY_SIZE],
+		});
+
+		// then
+		assert!(matches!(result, Err(FileError::ReadOnly)));
 	}
 
 	#[test]
 	fn write_to_page() {
 		// given
 		let tempdir = tempfile::tempdir().unwrap();
-		let segment = SegmentFile::create_file(tempdir.path().join("0")).unwrap();
+		let segment =
+			SegmentFile::create_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
 
 		// when
 		segment
 			.write(SegmentWriteOp {
 				page_num: non_zero!(3),
 				wal_index: wal_index!(69, 420),
-				buf: &[3; PAGE_BODY_SIZE],
+				buf: &[3; DEFAULT_PAGE_BODY_SIZE],
 			})
 			.unwrap();
 
 		// then
 		let mut file = File::open(tempdir.path().join("0")).unwrap();
-		file.seek(SeekFrom::Start((3 * PAGE_SIZE) as u64)).unwrap();
-		let received: &mut [u8] = &mut [0; PAGE_SIZE];
+		file.seek(SeekFrom::Start((3 * DEFAULT_PAGE_SIZE) as u64))
+			.unwrap();
+		let received: &mut [u8] = &mut [0; DEFAULT_PAGE_SIZE];
 		file.read_exact(received).unwrap();
 
 		assert_buf_eq!(
 			received,
 			[
-				PageHeaderRepr {
-					wal_generation: 69,
-					wal_offset: 420,
-					crc: 0x0c78,
-					format_version: 1
+				PageHeaderRepr::<LittleEndian> {
+					wal_generation: 69.into(),
+					wal_offset: 420.into(),
+					checksum: 0x882a_b20b.into(),
+					format_version: 2
 				}
 				.as_bytes(),
-				&[3; PAGE_BODY_SIZE]
+				&[3; DEFAULT_PAGE_BODY_SIZE]
 			]
 			.concat()
 		);
@@ -508,17 +1845,18 @@ mod tests {
 	fn read_from_page() {
 		// given
 		let tempdir = tempfile::tempdir().unwrap();
-		let segment = SegmentFile::create_file(tempdir.path().join("0")).unwrap();
+		let segment =
+			SegmentFile::create_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
 		segment
 			.write(SegmentWriteOp {
 				page_num: non_zero!(5),
 				wal_index: wal_index!(69, 420),
-				buf: &[25; PAGE_BODY_SIZE],
+				buf: &[25; DEFAULT_PAGE_BODY_SIZE],
 			})
 			.unwrap();
 
 		// when
-		let mut data = [0; PAGE_BODY_SIZE];
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
 		let mut wal_index = None;
 		segment
 			.read(SegmentReadOp {
@@ -530,6 +1868,472 @@ mod tests {
 
 		// then
 		assert_eq!(wal_index, Some(wal_index!(69, 420)));
-		assert_eq!(data, [25; PAGE_BODY_SIZE]);
+		assert_eq!(data, [25; DEFAULT_PAGE_BODY_SIZE]);
+	}
+
+	#[test]
+	fn write_and_read_with_xxh64() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			checksum: ChecksumAlgorithm::Xxh64,
+			..Default::default()
+		};
+		let segment = SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(2),
+				wal_index: wal_index!(69, 420),
+				buf: &[25; DEFAULT_PAGE_BODY_SIZE],
+			})
+			.unwrap();
+		drop(segment);
+
+		// when
+		let segment = SegmentFile::open_file(tempdir.path().join("0"), &config).unwrap();
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(2),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(69, 420)));
+		assert_eq!(data, [25; DEFAULT_PAGE_BODY_SIZE]);
+	}
+
+	#[test]
+	fn write_and_read_compressed_page() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			compress_pages: true,
+			..Default::default()
+		};
+		let segment = SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+		let mut body = [0; DEFAULT_PAGE_BODY_SIZE];
+		body[0..4].copy_from_slice(&[1, 2, 3, 4]);
+
+		// when
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(2),
+				wal_index: wal_index!(69, 420),
+				buf: &body,
+			})
+			.unwrap();
+		let mut data = [0xff; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(2),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(69, 420)));
+		assert_buf_eq!(data, body);
+
+		let mut file = File::open(tempdir.path().join("0")).unwrap();
+		file.seek(SeekFrom::Start((2 * DEFAULT_PAGE_SIZE) as u64))
+			.unwrap();
+		let mut header = [0; <PageHeaderRepr>::SIZE];
+		file.read_exact(&mut header).unwrap();
+		let header = <PageHeaderRepr>::read_from_bytes(&header).unwrap();
+		assert_eq!(header.format_version, 2 | PAGE_FLAG_COMPRESSED);
+
+		let stats = segment.stats();
+		assert_eq!(stats.pages_written, 1);
+		assert_eq!(stats.compressed_pages_written, 1);
+		assert_eq!(stats.body_bytes_written, DEFAULT_PAGE_BODY_SIZE as u64);
+		assert!(stats.compression_ratio() > 10.0);
+	}
+
+	#[test]
+	fn compressed_pages_free_unused_space() {
+		use std::os::unix::fs::MetadataExt;
+
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let write_pages = |name: &str, compress_pages: bool| {
+			let config = SegmentConfig {
+				compress_pages,
+				..Default::default()
+			};
+			let segment = SegmentFile::create_file(tempdir.path().join(name), &config).unwrap();
+			for page_num in 1..=16 {
+				segment
+					.write(SegmentWriteOp {
+						page_num: non_zero!(page_num),
+						wal_index: wal_index!(69, 420),
+						buf: &[0; DEFAULT_PAGE_BODY_SIZE],
+					})
+					.unwrap();
+			}
+		};
+
+		// when
+		write_pages("0", false);
+		write_pages("1", true);
+
+		// then
+		let uncompressed = std::fs::metadata(tempdir.path().join("0")).unwrap();
+		let compressed = std::fs::metadata(tempdir.path().join("1")).unwrap();
+		assert_eq!(compressed.len(), uncompressed.len());
+		assert!(compressed.blocks() < uncompressed.blocks());
+	}
+
+	#[test]
+	#[cfg(not(feature = "io_uring"))]
+	fn merge_writes_to_adjacent_pages() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let segment =
+			SegmentFile::create_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+		let bodies: Vec<[u8; DEFAULT_PAGE_BODY_SIZE]> =
+			(1..=4).map(|i| [i; DEFAULT_PAGE_BODY_SIZE]).collect();
+		let page_nums = [non_zero!(1), non_zero!(2), non_zero!(3), non_zero!(5)];
+
+		// when
+		let mut ops: Vec<SegmentOp> = page_nums
+			.iter()
+			.zip(&bodies)
+			.map(|(page_num, body)| {
+				SegmentOp::Write(SegmentWriteOp {
+					page_num: *page_num,
+					wal_index: wal_index!(69, 420),
+					buf: body,
+				})
+			})
+			.collect();
+		segment.batch(&mut ops).unwrap();
+
+		// then
+		assert_eq!(segment.stats().pages_written, 4);
+		assert_eq!(segment.stats().write_calls, 2);
+		for (page_num, body) in page_nums.iter().zip(&bodies) {
+			let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+			let mut wal_index = None;
+			segment
+				.read(SegmentReadOp {
+					page_num: *page_num,
+					wal_index: &mut wal_index,
+					buf: &mut data,
+				})
+				.unwrap();
+			assert_eq!(wal_index, Some(wal_index!(69, 420)));
+			assert_buf_eq!(data, *body);
+		}
+	}
+
+	#[test]
+	fn write_and_read_compressed_pages_in_batch() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			compress_pages: true,
+			..Default::default()
+		};
+		let segment = SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+		let body_1 = [1; DEFAULT_PAGE_BODY_SIZE];
+		let body_2 = [2; DEFAULT_PAGE_BODY_SIZE];
+		segment
+			.batch(&mut [
+				SegmentOp::Write(SegmentWriteOp {
+					page_num: non_zero!(1),
+					wal_index: wal_index!(69, 420),
+					buf: &body_1,
+				}),
+				SegmentOp::Write(SegmentWriteOp {
+					page_num: non_zero!(2),
+					wal_index: wal_index!(69, 421),
+					buf: &body_2,
+				}),
+			])
+			.unwrap();
+
+		// when
+		let mut data_1 = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index_1 = None;
+		let mut data_2 = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index_2 = None;
+		segment
+			.batch(&mut [
+				SegmentOp::Read(SegmentReadOp {
+					page_num: non_zero!(1),
+					wal_index: &mut wal_index_1,
+					buf: &mut data_1,
+				}),
+				SegmentOp::Read(SegmentReadOp {
+					page_num: non_zero!(2),
+					wal_index: &mut wal_index_2,
+					buf: &mut data_2,
+				}),
+			])
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index_1, Some(wal_index!(69, 420)));
+		assert_buf_eq!(data_1, body_1);
+		assert_eq!(wal_index_2, Some(wal_index!(69, 421)));
+		assert_buf_eq!(data_2, body_2);
+		assert_eq!(segment.stats().compressed_pages_written, 2);
+	}
+
+	#[test]
+	fn store_incompressible_page_uncompressed() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			compress_pages: true,
+			..Default::default()
+		};
+		let segment = SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+		let mut body = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut state: u32 = 25;
+		for byte in &mut body {
+			state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+			*byte = (state >> 24) as u8;
+		}
+
+		// when
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(1),
+				wal_index: wal_index!(69, 420),
+				buf: &body,
+			})
+			.unwrap();
+
+		// then
+		let mut file = File::open(tempdir.path().join("0")).unwrap();
+		file.seek(SeekFrom::Start(DEFAULT_PAGE_SIZE as u64))
+			.unwrap();
+		let received: &mut [u8] = &mut [0; DEFAULT_PAGE_SIZE];
+		file.read_exact(received).unwrap();
+		let header =
+			<PageHeaderRepr>::read_from_bytes(&received[0..<PageHeaderRepr>::SIZE]).unwrap();
+		assert_eq!(header.format_version, 2);
+		assert_buf_eq!(&received[<PageHeaderRepr>::SIZE..], body);
+
+		let stats = segment.stats();
+		assert_eq!(stats.compressed_pages_written, 0);
+		assert_eq!(stats.compression_ratio(), 1.0);
+	}
+
+	#[test]
+	fn try_read_corrupted_compressed_page() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			compress_pages: true,
+			..Default::default()
+		};
+		let segment = SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(1),
+				wal_index: wal_index!(69, 420),
+				buf: &[0; DEFAULT_PAGE_BODY_SIZE],
+			})
+			.unwrap();
+		let mut file = OpenOptions::new()
+			.write(true)
+			.open(tempdir.path().join("0"))
+			.unwrap();
+		file.seek(SeekFrom::Start(
+			(DEFAULT_PAGE_SIZE + <PageHeaderRepr>::SIZE) as u64,
+		))
+		.unwrap();
+		file.write_all(&[0xff, 0xff]).unwrap();
+
+		// when
+		let result = segment.read(SegmentReadOp {
+			page_num: non_zero!(1),
+			wal_index: &mut None,
+			buf: &mut [0; DEFAULT_PAGE_BODY_SIZE],
+		});
+
+		// then
+		assert!(matches!(result, Err(FileError::Corrupted(..))));
+	}
+
+	#[test]
+	fn write_and_read_with_small_pages() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let config = SegmentConfig {
+			page_size: 4 * KIB,
+			..Default::default()
+		};
+		let segment = SegmentFile::create_file(tempdir.path().join("0"), &config).unwrap();
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(2),
+				wal_index: wal_index!(69, 420),
+				buf: &[25; page_body_size(4 * KIB, DEFAULT_CHECKSUM_ALGORITHM)],
+			})
+			.unwrap();
+
+		// when
+		let mut data = [0; page_body_size(4 * KIB, DEFAULT_CHECKSUM_ALGORITHM)];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(2),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(69, 420)));
+		assert_eq!(
+			data,
+			[25; page_body_size(4 * KIB, DEFAULT_CHECKSUM_ALGORITHM)]
+		);
+
+		let file = File::open(tempdir.path().join("0")).unwrap();
+		assert_eq!(
+			file.metadata().unwrap().len(),
+			DEFAULT_SEGMENT_EXTENT_SIZE as u64
+		);
+	}
+
+	/// Upgrades pages of a made-up page format version 1, which stored page
+	/// bodies inverted, to version 2.
+	fn upgrade_inverted_page(page: &mut [u8], byte_order: ByteOrder) -> Result<(), FileError> {
+		let format = PageFormat {
+			checksum: ChecksumAlgorithm::Crc32c,
+			byte_order,
+		};
+		let header_size = page_header_size(format.checksum);
+		page[header_size - 1] = PAGE_FORMAT_VERSION;
+		let PageHeader::Init(mut header) = read_page_header(format, &page[0..header_size])? else {
+			return Err(FileError::Unexpected);
+		};
+		let (header_buf, body) = page.split_at_mut(header_size);
+		body.iter_mut().for_each(|byte| *byte = !*byte);
+		header.checksum = format.checksum.checksum(body);
+		write_page_header(format, PageHeader::Init(header), header_buf);
+		Ok(())
+	}
+
+	static INVERTED_PAGE_MIGRATIONS: MigrationRegistry = MigrationRegistry {
+		file_migrations: &[],
+		page_migrations: &[PageMigration {
+			from_version: 1,
+			to_version: 2,
+			migrate: upgrade_inverted_page,
+		}],
+	};
+
+	/// Creates a segment with page 2 stored in the made-up page format version 1.
+	fn create_segment_with_inverted_page(path: &Path, body: &[u8]) {
+		let segment = SegmentFile::create_file(path, &SegmentConfig::default()).unwrap();
+		segment
+			.write(SegmentWriteOp {
+				page_num: non_zero!(2),
+				wal_index: wal_index!(69, 420),
+				buf: body,
+			})
+			.unwrap();
+
+		let mut page = vec![0; DEFAULT_PAGE_SIZE];
+		let offset = (2 * DEFAULT_PAGE_SIZE) as u64;
+		os::unix::fs::FileExt::read_exact_at(&segment.file, &mut page, offset).unwrap();
+		let header_size = <PageHeaderRepr>::SIZE;
+		page[header_size - 1] = 1;
+		page[header_size..]
+			.iter_mut()
+			.for_each(|byte| *byte = !*byte);
+		os::unix::fs::FileExt::write_all_at(&segment.file, &page, offset).unwrap();
+	}
+
+	#[test]
+	fn read_page_of_older_format_version() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let mut body = [0; DEFAULT_PAGE_BODY_SIZE];
+		body[0..4].copy_from_slice(&[1, 2, 3, 4]);
+		create_segment_with_inverted_page(&tempdir.path().join("0"), &body);
+		let mut segment =
+			SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+		segment.migrations = &INVERTED_PAGE_MIGRATIONS;
+
+		// when
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+		let mut wal_index = None;
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(2),
+				wal_index: &mut wal_index,
+				buf: &mut data,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, Some(wal_index!(69, 420)));
+		assert_buf_eq!(data, body);
+	}
+
+	#[test]
+	fn try_read_page_of_older_format_version_without_migration() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		create_segment_with_inverted_page(&tempdir.path().join("0"), &[0; DEFAULT_PAGE_BODY_SIZE]);
+		let segment =
+			SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+
+		// when
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+		let result = segment.read(SegmentReadOp {
+			page_num: non_zero!(2),
+			wal_index: &mut None,
+			buf: &mut data,
+		});
+
+		// then
+		assert!(matches!(result, Err(FileError::IncompatiblePageVersion(1))));
+	}
+
+	#[test]
+	fn upgrade_pages_of_older_format_version() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let mut body = [0; DEFAULT_PAGE_BODY_SIZE];
+		body[0..4].copy_from_slice(&[1, 2, 3, 4]);
+		create_segment_with_inverted_page(&tempdir.path().join("0"), &body);
+		let segment =
+			SegmentFile::open_file(tempdir.path().join("0"), &SegmentConfig::default()).unwrap();
+
+		// when
+		let num_pages_dry_run = segment
+			.upgrade_pages(&INVERTED_PAGE_MIGRATIONS, true)
+			.unwrap();
+		let num_pages = segment
+			.upgrade_pages(&INVERTED_PAGE_MIGRATIONS, false)
+			.unwrap();
+
+		// then
+		assert_eq!(num_pages_dry_run, 1);
+		assert_eq!(num_pages, 1);
+		assert_eq!(segment.upgrade_pages(&MIGRATIONS, true).unwrap(), 0);
+
+		let mut data = [0; DEFAULT_PAGE_BODY_SIZE];
+		segment
+			.read(SegmentReadOp {
+				page_num: non_zero!(2),
+				wal_index: &mut None,
+				buf: &mut data,
+			})
+			.unwrap();
+		assert_buf_eq!(data, body);
 	}
 }
@@ -1,16 +1,23 @@
 use std::{
 	borrow::Cow,
 	collections::HashMap,
-	fs::{File, OpenOptions},
-	io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
+	fs::{self, File, OpenOptions},
+	io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write},
 	num::{NonZeroU16, NonZeroU64},
 	path::Path,
 };
 
+use log::warn;
 use static_assertions::assert_impl_all;
-use zerocopy::{FromBytes, Immutable, IntoBytes};
+use zerocopy::{
+	byteorder::{self, LittleEndian, U16, U32, U64},
+	FromBytes, FromZeros, Immutable, IntoBytes,
+};
+
+pub(super) const FORMAT_VERSION: u8 = 2;
 
-const FORMAT_VERSION: u8 = 1;
+/// Version 1 WAL files have no WAL header, and always use CRC32 checksums.
+const CRC32_FORMAT_VERSION: u8 = 1;
 
 #[cfg(test)]
 use mockall::automock;
@@ -21,73 +28,107 @@ use crate::{
 };
 
 use super::{
-	generic::{FileType, GenericHeader, GenericHeaderRepr},
-	utils::CRC32,
+	checksum::ChecksumAlgorithm,
+	encryption::WalCipher,
+	generic::{with_byte_order, ByteOrder, FileType, GenericHeader, GenericHeaderRepr},
 	FileError, PageAddress, TransactionState, WalIndex,
 };
 
 const FLAG_UNDO: u8 = 0b00000001;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WalHeader {
+	checksum: ChecksumAlgorithm,
+}
+
+#[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
+#[repr(C, packed)]
+struct WalHeaderRepr {
+	checksum: u8,
+}
+
+impl From<WalHeader> for WalHeaderRepr {
+	fn from(value: WalHeader) -> Self {
+		Self {
+			checksum: value.checksum as u8,
+		}
+	}
+}
+
+impl TryFrom<WalHeaderRepr> for WalHeader {
+	type Error = FileError;
+
+	fn try_from(value: WalHeaderRepr) -> Result<Self, Self::Error> {
+		Ok(Self {
+			checksum: ChecksumAlgorithm::from_byte(value.checksum)?,
+		})
+	}
+}
+
+impl Repr<WalHeader> for WalHeaderRepr {
+	type Error = FileError;
+}
+
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
-#[repr(C)]
-struct ItemHeaderRepr {
+#[repr(C, packed)]
+struct ItemHeaderRepr<O: byteorder::ByteOrder = LittleEndian> {
 	kind: u8,
 	flags: u8,
-	body_length: u16,
-	crc: u32,
-	prev_item: Option<NonZeroU64>,
+	body_length: U16<O>,
+	crc: U32<O>,
+	prev_item: U64<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
-#[repr(C)]
-struct ItemFooterRepr {
-	item_start: u64,
+#[repr(C, packed)]
+struct ItemFooterRepr<O: byteorder::ByteOrder = LittleEndian> {
+	item_start: U64<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
-#[repr(C)]
-struct TransactionBlockRepr {
-	transaction_id: u64,
-	prev_transaction_generation: u64,
-	prev_transaction_offset: Option<NonZeroU64>,
+#[repr(C, packed)]
+struct TransactionBlockRepr<O: byteorder::ByteOrder = LittleEndian> {
+	transaction_id: U64<O>,
+	prev_transaction_generation: U64<O>,
+	prev_transaction_offset: U64<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
 #[repr(C, packed)]
-struct WriteBlockRepr {
-	segment_num: u32,
-	page_num: u16,
-	offset: u16,
-	write_length: u16,
+struct WriteBlockRepr<O: byteorder::ByteOrder = LittleEndian> {
+	segment_num: U32<O>,
+	page_num: U16<O>,
+	offset: U16<O>,
+	write_length: U16<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
-#[repr(C)]
-struct CheckpointBlockRepr {
-	num_dirty_pages: u64,
-	num_transactions: u64,
+#[repr(C, packed)]
+struct CheckpointBlockRepr<O: byteorder::ByteOrder = LittleEndian> {
+	num_dirty_pages: U64<O>,
+	num_transactions: U64<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
 #[repr(C, packed)]
-struct PageAddressRepr {
-	segment_num: u32,
-	page_num: u16,
+struct PageAddressRepr<O: byteorder::ByteOrder = LittleEndian> {
+	segment_num: U32<O>,
+	page_num: U16<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
-#[repr(C)]
-struct WalIndexRepr {
-	generation: u64,
-	offset: u64,
+#[repr(C, packed)]
+struct WalIndexRepr<O: byteorder::ByteOrder = LittleEndian> {
+	generation: U64<O>,
+	offset: U64<O>,
 }
 
 #[derive(Debug, Clone, Immutable, FromBytes, IntoBytes)]
-#[repr(C)]
-struct TransactionStateRepr {
-	first_generation: u64,
-	last_generation: u64,
-	last_offset: u64,
+#[repr(C, packed)]
+struct TransactionStateRepr<O: byteorder::ByteOrder = LittleEndian> {
+	first_generation: U64<O>,
+	last_generation: U64<O>,
+	last_offset: U64<O>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -122,33 +163,37 @@ struct ItemHeader {
 	prev_item: Option<NonZeroU64>,
 }
 
-impl From<ItemHeader> for ItemHeaderRepr {
+impl<O: byteorder::ByteOrder> From<ItemHeader> for ItemHeaderRepr<O> {
 	fn from(value: ItemHeader) -> Self {
 		Self {
 			kind: value.kind as u8,
 			flags: value.flags,
-			body_length: value.body_length,
-			crc: value.crc,
-			prev_item: value.prev_item,
+			body_length: value.body_length.into(),
+			crc: value.crc.into(),
+			prev_item: value
+				.prev_item
+				.map(NonZeroU64::get)
+				.unwrap_or_default()
+				.into(),
 		}
 	}
 }
 
-impl TryFrom<ItemHeaderRepr> for ItemHeader {
+impl<O: byteorder::ByteOrder> TryFrom<ItemHeaderRepr<O>> for ItemHeader {
 	type Error = FileError;
 
-	fn try_from(value: ItemHeaderRepr) -> Result<Self, Self::Error> {
+	fn try_from(value: ItemHeaderRepr<O>) -> Result<Self, Self::Error> {
 		Ok(Self {
 			kind: ItemKind::try_from(value.kind)?,
 			flags: value.flags,
-			body_length: value.body_length,
-			crc: value.crc,
-			prev_item: value.prev_item,
+			body_length: value.body_length.get(),
+			crc: value.crc.get(),
+			prev_item: NonZeroU64::new(value.prev_item.get()),
 		})
 	}
 }
 
-impl Repr<ItemHeader> for ItemHeaderRepr {
+impl<O: byteorder::ByteOrder> Repr<ItemHeader> for ItemHeaderRepr<O> {
 	type Error = FileError;
 }
 
@@ -157,19 +202,19 @@ struct ItemFooter {
 	item_start: NonZeroU64,
 }
 
-impl From<ItemFooter> for ItemFooterRepr {
+impl<O: byteorder::ByteOrder> From<ItemFooter> for ItemFooterRepr<O> {
 	fn from(value: ItemFooter) -> Self {
 		Self {
-			item_start: value.item_start.get(),
+			item_start: value.item_start.get().into(),
 		}
 	}
 }
 
-impl TryFrom<ItemFooterRepr> for ItemFooter {
+impl<O: byteorder::ByteOrder> TryFrom<ItemFooterRepr<O>> for ItemFooter {
 	type Error = FileError;
 
-	fn try_from(value: ItemFooterRepr) -> Result<Self, Self::Error> {
-		let Some(item_start) = NonZeroU64::new(value.item_start) else {
+	fn try_from(value: ItemFooterRepr<O>) -> Result<Self, Self::Error> {
+		let Some(item_start) = NonZeroU64::new(value.item_start.get()) else {
 			return Err(FileError::Corrupted(
 				"WAL items cannot start at position 0".to_string(),
 			));
@@ -178,7 +223,7 @@ impl TryFrom<ItemFooterRepr> for ItemFooter {
 	}
 }
 
-impl Repr<ItemFooter> for ItemFooterRepr {
+impl<O: byteorder::ByteOrder> Repr<ItemFooter> for ItemFooterRepr<O> {
 	type Error = FileError;
 }
 
@@ -187,31 +232,35 @@ struct TransactionBlock {
 	prev_transaction_item: Option<WalIndex>,
 }
 
-impl From<TransactionBlock> for TransactionBlockRepr {
+impl<O: byteorder::ByteOrder> From<TransactionBlock> for TransactionBlockRepr<O> {
 	fn from(value: TransactionBlock) -> Self {
 		Self {
-			transaction_id: value.transaction_id,
+			transaction_id: value.transaction_id.into(),
 			prev_transaction_generation: value
 				.prev_transaction_item
 				.map(|idx| idx.generation)
-				.unwrap_or_default(),
-			prev_transaction_offset: value.prev_transaction_item.map(|idx| idx.offset),
+				.unwrap_or_default()
+				.into(),
+			prev_transaction_offset: value
+				.prev_transaction_item
+				.map(|idx| idx.offset.get())
+				.unwrap_or_default()
+				.into(),
 		}
 	}
 }
 
-impl From<TransactionBlockRepr> for TransactionBlock {
-	fn from(value: TransactionBlockRepr) -> Self {
+impl<O: byteorder::ByteOrder> From<TransactionBlockRepr<O>> for TransactionBlock {
+	fn from(value: TransactionBlockRepr<O>) -> Self {
 		Self {
-			transaction_id: value.transaction_id,
-			prev_transaction_item: value
-				.prev_transaction_offset
-				.map(|offset| WalIndex::new(value.prev_transaction_generation, offset)),
+			transaction_id: value.transaction_id.get(),
+			prev_transaction_item: NonZeroU64::new(value.prev_transaction_offset.get())
+				.map(|offset| WalIndex::new(value.prev_transaction_generation.get(), offset)),
 		}
 	}
 }
 
-impl Repr<TransactionBlock> for TransactionBlockRepr {
+impl<O: byteorder::ByteOrder> Repr<TransactionBlock> for TransactionBlockRepr<O> {
 	type Error = FileError;
 }
 
@@ -222,216 +271,382 @@ struct WriteBlock {
 	write_length: u16,
 }
 
-impl From<WriteBlock> for WriteBlockRepr {
+impl<O: byteorder::ByteOrder> From<WriteBlock> for WriteBlockRepr<O> {
 	fn from(value: WriteBlock) -> Self {
 		Self {
-			segment_num: value.page_address.segment_num,
-			page_num: value.page_address.page_num.get(),
-			offset: value.offset,
-			write_length: value.write_length,
+			segment_num: value.page_address.segment_num.into(),
+			page_num: value.page_address.page_num.get().into(),
+			offset: value.offset.into(),
+			write_length: value.write_length.into(),
 		}
 	}
 }
 
-impl TryFrom<WriteBlockRepr> for WriteBlock {
+impl<O: byteorder::ByteOrder> TryFrom<WriteBlockRepr<O>> for WriteBlock {
 	type Error = FileError;
 
-	fn try_from(value: WriteBlockRepr) -> Result<Self, Self::Error> {
-		let Some(page_num) = NonZeroU16::new(value.page_num) else {
+	fn try_from(value: WriteBlockRepr<O>) -> Result<Self, Self::Error> {
+		let Some(page_num) = NonZeroU16::new(value.page_num.get()) else {
 			return Err(FileError::Corrupted(
 				"0 is not a valid page number".to_string(),
 			));
 		};
 		Ok(Self {
-			page_address: PageAddress::new(value.segment_num, page_num),
-			offset: value.offset,
-			write_length: value.write_length,
+			page_address: PageAddress::new(value.segment_num.get(), page_num),
+			offset: value.offset.get(),
+			write_length: value.write_length.get(),
 		})
 	}
 }
 
-impl Repr<WriteBlock> for WriteBlockRepr {
+impl<O: byteorder::ByteOrder> Repr<WriteBlock> for WriteBlockRepr<O> {
 	type Error = FileError;
 }
 
-type CheckpointBlock = CheckpointBlockRepr;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CheckpointBlock {
+	num_dirty_pages: u64,
+	num_transactions: u64,
+}
+
+impl<O: byteorder::ByteOrder> From<CheckpointBlock> for CheckpointBlockRepr<O> {
+	fn from(value: CheckpointBlock) -> Self {
+		Self {
+			num_dirty_pages: value.num_dirty_pages.into(),
+			num_transactions: value.num_transactions.into(),
+		}
+	}
+}
 
-impl Repr<CheckpointBlock> for CheckpointBlockRepr {
+impl<O: byteorder::ByteOrder> From<CheckpointBlockRepr<O>> for CheckpointBlock {
+	fn from(value: CheckpointBlockRepr<O>) -> Self {
+		Self {
+			num_dirty_pages: value.num_dirty_pages.get(),
+			num_transactions: value.num_transactions.get(),
+		}
+	}
+}
+
+impl<O: byteorder::ByteOrder> Repr<CheckpointBlock> for CheckpointBlockRepr<O> {
 	type Error = FileError;
 }
 
-impl From<PageAddress> for PageAddressRepr {
+impl<O: byteorder::ByteOrder> From<PageAddress> for PageAddressRepr<O> {
 	fn from(value: PageAddress) -> Self {
 		Self {
-			segment_num: value.segment_num,
-			page_num: value.page_num.get(),
+			segment_num: value.segment_num.into(),
+			page_num: value.page_num.get().into(),
 		}
 	}
 }
 
-impl TryFrom<PageAddressRepr> for PageAddress {
+impl<O: byteorder::ByteOrder> TryFrom<PageAddressRepr<O>> for PageAddress {
 	type Error = FileError;
 
-	fn try_from(value: PageAddressRepr) -> Result<Self, Self::Error> {
-		let Some(page_num) = NonZeroU16::new(value.page_num) else {
+	fn try_from(value: PageAddressRepr<O>) -> Result<Self, Self::Error> {
+		let Some(page_num) = NonZeroU16::new(value.page_num.get()) else {
 			return Err(FileError::Corrupted(
 				"Found invalid page number 0".to_string(),
 			));
 		};
-		Ok(PageAddress::new(value.segment_num, page_num))
+		Ok(PageAddress::new(value.segment_num.get(), page_num))
 	}
 }
 
-impl Repr<PageAddress> for PageAddressRepr {
+impl<O: byteorder::ByteOrder> Repr<PageAddress> for PageAddressRepr<O> {
 	type Error = FileError;
 }
 
-impl From<WalIndex> for WalIndexRepr {
+impl<O: byteorder::ByteOrder> From<WalIndex> for WalIndexRepr<O> {
 	fn from(value: WalIndex) -> Self {
 		Self {
-			offset: value.offset.get(),
-			generation: value.generation,
+			offset: value.offset.get().into(),
+			generation: value.generation.into(),
 		}
 	}
 }
 
-impl TryFrom<WalIndexRepr> for WalIndex {
+impl<O: byteorder::ByteOrder> TryFrom<WalIndexRepr<O>> for WalIndex {
 	type Error = FileError;
 
-	fn try_from(value: WalIndexRepr) -> Result<Self, Self::Error> {
-		let Some(offset) = NonZeroU64::new(value.offset) else {
+	fn try_from(value: WalIndexRepr<O>) -> Result<Self, Self::Error> {
+		let Some(offset) = NonZeroU64::new(value.offset.get()) else {
 			return Err(FileError::Corrupted(
 				"Found invalid WAL offset '0'".to_string(),
 			));
 		};
 		Ok(Self {
-			generation: value.generation,
+			generation: value.generation.get(),
 			offset,
 		})
 	}
 }
 
-impl Repr<WalIndex> for WalIndexRepr {
+impl<O: byteorder::ByteOrder> Repr<WalIndex> for WalIndexRepr<O> {
 	type Error = FileError;
 }
 
-impl From<TransactionState> for TransactionStateRepr {
+impl<O: byteorder::ByteOrder> From<TransactionState> for TransactionStateRepr<O> {
 	fn from(value: TransactionState) -> Self {
 		Self {
-			first_generation: value.first_gen,
-			last_generation: value.last_index.generation,
-			last_offset: value.last_index.offset.get(),
+			first_generation: value.first_gen.into(),
+			last_generation: value.last_index.generation.into(),
+			last_offset: value.last_index.offset.get().into(),
 		}
 	}
 }
 
-impl Repr<TransactionState> for TransactionStateRepr {
+impl<O: byteorder::ByteOrder> Repr<TransactionState> for TransactionStateRepr<O> {
 	type Error = FileError;
 }
 
-impl TryFrom<TransactionStateRepr> for TransactionState {
+impl<O: byteorder::ByteOrder> TryFrom<TransactionStateRepr<O>> for TransactionState {
 	type Error = FileError;
 
-	fn try_from(value: TransactionStateRepr) -> Result<Self, Self::Error> {
-		let Some(last_offset) = NonZeroU64::new(value.last_offset) else {
+	fn try_from(value: TransactionStateRepr<O>) -> Result<Self, Self::Error> {
+		let Some(last_offset) = NonZeroU64::new(value.last_offset.get()) else {
 			return Err(FileError::Corrupted(
 				"Found invalid WAL offset '0'".to_string(),
 			));
 		};
 		Ok(Self {
-			first_gen: value.first_generation,
-			last_index: WalIndex::new(value.last_generation, last_offset),
+			first_gen: value.first_generation.get(),
+			last_index: WalIndex::new(value.last_generation.get(), last_offset),
 		})
 	}
 }
 
 const WRITE_BUF_LIMIT: usize = 2 * MIB;
 
-pub(crate) struct WalFile<F: Seek + Read + Write = File> {
+/// The file a WAL generation is stored in.
+pub(crate) trait WalStorage: Seek + Read + Write {
+	fn set_len(&mut self, len: u64) -> io::Result<()>;
+
+	/// Makes everything written so far durable.
+	fn sync_data(&mut self) -> io::Result<()>;
+}
+
+impl WalStorage for File {
+	fn set_len(&mut self, len: u64) -> io::Result<()> {
+		File::set_len(self, len)
+	}
+
+	fn sync_data(&mut self) -> io::Result<()> {
+		File::sync_data(self)
+	}
+}
+
+impl WalStorage for Cursor<Vec<u8>> {
+	fn set_len(&mut self, len: u64) -> io::Result<()> {
+		self.get_mut().truncate(usize::try_from(len).unwrap());
+		Ok(())
+	}
+
+	fn sync_data(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl WalStorage for Cursor<&mut Vec<u8>> {
+	fn set_len(&mut self, len: u64) -> io::Result<()> {
+		self.get_mut().truncate(usize::try_from(len).unwrap());
+		Ok(())
+	}
+
+	fn sync_data(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+pub(crate) struct WalFile<F: WalStorage = File> {
 	body_start: u64,
+	byte_order: ByteOrder,
+	checksum: ChecksumAlgorithm,
+	cipher: Option<WalCipher>,
 	prev_item: Option<NonZeroU64>,
 	write_buf: Vec<u8>,
 	file: F,
 	next_offset: NonZeroU64,
+	/// Whether the file ends with an incomplete item, which is cut off before
+	/// anything is appended
+	torn_tail: bool,
+	/// Whether anything was written since the file was last synced
+	needs_sync: bool,
 }
 assert_impl_all!(WalFile: Send, Sync);
 
 impl WalFile {
-	pub fn create_file(path: impl AsRef<Path>) -> Result<Self, FileError> {
-		Self::create(
+	/// The extension of WAL files whose header is still being written.
+	pub const TMP_EXTENSION: &'static str = "tmp";
+
+	/// Creates a new WAL file at `path`. The header is written to a temporary
+	/// file that is only moved into place once it is durable, so that a crash
+	/// never leaves behind a WAL file that can't be opened.
+	pub fn create_file(
+		path: impl AsRef<Path>,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
+		let path = path.as_ref();
+		let tmp_path = path.with_extension(Self::TMP_EXTENSION);
+		let wal_file = Self::create(
 			OpenOptions::new()
 				.create(true)
 				.truncate(true)
 				.read(true)
 				.write(true)
-				.open(path)?,
+				.open(&tmp_path)?,
+			checksum,
+			cipher,
+		)?;
+		wal_file.file.sync_all()?;
+
+		fs::rename(&tmp_path, path)?;
+		if let Some(parent) = path.parent() {
+			File::open(parent)?.sync_all()?;
+		}
+		Ok(wal_file)
+	}
+
+	pub fn open_file(path: impl AsRef<Path>, cipher: Option<WalCipher>) -> Result<Self, FileError> {
+		Self::open(
+			OpenOptions::new().read(true).write(true).open(path)?,
+			cipher,
 		)
 	}
 
-	pub fn open_file(path: impl AsRef<Path>) -> Result<Self, FileError> {
-		Self::open(OpenOptions::new().read(true).write(true).open(path)?)
+	pub fn open_file_read_only(
+		path: impl AsRef<Path>,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
+		Self::open(OpenOptions::new().read(true).open(path)?, cipher)
 	}
 }
 
-impl<F: Seek + Read + Write> WalFile<F> {
-	fn create(mut file: F) -> Result<Self, FileError> {
+impl<F: WalStorage> WalFile<F> {
+	pub(super) fn create(
+		mut file: F,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
 		file.seek(SeekFrom::Start(0))?;
 		let content_offset = u16::try_from(GenericHeaderRepr::SIZE).unwrap();
 		let meta = GenericHeader {
+			byte_order: ByteOrder::PORTABLE,
 			file_type: FileType::Wal,
 			content_offset,
 			version: FORMAT_VERSION,
 		};
 		GenericHeaderRepr::serialize(meta, &mut file)?;
-		Self::new(file, content_offset.into())
+		WalHeaderRepr::serialize(WalHeader { checksum }, &mut file)?;
+		let body_start = (GenericHeaderRepr::SIZE + WalHeaderRepr::SIZE) as u64;
+		Self::new(file, body_start, ByteOrder::PORTABLE, checksum, cipher)
 	}
 
-	fn open(mut file: F) -> Result<Self, FileError> {
+	pub(super) fn open(mut file: F, cipher: Option<WalCipher>) -> Result<Self, FileError> {
 		file.seek(SeekFrom::Start(0))?;
 		let header = GenericHeaderRepr::deserialize(&mut file)?;
 		if header.file_type != FileType::Wal {
 			return Err(FileError::WrongFileType(header.file_type));
 		}
-		if header.version != FORMAT_VERSION {
-			return Err(FileError::IncompatibleVersion(
-				header.file_type,
-				header.version,
-			));
-		}
 
-		Self::new(file, header.content_offset.into())
+		match header.version {
+			CRC32_FORMAT_VERSION => Self::new(
+				file,
+				header.content_offset.into(),
+				header.byte_order,
+				ChecksumAlgorithm::Crc32,
+				cipher,
+			),
+			FORMAT_VERSION => {
+				file.seek(SeekFrom::Start(header.content_offset.into()))?;
+				let wal_header = WalHeaderRepr::deserialize(&mut file)?;
+				let body_start = file.stream_position()?;
+				Self::new(
+					file,
+					body_start,
+					header.byte_order,
+					wal_header.checksum,
+					cipher,
+				)
+			}
+			version => Err(FileError::IncompatibleVersion(header.file_type, version)),
+		}
 	}
 
-	fn new(mut file: F, body_start: u64) -> Result<Self, FileError> {
-		let prev_footer_start =
-			file.seek(SeekFrom::End(-i64::try_from(ItemFooterRepr::SIZE).unwrap()))?;
-		let prev_item = if prev_footer_start > body_start {
-			let footer = ItemFooterRepr::deserialize(&mut file)?;
-			Some(footer.item_start)
-		} else {
-			None
-		};
-		let next_offset = NonZeroU64::new(file.seek(SeekFrom::End(0))?).unwrap();
+	fn new(
+		mut file: F,
+		body_start: u64,
+		byte_order: ByteOrder,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
+		let (prev_item, end) =
+			Self::find_end(&mut file, body_start, byte_order, checksum, cipher.clone())?;
+		let file_len = file.seek(SeekFrom::End(0))?;
+		if end < file_len {
+			warn!(
+				"Ignoring {} bytes of incomplete items at the end of the WAL",
+				file_len - end
+			);
+		}
 		Ok(Self {
 			body_start,
+			byte_order,
+			checksum,
+			cipher,
 			file,
 			write_buf: Vec::new(),
 			prev_item,
-			next_offset,
+			next_offset: NonZeroU64::new(end).unwrap(),
+			torn_tail: end < file_len,
+			needs_sync: true,
 		})
 	}
 
-	fn write_transaction_block(writer: impl Write, data: TransactionData) -> Result<(), FileError> {
+	/// Finds the last complete item, and the offset where it ends. Anything
+	/// after it was torn by a crash before it became durable, and is ignored.
+	///
+	/// Since writes that weren't synced can be lost in any order, the items are
+	/// checked from the start, and the first invalid one ends the WAL.
+	fn find_end(
+		file: &mut F,
+		body_start: u64,
+		byte_order: ByteOrder,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<(Option<NonZeroU64>, u64), FileError> {
+		file.seek(SeekFrom::Start(body_start))?;
+		let mut reader = ItemReader::new(file, None, byte_order, checksum, cipher)?;
+		let mut last_item: Option<NonZeroU64> = None;
+		let mut end = body_start;
+		while let Ok((offset, _)) = reader.read_item_exact() {
+			if reader.prev_item != last_item {
+				break;
+			}
+			last_item = Some(offset);
+			end = reader.offset;
+		}
+		Ok((last_item, end))
+	}
+
+	fn write_transaction_block<O: byteorder::ByteOrder>(
+		writer: impl Write,
+		data: TransactionData,
+	) -> Result<(), FileError> {
 		let block = TransactionBlock {
 			transaction_id: data.transaction_id,
 			prev_transaction_item: data.prev_transaction_item,
 		};
-		TransactionBlockRepr::serialize(block, writer)?;
+		TransactionBlockRepr::<O>::serialize(block, writer)?;
 		Ok(())
 	}
 
-	fn write_write_block(mut writer: impl Write, data: WriteData) -> Result<(), FileError> {
-		Self::write_transaction_block(&mut writer, data.transaction_data)?;
+	fn write_write_block<O: byteorder::ByteOrder>(
+		mut writer: impl Write,
+		data: WriteData,
+	) -> Result<(), FileError> {
+		Self::write_transaction_block::<O>(&mut writer, data.transaction_data)?;
 
 		let block = WriteBlock {
 			page_address: data.page_address,
@@ -442,7 +657,7 @@ impl<F: Seek + Read + Write> WalFile<F> {
 				.try_into()
 				.expect("Write length must be 16-bit!"),
 		};
-		WriteBlockRepr::serialize(block, &mut writer)?;
+		WriteBlockRepr::<O>::serialize(block, &mut writer)?;
 		if let Some(from) = data.from {
 			debug_assert_eq!(from.len(), data.to.len());
 			writer.write_all(&from)?;
@@ -451,7 +666,7 @@ impl<F: Seek + Read + Write> WalFile<F> {
 		Ok(())
 	}
 
-	fn write_checkpoint_block(
+	fn write_checkpoint_block<O: byteorder::ByteOrder>(
 		mut writer: impl Write,
 		data: CheckpointData,
 	) -> Result<(), FileError> {
@@ -459,14 +674,14 @@ impl<F: Seek + Read + Write> WalFile<F> {
 			num_dirty_pages: data.dirty_pages.len() as u64,
 			num_transactions: data.transactions.len() as u64,
 		};
-		CheckpointBlockRepr::serialize(block, &mut writer)?;
+		CheckpointBlockRepr::<O>::serialize(block, &mut writer)?;
 		for (page_address, wal_index) in data.dirty_pages.iter() {
-			PageAddressRepr::serialize(*page_address, &mut writer)?;
-			WalIndexRepr::serialize(*wal_index, &mut writer)?;
+			PageAddressRepr::<O>::serialize(*page_address, &mut writer)?;
+			WalIndexRepr::<O>::serialize(*wal_index, &mut writer)?;
 		}
 		for (transaction_id, transaction_state) in data.transactions.iter() {
-			writer.write_all(transaction_id.as_bytes())?;
-			TransactionStateRepr::serialize(transaction_state.clone(), &mut writer)?;
+			writer.write_all(U64::<O>::new(*transaction_id).as_bytes())?;
+			TransactionStateRepr::<O>::serialize(transaction_state.clone(), &mut writer)?;
 		}
 
 		Ok(())
@@ -516,6 +731,9 @@ pub(crate) trait WalFileApi {
 
 	fn push_item<'a>(&mut self, item: Item<'a>) -> Result<NonZeroU64, FileError>;
 	fn flush(&mut self) -> Result<(), FileError>;
+
+	/// Flushes all pushed items, and makes them durable.
+	fn sync(&mut self) -> Result<(), FileError>;
 	fn read_item_at(&mut self, offset: NonZeroU64) -> Result<Item<'static>, FileError>;
 	fn iter_items<'a>(&'a mut self) -> Result<Self::IterItems<'a>, FileError>;
 	fn iter_items_reverse<'a>(&'a mut self) -> Result<Self::IterItemsReverse<'a>, FileError>;
@@ -523,7 +741,7 @@ pub(crate) trait WalFileApi {
 	fn size(&self) -> usize;
 }
 
-impl<F: Seek + Read + Write> WalFileApi for WalFile<F> {
+impl<F: WalStorage> WalFileApi for WalFile<F> {
 	type IterItems<'a> = IterItems<&'a mut F> where F: 'a;
 	type IterItemsReverse<'a> = IterItemsReverse<&'a mut F> where F: 'a;
 
@@ -533,24 +751,27 @@ impl<F: Seek + Read + Write> WalFileApi for WalFile<F> {
 		let mut body_buffer: Vec<u8> = vec![];
 		let kind: ItemKind;
 		let mut flags: u8 = 0;
-		match item {
+		with_byte_order!(self.byte_order, O => match item {
 			Item::Write(write_data) => {
 				kind = ItemKind::Write;
 				if write_data.from.is_none() {
 					flags |= FLAG_UNDO;
 				}
-				Self::write_write_block(&mut body_buffer, write_data)?;
+				Self::write_write_block::<O>(&mut body_buffer, write_data)?;
 			}
 			Item::Commit(transaction_data) => {
 				kind = ItemKind::Commit;
-				Self::write_transaction_block(&mut body_buffer, transaction_data)?
+				Self::write_transaction_block::<O>(&mut body_buffer, transaction_data)?
 			}
 			Item::Checkpoint(checkpoint_data) => {
 				kind = ItemKind::Checkpoint;
-				Self::write_checkpoint_block(&mut body_buffer, checkpoint_data)?
+				Self::write_checkpoint_block::<O>(&mut body_buffer, checkpoint_data)?
 			}
-		};
-		let crc = CRC32.checksum(&body_buffer);
+		});
+		if let Some(cipher) = &self.cipher {
+			cipher.encrypt_item(current_pos, &mut body_buffer);
+		}
+		let crc = self.checksum.checksum_32(&body_buffer);
 
 		let item_header = ItemHeader {
 			kind,
@@ -562,20 +783,20 @@ impl<F: Seek + Read + Write> WalFileApi for WalFile<F> {
 			crc,
 			prev_item: self.prev_item,
 		};
-		ItemHeaderRepr::serialize(item_header, &mut self.write_buf)?;
-
-		self.write_buf.write_all(&body_buffer)?;
-
 		let item_footer = ItemFooter {
 			item_start: current_pos,
 		};
-		ItemFooterRepr::serialize(item_footer, &mut self.write_buf)?;
+		let item_start = self.write_buf.len();
+		with_byte_order!(self.byte_order, O => {
+			ItemHeaderRepr::<O>::serialize(item_header, &mut self.write_buf)?;
+			self.write_buf.write_all(&body_buffer)?;
+			ItemFooterRepr::<O>::serialize(item_footer, &mut self.write_buf)?;
+		});
 
 		self.prev_item = Some(current_pos);
-
-		self.next_offset =
-			NonZeroU64::new(self.file.seek(SeekFrom::End(0))? + self.write_buf.len() as u64)
-				.expect("WAL file unexpectedly at position 0");
+		self.next_offset = current_pos
+			.checked_add((self.write_buf.len() - item_start) as u64)
+			.expect("WAL size exceeded u64::MAX");
 
 		if self.write_buf.len() < WRITE_BUF_LIMIT {
 			self.flush()?;
@@ -585,17 +806,44 @@ impl<F: Seek + Read + Write> WalFileApi for WalFile<F> {
 	}
 
 	fn flush(&mut self) -> Result<(), FileError> {
+		if self.write_buf.is_empty() {
+			return Ok(());
+		}
+		// If writing fails, the buffer is kept, and written to the same place again
+		// by the next flush.
+		let start = self.next_offset.get() - self.write_buf.len() as u64;
+		if self.torn_tail {
+			self.file.set_len(start)?;
+			self.torn_tail = false;
+		}
+		self.file.seek(SeekFrom::Start(start))?;
+		self.needs_sync = true;
 		self.file.write_all(&self.write_buf)?;
 		self.write_buf.clear();
 		Ok(())
 	}
 
+	fn sync(&mut self) -> Result<(), FileError> {
+		self.flush()?;
+		if self.needs_sync {
+			self.file.sync_data()?;
+			self.needs_sync = false;
+		}
+		Ok(())
+	}
+
 	fn read_item_at(&mut self, offset: NonZeroU64) -> Result<Item<'static>, FileError> {
 		debug_assert!(offset.get() >= self.body_start);
 
 		self.flush()?;
 		self.file.seek(SeekFrom::Start(offset.get()))?;
-		let mut reader = ItemReader::new(&mut self.file, None)?;
+		let mut reader = ItemReader::new(
+			&mut self.file,
+			None,
+			self.byte_order,
+			self.checksum,
+			self.cipher.clone(),
+		)?;
 		let Some((read_offset, item)) = reader.read_item()? else {
 			return Err(FileError::UnexpectedEof);
 		};
@@ -607,13 +855,25 @@ impl<F: Seek + Read + Write> WalFileApi for WalFile<F> {
 	fn iter_items(&mut self) -> Result<Self::IterItems<'_>, FileError> {
 		self.flush()?;
 		self.file.seek(SeekFrom::Start(self.body_start))?;
-		IterItems::new(&mut self.file)
+		IterItems::new(
+			&mut self.file,
+			self.next_offset.get(),
+			self.byte_order,
+			self.checksum,
+			self.cipher.clone(),
+		)
 	}
 
 	fn iter_items_reverse(&mut self) -> Result<Self::IterItemsReverse<'_>, FileError> {
 		self.flush()?;
 		self.file.seek(SeekFrom::End(0))?;
-		IterItemsReverse::new(&mut self.file, self.prev_item)
+		IterItemsReverse::new(
+			&mut self.file,
+			self.prev_item,
+			self.byte_order,
+			self.checksum,
+			self.cipher.clone(),
+		)
 	}
 
 	#[inline]
@@ -631,20 +891,34 @@ struct ItemReader<F: Read + Seek> {
 	offset: u64,
 	reader: BufReader<F>,
 	prev_item: Option<NonZeroU64>,
+	byte_order: ByteOrder,
+	checksum: ChecksumAlgorithm,
+	cipher: Option<WalCipher>,
 }
 
 impl<F: Read + Seek> ItemReader<F> {
-	fn new(mut file: F, prev_item: Option<NonZeroU64>) -> Result<Self, FileError> {
+	fn new(
+		mut file: F,
+		prev_item: Option<NonZeroU64>,
+		byte_order: ByteOrder,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
 		let offset = file.stream_position()?;
 		Ok(Self {
 			offset,
 			reader: BufReader::new(file),
 			prev_item,
+			byte_order,
+			checksum,
+			cipher,
 		})
 	}
 
-	fn read_transaction_data(body: impl Read) -> Result<TransactionData, FileError> {
-		let transaction_block = TransactionBlockRepr::deserialize(body)?;
+	fn read_transaction_data<O: byteorder::ByteOrder>(
+		body: impl Read,
+	) -> Result<TransactionData, FileError> {
+		let transaction_block = TransactionBlockRepr::<O>::deserialize(body)?;
 
 		Ok(TransactionData {
 			transaction_id: transaction_block.transaction_id,
@@ -652,13 +926,13 @@ impl<F: Read + Seek> ItemReader<F> {
 		})
 	}
 
-	fn read_write_data(
+	fn read_write_data<O: byteorder::ByteOrder>(
 		mut body: impl Read,
 		is_undo: bool,
 	) -> Result<WriteData<'static>, FileError> {
-		let transaction_data = Self::read_transaction_data(&mut body)?;
+		let transaction_data = Self::read_transaction_data::<O>(&mut body)?;
 
-		let write_block = WriteBlockRepr::deserialize(&mut body)?;
+		let write_block = WriteBlockRepr::<O>::deserialize(&mut body)?;
 		let from: Option<Vec<u8>> = if is_undo {
 			None
 		} else {
@@ -678,22 +952,24 @@ impl<F: Read + Seek> ItemReader<F> {
 		})
 	}
 
-	fn read_checkpoint_data(mut body: impl Read) -> Result<CheckpointData<'static>, FileError> {
-		let checkpoint_block = CheckpointBlock::deserialize(&mut body)?;
+	fn read_checkpoint_data<O: byteorder::ByteOrder>(
+		mut body: impl Read,
+	) -> Result<CheckpointData<'static>, FileError> {
+		let checkpoint_block = CheckpointBlockRepr::<O>::deserialize(&mut body)?;
 
 		let mut dirty_pages: HashMap<PageAddress, WalIndex> = HashMap::new();
 		for _ in 0..checkpoint_block.num_dirty_pages {
-			let page_address = PageAddressRepr::deserialize(&mut body)?;
-			let wal_index = WalIndexRepr::deserialize(&mut body)?;
+			let page_address = PageAddressRepr::<O>::deserialize(&mut body)?;
+			let wal_index = WalIndexRepr::<O>::deserialize(&mut body)?;
 			dirty_pages.insert(page_address, wal_index);
 		}
 
 		let mut transactions: HashMap<u64, TransactionState> = HashMap::new();
 		for _ in 0..checkpoint_block.num_transactions {
-			let mut tid_bytes = [0; 8];
-			body.read_exact(&mut tid_bytes)?;
-			let transaction_id = u64::from_ne_bytes(tid_bytes);
-			let transaction_state = TransactionStateRepr::deserialize(&mut body)?;
+			let mut transaction_id = U64::<O>::new_zeroed();
+			body.read_exact(transaction_id.as_mut_bytes())?;
+			let transaction_id = transaction_id.get();
+			let transaction_state = TransactionStateRepr::<O>::deserialize(&mut body)?;
 			transactions.insert(transaction_id, transaction_state);
 		}
 
@@ -704,30 +980,48 @@ impl<F: Read + Seek> ItemReader<F> {
 	}
 
 	fn read_item_exact(&mut self) -> Result<(NonZeroU64, Item<'static>), FileError> {
-		let header = ItemHeaderRepr::deserialize(&mut self.reader)?;
+		let header = with_byte_order!(self.byte_order, O => {
+			ItemHeaderRepr::<O>::deserialize(&mut self.reader)?
+		});
 		let mut body_buf: Box<[u8]> = vec![0; header.body_length.into()].into();
 		self.reader.read_exact(&mut body_buf)?;
 		self.prev_item = header.prev_item;
 
-		if CRC32.checksum(&body_buf) != header.crc {
+		if self.checksum.checksum_32(&body_buf) != header.crc {
 			return Err(FileError::ChecksumMismatch);
 		}
 
+		let mut body_len = body_buf.len();
+		if let Some(cipher) = &self.cipher {
+			let item_offset =
+				NonZeroU64::new(self.offset).expect("WAL was unexpectedly read at offset 0");
+			body_len = cipher.decrypt_item(item_offset, &mut body_buf)?;
+		}
+
 		let is_undo = header.flags & FLAG_UNDO != 0;
 
-		let mut body_cursor = Cursor::new(body_buf);
-		let item = match header.kind {
-			ItemKind::Write => Item::Write(Self::read_write_data(&mut body_cursor, is_undo)?),
-			ItemKind::Commit => Item::Commit(Self::read_transaction_data(&mut body_cursor)?),
-			ItemKind::Checkpoint => Item::Checkpoint(Self::read_checkpoint_data(&mut body_cursor)?),
-		};
+		let mut body_cursor = Cursor::new(&body_buf[..body_len]);
+		let item = with_byte_order!(self.byte_order, O => match header.kind {
+			ItemKind::Write => Item::Write(Self::read_write_data::<O>(&mut body_cursor, is_undo)?),
+			ItemKind::Commit => Item::Commit(Self::read_transaction_data::<O>(&mut body_cursor)?),
+			ItemKind::Checkpoint => {
+				Item::Checkpoint(Self::read_checkpoint_data::<O>(&mut body_cursor)?)
+			}
+		});
 
-		self.reader
-			.seek_relative(i64::try_from(ItemFooterRepr::SIZE).unwrap())?;
+		let footer = with_byte_order!(self.byte_order, O => {
+			ItemFooterRepr::<O>::deserialize(&mut self.reader)?
+		});
+		if footer.item_start.get() != self.offset {
+			return Err(FileError::Corrupted(format!(
+				"The footer of the WAL item at {} points to {}",
+				self.offset, footer.item_start
+			)));
+		}
 
 		let item_offset = self.offset;
 		self.offset +=
-			(ItemHeaderRepr::SIZE + header.body_length as usize + ItemFooterRepr::SIZE) as u64;
+			(<ItemHeaderRepr>::SIZE + header.body_length as usize + <ItemFooterRepr>::SIZE) as u64;
 
 		Ok((
 			NonZeroU64::new(item_offset).expect("WAL was unexpectedly read at offset 0"),
@@ -762,12 +1056,21 @@ impl<F: Read + Seek> ItemReader<F> {
 
 pub(crate) struct IterItems<F: Read + Seek> {
 	reader: ItemReader<F>,
+	/// The offset after the last complete item
+	end: u64,
 }
 
 impl<F: Read + Seek> IterItems<F> {
-	fn new(file: F) -> Result<Self, FileError> {
+	fn new(
+		file: F,
+		end: u64,
+		byte_order: ByteOrder,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
 		Ok(Self {
-			reader: ItemReader::new(file, None)?,
+			reader: ItemReader::new(file, None, byte_order, checksum, cipher)?,
+			end,
 		})
 	}
 }
@@ -776,6 +1079,9 @@ impl<F: Read + Seek> Iterator for IterItems<F> {
 	type Item = Result<(NonZeroU64, Item<'static>), FileError>;
 
 	fn next(&mut self) -> Option<Self::Item> {
+		if self.reader.offset >= self.end {
+			return None;
+		}
 		self.reader.read_item().transpose()
 	}
 }
@@ -785,9 +1091,15 @@ pub(crate) struct IterItemsReverse<F: Read + Seek> {
 }
 
 impl<F: Read + Seek> IterItemsReverse<F> {
-	fn new(file: F, prev_item: Option<NonZeroU64>) -> Result<Self, FileError> {
+	fn new(
+		file: F,
+		prev_item: Option<NonZeroU64>,
+		byte_order: ByteOrder,
+		checksum: ChecksumAlgorithm,
+		cipher: Option<WalCipher>,
+	) -> Result<Self, FileError> {
 		Ok(Self {
-			reader: ItemReader::new(file, prev_item)?,
+			reader: ItemReader::new(file, prev_item, byte_order, checksum, cipher)?,
 		})
 	}
 }
@@ -802,38 +1114,50 @@ impl<F: Read + Seek> Iterator for IterItemsReverse<F> {
 
 #[cfg(test)]
 mod tests {
+	use std::sync::Arc;
+
 	use pretty_assertions::assert_buf_eq;
+	use zerocopy::BigEndian;
 
 	use crate::{
 		files::{
 			generic::GenericHeaderRepr,
-			test_helpers::{page_address, wal_index},
+			test_helpers::{page_address, wal_index, TestCipher},
 		},
 		utils::test_helpers::non_zero,
 	};
 
 	use super::*;
 
+	const BODY_START: usize = GenericHeaderRepr::SIZE + WalHeaderRepr::SIZE;
+
 	#[test]
 	fn create_wal() {
 		// given
 		let mut file = Vec::<u8>::new();
 
 		// when
-		WalFile::create(Cursor::new(&mut file)).unwrap();
+		WalFile::create(Cursor::new(&mut file), ChecksumAlgorithm::Crc32, None).unwrap();
 
 		// then
 		let mut expected_data = Vec::<u8>::new();
 		expected_data.extend(
 			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Little,
 				file_type: FileType::Wal,
 				content_offset: GenericHeaderRepr::SIZE as u16,
 				version: FORMAT_VERSION,
 			})
 			.as_bytes(),
 		);
+		expected_data.extend(
+			WalHeaderRepr::from(WalHeader {
+				checksum: ChecksumAlgorithm::Crc32,
+			})
+			.as_bytes(),
+		);
 
-		assert_eq!(file.len(), GenericHeaderRepr::SIZE);
+		assert_eq!(file.len(), BODY_START);
 		assert_buf_eq!(file, expected_data);
 	}
 
@@ -843,25 +1167,194 @@ mod tests {
 		let mut file = Vec::<u8>::new();
 		file.extend(
 			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Little,
+				file_type: FileType::Wal,
+				content_offset: GenericHeaderRepr::SIZE as u16,
+				version: FORMAT_VERSION,
+			})
+			.as_bytes(),
+		);
+		file.extend(
+			WalHeaderRepr::from(WalHeader {
+				checksum: ChecksumAlgorithm::Xxh64,
+			})
+			.as_bytes(),
+		);
+
+		// when
+		let wal_file = WalFile::open(Cursor::new(&mut file), None).unwrap();
+
+		// then
+		assert_eq!(wal_file.checksum, ChecksumAlgorithm::Xxh64);
+		assert_eq!(wal_file.body_start, BODY_START as u64);
+	}
+
+	#[test]
+	fn read_item_from_crc32_wal() {
+		// given
+		let mut file = Vec::<u8>::new();
+		file.extend(
+			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Little,
+				file_type: FileType::Wal,
+				content_offset: GenericHeaderRepr::SIZE as u16,
+				version: CRC32_FORMAT_VERSION,
+			})
+			.as_bytes(),
+		);
+		file.extend(
+			ItemHeaderRepr::<LittleEndian> {
+				kind: ItemKind::Commit as u8,
+				flags: 0,
+				body_length: 24.into(),
+				crc: 0x8b777949.into(),
+				prev_item: 0.into(),
+			}
+			.as_bytes(),
+		);
+		file.extend(
+			TransactionBlockRepr::<LittleEndian> {
+				prev_transaction_generation: 123.into(),
+				prev_transaction_offset: 25.into(),
+				transaction_id: 69.into(),
+			}
+			.as_bytes(),
+		);
+		file.extend(
+			ItemFooterRepr::<LittleEndian> {
+				item_start: (GenericHeaderRepr::SIZE as u64).into(),
+			}
+			.as_bytes(),
+		);
+
+		// when
+		let mut wal_file = WalFile::open(Cursor::new(&mut file), None).unwrap();
+		let item = wal_file
+			.read_item_at(non_zero!(GenericHeaderRepr::SIZE as u64))
+			.unwrap();
+
+		// then
+		assert_eq!(
+			item,
+			Item::Commit(TransactionData {
+				transaction_id: 69,
+				prev_transaction_item: Some(wal_index!(123, 25)),
+			})
+		);
+	}
+
+	#[test]
+	fn read_and_push_items_in_big_endian_wal() {
+		// given
+		let mut file = Vec::<u8>::new();
+		file.extend(
+			GenericHeaderRepr::from(GenericHeader {
+				byte_order: ByteOrder::Big,
 				file_type: FileType::Wal,
 				content_offset: GenericHeaderRepr::SIZE as u16,
 				version: FORMAT_VERSION,
 			})
 			.as_bytes(),
 		);
+		file.extend(
+			WalHeaderRepr::from(WalHeader {
+				checksum: ChecksumAlgorithm::Crc32c,
+			})
+			.as_bytes(),
+		);
+		let body = TransactionBlockRepr::<BigEndian> {
+			prev_transaction_generation: 123.into(),
+			prev_transaction_offset: 25.into(),
+			transaction_id: 69.into(),
+		};
+		file.extend(
+			ItemHeaderRepr::<BigEndian> {
+				kind: ItemKind::Commit as u8,
+				flags: 0,
+				body_length: 24.into(),
+				crc: ChecksumAlgorithm::Crc32c
+					.checksum_32(body.as_bytes())
+					.into(),
+				prev_item: 0.into(),
+			}
+			.as_bytes(),
+		);
+		file.extend(body.as_bytes());
+		file.extend(
+			ItemFooterRepr::<BigEndian> {
+				item_start: (BODY_START as u64).into(),
+			}
+			.as_bytes(),
+		);
 
 		// when
-		let result = WalFile::open(Cursor::new(&mut file));
+		let mut wal_file = WalFile::open(Cursor::new(&mut file), None).unwrap();
+		wal_file
+			.push_item(Item::Commit(TransactionData {
+				transaction_id: 25,
+				prev_transaction_item: None,
+			}))
+			.unwrap();
+		let items: Vec<Item> = wal_file
+			.iter_items()
+			.unwrap()
+			.map(|result| result.unwrap().1)
+			.collect();
 
 		// then
-		assert!(result.is_ok());
+		assert_eq!(
+			items,
+			vec![
+				Item::Commit(TransactionData {
+					transaction_id: 69,
+					prev_transaction_item: Some(wal_index!(123, 25)),
+				}),
+				Item::Commit(TransactionData {
+					transaction_id: 25,
+					prev_transaction_item: None,
+				}),
+			]
+		);
+	}
+
+	#[test]
+	fn push_and_read_encrypted_item() {
+		// given
+		let mut file = Vec::<u8>::new();
+		let cipher = WalCipher::new(Arc::new(TestCipher), 25);
+		let mut wal_file = WalFile::create(
+			Cursor::new(&mut file),
+			ChecksumAlgorithm::Crc32c,
+			Some(cipher),
+		)
+		.unwrap();
+		let item = Item::Write(WriteData {
+			transaction_data: TransactionData {
+				transaction_id: 25,
+				prev_transaction_item: None,
+			},
+			page_address: page_address!(123, 456),
+			offset: 445,
+			from: Some(Cow::Owned(vec![0xaa; 16])),
+			to: Cow::Owned(vec![0xbb; 16]),
+		});
+
+		// when
+		let offset = wal_file.push_item(item.clone()).unwrap();
+		let received = wal_file.read_item_at(offset).unwrap();
+		drop(wal_file);
+
+		// then
+		assert_eq!(received, item);
+		assert!(!file.windows(16).any(|window| window == [0xbb; 16]));
 	}
 
 	#[test]
 	fn push_write_item() {
 		// given
 		let mut file = Vec::<u8>::new();
-		let mut wal_file = WalFile::create(Cursor::new(&mut file)).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(&mut file), ChecksumAlgorithm::Crc32, None).unwrap();
 
 		// when
 		wal_file
@@ -881,50 +1374,51 @@ mod tests {
 		// then
 		let mut expected_body = Vec::<u8>::new();
 		expected_body.extend(
-			ItemHeaderRepr {
+			ItemHeaderRepr::<LittleEndian> {
 				kind: ItemKind::Write as u8,
 				flags: 0,
-				body_length: 42,
-				crc: 0x994f0abc,
-				prev_item: NonZeroU64::new(0),
+				body_length: 42.into(),
+				crc: 0x994f0abc.into(),
+				prev_item: 0.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			TransactionBlockRepr {
-				prev_transaction_generation: 123,
-				prev_transaction_offset: NonZeroU64::new(24),
-				transaction_id: 25,
+			TransactionBlockRepr::<LittleEndian> {
+				prev_transaction_generation: 123.into(),
+				prev_transaction_offset: 24.into(),
+				transaction_id: 25.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			WriteBlockRepr {
-				segment_num: 123,
-				page_num: 456,
-				offset: 445,
-				write_length: 4,
+			WriteBlockRepr::<LittleEndian> {
+				segment_num: 123.into(),
+				page_num: 456.into(),
+				offset: 445.into(),
+				write_length: 4.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend([1, 2, 3, 4]);
 		expected_body.extend([4, 5, 6, 7]);
 		expected_body.extend(
-			ItemFooterRepr {
-				item_start: GenericHeaderRepr::SIZE as u64,
+			ItemFooterRepr::<LittleEndian> {
+				item_start: (BODY_START as u64).into(),
 			}
 			.as_bytes(),
 		);
 
 		assert_eq!(wal_file.size(), file.len());
-		assert_buf_eq!(&file[GenericHeaderRepr::SIZE..], expected_body);
+		assert_buf_eq!(&file[BODY_START..], expected_body);
 	}
 
 	#[test]
 	fn push_commit_item() {
 		// given
 		let mut file = Vec::<u8>::new();
-		let mut wal_file = WalFile::create(Cursor::new(&mut file)).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(&mut file), ChecksumAlgorithm::Crc32, None).unwrap();
 
 		// when
 		wal_file
@@ -938,39 +1432,40 @@ mod tests {
 		// then
 		let mut expected_body = Vec::<u8>::new();
 		expected_body.extend(
-			ItemHeaderRepr {
+			ItemHeaderRepr::<LittleEndian> {
 				kind: ItemKind::Commit as u8,
 				flags: 0,
-				body_length: 24,
-				crc: 0x8b777949,
-				prev_item: NonZeroU64::new(0),
+				body_length: 24.into(),
+				crc: 0x8b777949.into(),
+				prev_item: 0.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			TransactionBlockRepr {
-				prev_transaction_generation: 123,
-				prev_transaction_offset: NonZeroU64::new(25),
-				transaction_id: 69,
+			TransactionBlockRepr::<LittleEndian> {
+				prev_transaction_generation: 123.into(),
+				prev_transaction_offset: 25.into(),
+				transaction_id: 69.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			ItemFooterRepr {
-				item_start: GenericHeaderRepr::SIZE as u64,
+			ItemFooterRepr::<LittleEndian> {
+				item_start: (BODY_START as u64).into(),
 			}
 			.as_bytes(),
 		);
 
 		assert_eq!(wal_file.size(), file.len());
-		assert_buf_eq!(&file[GenericHeaderRepr::SIZE..], expected_body);
+		assert_buf_eq!(&file[BODY_START..], expected_body);
 	}
 
 	#[test]
 	fn push_undo_item() {
 		// given
 		let mut file = Vec::<u8>::new();
-		let mut wal_file = WalFile::create(Cursor::new(&mut file)).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(&mut file), ChecksumAlgorithm::Crc32, None).unwrap();
 
 		// when
 		wal_file
@@ -990,49 +1485,50 @@ mod tests {
 		// then
 		let mut expected_body = Vec::<u8>::new();
 		expected_body.extend(
-			ItemHeaderRepr {
+			ItemHeaderRepr::<LittleEndian> {
 				kind: ItemKind::Write as u8,
 				flags: FLAG_UNDO,
-				body_length: 38,
-				crc: 0x1af2b54e,
-				prev_item: NonZeroU64::new(0),
+				body_length: 38.into(),
+				crc: 0x1af2b54e.into(),
+				prev_item: 0.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			TransactionBlockRepr {
-				prev_transaction_generation: 123,
-				prev_transaction_offset: NonZeroU64::new(24),
-				transaction_id: 25,
+			TransactionBlockRepr::<LittleEndian> {
+				prev_transaction_generation: 123.into(),
+				prev_transaction_offset: 24.into(),
+				transaction_id: 25.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			WriteBlockRepr {
-				offset: 445,
-				segment_num: 123,
-				page_num: 456,
-				write_length: 4,
+			WriteBlockRepr::<LittleEndian> {
+				offset: 445.into(),
+				segment_num: 123.into(),
+				page_num: 456.into(),
+				write_length: 4.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend([4, 5, 6, 7]);
 		expected_body.extend(
-			ItemFooterRepr {
-				item_start: GenericHeaderRepr::SIZE as u64,
+			ItemFooterRepr::<LittleEndian> {
+				item_start: (BODY_START as u64).into(),
 			}
 			.as_bytes(),
 		);
 
 		assert_eq!(wal_file.size(), file.len());
-		assert_buf_eq!(&file[GenericHeaderRepr::SIZE..], expected_body);
+		assert_buf_eq!(&file[BODY_START..], expected_body);
 	}
 
 	#[test]
 	fn push_checkpoint_item() {
 		// given
 		let mut file = Vec::<u8>::new();
-		let mut wal_file = WalFile::create(Cursor::new(&mut file)).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(&mut file), ChecksumAlgorithm::Crc32, None).unwrap();
 
 		// when
 		let mut dirty_pages = HashMap::new();
@@ -1056,60 +1552,61 @@ mod tests {
 		// then
 		let mut expected_body = Vec::<u8>::new();
 		expected_body.extend(
-			ItemHeaderRepr {
+			ItemHeaderRepr::<LittleEndian> {
 				kind: ItemKind::Checkpoint as u8,
 				flags: 0,
-				body_length: 70,
-				crc: 0x3420af22,
-				prev_item: NonZeroU64::new(0),
+				body_length: 70.into(),
+				crc: 0x3420af22.into(),
+				prev_item: 0.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			CheckpointBlockRepr {
-				num_dirty_pages: 1,
-				num_transactions: 1,
+			CheckpointBlockRepr::<LittleEndian> {
+				num_dirty_pages: 1.into(),
+				num_transactions: 1.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			PageAddressRepr {
-				segment_num: 1,
-				page_num: 2,
+			PageAddressRepr::<LittleEndian> {
+				segment_num: 1.into(),
+				page_num: 2.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			WalIndexRepr {
-				generation: 0,
-				offset: 3,
+			WalIndexRepr::<LittleEndian> {
+				generation: 0.into(),
+				offset: 3.into(),
 			}
 			.as_bytes(),
 		);
-		expected_body.extend(69_u64.to_ne_bytes());
+		expected_body.extend(69_u64.to_le_bytes());
 		expected_body.extend(
-			TransactionStateRepr {
-				first_generation: 0,
-				last_generation: 1,
-				last_offset: 420,
+			TransactionStateRepr::<LittleEndian> {
+				first_generation: 0.into(),
+				last_generation: 1.into(),
+				last_offset: 420.into(),
 			}
 			.as_bytes(),
 		);
 		expected_body.extend(
-			ItemFooterRepr {
-				item_start: GenericHeaderRepr::SIZE as u64,
+			ItemFooterRepr::<LittleEndian> {
+				item_start: (BODY_START as u64).into(),
 			}
 			.as_bytes(),
 		);
 
 		assert_eq!(wal_file.size(), file.len());
-		assert_buf_eq!(&file[GenericHeaderRepr::SIZE..], expected_body);
+		assert_buf_eq!(&file[BODY_START..], expected_body);
 	}
 
 	#[test]
 	fn write_and_read() {
 		// given
-		let mut wal_file = WalFile::create(Cursor::new(Vec::new())).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(Vec::new()), ChecksumAlgorithm::Crc32, None).unwrap();
 		let item = Item::Write(WriteData {
 			transaction_data: TransactionData {
 				transaction_id: 0,
@@ -1132,7 +1629,8 @@ mod tests {
 	#[test]
 	fn write_and_iter() {
 		// given
-		let mut wal_file = WalFile::create(Cursor::new(Vec::new())).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(Vec::new()), ChecksumAlgorithm::Crc32, None).unwrap();
 		let items = [
 			Item::Write(WriteData {
 				transaction_data: TransactionData {
@@ -1160,11 +1658,11 @@ mod tests {
 		let mut iter = wal_file.iter_items().unwrap();
 		assert_eq!(
 			iter.next().unwrap().unwrap(),
-			(non_zero!(9), items[0].clone())
+			(non_zero!(10), items[0].clone())
 		);
 		assert_eq!(
 			iter.next().unwrap().unwrap(),
-			(non_zero!(75), items[1].clone())
+			(non_zero!(76), items[1].clone())
 		);
 		assert!(iter.next().is_none());
 	}
@@ -1172,7 +1670,8 @@ mod tests {
 	#[test]
 	fn write_and_iter_reverse() {
 		// given
-		let mut wal_file = WalFile::create(Cursor::new(Vec::new())).unwrap();
+		let mut wal_file =
+			WalFile::create(Cursor::new(Vec::new()), ChecksumAlgorithm::Crc32, None).unwrap();
 		let items = [
 			Item::Write(WriteData {
 				transaction_data: TransactionData {
@@ -1200,11 +1699,11 @@ mod tests {
 		let mut iter = wal_file.iter_items_reverse().unwrap();
 		assert_eq!(
 			iter.next().unwrap().unwrap(),
-			(non_zero!(75), items[1].clone())
+			(non_zero!(76), items[1].clone())
 		);
 		assert_eq!(
 			iter.next().unwrap().unwrap(),
-			(non_zero!(9), items[0].clone())
+			(non_zero!(10), items[0].clone())
 		);
 		assert!(iter.next().is_none());
 	}
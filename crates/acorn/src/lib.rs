@@ -1,3 +1,36 @@
+//! Acorn is an embedded document database.
+//!
+//! A [`Database`] lives in a folder on disk, and every document in it matches
+//! the [`Schema`] it was created with. Documents are read and written in
+//! transactions, which are atomic and durable once they are committed:
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use acorn::{Database, Document, Options, Primitive, Schema, Value};
+//!
+//! # fn main() -> Result<(), acorn::Error> {
+//! # let tempdir = tempfile::tempdir().unwrap();
+//! # let path = tempdir.path().join("db");
+//! let schema = Schema::Struct(HashMap::from([(
+//!     "name".to_string(),
+//!     Box::new(Schema::Primitive(Primitive::String)),
+//! )]));
+//! let db = Database::create(&path, Options::new().schema(schema))?;
+//!
+//! let mut t = db.transaction()?;
+//! let id = t.insert(&Document::Struct(HashMap::from([(
+//!     "name".to_string(),
+//!     Document::Value(Value::String("acorn".to_string())),
+//! )])))?;
+//! t.commit()?;
+//!
+//! let t = db.transaction()?;
+//! assert!(t.get(id)?.is_some());
+//! # Ok(())
+//! # }
+//! ```
+
 // Lint config
 #![allow(dead_code)] // TODO: temporary
 #![cfg_attr(
@@ -12,9 +45,18 @@
 extern crate test;
 
 mod consts;
+mod database;
 mod doc_store;
 mod files;
 mod page_store;
 mod repr;
 mod tasks;
 mod utils;
+
+pub use database::{Database, DocumentId, Error, ErrorKind, Options, Reader, Transaction};
+pub use doc_store::{Document, HashablePrimitive, HashableValue, Primitive, Schema, Value};
+pub use files::{
+	checksum::ChecksumAlgorithm,
+	encryption::{EncryptionKey, KeyProvider},
+};
+pub use uuid::Uuid;
@@ -1,22 +1,25 @@
 use std::{
-	alloc::{alloc_zeroed, dealloc, Layout},
-	collections::HashMap,
+	alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout},
+	collections::{HashMap, HashSet},
 	marker::PhantomData,
 	mem,
 	num::NonZeroU64,
-	ptr::{self, NonNull},
+	ops::Index,
+	path::PathBuf,
+	ptr::NonNull,
 	sync::{
-		atomic::{AtomicBool, AtomicUsize, Ordering},
-		Arc,
+		atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+		Arc, OnceLock,
 	},
-	time::Duration,
+	task::{Poll, Waker},
+	time::{Duration, Instant},
 };
 
-use futures::executor::ThreadPool;
+use futures::{future, Future};
 use log::error;
 use parking_lot::{
-	lock_api::{RawRwLock as _, RawRwLockDowngrade},
-	Mutex, RawRwLock, RwLock, RwLockReadGuard,
+	lock_api::{RawRwLock as _, RawRwLockDowngrade, RawRwLockTimed},
+	Condvar, Mutex, RawRwLock, RwLock, RwLockReadGuard,
 };
 use static_assertions::assert_impl_all;
 
@@ -26,22 +29,43 @@ use mockall::automock;
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use crate::{
-	consts::{DEFAULT_FLUSH_PERIOD, DEFAULT_MAX_DIRTY_PAGES, DEFAULT_PAGE_CACHE_SIZE},
-	files::{segment::PAGE_BODY_SIZE, WalIndex},
-	tasks::{Timer, TimerHandle},
-	utils::cache::CacheReplacer,
+	consts::{
+		DEFAULT_EVICTION_TIMEOUT, DEFAULT_FLUSH_PERIOD, DEFAULT_MAX_DIRTY_PAGES,
+		DEFAULT_MAX_WRITE_RATE, DEFAULT_PAGE_CACHE_SIZE, DEFAULT_REPLACEMENT_POLICY,
+	},
+	files::WalIndex,
+	tasks::{FailureStrategy, JobKind, JobManager, Scheduler},
+	utils::cache::{trace::TraceRecorder, ReplacementPolicy, ReplacementPolicyKind},
 };
 
 use super::{
-	physical::{Op, PhysicalStorage, PhysicalStorageApi, WriteOp},
+	physical::{Op, PhysicalStorage, PhysicalStorageApi, ReadOp, WriteOp},
 	PageAddress, StorageError,
 };
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct PageCacheConfig {
 	pub page_cache_size: usize,
+	/// The fraction of cached pages that may be dirty. The background writer
+	/// starts writing pages back once half of that is reached.
 	pub max_dirty_pages: f32,
+	/// How often the background writer writes back all dirty pages
 	pub flush_period: Duration,
+	/// The maximum number of bytes per second that the background writer writes,
+	/// or `None` if it isn't limited. Explicit flushes aren't limited.
+	pub max_write_rate: Option<usize>,
+	/// How long storing a page waits for a cached page to be unlocked when
+	/// every cached page is locked, before failing with
+	/// [`StorageError::CacheExhausted`].
+	pub eviction_timeout: Duration,
+	/// The policy that decides which pages are evicted from the cache
+	pub replacement_policy: ReplacementPolicyKind,
+	/// If set, every page request is recorded to a trace at this path, see
+	/// [`crate::utils::cache::trace`].
+	pub trace_path: Option<PathBuf>,
+	/// How the background writer retries failed writes. Pages that can't be
+	/// written stay dirty, so by default, failures aren't fatal.
+	pub write_back_failures: FailureStrategy,
 }
 
 impl Default for PageCacheConfig {
@@ -50,6 +74,51 @@ impl Default for PageCacheConfig {
 			page_cache_size: DEFAULT_PAGE_CACHE_SIZE,
 			max_dirty_pages: DEFAULT_MAX_DIRTY_PAGES,
 			flush_period: DEFAULT_FLUSH_PERIOD,
+			max_write_rate: DEFAULT_MAX_WRITE_RATE,
+			eviction_timeout: DEFAULT_EVICTION_TIMEOUT,
+			replacement_policy: DEFAULT_REPLACEMENT_POLICY,
+			trace_path: None,
+			write_back_failures: FailureStrategy::default(),
+		}
+	}
+}
+
+/// Statistics about the page cache since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PageCacheStats {
+	/// The number of loads that found the page in the cache
+	pub hits: u64,
+	/// The number of loads that didn't find the page in the cache
+	pub misses: u64,
+	/// The number of pages that were evicted to make space for another page
+	pub evictions: u64,
+	/// The number of times storing a page had to wait because none of the
+	/// cached pages could be evicted
+	pub eviction_stalls: u64,
+	/// The number of those waits that ran into the eviction timeout
+	pub eviction_timeouts: u64,
+	/// The number of pages currently waiting to be flushed
+	pub dirty_pages: u64,
+}
+
+#[derive(Debug, Default)]
+struct AtomicPageCacheStats {
+	hits: AtomicU64,
+	misses: AtomicU64,
+	evictions: AtomicU64,
+	eviction_stalls: AtomicU64,
+	eviction_timeouts: AtomicU64,
+}
+
+impl AtomicPageCacheStats {
+	fn get(&self, dirty_pages: u64) -> PageCacheStats {
+		PageCacheStats {
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+			evictions: self.evictions.load(Ordering::Relaxed),
+			eviction_stalls: self.eviction_stalls.load(Ordering::Relaxed),
+			eviction_timeouts: self.eviction_timeouts.load(Ordering::Relaxed),
+			dirty_pages,
 		}
 	}
 }
@@ -93,65 +162,151 @@ impl BufferedPageHeader {
 }
 
 const HEADER_SIZE: usize = mem::size_of::<BufferedPageHeader>();
-const BUFFERED_PAGE_SIZE: usize = PAGE_BODY_SIZE + HEADER_SIZE;
 
+#[inline]
+const fn buffered_page_size(page_body_size: usize) -> usize {
+	page_body_size + HEADER_SIZE
+}
+
+/// The number of pages in each chunk of the page buffer. The memory of the
+/// buffer is allocated and released in chunks.
+const CHUNK_NUM_PAGES: usize = 64;
+
+/// The memory that buffers the cached pages. Chunks are only allocated once a
+/// page in them is used, so memory is committed as the cache fills up, not
+/// when it is created.
 struct PageBuffer {
-	buf: Option<NonNull<u8>>,
-	num_pages: usize,
+	/// The memory of each chunk, or `None` if the chunk isn't allocated
+	chunks: RwLock<Vec<Option<NonNull<u8>>>>,
+	page_size: usize,
+	num_pages: AtomicUsize,
 	num_filled: AtomicUsize,
 }
 
 impl PageBuffer {
-	fn new(num_pages: usize) -> Self {
-		let buf_size = num_pages * BUFFERED_PAGE_SIZE;
-		let buf = if buf_size != 0 {
-			// Safety: buf_size is guaranteed not to be zero, so the layout is not
-			// zero-sized.
-			unsafe { alloc_zeroed(Layout::from_size_align(buf_size, 1).unwrap()) }
-		} else {
-			ptr::null_mut()
-		};
+	fn new(page_size: usize, num_pages: usize) -> Self {
 		Self {
-			buf: NonNull::new(buf),
-			num_pages,
+			chunks: RwLock::new(Vec::new()),
+			page_size,
+			num_pages: AtomicUsize::new(num_pages),
 			num_filled: AtomicUsize::new(0),
 		}
 	}
 
+	fn chunk_layout(&self) -> Layout {
+		Layout::from_size_align(
+			CHUNK_NUM_PAGES * self.page_size,
+			mem::align_of::<BufferedPageHeader>(),
+		)
+		.unwrap()
+	}
+
+	fn num_pages(&self) -> usize {
+		self.num_pages.load(Ordering::Acquire)
+	}
+
+	/// Changes the number of pages that can be pushed. When the buffer shrinks,
+	/// pages beyond the new size stay accessible until their chunks are released.
+	///
+	/// This must not be called concurrently with `push_page`.
+	fn set_num_pages(&self, num_pages: usize) {
+		self.num_pages.store(num_pages, Ordering::Release);
+		let num_filled = self.num_filled.load(Ordering::Acquire);
+		self.num_filled
+			.store(usize::min(num_filled, num_pages), Ordering::Release);
+	}
+
+	/// The number of bytes that are currently allocated for pages.
+	fn allocated_size(&self) -> usize {
+		let num_allocated = self.chunks.read().iter().flatten().count();
+		num_allocated * CHUNK_NUM_PAGES * self.page_size
+	}
+
+	/// This must not be called concurrently with itself or `set_num_pages`.
 	fn push_page(&self) -> Option<usize> {
 		let num_filled = self.num_filled.load(Ordering::Acquire);
-		if num_filled == self.num_pages {
+		if num_filled >= self.num_pages() {
 			return None;
 		}
+
+		let chunk_index = num_filled / CHUNK_NUM_PAGES;
+		let mut chunks = self.chunks.write();
+		if chunks.len() <= chunk_index {
+			chunks.resize(chunk_index + 1, None);
+		}
+		if chunks[chunk_index].is_none() {
+			let layout = self.chunk_layout();
+			// Safety: The layout is not zero-sized, since pages aren't.
+			let chunk = unsafe { alloc_zeroed(layout) };
+			chunks[chunk_index] =
+				Some(NonNull::new(chunk).unwrap_or_else(|| handle_alloc_error(layout)));
+		}
+		mem::drop(chunks);
+
 		self.num_filled.store(num_filled + 1, Ordering::Release);
 		Some(num_filled)
 	}
 
-	fn page_ptr(&self, index: usize) -> Option<NonNull<u8>> {
-		if index >= self.num_pages {
-			return None;
+	/// Releases the chunks that lie entirely beyond the current number of pages.
+	/// Each chunk is released while holding the exclusive locks of all of its
+	/// pages, so that references to it can't outlive it. Chunks whose pages stay
+	/// locked until the deadline are kept, and released by a later call.
+	///
+	/// # Safety:
+	/// The cache must not hand out any page beyond the current number of pages.
+	unsafe fn release_chunks(&self, locks: &PageLocks, deadline: Instant) {
+		let first_released = self.num_pages().div_ceil(CHUNK_NUM_PAGES);
+		let num_chunks = self.chunks.read().len();
+		for chunk_index in first_released..num_chunks {
+			let page_indices = chunk_index * CHUNK_NUM_PAGES..(chunk_index + 1) * CHUNK_NUM_PAGES;
+			let mut locked = Vec::with_capacity(CHUNK_NUM_PAGES);
+			for index in page_indices {
+				if !locks[index].try_lock_exclusive_until(deadline) {
+					break;
+				}
+				locked.push(index);
+			}
+			if locked.len() == CHUNK_NUM_PAGES {
+				if let Some(chunk) = self.chunks.write()[chunk_index].take() {
+					dealloc(chunk.as_ptr(), self.chunk_layout());
+				}
+			}
+			for index in locked {
+				locks[index].unlock_exclusive();
+			}
 		}
-		// Safety: the resulting pointer is guaranteed to be in the allocated buffer.
-		Some(unsafe { self.buf?.add(index * BUFFERED_PAGE_SIZE) })
 	}
 
+	fn page_ptr(&self, index: usize) -> Option<NonNull<u8>> {
+		let chunk = (*self.chunks.read().get(index / CHUNK_NUM_PAGES)?)?;
+		// Safety: the resulting pointer is guaranteed to be in the allocated chunk.
+		Some(unsafe { chunk.add(index % CHUNK_NUM_PAGES * self.page_size) })
+	}
+
+	/// Returns `None` if the page's chunk isn't allocated.
+	///
 	/// # Safety:
 	/// The caller must ensure that no mutable reference to the same page
+	/// exists, and that the page's chunk isn't released while the reference
 	/// exists.
 	unsafe fn get_page(&self, index: usize) -> Option<&[u8]> {
 		Some(std::slice::from_raw_parts(
 			self.page_ptr(index)?.as_ptr(),
-			BUFFERED_PAGE_SIZE,
+			self.page_size,
 		))
 	}
 
+	/// Returns `None` if the page's chunk isn't allocated.
+	///
 	/// # Safety:
 	/// The caller must ensure that no shared reference, and no other mutable
-	/// references to the same page exist.
+	/// references to the same page exist, and that the page's chunk isn't
+	/// released while the reference exists.
+	#[allow(clippy::mut_from_ref)]
 	unsafe fn get_page_mut(&self, index: usize) -> Option<&mut [u8]> {
 		Some(std::slice::from_raw_parts_mut(
 			self.page_ptr(index)?.as_ptr(),
-			BUFFERED_PAGE_SIZE,
+			self.page_size,
 		))
 	}
 }
@@ -166,19 +321,97 @@ unsafe impl Sync for PageBuffer {}
 
 impl Drop for PageBuffer {
 	fn drop(&mut self) {
-		if let Some(buf) = self.buf {
-			let buf_size = self.num_pages * BUFFERED_PAGE_SIZE;
+		let layout = self.chunk_layout();
+		for chunk in self.chunks.get_mut().iter().flatten() {
 			// Safety:
-			// - `self.buf` is guaranteed not to be null
-			// - The buffer is never reallocated, so the layout stays the same
-			unsafe {
-				dealloc(
-					buf.as_ptr(),
-					Layout::from_size_align(buf_size, mem::align_of::<BufferedPageHeader>())
-						.unwrap(),
-				)
+			// - Chunks are always allocated with the same layout
+			// - Chunks are never reallocated
+			unsafe { dealloc(chunk.as_ptr(), layout) }
+		}
+	}
+}
+
+/// Wakes up evictions that are waiting for a cached page to be unlocked.
+#[derive(Default)]
+struct UnlockSignal {
+	num_unlocks: AtomicU64,
+	num_waiting: AtomicUsize,
+	mutex: Mutex<()>,
+	condvar: Condvar,
+}
+
+impl UnlockSignal {
+	fn num_unlocks(&self) -> u64 {
+		self.num_unlocks.load(Ordering::SeqCst)
+	}
+
+	fn notify(&self) {
+		self.num_unlocks.fetch_add(1, Ordering::SeqCst);
+		// Waiters register themselves before checking `num_unlocks` again, so either
+		// they see the increment, or we see them here.
+		if self.num_waiting.load(Ordering::SeqCst) != 0 {
+			let _guard = self.mutex.lock();
+			self.condvar.notify_all();
+		}
+	}
+
+	/// Waits until a page is unlocked after `num_unlocks` was read, or until the
+	/// deadline has passed. Returns `false` if the deadline has passed.
+	fn wait(&self, num_unlocks: u64, deadline: Instant) -> bool {
+		let mut guard = self.mutex.lock();
+		self.num_waiting.fetch_add(1, Ordering::SeqCst);
+		while self.num_unlocks() == num_unlocks {
+			if self.condvar.wait_until(&mut guard, deadline).timed_out() {
+				break;
 			}
 		}
+		self.num_waiting.fetch_sub(1, Ordering::SeqCst);
+		self.num_unlocks() != num_unlocks
+	}
+}
+
+/// The locks of the pages in the page buffer, along with a signal that is
+/// notified whenever a page guard is released.
+///
+/// Locks are allocated in chunks like the pages, but they are never released,
+/// since they are small compared to the pages.
+struct PageLocks {
+	chunks: RwLock<Vec<Box<[RawRwLock]>>>,
+	unlocked: UnlockSignal,
+}
+
+impl PageLocks {
+	fn new(num_pages: usize) -> Self {
+		let locks = Self {
+			chunks: RwLock::new(Vec::new()),
+			unlocked: UnlockSignal::default(),
+		};
+		locks.grow(num_pages);
+		locks
+	}
+
+	/// Makes sure that there are locks for at least `num_pages` pages.
+	fn grow(&self, num_pages: usize) {
+		let mut chunks = self.chunks.write();
+		while chunks.len() * CHUNK_NUM_PAGES < num_pages {
+			chunks.push(
+				std::iter::repeat_with(|| RawRwLock::INIT)
+					.take(CHUNK_NUM_PAGES)
+					.collect(),
+			);
+		}
+	}
+}
+
+impl Index<usize> for PageLocks {
+	type Output = RawRwLock;
+
+	fn index(&self, index: usize) -> &RawRwLock {
+		let chunks = self.chunks.read();
+		let lock: *const RawRwLock = &chunks[index / CHUNK_NUM_PAGES][index % CHUNK_NUM_PAGES];
+		// Safety: The chunks are boxed, so they don't move when more are added, and
+		// they are only dropped along with `self`.
+		unsafe { &*lock }
 	}
 }
 
@@ -186,6 +419,7 @@ impl Drop for PageBuffer {
 pub(crate) struct PageReadGuard<'a> {
 	page: &'a [u8],
 	lock: &'a RawRwLock,
+	unlocked: &'a UnlockSignal,
 	_marker: PhantomData<RwLockReadGuard<'a, [u8]>>,
 }
 
@@ -215,6 +449,7 @@ impl<'a> Drop for PageReadGuard<'a> {
 		// Safety: the existence of this object guarantees the lock is owned by the
 		// current context
 		unsafe { self.lock.unlock_shared() };
+		self.unlocked.notify();
 	}
 }
 
@@ -222,6 +457,7 @@ pub(crate) struct PageWriteGuard<'a> {
 	index: usize,
 	page: &'a mut [u8],
 	lock: &'a RawRwLock,
+	unlocked: &'a UnlockSignal,
 	_marker: PhantomData<RwLockReadGuard<'a, [u8]>>,
 }
 
@@ -269,21 +505,129 @@ impl<'a> Drop for PageWriteGuard<'a> {
 		// Safety: the existence of this object guarantees the lock is owned by the
 		// current context
 		unsafe { self.lock.unlock_exclusive() };
+		self.unlocked.notify();
+	}
+}
+
+/// The maximum number of pages the background writer writes in one batch
+const WRITE_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WriterWake {
+	/// Enough pages are dirty that some of them should be written back
+	DirtyPages,
+	/// All dirty pages should be written back
+	Flush,
+}
+
+#[derive(Debug, Default)]
+struct WriterState {
+	wake: Option<WriterWake>,
+	stopped: bool,
+	/// Wakes the writer task while it waits
+	waker: Option<Waker>,
+}
+
+/// Wakes the background writer, and tells it to stop once the cache is
+/// dropped.
+#[derive(Debug, Default)]
+struct WriterSignal {
+	state: Mutex<WriterState>,
+}
+
+impl WriterSignal {
+	fn wake(&self, wake: WriterWake) {
+		let mut state = self.state.lock();
+		if state.wake != Some(WriterWake::Flush) {
+			state.wake = Some(wake);
+		}
+		let waker = state.waker.take();
+		mem::drop(state);
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+
+	fn stop(&self) {
+		let mut state = self.state.lock();
+		state.stopped = true;
+		let waker = state.waker.take();
+		mem::drop(state);
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+
+	/// Returns a future that completes once `done` holds for the state.
+	fn until<'a>(
+		&'a self,
+		done: impl Fn(&WriterState) -> bool + 'a,
+	) -> impl Future<Output = ()> + Unpin + 'a {
+		future::poll_fn(move |cx| {
+			let mut state = self.state.lock();
+			if done(&state) {
+				return Poll::Ready(());
+			}
+			state.waker = Some(cx.waker().clone());
+			Poll::Pending
+		})
+	}
+
+	/// Waits until the writer is woken, or until `timeout` passes, which
+	/// counts as a flush. Returns `None` if the writer was stopped.
+	async fn wait(&self, scheduler: &dyn Scheduler, timeout: Duration) -> Option<WriterWake> {
+		let woken = self.until(|state| state.stopped || state.wake.is_some());
+		future::select(scheduler.sleep_until(scheduler.now() + timeout), woken).await;
+		let mut state = self.state.lock();
+		if state.stopped {
+			return None;
+		}
+		Some(state.wake.take().unwrap_or(WriterWake::Flush))
+	}
+
+	/// Sleeps until `deadline`. Returns `false` if the writer was stopped in the
+	/// meantime.
+	async fn sleep_until(&self, scheduler: &dyn Scheduler, deadline: Instant) -> bool {
+		let stopped = self.until(|state| state.stopped);
+		future::select(scheduler.sleep_until(deadline), stopped).await;
+		!self.state.lock().stopped
 	}
 }
 
+/// Makes the WAL items of all changes to cached pages durable, and returns the
+/// index before which all items are durable. No change may reach the segment
+/// files before the items needed to undo it, so pages that were changed at or
+/// after that index aren't written back.
+pub(crate) type WriteBarrier = Box<dyn Fn() -> Result<WalIndex, StorageError> + Send + Sync>;
+
 pub(crate) struct PageCache<PS: PhysicalStorageApi = PhysicalStorage> {
 	buf: Arc<PageBuffer>,
 	physical_storage: Arc<PS>,
-	thread_pool: Arc<ThreadPool>,
+	jobs: Arc<JobManager>,
 	indices: Arc<RwLock<HashMap<PageAddress, usize>>>,
-	replacer: RwLock<CacheReplacer<PageAddress>>,
-	scrap: Mutex<Vec<usize>>,
-	has_scrap: AtomicBool,
-	dirty_list: Arc<Mutex<Vec<PageAddress>>>,
-	locks: Arc<Box<[RawRwLock]>>,
-	max_num_dirty: usize,
-	flush_timer_handle: TimerHandle,
+	replacer: Arc<RwLock<Box<dyn ReplacementPolicy<PageAddress>>>>,
+	scrap: Arc<Mutex<Vec<usize>>>,
+	has_scrap: Arc<AtomicBool>,
+	/// The pages that may have been modified since they were last written back
+	dirty: Arc<Mutex<HashSet<PageAddress>>>,
+	/// Held while dirty pages are written back, so that `flush_sync` doesn't
+	/// return while the background writer is still writing a page.
+	write_lock: Arc<Mutex<()>>,
+	write_barrier: Arc<OnceLock<WriteBarrier>>,
+	locks: Arc<PageLocks>,
+	// The number of pins held on each pinned page. Pinned pages are never evicted,
+	// whether or not they are currently cached.
+	pins: Mutex<HashMap<PageAddress, usize>>,
+	max_num_pinned: AtomicUsize,
+	max_num_dirty: Arc<AtomicUsize>,
+	max_dirty_pages: f32,
+	eviction_timeout: Duration,
+	/// Serializes resizes of the cache
+	resize_lock: Mutex<()>,
+	stats: AtomicPageCacheStats,
+	read_only: bool,
+	writer_signal: Arc<WriterSignal>,
+	trace: Option<TraceRecorder>,
 }
 assert_impl_all!(PageCache: Send, Sync);
 
@@ -304,111 +648,306 @@ struct DirtyPage<'a> {
 impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCache<PS> {
 	pub fn new(
 		config: &PageCacheConfig,
+		page_body_size: usize,
+		physical_storage: Arc<PS>,
+		jobs: Arc<JobManager>,
+	) -> Self {
+		Self::new_impl(config, page_body_size, physical_storage, jobs, false)
+	}
+
+	/// Creates a page cache that never writes back to physical storage. Pages
+	/// can still be modified in memory, which is used to apply WAL redo without
+	/// touching the database files.
+	pub fn new_read_only(
+		config: &PageCacheConfig,
+		page_body_size: usize,
 		physical_storage: Arc<PS>,
-		thread_pool: Arc<ThreadPool>,
+		jobs: Arc<JobManager>,
 	) -> Self {
-		let num_pages = config.page_cache_size / BUFFERED_PAGE_SIZE;
-		let buf = Arc::new(PageBuffer::new(num_pages));
-		let replacer = CacheReplacer::new(num_pages);
+		Self::new_impl(config, page_body_size, physical_storage, jobs, true)
+	}
+
+	fn new_impl(
+		config: &PageCacheConfig,
+		page_body_size: usize,
+		physical_storage: Arc<PS>,
+		jobs: Arc<JobManager>,
+		read_only: bool,
+	) -> Self {
+		let page_size = buffered_page_size(page_body_size);
+		let num_pages = config.page_cache_size / page_size;
+		let buf = Arc::new(PageBuffer::new(page_size, num_pages));
+		let replacer = config.replacement_policy.create(num_pages);
+		let trace = config
+			.trace_path
+			.as_ref()
+			.and_then(|path| match TraceRecorder::create(path) {
+				Ok(trace) => Some(trace),
+				Err(err) => {
+					error!(
+						"Failed to create page cache trace at {}: {err}",
+						path.display()
+					);
+					None
+				}
+			});
 		let indices = Arc::new(RwLock::new(HashMap::new()));
-		let dirty_list = Arc::new(Mutex::new(Vec::new()));
-		let locks = Arc::new(
-			std::iter::repeat_with(|| RawRwLock::INIT)
-				.take(num_pages)
-				.collect(),
-		);
-
-		let (flush_timer, flush_timer_handle) = Timer::new(config.flush_period);
-		thread_pool.spawn_ok(Self::periodic_flush_task(
-			flush_timer,
-			Arc::clone(&physical_storage),
-			Arc::clone(&dirty_list),
-			Arc::clone(&indices),
-			Arc::clone(&locks),
-			Arc::clone(&buf),
-		));
+		let dirty = Arc::new(Mutex::new(HashSet::new()));
+		let write_lock = Arc::new(Mutex::new(()));
+		let write_barrier = Arc::new(OnceLock::new());
+		let locks = Arc::new(PageLocks::new(num_pages));
+		let max_num_dirty = Arc::new(AtomicUsize::new(Self::max_num_dirty(
+			num_pages,
+			config.max_dirty_pages,
+		)));
+		let writer_signal = Arc::new(WriterSignal::default());
+
+		if !read_only {
+			let writer = BackgroundWriter {
+				physical_storage: Arc::clone(&physical_storage),
+				dirty: Arc::clone(&dirty),
+				write_lock: Arc::clone(&write_lock),
+				write_barrier: Arc::clone(&write_barrier),
+				indices: Arc::clone(&indices),
+				locks: Arc::clone(&locks),
+				buf: Arc::clone(&buf),
+				signal: Arc::clone(&writer_signal),
+				jobs: Arc::clone(&jobs),
+				failure_strategy: config.write_back_failures.clone(),
+				max_num_dirty: Arc::clone(&max_num_dirty),
+				flush_period: config.flush_period,
+				max_write_rate: config.max_write_rate,
+				cursor: None,
+			};
+			let signal = Arc::downgrade(&writer_signal);
+			jobs.on_stop(move || {
+				if let Some(signal) = signal.upgrade() {
+					signal.stop();
+				}
+			});
+			jobs.spawn(writer.run());
+		}
 
 		Self {
 			buf,
 			physical_storage,
-			thread_pool,
-			replacer: RwLock::new(replacer),
+			jobs,
+			replacer: Arc::new(RwLock::new(replacer)),
 			indices,
-			scrap: Mutex::new(Vec::new()),
-			has_scrap: AtomicBool::new(false),
-			dirty_list,
+			scrap: Arc::new(Mutex::new(Vec::new())),
+			has_scrap: Arc::new(AtomicBool::new(false)),
+			dirty,
+			write_lock,
+			write_barrier,
 			locks,
-			#[allow(clippy::cast_possible_truncation)]
-			max_num_dirty: usize::max((num_pages as f32 * config.max_dirty_pages) as usize, 1),
-			flush_timer_handle,
+			pins: Mutex::new(HashMap::new()),
+			max_num_pinned: AtomicUsize::new(Self::max_num_pinned(num_pages)),
+			max_num_dirty,
+			max_dirty_pages: config.max_dirty_pages,
+			eviction_timeout: config.eviction_timeout,
+			resize_lock: Mutex::new(()),
+			stats: AtomicPageCacheStats::default(),
+			read_only,
+			writer_signal,
+			trace,
+		}
+	}
+
+	/// Sets the barrier that is passed before any page is written back. It can
+	/// only be set once.
+	pub fn set_write_barrier(&self, write_barrier: WriteBarrier) {
+		if self.write_barrier.set(write_barrier).is_err() {
+			panic!("The write barrier of the page cache was already set");
 		}
 	}
 
-	fn evict_for(&self, page_address: PageAddress) -> Option<PageAddress> {
+	fn max_num_pinned(num_pages: usize) -> usize {
+		// At least half of the cache is always left for unpinned pages, so that
+		// eviction can't run out of candidates.
+		num_pages / 2
+	}
+
+	fn max_num_dirty(num_pages: usize, max_dirty_pages: f32) -> usize {
+		#[allow(clippy::cast_possible_truncation)]
+		usize::max((num_pages as f32 * max_dirty_pages) as usize, 1)
+	}
+
+	fn evict_for(
+		&self,
+		page_address: PageAddress,
+		timeout: Duration,
+	) -> Result<Option<PageAddress>, StorageError> {
+		let mut num_unlocks = self.locks.unlocked.num_unlocks();
 		let mut replacer = self.replacer.write();
 		let mut maybe_evict = replacer.evict_replace(page_address);
 		mem::drop(replacer);
 
-		loop {
-			if let Some(evicted) = maybe_evict {
+		let mut num_skipped: usize = 0;
+		let mut deadline: Option<Instant> = None;
+		while let Some(evicted) = maybe_evict {
+			// If we are trying to evict the same page that we're inserting, or if the page
+			// we're trying to evict is currently locked or pinned, we reinsert it and try
+			// the next candidate.
+			if evicted != page_address {
 				let indices = self.indices.read();
-				let index = *indices
-					.get(&evicted)
-					.expect("Tried to evict a page that is not in the cache!");
-
-				// If we are trying to evict the same page that we're inserting, or if the page
-				// we're trying to evict is currently locked, we reinsert it and try the next
-				// candidate.
-				//
-				// Note that this ends up in an infinite loop if all pages in the cache are
-				// locked over an extended period, but that should rarely happen.
-				if evicted == page_address || self.locks[index].is_locked() {
-					let mut replacer = self.replacer.write();
-					maybe_evict = replacer.evict_replace(evicted);
-					continue;
+				let Some(index) = indices.get(&evicted).copied() else {
+					// The page was removed from the cache concurrently, which
+					// `get_store_index` deals with.
+					break;
+				};
+				mem::drop(indices);
+
+				if self.is_evictable(evicted, index) {
+					break;
+				}
+			}
+
+			// If none of the cached pages could be evicted, we wait until one of them is
+			// unlocked instead of spinning.
+			num_skipped += 1;
+			if num_skipped >= self.buf.num_pages() {
+				let deadline = *deadline.get_or_insert_with(|| {
+					self.stats.eviction_stalls.fetch_add(1, Ordering::Relaxed);
+					Instant::now() + timeout
+				});
+				if !self.locks.unlocked.wait(num_unlocks, deadline) {
+					self.stats.eviction_timeouts.fetch_add(1, Ordering::Relaxed);
+					// Everything but `evicted` is still in the replacer, along with the page we
+					// tried to insert, so we put `evicted` back in its place.
+					if evicted != page_address {
+						let mut replacer = self.replacer.write();
+						let replaced = replacer.replace(&page_address, evicted);
+						debug_assert!(replaced);
+					}
+					return Err(StorageError::CacheExhausted);
 				}
+				num_unlocks = self.locks.unlocked.num_unlocks();
+				num_skipped = 0;
 			}
-			break;
+
+			let mut replacer = self.replacer.write();
+			maybe_evict = replacer.evict_replace(evicted);
+		}
+		Ok(maybe_evict)
+	}
+
+	/// Whether the page in the slot can be dropped from the cache. Locked and
+	/// pinned pages can't, and neither can dirty ones, since their changes would
+	/// be lost; for those, the background writer is woken to write them back.
+	fn is_evictable(&self, page_address: PageAddress, index: usize) -> bool {
+		if self.pins.lock().contains_key(&page_address) {
+			return false;
 		}
-		maybe_evict
+		let lock = &self.locks[index];
+		if !lock.try_lock_shared() {
+			return false;
+		}
+		// Safety: The shared lock is held.
+		let dirty = unsafe { self.buf.get_page(index) }.is_some_and(|page| {
+			BufferedPageHeader::ref_from_bytes(&page[0..HEADER_SIZE])
+				.unwrap()
+				.dirty()
+		});
+		// The lock is released without notifying waiting evictions, since the page
+		// didn't become evictable by being looked at.
+		// Safety: The shared lock was acquired above.
+		unsafe { lock.unlock_shared() };
+		// Pages of a read-only cache are only dirty from recovery, which keeps
+		// them in its overlay as well.
+		if dirty && !self.read_only {
+			self.writer_signal.wake(WriterWake::Flush);
+			return false;
+		}
+		true
 	}
 
-	fn get_store_index(&self, page_address: PageAddress) -> usize {
+	fn get_store_index(
+		&self,
+		page_address: PageAddress,
+		timeout: Duration,
+	) -> Result<usize, StorageError> {
+		self.record(page_address);
+
 		let indices = self.indices.read();
 		if let Some(stored_index) = indices.get(&page_address).copied() {
-			return stored_index;
+			return Ok(stored_index);
 		}
 		mem::drop(indices);
 
-		if self.has_scrap.load(Ordering::Relaxed) {
-			let mut scrap = self.scrap.lock();
-			if let Some(scrap_index) = scrap.pop() {
-				return scrap_index;
+		loop {
+			let maybe_evict = self.evict_for(page_address, timeout)?;
+			let mut indices = self.indices.write();
+			let index = if let Some(evict) = maybe_evict {
+				let index = indices.remove(&evict);
+				if index.is_some() {
+					self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+				}
+				index
+			} else {
+				self.pop_scrap().or_else(|| self.buf.push_page())
+			};
+			if let Some(index) = index {
+				indices.insert(page_address, index);
+				return Ok(index);
+			}
+			mem::drop(indices);
+
+			// The evicted page was removed from the cache concurrently, and its slot was
+			// taken already. The page is removed from the replacer again, so that we can
+			// retry.
+			self.replacer.write().remove(&page_address);
+		}
+	}
+
+	/// Takes a scrapped slot. Scrapped slots are only taken while holding the
+	/// write lock of `indices`.
+	fn pop_scrap(&self) -> Option<usize> {
+		if !self.has_scrap.load(Ordering::Relaxed) {
+			return None;
+		}
+		let mut scrap = self.scrap.lock();
+		let num_pages = self.buf.num_pages();
+		while let Some(index) = scrap.pop() {
+			// Slots beyond the size of the cache are released instead of reused.
+			if index < num_pages {
+				return Some(index);
 			}
 		}
+		self.has_scrap.store(false, Ordering::Relaxed);
+		None
+	}
 
-		let maybe_evict = self.evict_for(page_address);
-		let mut indices = self.indices.write();
-		if let Some(evict) = maybe_evict {
-			let index = indices
-				.remove(&evict)
-				.expect("Tried to evict a page that is not in the cache!");
-			indices.insert(page_address, index);
-			index
-		} else {
-			let index = self
-				.buf
-				.push_page()
-				.expect("Failed to evict a page when the buffer was full!");
-			indices.insert(page_address, index);
-			index
+	/// Locks the slot that `get_store_index` returned for a page. Returns `None`
+	/// if the page was moved out of the slot in the meantime.
+	fn lock_store_index(
+		&self,
+		page_address: PageAddress,
+		index: usize,
+	) -> Option<PageWriteGuard<'_>> {
+		let guard = Self::load_mut_direct(&self.locks, &self.buf, index)?;
+		if self.indices.read().get(&page_address) != Some(&index) {
+			return None;
+		}
+		Some(guard)
+	}
+
+	fn record(&self, page_address: PageAddress) {
+		if let Some(trace) = &self.trace {
+			trace.record(
+				u64::from(page_address.segment_num) << 16 | u64::from(page_address.page_num.get()),
+			);
 		}
 	}
 
 	fn get_load_index(&self, page_address: PageAddress) -> Option<usize> {
 		let indices = self.indices.read();
-		let index = indices.get(&page_address).copied()?;
+		let Some(index) = indices.get(&page_address).copied() else {
+			self.stats.misses.fetch_add(1, Ordering::Relaxed);
+			return None;
+		};
 		mem::drop(indices);
+		self.stats.hits.fetch_add(1, Ordering::Relaxed);
+		self.record(page_address);
 
 		let replacer = self.replacer.read();
 		let access_successful = replacer.access(&page_address);
@@ -418,69 +957,147 @@ impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCache<PS> {
 		Some(index)
 	}
 
+	/// Returns `None` if the slot's memory was released, because the cache was
+	/// shrunk.
 	fn load_direct<'a>(
-		locks: &'a [RawRwLock],
+		locks: &'a PageLocks,
+		buf: &'a PageBuffer,
+		index: usize,
+	) -> Option<PageReadGuard<'a>> {
+		locks[index].lock_shared();
+		Self::read_guard(locks, buf, index)
+	}
+
+	/// Creates the guard for a slot whose shared lock is held.
+	fn read_guard<'a>(
+		locks: &'a PageLocks,
 		buf: &'a PageBuffer,
 		index: usize,
-	) -> PageReadGuard<'a> {
+	) -> Option<PageReadGuard<'a>> {
 		let lock = &locks[index];
-		lock.lock_shared();
 		// Safety: The safety of the reference is guaranteed by acquiring the shared
 		// lock.
-		let page =
-			unsafe { buf.get_page(index) }.expect("Tried to index page buffer out of bounds!");
+		let Some(page) = (unsafe { buf.get_page(index) }) else {
+			// Safety: The caller acquired the lock.
+			unsafe { lock.unlock_shared() };
+			return None;
+		};
 
-		PageReadGuard {
+		Some(PageReadGuard {
 			lock,
+			unlocked: &locks.unlocked,
 			page,
 			_marker: PhantomData,
-		}
+		})
 	}
 
+	/// Returns `None` if the slot's memory was released, because the cache was
+	/// shrunk.
 	fn load_mut_direct<'a>(
-		locks: &'a [RawRwLock],
+		locks: &'a PageLocks,
 		buf: &'a PageBuffer,
 		index: usize,
-	) -> PageWriteGuard<'a> {
+	) -> Option<PageWriteGuard<'a>> {
 		let lock = &locks[index];
 		lock.lock_exclusive();
+		Self::write_guard(locks, buf, index)
+	}
+
+	/// Like `load_mut_direct`, but gives up if the slot isn't unlocked before the
+	/// deadline.
+	fn try_load_mut_direct_until<'a>(
+		locks: &'a PageLocks,
+		buf: &'a PageBuffer,
+		index: usize,
+		deadline: Instant,
+	) -> Option<PageWriteGuard<'a>> {
+		if !locks[index].try_lock_exclusive_until(deadline) {
+			return None;
+		}
+		Self::write_guard(locks, buf, index)
+	}
+
+	/// Creates the guard for a slot whose exclusive lock is held.
+	fn write_guard<'a>(
+		locks: &'a PageLocks,
+		buf: &'a PageBuffer,
+		index: usize,
+	) -> Option<PageWriteGuard<'a>> {
+		let lock = &locks[index];
 		// Safety: The safety of the reference is guaranteed by acquiring the exclusive
 		// lock.
-		let page =
-			unsafe { buf.get_page_mut(index) }.expect("Triet to index page buffer out of bounds!");
+		let Some(page) = (unsafe { buf.get_page_mut(index) }) else {
+			// Safety: The caller acquired the lock.
+			unsafe { lock.unlock_exclusive() };
+			return None;
+		};
 
-		PageWriteGuard {
+		Some(PageWriteGuard {
 			index,
 			lock,
+			unlocked: &locks.unlocked,
 			page,
 			_marker: PhantomData,
-		}
+		})
 	}
 
-	fn flush(
+	/// Writes the given pages back to physical storage if they are dirty, and
+	/// marks them as clean. Unless `wait` is set, pages that are locked are
+	/// skipped, and stay dirty. Returns the number of pages that were written.
+	#[allow(clippy::too_many_arguments)]
+	fn write_back(
 		physical_storage: &PS,
-		dirty_list: &Mutex<Vec<PageAddress>>,
+		write_barrier: Option<&WriteBarrier>,
+		dirty: &Mutex<HashSet<PageAddress>>,
 		indices: &RwLock<HashMap<PageAddress, usize>>,
-		locks: &[RawRwLock],
+		locks: &PageLocks,
 		buf: &PageBuffer,
-	) -> Result<(), StorageError> {
-		let mut dirty_list_guard = dirty_list.lock();
-		let dirty_list_copy = dirty_list_guard.clone();
-		dirty_list_guard.clear();
-		mem::drop(dirty_list_guard);
-
-		let mut error: Option<StorageError> = None;
-		let mut dirty_pages: Vec<DirtyPage> = Vec::with_capacity(dirty_list_copy.len());
-
-		for page_address in dirty_list_copy.iter() {
-			let indices = indices.read();
-			let Some(index) = indices.get(page_address).copied() else {
+		page_addresses: &[PageAddress],
+		wait: bool,
+	) -> Result<usize, StorageError> {
+		let mut dirty_guard = dirty.lock();
+		for page_address in page_addresses {
+			dirty_guard.remove(page_address);
+		}
+		mem::drop(dirty_guard);
+
+		// The barrier is passed before any page is locked, since making the WAL
+		// durable may have to wait for a thread that holds page locks.
+		let durable = match write_barrier
+			.map(|write_barrier| write_barrier())
+			.transpose()
+		{
+			Ok(durable) => durable,
+			Err(err) => {
+				dirty.lock().extend(page_addresses);
+				return Err(err);
+			}
+		};
+
+		let mut skipped: Vec<PageAddress> = Vec::new();
+		let mut dirty_pages: Vec<DirtyPage> = Vec::with_capacity(page_addresses.len());
+		for page_address in page_addresses {
+			let Some(index) = indices.read().get(page_address).copied() else {
 				continue;
 			};
-			mem::drop(indices);
 
-			let guard = Self::load_direct(locks, buf, index);
-			if !guard.header().dirty() {
+			let lock = &locks[index];
+			if wait {
+				lock.lock_shared();
+			} else if !lock.try_lock_shared() {
+				skipped.push(*page_address);
+				continue;
+			}
+			let Some(guard) = Self::read_guard(locks, buf, index) else {
+				continue;
+			};
+			// The page may have been evicted before we locked it.
+			if indices.read().get(page_address) != Some(&index) || !guard.header().dirty() {
+				continue;
+			}
+			// The page was changed after passing the barrier.
+			if durable.is_some_and(|durable| guard.header().wal_index() >= durable) {
+				skipped.push(*page_address);
 				continue;
 			}
 
@@ -491,69 +1108,238 @@ impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCache<PS> {
 			});
 		}
 
-		let ops: Vec<Op> = dirty_pages
-			.iter()
-			.map(|dp| {
-				Op::Write(WriteOp {
-					wal_index: dp.guard.header().wal_index(),
-					page_address: dp.page_address,
-					buf: dp.guard.body(),
+		let result = if dirty_pages.is_empty() {
+			Ok(())
+		} else {
+			let ops: Vec<Op> = dirty_pages
+				.iter()
+				.map(|dp| {
+					Op::Write(WriteOp {
+						wal_index: dp.guard.header().wal_index(),
+						page_address: dp.page_address,
+						buf: dp.guard.body(),
+					})
 				})
-			})
-			.collect();
+				.collect();
+			physical_storage.batch(ops.into())
+		};
 
-		if let Err(err) = physical_storage.batch(ops.into()) {
-			error = Some(err);
+		let written: Vec<(PageAddress, usize, WalIndex)> = dirty_pages
+			.into_iter()
+			.map(|dp| (dp.page_address, dp.index, dp.guard.header().wal_index()))
+			.collect();
+		if let Err(err) = result {
+			dirty.lock().extend(page_addresses);
+			return Err(err);
 		}
 
-		for dirty_page in dirty_pages.into_iter() {
-			mem::drop(dirty_page.guard);
-			let mut guard_mut = Self::load_mut_direct(locks, buf, dirty_page.index);
-			guard_mut.header_mut().set_dirty(false);
+		for (page_address, index, wal_index) in &written {
+			let lock = &locks[*index];
+			if wait {
+				lock.lock_exclusive();
+			} else if !lock.try_lock_exclusive() {
+				skipped.push(*page_address);
+				continue;
+			}
+			let Some(mut guard) = Self::write_guard(locks, buf, *index) else {
+				continue;
+			};
+			// The page may have been modified or evicted since it was written, in which
+			// case it has to stay dirty.
+			if indices.read().get(page_address) == Some(index)
+				&& guard.header().wal_index() == *wal_index
+			{
+				guard.header_mut().set_dirty(false);
+			}
 		}
 
-		if let Some(err) = error {
-			let mut dirty_list_guard = dirty_list.lock();
-			dirty_list_guard.extend(&dirty_list_copy);
-			return Err(err);
+		if !skipped.is_empty() {
+			dirty.lock().extend(skipped);
 		}
-
-		Ok(())
+		Ok(written.len())
 	}
 
-	async fn flush_ok(
-		physical_storage: &PS,
-		dirty_list: &Mutex<Vec<PageAddress>>,
-		indices: &RwLock<HashMap<PageAddress, usize>>,
-		locks: &[RawRwLock],
-		buf: &PageBuffer,
-	) {
-		if let Err(err) = Self::flush(physical_storage, dirty_list, indices, locks, buf) {
-			error!("Page cache flush failed: {err}");
+	/// Records that a page may be modified, and wakes the background writer if
+	/// too many pages are dirty.
+	fn mark_dirty(&self, page_address: PageAddress) {
+		let mut dirty = self.dirty.lock();
+		dirty.insert(page_address);
+		let num_dirty = dirty.len();
+		mem::drop(dirty);
+
+		// The writer starts at half of the allowed dirty pages, so that it can keep
+		// up before the limit is reached.
+		if num_dirty >= usize::max(self.max_num_dirty.load(Ordering::Relaxed) / 2, 1) {
+			self.writer_signal.wake(WriterWake::DirtyPages);
 		}
 	}
 
-	async fn single_flush_task(
+	/// Reads the given pages into their reserved slots. The slots must have been
+	/// locked exclusively by `prefetch`, and are unlocked once they are filled.
+	/// If the read fails, the slots are scrapped, and the pages will be read again
+	/// when they are accessed.
+	#[allow(clippy::too_many_arguments)]
+	async fn prefetch_task(
+		pages: Vec<(PageAddress, usize)>,
 		physical_storage: Arc<PS>,
-		dirty_list: Arc<Mutex<Vec<PageAddress>>>,
 		indices: Arc<RwLock<HashMap<PageAddress, usize>>>,
-		locks: Arc<Box<[RawRwLock]>>,
+		replacer: Arc<RwLock<Box<dyn ReplacementPolicy<PageAddress>>>>,
+		locks: Arc<PageLocks>,
 		buf: Arc<PageBuffer>,
+		scrap: Arc<Mutex<Vec<usize>>>,
+		has_scrap: Arc<AtomicBool>,
 	) {
-		Self::flush_ok(&physical_storage, &dirty_list, &indices, &locks, &buf).await;
+		let mut page_bufs: Vec<&mut [u8]> = pages
+			.iter()
+			.map(|(_, index)| {
+				// Safety: The slot was locked exclusively by `prefetch`, which handed the
+				// lock over to this task.
+				unsafe { buf.get_page_mut(*index) }
+					.expect("Tried to index page buffer out of bounds!")
+			})
+			.collect();
+		let mut wal_indices: Vec<Option<WalIndex>> = vec![None; pages.len()];
+
+		let ops: Box<[Op]> = pages
+			.iter()
+			.zip(page_bufs.iter_mut())
+			.zip(wal_indices.iter_mut())
+			.map(|(((page_address, _), page), wal_index)| {
+				Op::Read(ReadOp {
+					page_address: *page_address,
+					wal_index,
+					buf: &mut page[HEADER_SIZE..],
+				})
+			})
+			.collect();
+		let result = physical_storage.batch(ops);
+
+		if let Err(err) = &result {
+			error!("Page prefetch failed: {err}");
+			let mut indices = indices.write();
+			let mut replacer = replacer.write();
+			let mut scrap = scrap.lock();
+			for (page_address, index) in &pages {
+				if indices.get(page_address) == Some(index) {
+					indices.remove(page_address);
+					replacer.remove(page_address);
+					scrap.push(*index);
+				}
+			}
+			has_scrap.store(true, Ordering::Relaxed);
+		} else {
+			for (page, wal_index) in page_bufs.iter_mut().zip(wal_indices) {
+				let header = BufferedPageHeader::mut_from_bytes(&mut page[0..HEADER_SIZE]).unwrap();
+				if let Some(wal_index) = wal_index {
+					header.set_wal_index(wal_index);
+				}
+				header.set_dirty(false);
+			}
+		}
+
+		mem::drop(page_bufs);
+		for (_, index) in &pages {
+			// Safety: The lock was acquired by `prefetch`, and handed over to this task.
+			unsafe { locks[*index].unlock_exclusive() };
+		}
+		locks.unlocked.notify();
 	}
+}
 
-	async fn periodic_flush_task(
-		timer: Timer,
-		physical_storage: Arc<PS>,
-		dirty_list: Arc<Mutex<Vec<PageAddress>>>,
-		indices: Arc<RwLock<HashMap<PageAddress, usize>>>,
-		locks: Arc<Box<[RawRwLock]>>,
-		buf: Arc<PageBuffer>,
-	) {
-		while timer.wait() {
-			Self::flush_ok(&physical_storage, &dirty_list, &indices, &locks, &buf).await;
+impl<PS: PhysicalStorageApi> Drop for PageCache<PS> {
+	fn drop(&mut self) {
+		self.writer_signal.stop();
+	}
+}
+
+/// Writes dirty pages back to physical storage in the background. Pages are
+/// written in the order of their addresses, so that adjacent pages can be
+/// merged into a single write, and the rate of writes can be limited, so that
+/// the writer doesn't starve other IO.
+struct BackgroundWriter<PS: PhysicalStorageApi> {
+	physical_storage: Arc<PS>,
+	dirty: Arc<Mutex<HashSet<PageAddress>>>,
+	write_lock: Arc<Mutex<()>>,
+	write_barrier: Arc<OnceLock<WriteBarrier>>,
+	indices: Arc<RwLock<HashMap<PageAddress, usize>>>,
+	locks: Arc<PageLocks>,
+	buf: Arc<PageBuffer>,
+	signal: Arc<WriterSignal>,
+	jobs: Arc<JobManager>,
+	failure_strategy: FailureStrategy,
+	max_num_dirty: Arc<AtomicUsize>,
+	flush_period: Duration,
+	max_write_rate: Option<usize>,
+	/// The page that was written last. The next pass continues after it, so
+	/// that pages with high addresses aren't starved.
+	cursor: Option<PageAddress>,
+}
+
+impl<PS: PhysicalStorageApi + Send + Sync + 'static> BackgroundWriter<PS> {
+	async fn run(mut self) {
+		while let Some(wake) = self
+			.signal
+			.wait(self.jobs.scheduler(), self.flush_period)
+			.await
+		{
+			// Failures are logged by the job manager, and the pages stay dirty.
+			let _ = self.write_pass(wake).await;
+		}
+	}
+
+	/// Writes back dirty pages in batches. A flush writes all of them, otherwise
+	/// the pass stops once only a quarter of the allowed dirty pages are left.
+	async fn write_pass(&mut self, wake: WriterWake) -> Result<(), StorageError> {
+		let target = match wake {
+			WriterWake::DirtyPages => self.max_num_dirty.load(Ordering::Relaxed) / 4,
+			WriterWake::Flush => 0,
+		};
+
+		let mut page_addresses: Vec<PageAddress> = self.dirty.lock().iter().copied().collect();
+		page_addresses.sort_unstable();
+		if let Some(cursor) = self.cursor {
+			let start = page_addresses.partition_point(|page_address| *page_address <= cursor);
+			page_addresses.rotate_left(start);
+		}
+
+		let start = self.jobs.scheduler().now();
+		let mut num_written: usize = 0;
+		for batch in page_addresses.chunks(WRITE_BATCH_SIZE) {
+			if self.dirty.lock().len() <= target {
+				break;
+			}
+
+			num_written += self
+				.jobs
+				.run(JobKind::WriteBack, &self.failure_strategy, || {
+					let _write_guard = self.write_lock.lock();
+					PageCache::write_back(
+						&*self.physical_storage,
+						self.write_barrier.get(),
+						&self.dirty,
+						&self.indices,
+						&self.locks,
+						&self.buf,
+						batch,
+						false,
+					)
+				})
+				.await?;
+			self.cursor = batch.last().copied();
+
+			if let Some(max_write_rate) = self.max_write_rate {
+				let bytes_written = num_written * self.buf.page_size;
+				let due = Duration::from_secs_f64(bytes_written as f64 / max_write_rate as f64);
+				if !self
+					.signal
+					.sleep_until(self.jobs.scheduler(), start + due)
+					.await
+				{
+					break;
+				}
+			}
 		}
+		Ok(())
 	}
 }
 
@@ -573,11 +1359,31 @@ pub(crate) trait PageCacheApi {
 	fn has_page(&self, page_address: PageAddress) -> bool;
 	fn load<'a>(&'a self, page_address: PageAddress) -> Option<Self::ReadGuard<'a>>;
 	fn load_mut<'a>(&'a self, page_address: PageAddress) -> Option<Self::WriteGuard<'a>>;
-	fn store<'a>(&'a self, page_address: PageAddress) -> Self::WriteGuard<'a>;
+	fn store<'a>(&'a self, page_address: PageAddress)
+		-> Result<Self::WriteGuard<'a>, StorageError>;
 	fn flush(&self);
 	fn flush_sync(&self) -> Result<(), StorageError>;
 	fn scrap(&self, page_address: PageAddress);
 	fn downgrade_guard<'a>(&'a self, guard: Self::WriteGuard<'a>) -> Self::ReadGuard<'a>;
+
+	/// Keeps the page from being evicted until it is unpinned as often as it was
+	/// pinned. The page doesn't need to be cached yet; once it is loaded, it stays.
+	fn pin(&self, page_address: PageAddress) -> Result<(), StorageError>;
+	fn unpin(&self, page_address: PageAddress);
+
+	/// Loads all of the given pages that aren't cached yet in the background.
+	/// Accessing one of them while it is being loaded waits for the load to
+	/// finish.
+	fn prefetch(&self, page_addresses: &[PageAddress]);
+
+	/// Changes the size of the cache in bytes. When the cache shrinks, the pages
+	/// that no longer fit are written back if they are dirty and dropped from
+	/// the cache, even if they are pinned. Shrinking fails with
+	/// [`StorageError::CacheExhausted`] if one of those pages stays locked for
+	/// longer than the eviction timeout, in which case it can be retried.
+	fn resize(&self, page_cache_size: usize) -> Result<(), StorageError>;
+
+	fn stats(&self) -> PageCacheStats;
 }
 
 impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCacheApi for PageCache<PS> {
@@ -591,55 +1397,62 @@ impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCacheApi for PageCache<
 
 	fn load(&self, page_address: PageAddress) -> Option<PageReadGuard<'_>> {
 		let index = self.get_load_index(page_address)?;
-		Some(Self::load_direct(&self.locks, &self.buf, index))
+		Self::load_direct(&self.locks, &self.buf, index)
 	}
 
 	fn load_mut(&self, page_address: PageAddress) -> Option<Self::WriteGuard<'_>> {
 		let index = self.get_load_index(page_address)?;
-		Some(Self::load_mut_direct(&self.locks, &self.buf, index))
+		let guard = Self::load_mut_direct(&self.locks, &self.buf, index)?;
+		if !self.read_only {
+			self.mark_dirty(page_address);
+		}
+		Some(guard)
 	}
 
-	fn store(&self, page_address: PageAddress) -> PageWriteGuard<'_> {
-		let mut dirty_list = self.dirty_list.lock();
-		dirty_list.push(page_address);
-		if dirty_list.len() >= self.max_num_dirty {
-			self.thread_pool.spawn_ok(Self::single_flush_task(
-				Arc::clone(&self.physical_storage),
-				Arc::clone(&self.dirty_list),
-				Arc::clone(&self.indices),
-				Arc::clone(&self.locks),
-				Arc::clone(&self.buf),
-			));
+	fn store(&self, page_address: PageAddress) -> Result<PageWriteGuard<'_>, StorageError> {
+		// The page can be moved out of its slot by a concurrent resize before the
+		// slot is locked, in which case we have to find it a new one.
+		let guard = loop {
+			let index = self.get_store_index(page_address, self.eviction_timeout)?;
+			if let Some(guard) = self.lock_store_index(page_address, index) {
+				break guard;
+			}
+		};
+		// The page is only marked while it is locked, so that the background writer
+		// can't miss the modification.
+		if !self.read_only {
+			self.mark_dirty(page_address);
 		}
-		mem::drop(dirty_list);
-
-		let index = self.get_store_index(page_address);
-		Self::load_mut_direct(&self.locks, &self.buf, index)
+		Ok(guard)
 	}
 
 	fn flush(&self) {
-		let physical_storage = Arc::clone(&self.physical_storage);
-		let dirty_list = Arc::clone(&self.dirty_list);
-		let indices = Arc::clone(&self.indices);
-		let locks = Arc::clone(&self.locks);
-		let buf = Arc::clone(&self.buf);
-		self.thread_pool.spawn_ok(Self::single_flush_task(
-			physical_storage,
-			dirty_list,
-			indices,
-			locks,
-			buf,
-		))
+		if self.read_only {
+			return;
+		}
+		self.writer_signal.wake(WriterWake::Flush);
 	}
 
 	fn flush_sync(&self) -> Result<(), StorageError> {
-		Self::flush(
-			&self.physical_storage,
-			&self.dirty_list,
-			&self.indices,
-			&self.locks,
-			&self.buf,
-		)
+		if self.read_only {
+			return Ok(());
+		}
+		let _write_guard = self.write_lock.lock();
+		let mut page_addresses: Vec<PageAddress> = self.dirty.lock().iter().copied().collect();
+		page_addresses.sort_unstable();
+		for batch in page_addresses.chunks(WRITE_BATCH_SIZE) {
+			Self::write_back(
+				&self.physical_storage,
+				self.write_barrier.get(),
+				&self.dirty,
+				&self.indices,
+				&self.locks,
+				&self.buf,
+				batch,
+				true,
+			)?;
+		}
+		Ok(())
 	}
 
 	fn scrap(&self, page_address: PageAddress) {
@@ -647,14 +1460,14 @@ impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCacheApi for PageCache<
 		let Some(index) = indices.remove(&page_address) else {
 			return;
 		};
-		mem::drop(indices);
-
-		self.has_scrap.store(true, Ordering::Relaxed);
+		self.replacer.write().remove(&page_address);
 		self.scrap.lock().push(index);
+		self.has_scrap.store(true, Ordering::Relaxed);
 	}
 
 	fn downgrade_guard<'a>(&'a self, guard: PageWriteGuard<'a>) -> PageReadGuard<'a> {
 		let lock = guard.lock;
+		let unlocked = guard.unlocked;
 		// Safety: the existance of the PageWriteGuard guarantees that the lock is owned
 		// in the current context
 		unsafe { lock.downgrade() };
@@ -667,21 +1480,179 @@ impl<PS: PhysicalStorageApi + Send + Sync + 'static> PageCacheApi for PageCache<
 		PageReadGuard {
 			page,
 			lock,
+			unlocked,
 			_marker: PhantomData,
 		}
 	}
+
+	fn pin(&self, page_address: PageAddress) -> Result<(), StorageError> {
+		let mut pins = self.pins.lock();
+		if let Some(num_pins) = pins.get_mut(&page_address) {
+			*num_pins += 1;
+			return Ok(());
+		}
+		if pins.len() >= self.max_num_pinned.load(Ordering::Relaxed) {
+			return Err(StorageError::PinLimitReached);
+		}
+		pins.insert(page_address, 1);
+		Ok(())
+	}
+
+	fn unpin(&self, page_address: PageAddress) {
+		let mut pins = self.pins.lock();
+		let Some(num_pins) = pins.get_mut(&page_address) else {
+			return;
+		};
+		*num_pins -= 1;
+		if *num_pins == 0 {
+			pins.remove(&page_address);
+		}
+	}
+
+	fn prefetch(&self, page_addresses: &[PageAddress]) {
+		let mut pages: Vec<(PageAddress, usize)> = Vec::with_capacity(page_addresses.len());
+		for &page_address in page_addresses {
+			if self.has_page(page_address) {
+				continue;
+			}
+			// Prefetching is only a hint, so it doesn't wait for cached pages to be
+			// unlocked.
+			let Ok(index) = self.get_store_index(page_address, Duration::ZERO) else {
+				break;
+			};
+			let Some(guard) = self.lock_store_index(page_address, index) else {
+				continue;
+			};
+			// The slot stays locked until the page is read, so that concurrent
+			// accesses wait for it instead of seeing the previous page. The lock is
+			// handed over to the prefetch task.
+			mem::forget(guard);
+			pages.push((page_address, index));
+		}
+		if pages.is_empty() {
+			return;
+		}
+
+		self.jobs.spawn(Self::prefetch_task(
+			pages,
+			Arc::clone(&self.physical_storage),
+			Arc::clone(&self.indices),
+			Arc::clone(&self.replacer),
+			Arc::clone(&self.locks),
+			Arc::clone(&self.buf),
+			Arc::clone(&self.scrap),
+			Arc::clone(&self.has_scrap),
+		));
+	}
+
+	fn resize(&self, page_cache_size: usize) -> Result<(), StorageError> {
+		let _resize_guard = self.resize_lock.lock();
+		let num_pages = page_cache_size / self.buf.page_size;
+		let old_num_pages = self.buf.num_pages();
+
+		self.max_num_pinned
+			.store(Self::max_num_pinned(num_pages), Ordering::Relaxed);
+		self.max_num_dirty.store(
+			Self::max_num_dirty(num_pages, self.max_dirty_pages),
+			Ordering::Relaxed,
+		);
+
+		if num_pages >= old_num_pages {
+			self.locks.grow(num_pages);
+			let indices = self.indices.write();
+			self.buf.set_num_pages(num_pages);
+			self.replacer.write().resize(num_pages);
+			mem::drop(indices);
+			return Ok(());
+		}
+
+		// From here on, no new pages are put into the slots beyond the new size, but
+		// pages can still be moved into slots that are freed by evicting a page.
+		let indices = self.indices.write();
+		self.buf.set_num_pages(num_pages);
+		self.replacer.write().resize(num_pages);
+		mem::drop(indices);
+
+		let deadline = Instant::now() + self.eviction_timeout;
+		loop {
+			let pages: Vec<(PageAddress, usize)> = self
+				.indices
+				.read()
+				.iter()
+				.filter(|(_, index)| **index >= num_pages)
+				.map(|(page_address, index)| (*page_address, *index))
+				.collect();
+			if pages.is_empty() {
+				break;
+			}
+
+			let mut guards: Vec<(PageAddress, PageWriteGuard)> = Vec::with_capacity(pages.len());
+			for (page_address, index) in pages {
+				let Some(guard) =
+					Self::try_load_mut_direct_until(&self.locks, &self.buf, index, deadline)
+				else {
+					return Err(StorageError::CacheExhausted);
+				};
+				guards.push((page_address, guard));
+			}
+			// Pages may have been evicted or scrapped before we locked them.
+			let indices = self.indices.read();
+			guards.retain(|(page_address, guard)| indices.get(page_address) == Some(&guard.index));
+			mem::drop(indices);
+
+			if !self.read_only {
+				let ops: Vec<Op> = guards
+					.iter()
+					.filter(|(_, guard)| guard.header().dirty())
+					.map(|(page_address, guard)| {
+						Op::Write(WriteOp {
+							wal_index: guard.header().wal_index(),
+							page_address: *page_address,
+							buf: guard.body(),
+						})
+					})
+					.collect();
+				if !ops.is_empty() {
+					self.physical_storage.batch(ops.into())?;
+				}
+			}
+
+			let mut indices = self.indices.write();
+			let mut replacer = self.replacer.write();
+			for (page_address, _) in &guards {
+				indices.remove(page_address);
+				replacer.remove(page_address);
+			}
+			self.scrap.lock().retain(|index| *index < num_pages);
+		}
+
+		// Safety: No page is stored beyond `num_pages` anymore, and no new ones will
+		// be.
+		unsafe { self.buf.release_chunks(&self.locks, deadline) };
+		Ok(())
+	}
+
+	fn stats(&self) -> PageCacheStats {
+		let dirty_pages = self.dirty.lock().len() as u64;
+		self.stats.get(dirty_pages)
+	}
 }
 
 #[cfg(test)]
 mod tests {
+	use std::sync::mpsc;
+
+	use futures::executor::ThreadPool;
 	use pretty_assertions::assert_buf_eq;
 
 	use crate::{
+		files::{segment::DEFAULT_PAGE_BODY_SIZE, FileError},
 		page_store::{
 			physical::MockPhysicalStorageApi,
 			test_helpers::{page_address, wal_index},
 		},
-		utils::units::MIB,
+		tasks::test_helpers::SimulatedScheduler,
+		utils::{cache::trace::read_trace, units::MIB},
 	};
 
 	use super::*;
@@ -694,17 +1665,19 @@ mod tests {
 				page_cache_size: 2 * MIB,
 				..Default::default()
 			},
+			DEFAULT_PAGE_BODY_SIZE,
 			Arc::new(MockPhysicalStorageApi::new()),
-			Arc::new(ThreadPool::new().unwrap()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
 		);
 
 		// when
-		let expected_page = [69; PAGE_BODY_SIZE];
+		let expected_page = [69; DEFAULT_PAGE_BODY_SIZE];
 		cache
 			.store(page_address!(69, 420))
+			.unwrap()
 			.write(0, &expected_page, wal_index!(1, 2));
 
-		let mut received_page = [0; PAGE_BODY_SIZE];
+		let mut received_page = [0; DEFAULT_PAGE_BODY_SIZE];
 		cache
 			.load(page_address!(69, 420))
 			.unwrap()
@@ -722,8 +1695,9 @@ mod tests {
 				page_cache_size: 2 * MIB,
 				..Default::default()
 			},
+			DEFAULT_PAGE_BODY_SIZE,
 			Arc::new(MockPhysicalStorageApi::new()),
-			Arc::new(ThreadPool::new().unwrap()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
 		);
 
 		// when
@@ -738,25 +1712,26 @@ mod tests {
 		// given
 		let cache = PageCache::new(
 			&PageCacheConfig {
-				page_cache_size: 4 * BUFFERED_PAGE_SIZE,
+				page_cache_size: 4 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
 				..Default::default()
 			},
+			DEFAULT_PAGE_BODY_SIZE,
 			Arc::new(MockPhysicalStorageApi::new()),
-			Arc::new(ThreadPool::new().unwrap()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
 		);
 
 		// when
-		cache.store(page_address!(1, 1)); // add 1, 1 to recent
-		cache.store(page_address!(2, 2)); // add 2, 2 to recent
-		cache.store(page_address!(3, 3)); // add 3, 3 to recent
-		cache.store(page_address!(4, 4)); // add 4, 4 to recent
+		cache.store(page_address!(1, 1)).unwrap(); // add 1, 1 to recent
+		cache.store(page_address!(2, 2)).unwrap(); // add 2, 2 to recent
+		cache.store(page_address!(3, 3)).unwrap(); // add 3, 3 to recent
+		cache.store(page_address!(4, 4)).unwrap(); // add 4, 4 to recent
 		cache.load(page_address!(1, 1)); // 1, 1 was referenced in recent
 		cache.load(page_address!(2, 2)); // 2, 2 was referenced in recent
 		cache.load(page_address!(1, 1)); // 1, 1 is promoted to frequent
 
 		// recent is large, therefore 3, 3 is evicted as it is the first
 		// non-referenced item in frequent
-		cache.store(page_address!(5, 5));
+		cache.store(page_address!(5, 5)).unwrap();
 
 		// then
 		assert!(cache.load(page_address!(1, 1)).is_some());
@@ -771,25 +1746,26 @@ mod tests {
 		// given
 		let cache = PageCache::new(
 			&PageCacheConfig {
-				page_cache_size: 4 * BUFFERED_PAGE_SIZE,
+				page_cache_size: 4 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
 				..Default::default()
 			},
+			DEFAULT_PAGE_BODY_SIZE,
 			Arc::new(MockPhysicalStorageApi::new()),
-			Arc::new(ThreadPool::new().unwrap()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
 		);
 
 		// when
-		cache.store(page_address!(1, 1)); // add 1, 1 to recent
-		cache.store(page_address!(2, 2)); // add 2, 2 to recent
-		let guard = cache.store(page_address!(3, 3)); // add 3, 3 to recent
-		cache.store(page_address!(4, 4)); // add 4, 4 to recent
+		cache.store(page_address!(1, 1)).unwrap(); // add 1, 1 to recent
+		cache.store(page_address!(2, 2)).unwrap(); // add 2, 2 to recent
+		let guard = cache.store(page_address!(3, 3)).unwrap(); // add 3, 3 to recent
+		cache.store(page_address!(4, 4)).unwrap(); // add 4, 4 to recent
 		cache.load(page_address!(1, 1)); // 1, 1 was referenced in recent
 		cache.load(page_address!(2, 2)); // 2, 2 was referenced in recent
 		cache.load(page_address!(1, 1)); // 1, 1 is promoted to frequent
 
 		// recent is large, therefore 3, 3 would be evicted, but it is locked, so 4, 4
 		// is evicted instead
-		cache.store(page_address!(5, 5));
+		cache.store(page_address!(5, 5)).unwrap();
 
 		mem::drop(guard);
 
@@ -800,4 +1776,737 @@ mod tests {
 		assert!(cache.load(page_address!(4, 4)).is_none());
 		assert!(cache.load(page_address!(5, 5)).is_some());
 	}
+
+	#[test]
+	fn doesnt_evict_dirty_page() {
+		// given
+		let mut physical_storage = MockPhysicalStorageApi::new();
+		physical_storage.expect_batch().returning(|_| Ok(()));
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 4 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical_storage),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+
+		// when
+		cache.store(page_address!(1, 1)).unwrap(); // add 1, 1 to recent
+		cache.store(page_address!(2, 2)).unwrap(); // add 2, 2 to recent
+		cache
+			.store(page_address!(3, 3))
+			.unwrap()
+			.write(0, &[69], wal_index!(1, 2)); // add 3, 3 to recent
+		cache.store(page_address!(4, 4)).unwrap(); // add 4, 4 to recent
+		cache.load(page_address!(1, 1)); // 1, 1 was referenced in recent
+		cache.load(page_address!(2, 2)); // 2, 2 was referenced in recent
+		cache.load(page_address!(1, 1)); // 1, 1 is promoted to frequent
+
+		// recent is large, therefore 3, 3 would be evicted, but it wasn't written back
+		// yet, so 4, 4 is evicted instead
+		cache.store(page_address!(5, 5)).unwrap();
+
+		// then
+		assert!(cache.load(page_address!(1, 1)).is_some());
+		assert!(cache.load(page_address!(2, 2)).is_some());
+		assert!(cache.load(page_address!(3, 3)).is_some());
+		assert!(cache.load(page_address!(4, 4)).is_none());
+		assert!(cache.load(page_address!(5, 5)).is_some());
+	}
+
+	#[test]
+	fn doesnt_evict_pinned_page() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 4 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+
+		// when
+		cache.pin(page_address!(3, 3)).unwrap();
+		cache.store(page_address!(1, 1)).unwrap(); // add 1, 1 to recent
+		cache.store(page_address!(2, 2)).unwrap(); // add 2, 2 to recent
+		cache.store(page_address!(3, 3)).unwrap(); // add 3, 3 to recent
+		cache.store(page_address!(4, 4)).unwrap(); // add 4, 4 to recent
+		cache.load(page_address!(1, 1)); // 1, 1 was referenced in recent
+		cache.load(page_address!(2, 2)); // 2, 2 was referenced in recent
+		cache.load(page_address!(1, 1)); // 1, 1 is promoted to frequent
+
+		// recent is large, therefore 3, 3 would be evicted, but it is pinned, so 4, 4
+		// is evicted instead
+		cache.store(page_address!(5, 5)).unwrap();
+
+		// then
+		assert!(cache.load(page_address!(1, 1)).is_some());
+		assert!(cache.load(page_address!(2, 2)).is_some());
+		assert!(cache.load(page_address!(3, 3)).is_some());
+		assert!(cache.load(page_address!(4, 4)).is_none());
+		assert!(cache.load(page_address!(5, 5)).is_some());
+	}
+
+	#[test]
+	fn evict_unpinned_page() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 4 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		cache.pin(page_address!(3, 3)).unwrap();
+		cache.pin(page_address!(3, 3)).unwrap();
+
+		// when
+		cache.unpin(page_address!(3, 3));
+		cache.unpin(page_address!(3, 3));
+		cache.store(page_address!(1, 1)).unwrap();
+		cache.store(page_address!(2, 2)).unwrap();
+		cache.store(page_address!(3, 3)).unwrap();
+		cache.store(page_address!(4, 4)).unwrap();
+		cache.load(page_address!(1, 1));
+		cache.load(page_address!(2, 2));
+		cache.load(page_address!(1, 1));
+		cache.store(page_address!(5, 5)).unwrap();
+
+		// then
+		assert!(cache.load(page_address!(3, 3)).is_none());
+		assert!(cache.load(page_address!(4, 4)).is_some());
+	}
+
+	#[test]
+	fn try_pin_too_many_pages() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 4 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		cache.pin(page_address!(1, 1)).unwrap();
+		cache.pin(page_address!(2, 2)).unwrap();
+
+		// when
+		let result = cache.pin(page_address!(3, 3));
+
+		// then
+		assert!(matches!(result, Err(StorageError::PinLimitReached)));
+		assert!(cache.pin(page_address!(1, 1)).is_ok());
+	}
+
+	#[test]
+	fn try_store_page_when_all_pages_are_locked() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				eviction_timeout: Duration::from_millis(10),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		let guard = cache.store(page_address!(1, 1)).unwrap();
+		let other_guard = cache.store(page_address!(2, 2)).unwrap();
+
+		// when
+		let result = cache.store(page_address!(3, 3));
+
+		// then
+		assert!(matches!(result, Err(StorageError::CacheExhausted)));
+		let stats = cache.stats();
+		assert_eq!(
+			stats,
+			PageCacheStats {
+				eviction_stalls: 1,
+				eviction_timeouts: 1,
+				dirty_pages: stats.dirty_pages,
+				..Default::default()
+			}
+		);
+		mem::drop(guard);
+		mem::drop(other_guard);
+		cache.store(page_address!(3, 3)).unwrap();
+		assert!(cache.has_page(page_address!(3, 3)));
+	}
+
+	#[test]
+	fn wait_for_page_to_be_unlocked() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		let guard = cache.store(page_address!(1, 1)).unwrap();
+		let other_guard = cache.store(page_address!(2, 2)).unwrap();
+
+		// when
+		let stored = std::thread::scope(|scope| {
+			let handle = scope.spawn(|| cache.store(page_address!(3, 3)).is_ok());
+			while cache.stats().eviction_stalls == 0 {
+				std::thread::yield_now();
+			}
+			mem::drop(guard);
+			handle.join().unwrap()
+		});
+
+		// then
+		assert!(stored);
+		assert!(!cache.has_page(page_address!(1, 1)));
+		let stats = cache.stats();
+		assert_eq!(
+			stats,
+			PageCacheStats {
+				evictions: 1,
+				eviction_stalls: 1,
+				eviction_timeouts: 0,
+				dirty_pages: stats.dirty_pages,
+				..Default::default()
+			}
+		);
+		mem::drop(other_guard);
+	}
+
+	#[test]
+	fn count_hits_misses_and_evictions() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * buffered_page_size(DEFAULT_PAGE_BODY_SIZE),
+				// keep the background writer from running
+				max_dirty_pages: 4.0,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+
+		// when
+		cache.store(page_address!(1, 1)).unwrap();
+		cache.load(page_address!(1, 1)).unwrap();
+		assert!(cache.load_mut(page_address!(2, 2)).is_none());
+		cache.store(page_address!(2, 2)).unwrap();
+		cache.store(page_address!(3, 3)).unwrap();
+
+		// then
+		assert_eq!(
+			cache.stats(),
+			PageCacheStats {
+				hits: 1,
+				misses: 1,
+				evictions: 1,
+				eviction_stalls: 0,
+				eviction_timeouts: 0,
+				dirty_pages: 3,
+			}
+		);
+	}
+
+	#[test]
+	fn record_trace() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let trace_path = tempdir.path().join("trace");
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				trace_path: Some(trace_path.clone()),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+
+		// when
+		cache.store(page_address!(1, 2)).unwrap();
+		cache.load(page_address!(1, 2)).unwrap();
+		assert!(cache.load(page_address!(3, 4)).is_none());
+		mem::drop(cache);
+
+		// then
+		assert_eq!(
+			read_trace(&trace_path).unwrap(),
+			vec![1 << 16 | 2, 1 << 16 | 2]
+		);
+	}
+
+	#[test]
+	fn prefetch_pages() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		physical
+			.expect_batch()
+			.once()
+			.withf(|ops| {
+				ops.len() == 2
+					&& matches!(&ops[0], Op::Read(op) if op.page_address == page_address!(1, 2))
+					&& matches!(&ops[1], Op::Read(op) if op.page_address == page_address!(3, 4))
+			})
+			.returning(|mut ops| {
+				for (i, op) in ops.iter_mut().enumerate() {
+					let Op::Read(read_op) = op else {
+						panic!("Expected only read ops");
+					};
+					read_op.buf.fill(i as u8 + 1);
+					*read_op.wal_index = Some(wal_index!(69, 420));
+				}
+				Ok(())
+			});
+
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			// the background writer occupies one thread of the pool
+			Arc::new(JobManager::new(Arc::new(
+				ThreadPool::builder().pool_size(2).create().unwrap(),
+			))),
+		);
+		cache.store(page_address!(5, 6)).unwrap();
+
+		// when
+		cache.prefetch(&[
+			page_address!(1, 2),
+			page_address!(5, 6),
+			page_address!(3, 4),
+		]);
+
+		// then
+		let guard = cache.load(page_address!(1, 2)).unwrap();
+		assert_buf_eq!(guard.body(), [1; DEFAULT_PAGE_BODY_SIZE]);
+		assert_eq!(guard.header().wal_index(), wal_index!(69, 420));
+		assert!(!guard.header().dirty());
+		let guard = cache.load(page_address!(3, 4)).unwrap();
+		assert_buf_eq!(guard.body(), [2; DEFAULT_PAGE_BODY_SIZE]);
+	}
+
+	#[test]
+	fn scrap_pages_if_prefetch_fails() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		physical
+			.expect_batch()
+			.once()
+			.returning(|_| Err(StorageError::File(FileError::ChecksumMismatch)));
+
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			// the background writer occupies one thread of the pool
+			Arc::new(JobManager::new(Arc::new(
+				ThreadPool::builder().pool_size(2).create().unwrap(),
+			))),
+		);
+
+		// when
+		cache.prefetch(&[page_address!(1, 2)]);
+
+		// then
+		while cache.has_page(page_address!(1, 2)) {
+			std::thread::yield_now();
+		}
+		assert!(cache.load(page_address!(1, 2)).is_none());
+	}
+
+	#[test]
+	fn allocate_pages_lazily() {
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		assert_eq!(cache.buf.allocated_size(), 0);
+
+		// when
+		cache.store(page_address!(1, 1)).unwrap();
+
+		// then
+		assert_eq!(
+			cache.buf.allocated_size(),
+			CHUNK_NUM_PAGES * buffered_page_size(DEFAULT_PAGE_BODY_SIZE)
+		);
+	}
+
+	#[test]
+	fn grow_cache() {
+		// given
+		let page_size = buffered_page_size(DEFAULT_PAGE_BODY_SIZE);
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * page_size,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(MockPhysicalStorageApi::new()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		cache.store(page_address!(1, 1)).unwrap();
+		cache.store(page_address!(2, 2)).unwrap();
+
+		// when
+		cache
+			.resize(CHUNK_NUM_PAGES * page_size + 2 * page_size)
+			.unwrap();
+		for i in 3..CHUNK_NUM_PAGES as u16 + 3 {
+			cache.store(page_address!(i.into(), i)).unwrap();
+		}
+
+		// then
+		for i in 1..CHUNK_NUM_PAGES as u16 + 3 {
+			assert!(cache.has_page(page_address!(i.into(), i)));
+		}
+		assert_eq!(cache.buf.allocated_size(), 2 * CHUNK_NUM_PAGES * page_size);
+	}
+
+	#[test]
+	fn shrink_cache() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		physical
+			.expect_batch()
+			.once()
+			.withf(|ops| {
+				ops.len() == 1
+					&& matches!(
+						&ops[0],
+						Op::Write(op) if op.page_address == page_address!(1, 1)
+							&& op.buf == [69; DEFAULT_PAGE_BODY_SIZE]
+					)
+			})
+			.returning(|_| Ok(()));
+
+		// given
+		let page_size = buffered_page_size(DEFAULT_PAGE_BODY_SIZE);
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * CHUNK_NUM_PAGES * page_size,
+				max_dirty_pages: 2.0,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		for i in 2..CHUNK_NUM_PAGES as u16 + 2 {
+			cache.store(page_address!(2, i)).unwrap();
+		}
+		cache.store(page_address!(1, 1)).unwrap().write(
+			0,
+			&[69; DEFAULT_PAGE_BODY_SIZE],
+			wal_index!(1, 2),
+		);
+
+		// when
+		cache.resize(CHUNK_NUM_PAGES * page_size).unwrap();
+
+		// then
+		assert!(!cache.has_page(page_address!(1, 1)));
+		for i in 2..CHUNK_NUM_PAGES as u16 + 2 {
+			assert!(cache.has_page(page_address!(2, i)));
+		}
+		assert_eq!(cache.buf.allocated_size(), CHUNK_NUM_PAGES * page_size);
+		cache.store(page_address!(1, 1)).unwrap();
+		assert_eq!(cache.indices.read().len(), CHUNK_NUM_PAGES);
+	}
+
+	fn written_pages(ops: &[Op]) -> Vec<PageAddress> {
+		ops.iter()
+			.map(|op| match op {
+				Op::Write(write_op) => write_op.page_address,
+				Op::Read(..) => panic!("Expected only write ops"),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn flush_pages_in_address_order() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		physical
+			.expect_batch()
+			.once()
+			.withf(|ops| {
+				written_pages(ops)
+					== [
+						page_address!(1, 2),
+						page_address!(1, 5),
+						page_address!(2, 1),
+						page_address!(3, 2),
+					]
+			})
+			.returning(|_| Ok(()));
+
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				max_dirty_pages: 1.0,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		for page_address in [
+			page_address!(3, 2),
+			page_address!(1, 5),
+			page_address!(2, 1),
+			page_address!(1, 2),
+		] {
+			cache.store(page_address).unwrap().write(
+				0,
+				&[69; DEFAULT_PAGE_BODY_SIZE],
+				wal_index!(1, 2),
+			);
+		}
+		cache.load(page_address!(1, 5)).unwrap();
+
+		// when
+		cache.flush_sync().unwrap();
+
+		// then
+		assert_eq!(cache.stats().dirty_pages, 0);
+		assert!(!cache.load(page_address!(1, 2)).unwrap().header().dirty());
+		cache.flush_sync().unwrap();
+	}
+
+	#[test]
+	fn track_pages_modified_after_flush() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		physical
+			.expect_batch()
+			.times(2)
+			.withf(|ops| written_pages(ops) == [page_address!(1, 1)])
+			.returning(|_| Ok(()));
+
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				max_dirty_pages: 1.0,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		cache.store(page_address!(1, 1)).unwrap().write(
+			0,
+			&[69; DEFAULT_PAGE_BODY_SIZE],
+			wal_index!(1, 2),
+		);
+		cache.flush_sync().unwrap();
+
+		// when
+		cache.load_mut(page_address!(1, 1)).unwrap().write(
+			0,
+			&[42; DEFAULT_PAGE_BODY_SIZE],
+			wal_index!(1, 3),
+		);
+
+		// then
+		assert_eq!(cache.stats().dirty_pages, 1);
+		cache.flush_sync().unwrap();
+	}
+
+	#[test]
+	fn keep_pages_dirty_if_flush_fails() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		physical
+			.expect_batch()
+			.once()
+			.returning(|_| Err(StorageError::File(FileError::Unexpected)));
+
+		// given
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * MIB,
+				max_dirty_pages: 1.0,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+		cache.store(page_address!(1, 1)).unwrap().write(
+			0,
+			&[69; DEFAULT_PAGE_BODY_SIZE],
+			wal_index!(1, 2),
+		);
+
+		// when
+		let result = cache.flush_sync();
+
+		// then
+		assert!(result.is_err());
+		assert_eq!(cache.stats().dirty_pages, 1);
+		assert!(cache.load(page_address!(1, 1)).unwrap().header().dirty());
+	}
+
+	#[test]
+	fn write_back_pages_in_background() {
+		// expect
+		let (sender, receiver) = mpsc::channel();
+		let sender = Mutex::new(sender);
+		let mut physical = MockPhysicalStorageApi::new();
+		physical.expect_batch().returning(move |ops| {
+			sender.lock().send(written_pages(&ops)).unwrap();
+			Ok(())
+		});
+
+		// given
+		let page_size = buffered_page_size(DEFAULT_PAGE_BODY_SIZE);
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				// The writer starts once 4 pages are dirty
+				page_cache_size: 16 * page_size,
+				max_dirty_pages: 0.5,
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
+		);
+
+		// when
+		for i in (1..=4).rev() {
+			cache.store(page_address!(1, i)).unwrap().write(
+				0,
+				&[69; DEFAULT_PAGE_BODY_SIZE],
+				wal_index!(1, 2),
+			);
+		}
+
+		// then
+		let written = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+		assert!(!written.is_empty());
+		assert!(written.is_sorted());
+		assert!(cache.stats().dirty_pages < 4);
+	}
+
+	#[test]
+	fn write_back_pages_periodically() {
+		// expect
+		let num_written = Arc::new(AtomicUsize::new(0));
+		let mut physical = MockPhysicalStorageApi::new();
+		let recorded = Arc::clone(&num_written);
+		physical.expect_batch().returning(move |ops| {
+			recorded.fetch_add(written_pages(&ops).len(), Ordering::Relaxed);
+			Ok(())
+		});
+
+		// given
+		let scheduler = Arc::new(SimulatedScheduler::new());
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: MIB,
+				flush_period: Duration::from_secs(1),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::clone(&scheduler) as Arc<dyn Scheduler>)),
+		);
+		scheduler.run_until_stalled();
+		cache.store(page_address!(1, 1)).unwrap().write(
+			0,
+			&[69; DEFAULT_PAGE_BODY_SIZE],
+			wal_index!(1, 2),
+		);
+
+		// when
+		scheduler.advance(Duration::from_millis(999));
+		let written_before = num_written.load(Ordering::Relaxed);
+		scheduler.advance(Duration::from_millis(1));
+
+		// then
+		assert_eq!(written_before, 0);
+		assert_eq!(num_written.load(Ordering::Relaxed), 1);
+		assert_eq!(cache.stats().dirty_pages, 0);
+	}
+
+	#[test]
+	fn limit_background_write_rate() {
+		// expect
+		let batch_sizes = Arc::new(Mutex::new(Vec::new()));
+		let mut physical = MockPhysicalStorageApi::new();
+		let recorded = Arc::clone(&batch_sizes);
+		physical.expect_batch().times(2).returning(move |ops| {
+			recorded.lock().push(ops.len());
+			Ok(())
+		});
+
+		// given
+		let scheduler = Arc::new(SimulatedScheduler::new());
+		let page_size = buffered_page_size(DEFAULT_PAGE_BODY_SIZE);
+		let num_pages = WRITE_BATCH_SIZE + 1;
+		let cache = PageCache::new(
+			&PageCacheConfig {
+				page_cache_size: 2 * num_pages * page_size,
+				max_dirty_pages: 1.0,
+				// One batch every 200ms
+				max_write_rate: Some(5 * WRITE_BATCH_SIZE * page_size),
+				..Default::default()
+			},
+			DEFAULT_PAGE_BODY_SIZE,
+			Arc::new(physical),
+			Arc::new(JobManager::new(Arc::clone(&scheduler) as Arc<dyn Scheduler>)),
+		);
+		for i in 1..=num_pages as u16 {
+			cache.store(page_address!(1, i)).unwrap().write(
+				0,
+				&[69; DEFAULT_PAGE_BODY_SIZE],
+				wal_index!(1, 2),
+			);
+		}
+
+		// when
+		cache.flush();
+		scheduler.run_until_stalled();
+		let first_batches = batch_sizes.lock().clone();
+		scheduler.advance(Duration::from_millis(199));
+		let batches_before_due = batch_sizes.lock().len();
+		scheduler.advance(Duration::from_millis(1));
+
+		// then
+		assert_eq!(first_batches, vec![WRITE_BATCH_SIZE]);
+		assert_eq!(batches_before_due, 1);
+		assert_eq!(*batch_sizes.lock(), vec![WRITE_BATCH_SIZE, 1]);
+	}
 }
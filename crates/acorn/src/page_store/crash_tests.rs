@@ -0,0 +1,326 @@
+//! Runs random transactions against a page storage whose files fail and lose
+//! power, and checks that recovery restores exactly the committed state.
+
+use std::{collections::HashMap, mem, sync::Arc, time::Duration};
+
+use futures::executor::ThreadPool;
+use tempfile::tempdir;
+
+use crate::{
+	files::{
+		fault::FaultyFolder,
+		segment::SegmentConfig,
+		test_helpers::{page_address, StaticKeyProvider},
+		DatabaseFolder,
+	},
+	utils::units::{KIB, MIB},
+};
+
+use super::*;
+
+type FaultyStorage = PageStorage<
+	PhysicalStorage<FaultyFolder>,
+	PageCache<PhysicalStorage<FaultyFolder>>,
+	Wal<FaultyFolder>,
+>;
+
+const NUM_SEGMENTS: u32 = 2;
+const PAGES_PER_SEGMENT: u16 = 8;
+const NUM_CYCLES: usize = 20;
+const TRANSACTIONS_PER_CYCLE: usize = 30;
+const MAX_REBOOTS: usize = 10;
+
+/// The committed contents of all pages that were written
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Model {
+	pages: HashMap<PageAddress, Vec<u8>>,
+}
+
+impl Model {
+	fn page(&self, page_address: PageAddress, body_size: usize) -> Vec<u8> {
+		self.pages
+			.get(&page_address)
+			.cloned()
+			.unwrap_or_else(|| vec![0; body_size])
+	}
+
+	fn write(&mut self, page_address: PageAddress, offset: usize, buf: &[u8], body_size: usize) {
+		let page = self
+			.pages
+			.entry(page_address)
+			.or_insert_with(|| vec![0; body_size]);
+		page[offset..offset + buf.len()].copy_from_slice(buf);
+	}
+}
+
+fn all_pages() -> impl Iterator<Item = PageAddress> {
+	(0..NUM_SEGMENTS)
+		.flat_map(|segment| (1..=PAGES_PER_SEGMENT).map(move |page| page_address!(segment, page)))
+}
+
+fn random_page(rng: &mut fastrand::Rng) -> PageAddress {
+	page_address!(rng.u32(0..NUM_SEGMENTS), rng.u16(1..=PAGES_PER_SEGMENT))
+}
+
+fn read_page(
+	storage: &FaultyStorage,
+	page_address: PageAddress,
+	body_size: usize,
+) -> Result<Vec<u8>, StorageError> {
+	let mut buf = vec![0; body_size];
+	storage.get_page(page_address)?.read(0, &mut buf)?;
+	Ok(buf)
+}
+
+fn is_corruption(err: &StorageError) -> bool {
+	matches!(
+		err,
+		StorageError::File(FileError::ChecksumMismatch | FileError::Corrupted(..))
+	)
+}
+
+fn storage_config(checkpoints: bool) -> PageStorageConfig {
+	let wal = if checkpoints {
+		WalConfig {
+			max_generation_size: 16 * KIB,
+			checkpoint_period: Duration::from_millis(50),
+			..Default::default()
+		}
+	} else {
+		// Everything stays in the first generation, so every torn page has to be
+		// rebuilt from the WAL.
+		WalConfig {
+			max_generation_size: usize::MAX,
+			checkpoint_period: Duration::from_secs(3600),
+			..Default::default()
+		}
+	};
+	PageStorageConfig {
+		page_cache: PageCacheConfig {
+			// Large enough that nothing is evicted
+			page_cache_size: MIB,
+			max_dirty_pages: 0.02,
+			flush_period: Duration::from_millis(20),
+			max_write_rate: None,
+			..Default::default()
+		},
+		wal,
+		..Default::default()
+	}
+}
+
+/// Runs random transactions until an operation fails, or the workload decides
+/// to crash. If the outcome of the last commit is unknown, returns the model
+/// as it is if that commit went through.
+fn run_workload(
+	storage: &FaultyStorage,
+	model: &mut Model,
+	rng: &mut fastrand::Rng,
+	body_size: usize,
+) -> Option<Model> {
+	'transactions: for _ in 0..TRANSACTIONS_PER_CYCLE {
+		let Ok(mut transaction) = storage.transaction() else {
+			return None;
+		};
+		let mut pending = model.clone();
+		for _ in 0..rng.usize(1..=4) {
+			let page_address = random_page(rng);
+			let len = rng.usize(1..=64);
+			let offset = rng.usize(0..=body_size - len);
+			let mut buf = vec![0; len];
+			rng.fill(&mut buf);
+
+			let result = transaction
+				.get_page_mut(page_address)
+				.and_then(|mut page| page.write(offset, &buf));
+			if result.is_err() {
+				mem::forget(transaction);
+				return None;
+			}
+			pending.write(page_address, offset, &buf, body_size);
+		}
+
+		match rng.u8(0..10) {
+			0 => {
+				// Crash in the middle of the transaction
+				mem::forget(transaction);
+				return None;
+			}
+			1 | 2 => {
+				if transaction.undo().is_err() {
+					return None;
+				}
+			}
+			_ => {
+				if transaction.commit().is_err() {
+					return Some(pending);
+				}
+				*model = pending;
+			}
+		}
+
+		if rng.u8(0..5) == 0 {
+			storage.flush();
+		}
+		let page_address = random_page(rng);
+		let mut buf = vec![0; body_size];
+		let Ok(page) = storage.get_page(page_address) else {
+			continue 'transactions;
+		};
+		page.read(0, &mut buf).unwrap();
+		assert_eq!(buf, model.page(page_address, body_size));
+	}
+	None
+}
+
+/// Opens and recovers the storage, retrying after crashes during recovery.
+/// Returns the last error if recovery failed `MAX_REBOOTS` times in a row, or
+/// right away if a page is corrupted, which retrying doesn't fix.
+fn reboot(
+	folder: &Arc<DatabaseFolder>,
+	scheduler: &Arc<dyn Scheduler>,
+	config: &PageStorageConfig,
+	rng: &mut fastrand::Rng,
+) -> Result<(FaultyStorage, Arc<FaultyFolder>), StorageError> {
+	let mut last_error = None;
+	for _ in 0..MAX_REBOOTS {
+		let faulty = Arc::new(FaultyFolder::new(Arc::clone(folder), rng.u64(..)));
+		if rng.u8(0..4) == 0 {
+			faulty.inject_random(50);
+		}
+		let result = PageStorage::open(Arc::clone(&faulty), Arc::clone(scheduler), config)
+			.and_then(|storage| storage.recover().map(|()| storage));
+		match result {
+			Ok(storage) => {
+				faulty.clear_faults();
+				return Ok((storage, faulty));
+			}
+			Err(err) if is_corruption(&err) => return Err(err),
+			Err(err) => {
+				log::info!("Crashed during recovery: {err}");
+				faulty.power_loss().unwrap();
+				last_error = Some(err);
+			}
+		}
+	}
+	Err(last_error.unwrap())
+}
+
+/// Crashes and recovers the storage over and over. With `checkpoints`, the
+/// storage is also closed now and then, after which the WAL no longer has the
+/// history of a torn page, so the page may be reported as corrupted, which ends
+/// the run.
+fn crash_and_recover(folder: DatabaseFolder, seed: u64, checkpoints: bool) {
+	let config = storage_config(checkpoints);
+	let folder = Arc::new(folder);
+	let body_size = folder.page_body_size();
+	let scheduler: Arc<dyn Scheduler> = Arc::new(ThreadPool::new().unwrap());
+	let mut rng = fastrand::Rng::with_seed(seed);
+	let mut model = Model::default();
+
+	let mut faulty = Arc::new(FaultyFolder::new(Arc::clone(&folder), rng.u64(..)));
+	let mut storage =
+		PageStorage::create(Arc::clone(&faulty), Arc::clone(&scheduler), &config).unwrap();
+
+	for cycle in 0..NUM_CYCLES {
+		// given
+		if checkpoints && rng.u8(0..4) == 0 {
+			// Closing deletes the first WAL generation, so pages torn by later crashes
+			// can't be rebuilt anymore
+			storage.close().unwrap();
+		}
+		faulty.inject_random(200);
+		let maybe_committed = run_workload(&storage, &mut model, &mut rng, body_size);
+
+		// when
+		faulty.power_loss().unwrap();
+		mem::drop(storage);
+		let (new_storage, new_faulty) = match reboot(&folder, &scheduler, &config, &mut rng) {
+			Ok(rebooted) => rebooted,
+			Err(err) if checkpoints && is_corruption(&err) => return,
+			Err(err) => panic!("Seed {seed}, cycle {cycle}: recovery keeps failing: {err}"),
+		};
+		storage = new_storage;
+		faulty = new_faulty;
+
+		// then
+		let pages: Result<Vec<(PageAddress, Vec<u8>)>, StorageError> = all_pages()
+			.map(|page_address| Ok((page_address, read_page(&storage, page_address, body_size)?)))
+			.collect();
+		let pages = match pages {
+			Ok(pages) => pages,
+			Err(err) if checkpoints && is_corruption(&err) => return,
+			Err(err) => panic!("Seed {seed}, cycle {cycle}: reading a page failed: {err}"),
+		};
+		let matches = |model: &Model| {
+			pages
+				.iter()
+				.all(|(page_address, page)| *page == model.page(*page_address, body_size))
+		};
+		if let Some(maybe_committed) = maybe_committed.filter(|_| !matches(&model)) {
+			assert!(
+				matches(&maybe_committed),
+				"Seed {seed}, cycle {cycle}: the recovered pages match neither model"
+			);
+			model = maybe_committed;
+		}
+		assert!(
+			matches(&model),
+			"Seed {seed}, cycle {cycle}: the recovered pages don't match the model"
+		);
+	}
+}
+
+fn segment_config() -> SegmentConfig {
+	SegmentConfig {
+		page_size: 4 * KIB,
+		extent_size: 64 * KIB,
+		..Default::default()
+	}
+}
+
+#[test]
+fn recover_from_crashes() {
+	for seed in 0..4 {
+		let tempdir = tempdir().unwrap();
+		let folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), segment_config()).unwrap();
+		crash_and_recover(folder, seed, false);
+	}
+}
+
+#[test]
+fn recover_from_crashes_with_checkpoints() {
+	for seed in 0..4 {
+		let tempdir = tempdir().unwrap();
+		let folder =
+			DatabaseFolder::create(tempdir.path().to_path_buf(), segment_config()).unwrap();
+		crash_and_recover(folder, seed, true);
+	}
+}
+
+#[test]
+fn recover_compressed_pages_from_crashes() {
+	for seed in 0..4 {
+		let tempdir = tempdir().unwrap();
+		let segment_config = SegmentConfig {
+			compress_pages: true,
+			..segment_config()
+		};
+		let folder = DatabaseFolder::create(tempdir.path().to_path_buf(), segment_config).unwrap();
+		crash_and_recover(folder, seed, false);
+	}
+}
+
+#[test]
+fn recover_encrypted_pages_from_crashes() {
+	// Encryption is slow in debug builds, so only one seed is tried.
+	let tempdir = tempdir().unwrap();
+	let folder = DatabaseFolder::create_encrypted(
+		tempdir.path().to_path_buf(),
+		segment_config(),
+		&StaticKeyProvider([25; 32]),
+	)
+	.unwrap();
+	crash_and_recover(folder, 0, false);
+}
@@ -0,0 +1,169 @@
+//! A snapshot of all metrics of the page storage, which can be rendered in the
+//! Prometheus text format.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::utils::metrics::PrometheusEncoder;
+
+use super::{cache::PageCacheStats, physical::PhysicalStorageStats, wal::WalStats};
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+
+/// Statistics about the transactions since the page storage was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct TransactionStats {
+	/// The number of transactions that are currently in progress
+	pub active: u64,
+	pub committed: u64,
+	/// The number of transactions that were undone, including those that were
+	/// dropped without being completed
+	pub aborted: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct AtomicTransactionStats {
+	pub committed: AtomicU64,
+	pub aborted: AtomicU64,
+}
+
+impl AtomicTransactionStats {
+	pub fn get(&self, active: u64) -> TransactionStats {
+		TransactionStats {
+			active,
+			committed: self.committed.load(Ordering::Relaxed),
+			aborted: self.aborted.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PageStorageMetrics {
+	pub cache: PageCacheStats,
+	pub wal: WalStats,
+	pub physical: PhysicalStorageStats,
+	pub transactions: TransactionStats,
+}
+
+impl PageStorageMetrics {
+	/// Renders the metrics in the Prometheus text format.
+	pub fn to_prometheus(&self) -> String {
+		let mut encoder = PrometheusEncoder::new();
+		let Self {
+			cache,
+			wal,
+			physical,
+			transactions,
+		} = self;
+
+		encoder.counter(
+			"acorn_page_cache_hits_total",
+			"Page loads that found the page in the cache",
+			cache.hits,
+		);
+		encoder.counter(
+			"acorn_page_cache_misses_total",
+			"Page loads that didn't find the page in the cache",
+			cache.misses,
+		);
+		encoder.counter(
+			"acorn_page_cache_evictions_total",
+			"Pages evicted from the cache",
+			cache.evictions,
+		);
+		encoder.counter(
+			"acorn_page_cache_eviction_stalls_total",
+			"Times storing a page waited for a cached page to be unlocked",
+			cache.eviction_stalls,
+		);
+		encoder.counter(
+			"acorn_page_cache_eviction_timeouts_total",
+			"Times storing a page timed out waiting for a cached page to be unlocked",
+			cache.eviction_timeouts,
+		);
+		encoder.gauge(
+			"acorn_page_cache_dirty_pages",
+			"Cached pages waiting to be flushed",
+			cache.dirty_pages,
+		);
+
+		encoder.counter(
+			"acorn_wal_bytes_written_total",
+			"Bytes written to the WAL",
+			wal.bytes_written,
+		);
+		encoder.labeled_counter(
+			"acorn_wal_items_total",
+			"Items written to the WAL",
+			"kind",
+			&[
+				("write", wal.write_items),
+				("undo", wal.undo_items),
+				("commit", wal.commit_items),
+				("checkpoint", wal.checkpoint_items),
+			],
+		);
+		encoder.gauge(
+			"acorn_wal_generations",
+			"WAL generations that are currently kept",
+			wal.num_generations,
+		);
+		encoder.counter(
+			"acorn_wal_checkpoints_total",
+			"Completed WAL checkpoints",
+			wal.checkpoints,
+		);
+		encoder.histogram(
+			"acorn_wal_flush_latency_seconds",
+			"Latency of writing buffered WAL items to the current generation",
+			&wal.flush_latency,
+			MICROS_PER_SECOND,
+		);
+		encoder.histogram(
+			"acorn_wal_sync_latency_seconds",
+			"Latency of making the current WAL generation durable",
+			&wal.sync_latency,
+			MICROS_PER_SECOND,
+		);
+		encoder.histogram(
+			"acorn_wal_checkpoint_duration_seconds",
+			"Duration of WAL checkpoints",
+			&wal.checkpoint_duration,
+			MICROS_PER_SECOND,
+		);
+
+		encoder.counter(
+			"acorn_segment_pages_read_total",
+			"Pages read from segment files",
+			physical.pages_read,
+		);
+		encoder.counter(
+			"acorn_segment_pages_written_total",
+			"Pages written to segment files",
+			physical.pages_written,
+		);
+		encoder.histogram(
+			"acorn_segment_batch_size",
+			"Operations per batch of segment reads and writes",
+			&physical.batch_sizes,
+			1.0,
+		);
+
+		encoder.gauge(
+			"acorn_transactions_active",
+			"Transactions in progress",
+			transactions.active,
+		);
+		encoder.counter(
+			"acorn_transactions_committed_total",
+			"Committed transactions",
+			transactions.committed,
+		);
+		encoder.counter(
+			"acorn_transactions_aborted_total",
+			"Undone transactions",
+			transactions.aborted,
+		);
+
+		encoder.finish()
+	}
+}
@@ -1,11 +1,13 @@
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::sync::atomic::AtomicU64;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::executor::ThreadPool;
 use log::warn;
+use parking_lot::{Condvar, Mutex, RwLock};
 use thiserror::Error;
 
 #[cfg(test)]
@@ -14,15 +16,20 @@ use mockall::{automock, mock};
 #[cfg(test)]
 use crate::page_store::cache::{MockPageReadGuardApi, MockPageWriteGuardApi};
 
-use crate::files::DatabaseFolder;
+use crate::consts::DEFAULT_SHUTDOWN_TIMEOUT;
+use crate::files::memory::MemoryFolder;
+use crate::files::segment::SegmentConfig;
+use crate::files::DatabaseFolderApi;
 use crate::files::FileError;
 use crate::page_store::cache::PageWriteGuardApi;
+use crate::tasks::{JobKind, JobManager, JobStatus, Scheduler};
 
 pub(crate) use crate::files::PageAddress;
 use crate::files::TransactionState;
 use crate::files::WalIndex;
 
-use cache::{PageCache, PageCacheApi, PageCacheConfig};
+use cache::{PageCache, PageCacheApi, PageCacheConfig, PageCacheStats};
+use metrics::{AtomicTransactionStats, PageStorageMetrics};
 use physical::{PhysicalStorage, PhysicalStorageApi, PhysicalStorageConfig};
 
 use wal::{Wal, WalApi, WalConfig};
@@ -32,6 +39,9 @@ use self::physical::ReadOp;
 use self::physical::WriteOp;
 
 mod cache;
+#[cfg(test)]
+mod crash_tests;
+mod metrics;
 mod physical;
 mod wal;
 
@@ -43,28 +53,76 @@ pub(crate) enum StorageError {
 	#[error("The maximum number of in-flight transactions has been reached")]
 	TransactionLimitReached,
 
+	#[error("The transaction was aborted because the database was closed")]
+	TransactionAborted,
+
+	#[error(
+		"{0} transactions were aborted because they didn't finish before the database was closed"
+	)]
+	TransactionsAborted(usize),
+
+	#[error("The database is closed")]
+	Closed,
+
+	#[error("The maximum number of pinned pages has been reached")]
+	PinLimitReached,
+
+	#[error("Timed out waiting for a page in the page cache to be unlocked")]
+	CacheExhausted,
+
+	#[error("The database was opened in read-only mode")]
+	ReadOnly,
+
+	#[error("The database is read-only after a background job failed: {0}")]
+	Degraded(String),
+
 	#[error(transparent)]
 	File(#[from] FileError),
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct PageStorageConfig {
 	pub physical_storage: PhysicalStorageConfig,
 	pub page_cache: PageCacheConfig,
 	pub wal: WalConfig,
+	/// How long closing the page storage waits for active transactions before
+	/// aborting them
+	pub shutdown_timeout: Duration,
+}
+
+impl Default for PageStorageConfig {
+	fn default() -> Self {
+		Self {
+			physical_storage: PhysicalStorageConfig::default(),
+			page_cache: PageCacheConfig::default(),
+			wal: WalConfig::default(),
+			shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+		}
+	}
 }
 
 pub(crate) trait ReadPage {
+	/// The size of the page body in bytes.
+	fn size(&self) -> usize;
+
 	fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError>;
 }
 
 impl<T: ReadPage> ReadPage for &T {
+	fn size(&self) -> usize {
+		(**self).size()
+	}
+
 	fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
 		(**self).read(offset, buf)
 	}
 }
 
 impl<T: ReadPage> ReadPage for &mut T {
+	fn size(&self) -> usize {
+		(**self).size()
+	}
+
 	fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
 		(**self).read(offset, buf)
 	}
@@ -99,6 +157,13 @@ impl<'t, 'a, PC> ReadPage for Page<'t, 'a, PC>
 where
 	PC: PageCacheApi + 't,
 {
+	fn size(&self) -> usize {
+		match &self.guard {
+			WriteablePageGuard::Shared(guard) => guard.body().len(),
+			WriteablePageGuard::Exclusive(guard) => guard.body().len(),
+		}
+	}
+
 	fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
 		match &self.guard {
 			WriteablePageGuard::Shared(guard) => guard.read(offset, buf),
@@ -122,6 +187,10 @@ impl<'t, 'a, PC, W> ReadPage for PageMut<'t, 'a, PC, W>
 where
 	PC: PageCacheApi + 'a,
 {
+	fn size(&self) -> usize {
+		self.guard.body().len()
+	}
+
 	fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
 		self.guard.read(offset, buf);
 		Ok(())
@@ -154,6 +223,7 @@ mock! {
 	pub(crate) Page {}
 
 	impl ReadPage for Page {
+		fn size(&self) -> usize;
 		fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError>;
 	}
 }
@@ -163,6 +233,7 @@ mock! {
 	pub(crate) PageMut {}
 
 	impl ReadPage for PageMut {
+		fn size(&self) -> usize;
 		fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError>;
 	}
 
@@ -214,7 +285,11 @@ where
 			guard.write(write_op.offset.into(), write_op.buf, write_op.index);
 			Ok(())
 		})?;
-		self.storage.transaction_enumerator.end();
+		self.storage.transaction_enumerator.end(self.id);
+		self.storage
+			.transaction_stats
+			.aborted
+			.fetch_add(1, Ordering::Relaxed);
 		Ok(())
 	}
 }
@@ -234,20 +309,45 @@ where
 	}
 }
 
-#[cfg_attr(test, automock(
-    type Page = MockPage;
-    type PageMut = MockPageMut;
-))]
-pub(crate) trait TransactionApi {
+/// Something to read pages from, either a transaction, or the page storage
+/// itself.
+pub(crate) trait PageSource {
 	type Page<'a>: ReadPage + 'a
 	where
 		Self: 'a;
+
+	fn get_page(&self, page_address: PageAddress) -> Result<Self::Page<'_>, StorageError>;
+}
+
+#[cfg(test)]
+mock! {
+	pub(crate) TransactionApi {}
+
+	impl PageSource for TransactionApi {
+		type Page<'a> = MockPage where Self: 'a;
+
+		fn get_page<'a>(&'a self, page_address: PageAddress) -> Result<MockPage, StorageError>;
+	}
+
+	impl TransactionApi for TransactionApi {
+		type PageMut<'a> = MockPageMut where Self: 'a;
+
+		fn id(&self) -> u64;
+		fn get_page_mut<'a>(
+			&'a mut self,
+			page_address: PageAddress,
+		) -> Result<MockPageMut, StorageError>;
+		fn commit(self) -> Result<(), StorageError>;
+		fn undo(self) -> Result<(), StorageError>;
+	}
+}
+
+pub(crate) trait TransactionApi: PageSource {
 	type PageMut<'a>: ReadPage + WritePage + 'a
 	where
 		Self: 'a;
 
 	fn id(&self) -> u64;
-	fn get_page(&self, page_address: PageAddress) -> Result<Self::Page<'_>, StorageError>;
 	fn get_page_mut(
 		&mut self,
 		page_address: PageAddress,
@@ -256,18 +356,13 @@ pub(crate) trait TransactionApi {
 	fn undo(self) -> Result<(), StorageError>;
 }
 
-impl<'t, PS, PC, W> TransactionApi for Transaction<'t, PS, PC, W>
+impl<'t, PS, PC, W> PageSource for Transaction<'t, PS, PC, W>
 where
 	PS: PhysicalStorageApi,
 	PC: PageCacheApi + 't,
 	W: WalApi + 't,
 {
 	type Page<'a> = Page<'t, 'a, PC> where Self: 'a;
-	type PageMut<'a> = PageMut<'t, 'a, PC, W> where Self: 'a;
-
-	fn id(&self) -> u64 {
-		self.id
-	}
 
 	fn get_page(&self, page_address: PageAddress) -> Result<Self::Page<'_>, StorageError> {
 		if let Some(guard) = self.locks.get(&page_address) {
@@ -280,11 +375,27 @@ where
 			})
 		}
 	}
+}
+
+impl<'t, PS, PC, W> TransactionApi for Transaction<'t, PS, PC, W>
+where
+	PS: PhysicalStorageApi,
+	PC: PageCacheApi + 't,
+	W: WalApi + 't,
+{
+	type PageMut<'a> = PageMut<'t, 'a, PC, W> where Self: 'a;
+
+	fn id(&self) -> u64 {
+		self.id
+	}
 
 	fn get_page_mut<'a>(
 		&'a mut self,
 		page_address: PageAddress,
 	) -> Result<Self::PageMut<'a>, StorageError> {
+		if self.storage.transaction_enumerator.is_aborted(self.id) {
+			return Err(StorageError::TransactionAborted);
+		}
 		self.acquire_lock(page_address)?;
 		let guard: &'a mut PC::WriteGuard<'t> = self.locks.get_mut(&page_address).unwrap();
 		Ok(PageMut {
@@ -296,10 +407,17 @@ where
 	}
 
 	fn commit(mut self) -> Result<(), StorageError> {
+		if self.storage.transaction_enumerator.is_aborted(self.id) {
+			return Err(StorageError::TransactionAborted);
+		}
 		self.storage.wal.log_commit(wal::CommitLog {
 			transaction_id: self.id,
 		})?;
-		self.storage.transaction_enumerator.end();
+		self.storage.transaction_enumerator.end(self.id);
+		self.storage
+			.transaction_stats
+			.committed
+			.fetch_add(1, Ordering::Relaxed);
 		self.completed = true;
 		Ok(())
 	}
@@ -311,34 +429,69 @@ where
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
+struct TransactionsState {
+	next_id: u64,
+	active: HashSet<u64>,
+	/// Active transactions that were aborted by closing the page storage. They
+	/// can only be undone.
+	aborted: HashSet<u64>,
+	closed: bool,
+}
+
+#[derive(Debug, Default)]
 struct TransactionEnumerator {
-	next_id: AtomicU64,
-	num_transactions: AtomicU64,
+	state: Mutex<TransactionsState>,
+	ended: Condvar,
 }
 
 impl TransactionEnumerator {
 	fn new() -> Self {
-		Self {
-			next_id: AtomicU64::new(0),
-			num_transactions: AtomicU64::new(0),
+		Self::default()
+	}
+
+	fn begin(&self) -> Result<u64, StorageError> {
+		let mut state = self.state.lock();
+		if state.closed {
+			return Err(StorageError::Closed);
+		}
+		let id = state.next_id;
+		if !state.active.insert(id) {
+			return Err(StorageError::TransactionLimitReached);
 		}
+		state.next_id = id.wrapping_add(1);
+		Ok(id)
 	}
 
-	fn begin(&self) -> Option<u64> {
-		let num_transactions = self.num_transactions.load(Ordering::Acquire);
-		let num_transactions = num_transactions.checked_add(1)?;
-		self.num_transactions
-			.store(num_transactions, Ordering::Release);
-		let id = self.next_id.load(Ordering::Acquire);
-		self.next_id.store(id.wrapping_add(1), Ordering::Release);
-		Some(id)
+	fn end(&self, id: u64) {
+		let mut state = self.state.lock();
+		state.active.remove(&id);
+		state.aborted.remove(&id);
+		self.ended.notify_all();
 	}
 
-	fn end(&self) {
-		let num_transactions = self.num_transactions.load(Ordering::Acquire);
-		self.num_transactions
-			.store(num_transactions.saturating_sub(1), Ordering::Release);
+	fn num_active(&self) -> usize {
+		self.state.lock().active.len()
+	}
+
+	fn is_aborted(&self, id: u64) -> bool {
+		self.state.lock().aborted.contains(&id)
+	}
+
+	/// Stops new transactions from starting, and waits up to `timeout` for the
+	/// active ones to end. Returns how many had to be aborted.
+	fn close(&self, timeout: Duration) -> usize {
+		let deadline = Instant::now() + timeout;
+		let mut state = self.state.lock();
+		state.closed = true;
+		while !state.active.is_empty() {
+			if self.ended.wait_until(&mut state, deadline).timed_out() {
+				break;
+			}
+		}
+		let active = state.active.clone();
+		state.aborted.extend(active);
+		state.active.len()
 	}
 }
 
@@ -346,48 +499,145 @@ pub(crate) struct PageStorage<PS = PhysicalStorage, PC = PageCache, W = Wal> {
 	physical: Arc<PS>,
 	cache: PC,
 	wal: W,
+	jobs: Arc<JobManager>,
 	transaction_enumerator: TransactionEnumerator,
+	transaction_stats: AtomicTransactionStats,
+	shutdown_timeout: Duration,
+	read_only: bool,
+	/// Whether the database wasn't shut down cleanly, so the WAL has to be
+	/// replayed before it is used
+	needs_recovery: bool,
+	// In read-only mode, pages changed by WAL redo are kept here, since they can
+	// neither be written back, nor be allowed to be evicted from the cache.
+	overlay: RwLock<HashMap<PageAddress, Box<[u8]>>>,
 }
 
-impl PageStorage {
+impl<DF> PageStorage<PhysicalStorage<DF>, PageCache<PhysicalStorage<DF>>, Wal<DF>>
+where
+	DF: DatabaseFolderApi + Send + Sync + 'static,
+{
 	pub fn create(
-		folder: Arc<DatabaseFolder>,
-		thread_pool: Arc<ThreadPool>,
+		folder: Arc<DF>,
+		scheduler: Arc<dyn Scheduler>,
 		config: &PageStorageConfig,
 	) -> Result<Self, StorageError> {
 		let physical_storage = Arc::new(PhysicalStorage::new(
 			Arc::clone(&folder),
 			&config.physical_storage,
 		));
-		Ok(Self::new(
+		let jobs = Arc::new(JobManager::new(scheduler));
+		let wal = Wal::create(Arc::clone(&folder), Arc::clone(&jobs), &config.wal)?;
+		let cache = PageCache::new(
+			&config.page_cache,
+			folder.page_body_size(),
 			Arc::clone(&physical_storage),
-			PageCache::new(
-				&config.page_cache,
-				Arc::clone(&physical_storage),
-				Arc::clone(&thread_pool),
-			),
-			Wal::create(Arc::clone(&folder), thread_pool, &config.wal)?,
-		))
+			Arc::clone(&jobs),
+		);
+		cache.set_write_barrier(Box::new(wal.sync_handle()));
+		let mut storage = Self::new(physical_storage, cache, wal, jobs);
+		storage.shutdown_timeout = config.shutdown_timeout;
+		storage.needs_recovery = false;
+		Ok(storage)
 	}
 
+	/// Opens the page storage of an existing database. If the folder was opened
+	/// read-only, so is the page storage; it then never writes to the folder, and
+	/// all attempts to start a transaction fail with `StorageError::ReadOnly`.
+	/// If the database was closed cleanly, recovery does nothing.
 	pub fn open(
-		folder: Arc<DatabaseFolder>,
-		thread_pool: Arc<ThreadPool>,
+		folder: Arc<DF>,
+		scheduler: Arc<dyn Scheduler>,
 		config: &PageStorageConfig,
 	) -> Result<Self, StorageError> {
+		if folder.is_read_only() {
+			return Self::open_read_only(folder, scheduler, config);
+		}
+
 		let physical_storage = Arc::new(PhysicalStorage::new(
 			Arc::clone(&folder),
 			&config.physical_storage,
 		));
-		Ok(Self::new(
+		let jobs = Arc::new(JobManager::new(scheduler));
+		let wal = Wal::open(Arc::clone(&folder), Arc::clone(&jobs), &config.wal)?;
+		let cache = PageCache::new(
+			&config.page_cache,
+			folder.page_body_size(),
 			Arc::clone(&physical_storage),
-			PageCache::new(
+			Arc::clone(&jobs),
+		);
+		cache.set_write_barrier(Box::new(wal.sync_handle()));
+		let mut storage = Self::new(physical_storage, cache, wal, jobs);
+		storage.shutdown_timeout = config.shutdown_timeout;
+		storage.needs_recovery = !folder.was_clean_shutdown();
+		Ok(storage)
+	}
+
+	fn open_read_only(
+		folder: Arc<DF>,
+		scheduler: Arc<dyn Scheduler>,
+		config: &PageStorageConfig,
+	) -> Result<Self, StorageError> {
+		let physical_storage = Arc::new(PhysicalStorage::new(
+			Arc::clone(&folder),
+			&config.physical_storage,
+		));
+		let jobs = Arc::new(JobManager::new(scheduler));
+		let mut storage = Self::new(
+			Arc::clone(&physical_storage),
+			PageCache::new_read_only(
 				&config.page_cache,
+				folder.page_body_size(),
 				Arc::clone(&physical_storage),
-				Arc::clone(&thread_pool),
+				Arc::clone(&jobs),
 			),
-			Wal::open(Arc::clone(&folder), thread_pool, &config.wal)?,
-		))
+			Wal::open_read_only(Arc::clone(&folder), Arc::clone(&jobs), &config.wal)?,
+			jobs,
+		);
+		storage.read_only = true;
+		storage.needs_recovery = !folder.was_clean_shutdown();
+		Ok(storage)
+	}
+
+	/// Shuts the page storage down cleanly, so that the next open can skip
+	/// recovery. New transactions fail with [`StorageError::Closed`], and active
+	/// ones get up to the shutdown timeout to finish. Any that don't are
+	/// aborted; their pages can't be flushed, so the database is then left for
+	/// recovery and [`StorageError::TransactionsAborted`] is returned.
+	/// Otherwise, background jobs are stopped, all dirty pages are flushed, and
+	/// a final checkpoint is written before the database is marked clean.
+	pub fn close(&self) -> Result<(), StorageError> {
+		if self.read_only {
+			return Ok(());
+		}
+		let num_aborted = self.transaction_enumerator.close(self.shutdown_timeout);
+		self.jobs.shutdown();
+		if num_aborted > 0 {
+			return Err(StorageError::TransactionsAborted(num_aborted));
+		}
+		self.flush_sync()?;
+		self.physical.sync()?;
+		self.wal.cache_did_flush();
+		self.wal.checkpoint()?;
+		self.physical.folder().mark_clean_shutdown()?;
+		Ok(())
+	}
+}
+
+/// A page storage that keeps everything in memory
+pub(crate) type MemoryPageStorage = PageStorage<
+	PhysicalStorage<MemoryFolder>,
+	PageCache<PhysicalStorage<MemoryFolder>>,
+	Wal<MemoryFolder>,
+>;
+
+impl MemoryPageStorage {
+	/// Creates a page storage that doesn't touch the filesystem. It behaves like
+	/// one created in a database folder, but everything is lost when it is
+	/// dropped.
+	pub fn create_in_memory(config: &PageStorageConfig) -> Result<Self, StorageError> {
+		let folder = Arc::new(MemoryFolder::new(SegmentConfig::default()));
+		let thread_pool = Arc::new(ThreadPool::new().map_err(FileError::from)?);
+		Self::create(folder, thread_pool, config)
 	}
 }
 
@@ -396,20 +646,43 @@ where
 	PS: PhysicalStorageApi,
 	PC: PageCacheApi,
 {
-	fn new(physical: Arc<PS>, cache: PC, wal: W) -> Self {
+	fn new(physical: Arc<PS>, cache: PC, wal: W, jobs: Arc<JobManager>) -> Self {
 		Self {
 			physical,
 			cache,
 			wal,
+			jobs,
 			transaction_enumerator: TransactionEnumerator::new(),
+			transaction_stats: AtomicTransactionStats::default(),
+			shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+			read_only: false,
+			needs_recovery: true,
+			overlay: RwLock::new(HashMap::new()),
 		}
 	}
 
+	pub fn job_status(&self, kind: JobKind) -> JobStatus {
+		self.jobs.status(kind)
+	}
+
+	/// Returns why the page storage became read-only, if a background job
+	/// failed fatally. Starting a transaction then fails with
+	/// [`StorageError::Degraded`].
+	pub fn degraded(&self) -> Option<String> {
+		self.jobs.degraded()
+	}
+
 	fn load_into_cache(
 		&self,
 		page_address: PageAddress,
 	) -> Result<PC::WriteGuard<'_>, StorageError> {
-		let mut guard = self.cache.store(page_address);
+		let mut guard = self.cache.store(page_address)?;
+		if self.read_only {
+			if let Some(page) = self.overlay.read().get(&page_address) {
+				guard.body_mut().copy_from_slice(page);
+				return Ok(guard);
+			}
+		}
 		if let Err(error) = self.physical.read(ReadOp {
 			page_address,
 			wal_index: &mut None,
@@ -435,39 +708,102 @@ where
 		}
 		self.load_into_cache(page_address)
 	}
+
+	/// Like `write_guard`, but if `rebuild_torn_pages` is set, a page that was
+	/// torn by a crash while it was written is rebuilt from scratch. This is only
+	/// correct if the WAL redoes every write to the page since it was created,
+	/// see `WalApi::has_full_history`; otherwise, the error is returned.
+	fn recovery_guard(
+		&self,
+		page_address: PageAddress,
+		rebuild_torn_pages: bool,
+	) -> Result<PC::WriteGuard<'_>, StorageError> {
+		match self.write_guard(page_address) {
+			Err(StorageError::File(FileError::ChecksumMismatch | FileError::Corrupted(..)))
+				if rebuild_torn_pages =>
+			{
+				warn!("Page {page_address} is corrupted, and is rebuilt from the WAL");
+				let mut guard = self.cache.store(page_address)?;
+				guard.body_mut().fill(0);
+				Ok(guard)
+			}
+			result => result,
+		}
+	}
 }
 
 #[cfg_attr(test, automock(
-    type Page<'a> = MockPage;
     type Transaction<'a> = MockTransactionApi;
 ))]
 pub(crate) trait PageStorageApi {
-	type Page<'a>: ReadPage + 'a
-	where
-		Self: 'a;
 	type Transaction<'a>: TransactionApi
 	where
 		Self: 'a;
 
 	fn recover(&self) -> Result<(), StorageError>;
-	fn get_page(&self, page_address: PageAddress) -> Result<Self::Page<'_>, StorageError>;
 	fn transaction(&self) -> Result<Self::Transaction<'_>, StorageError>;
 	fn flush(&self);
 	fn flush_sync(&self) -> Result<(), StorageError>;
+
+	/// Keeps the page resident in the page cache until it is unpinned again.
+	fn pin(&self, page_address: PageAddress) -> Result<(), StorageError>;
+	fn unpin(&self, page_address: PageAddress);
+
+	/// Starts loading the given pages into the page cache in the background, so
+	/// that later accesses don't have to wait for IO.
+	fn prefetch(&self, page_addresses: &[PageAddress]);
+
+	/// Changes the size of the page cache in bytes.
+	fn resize_cache(&self, page_cache_size: usize) -> Result<(), StorageError>;
+
+	fn cache_stats(&self) -> PageCacheStats;
+
+	/// Takes a snapshot of the metrics of the page cache, the WAL, the physical
+	/// storage and the transactions.
+	fn metrics(&self) -> PageStorageMetrics;
 }
 
-impl<PS, PC, W> PageStorageApi for PageStorage<PS, PC, W>
+impl<PS, PC, W> PageSource for PageStorage<PS, PC, W>
 where
 	PS: PhysicalStorageApi,
 	PC: PageCacheApi,
 	W: WalApi,
 {
 	type Page<'a> = Page<'a, 'a, PC> where Self: 'a;
+
+	fn get_page(&self, page_address: PageAddress) -> Result<Self::Page<'_>, StorageError> {
+		Ok(Page {
+			guard: WriteablePageGuard::Shared(self.read_guard(page_address)?),
+		})
+	}
+}
+
+impl<PS, PC, W> PageStorageApi for PageStorage<PS, PC, W>
+where
+	PS: PhysicalStorageApi,
+	PC: PageCacheApi,
+	W: WalApi,
+{
 	type Transaction<'a> = Transaction<'a, PS, PC, W> where Self: 'a;
 
 	fn recover(&self) -> Result<(), StorageError> {
+		if !self.needs_recovery {
+			return Ok(());
+		}
+		let rebuild_torn_pages = self.wal.has_full_history();
+		if self.read_only {
+			return self.wal.recover(&mut |write_op| {
+				let mut guard = self.recovery_guard(write_op.page_address, rebuild_torn_pages)?;
+				guard.write(write_op.offset.into(), write_op.buf, write_op.index);
+				self.overlay
+					.write()
+					.insert(write_op.page_address, guard.body().into());
+				Ok(())
+			});
+		}
+
 		self.wal.recover(&mut |write_op| {
-			let mut guard = self.write_guard(write_op.page_address)?;
+			let mut guard = self.recovery_guard(write_op.page_address, rebuild_torn_pages)?;
 			guard.write(write_op.offset.into(), write_op.buf, write_op.index);
 			self.physical.write(WriteOp {
 				wal_index: write_op.index,
@@ -478,16 +814,14 @@ where
 		})
 	}
 
-	fn get_page(&self, page_address: PageAddress) -> Result<Self::Page<'_>, StorageError> {
-		Ok(Page {
-			guard: WriteablePageGuard::Shared(self.read_guard(page_address)?),
-		})
-	}
-
 	fn transaction(&self) -> Result<Transaction<'_, PS, PC, W>, StorageError> {
-		let Some(transaction_id) = self.transaction_enumerator.begin() else {
-			return Err(StorageError::TransactionLimitReached);
-		};
+		if self.read_only {
+			return Err(StorageError::ReadOnly);
+		}
+		if let Some(reason) = self.jobs.degraded() {
+			return Err(StorageError::Degraded(reason));
+		}
+		let transaction_id = self.transaction_enumerator.begin()?;
 		Ok(Transaction::new(transaction_id, self))
 	}
 
@@ -498,6 +832,54 @@ where
 	fn flush_sync(&self) -> Result<(), StorageError> {
 		self.cache.flush_sync()
 	}
+
+	fn pin(&self, page_address: PageAddress) -> Result<(), StorageError> {
+		self.cache.pin(page_address)
+	}
+
+	fn unpin(&self, page_address: PageAddress) {
+		self.cache.unpin(page_address)
+	}
+
+	fn prefetch(&self, page_addresses: &[PageAddress]) {
+		if !self.read_only {
+			self.cache.prefetch(page_addresses);
+			return;
+		}
+		// Pages changed by WAL redo must not be read from disk again.
+		let overlay = self.overlay.read();
+		let page_addresses: Vec<PageAddress> = page_addresses
+			.iter()
+			.copied()
+			.filter(|page_address| !overlay.contains_key(page_address))
+			.collect();
+		mem::drop(overlay);
+		self.cache.prefetch(&page_addresses);
+	}
+
+	fn resize_cache(&self, page_cache_size: usize) -> Result<(), StorageError> {
+		self.cache.resize(page_cache_size)
+	}
+
+	fn cache_stats(&self) -> PageCacheStats {
+		self.cache.stats()
+	}
+
+	fn metrics(&self) -> PageStorageMetrics {
+		let active_transactions = self.transaction_enumerator.num_active() as u64;
+		PageStorageMetrics {
+			cache: self.cache.stats(),
+			wal: self.wal.stats(),
+			physical: self.physical.stats(),
+			transactions: self.transaction_stats.get(active_transactions),
+		}
+	}
+}
+
+impl<PS, PC, W> Drop for PageStorage<PS, PC, W> {
+	fn drop(&mut self) {
+		self.jobs.shutdown();
+	}
 }
 
 #[cfg(test)]
@@ -507,16 +889,23 @@ mod tests {
 		io::{Read, Seek, SeekFrom},
 	};
 
+	use futures::executor::block_on;
 	use mockall::{predicate::*, Sequence};
 	use pretty_assertions::assert_buf_eq;
 	use tempfile::tempdir;
 	use test::Bencher;
 	use tests::wal::{CommitLog, WriteLog};
 
-	use crate::{consts::PAGE_SIZE, files::segment::PAGE_BODY_SIZE, utils::units::KIB};
+	use crate::{
+		consts::DEFAULT_PAGE_SIZE,
+		files::{segment::DEFAULT_PAGE_BODY_SIZE, test_helpers::StaticKeyProvider, DatabaseFolder},
+		tasks::{test_helpers::SimulatedScheduler, FailureStrategy},
+		utils::{test_helpers::copy_dir, units::KIB},
+	};
 
 	use self::{
 		cache::MockPageCacheApi,
+		metrics::TransactionStats,
 		physical::MockPhysicalStorageApi,
 		test_helpers::{page_address, wal_index},
 		wal::MockWalApi,
@@ -524,6 +913,10 @@ mod tests {
 
 	use super::*;
 
+	fn simulated_jobs() -> Arc<JobManager> {
+		Arc::new(JobManager::new(Arc::new(SimulatedScheduler::new())))
+	}
+
 	#[test]
 	fn recover() {
 		// expect
@@ -531,6 +924,7 @@ mod tests {
 		let mut cache = MockPageCacheApi::new();
 		let mut wal = MockWalApi::new();
 
+		wal.expect_has_full_history().return_const(true);
 		wal.expect_recover().returning(|handler| {
 			handler(wal::PartialWriteOp {
 				index: wal_index!(69, 420),
@@ -565,12 +959,14 @@ mod tests {
 				let mut guard = MockPageWriteGuardApi::new();
 				guard
 					.expect_body_mut()
-					.returning(|| vec![0; PAGE_BODY_SIZE]);
-				guard.expect_body().return_const(vec![10; PAGE_BODY_SIZE]);
+					.returning(|| vec![0; DEFAULT_PAGE_BODY_SIZE]);
+				guard
+					.expect_body()
+					.return_const(vec![10; DEFAULT_PAGE_BODY_SIZE]);
 				guard
 					.expect_write()
 					.with(eq(10), eq([1, 2, 3]), eq(wal_index!(69, 420)));
-				guard
+				Ok(guard)
 			});
 		physical
 			.expect_read()
@@ -589,7 +985,7 @@ mod tests {
 			.withf(|write_op| {
 				write_op.wal_index == wal_index!(69, 420)
 					&& write_op.page_address == page_address!(1, 2)
-					&& write_op.buf == [10; PAGE_BODY_SIZE]
+					&& write_op.buf == [10; DEFAULT_PAGE_BODY_SIZE]
 			})
 			.returning(|_| Ok(()));
 		cache
@@ -601,8 +997,10 @@ mod tests {
 				let mut guard = MockPageWriteGuardApi::new();
 				guard
 					.expect_body_mut()
-					.returning(|| vec![0; PAGE_BODY_SIZE]);
-				guard.expect_body().return_const(vec![20; PAGE_BODY_SIZE]);
+					.returning(|| vec![0; DEFAULT_PAGE_BODY_SIZE]);
+				guard
+					.expect_body()
+					.return_const(vec![20; DEFAULT_PAGE_BODY_SIZE]);
 				guard
 					.expect_write()
 					.with(eq(12), eq([2, 2, 1]), eq(wal_index!(10, 24)));
@@ -615,16 +1013,71 @@ mod tests {
 			.withf(|write_op| {
 				write_op.wal_index == wal_index!(10, 24)
 					&& write_op.page_address == page_address!(4, 5)
-					&& write_op.buf == [20; PAGE_BODY_SIZE]
+					&& write_op.buf == [20; DEFAULT_PAGE_BODY_SIZE]
 			})
 			.returning(|_| Ok(()));
 		// given
-		let page_storage = PageStorage::new(Arc::new(physical), cache, wal);
+		let page_storage = PageStorage::new(Arc::new(physical), cache, wal, simulated_jobs());
 
 		// when
 		page_storage.recover().unwrap();
 	}
 
+	#[test]
+	fn try_recover_corrupted_page_without_full_history() {
+		// expect
+		let mut physical = MockPhysicalStorageApi::new();
+		let mut cache = MockPageCacheApi::new();
+		let mut wal = MockWalApi::new();
+
+		wal.expect_has_full_history().return_const(false);
+		wal.expect_recover().returning(|handler| {
+			handler(wal::PartialWriteOp {
+				index: wal_index!(69, 420),
+				page_address: page_address!(1, 2),
+				offset: 10,
+				buf: &[1, 2, 3],
+			})
+		});
+		cache
+			.expect_load_mut()
+			.with(eq(page_address!(1, 2)))
+			.returning(|_| None);
+		cache
+			.expect_store()
+			.once()
+			.with(eq(page_address!(1, 2)))
+			.returning(|_| {
+				let mut guard = MockPageWriteGuardApi::new();
+				guard
+					.expect_body_mut()
+					.returning(|| vec![0; DEFAULT_PAGE_BODY_SIZE]);
+				Ok(guard)
+			});
+		physical
+			.expect_read()
+			.once()
+			.returning(|_| Err(StorageError::File(FileError::ChecksumMismatch)));
+		cache
+			.expect_scrap()
+			.once()
+			.with(eq(page_address!(1, 2)))
+			.return_const(());
+		physical.expect_write().never();
+
+		// given
+		let page_storage = PageStorage::new(Arc::new(physical), cache, wal, simulated_jobs());
+
+		// when
+		let result = page_storage.recover();
+
+		// then
+		assert!(matches!(
+			result,
+			Err(StorageError::File(FileError::ChecksumMismatch))
+		));
+	}
+
 	#[test]
 	fn read() {
 		// expect
@@ -647,8 +1100,8 @@ mod tests {
 				let mut guard = MockPageWriteGuardApi::new();
 				guard
 					.expect_body_mut()
-					.returning(|| vec![0; PAGE_BODY_SIZE]);
-				guard
+					.returning(|| vec![0; DEFAULT_PAGE_BODY_SIZE]);
+				Ok(guard)
 			});
 		physical
 			.expect_read()
@@ -680,7 +1133,7 @@ mod tests {
 			});
 
 		// given
-		let storage = PageStorage::new(Arc::new(physical), cache, wal);
+		let storage = PageStorage::new(Arc::new(physical), cache, wal, simulated_jobs());
 
 		// when
 		let mut buf = [0; 5];
@@ -720,7 +1173,7 @@ mod tests {
 					.expect_body_mut()
 					.once()
 					.in_sequence(&mut seq)
-					.returning(|| vec![0; PAGE_BODY_SIZE]);
+					.returning(|| vec![0; DEFAULT_PAGE_BODY_SIZE]);
 				guard
 					.expect_read()
 					.once()
@@ -738,7 +1191,7 @@ mod tests {
 					.in_sequence(&mut seq)
 					.with(eq(10), always())
 					.returning(|_, buf| buf.copy_from_slice(&[1, 2]));
-				guard
+				Ok(guard)
 			});
 		physical
 			.expect_read()
@@ -771,7 +1224,7 @@ mod tests {
 			.returning(|_| Ok(wal_index!(24, 25)));
 
 		// given
-		let storage = PageStorage::new(Arc::new(physical), cache, wal);
+		let storage = PageStorage::new(Arc::new(physical), cache, wal, simulated_jobs());
 
 		// when
 		let mut t = storage.transaction().unwrap();
@@ -790,11 +1243,54 @@ mod tests {
 		assert_buf_eq!(received, [1, 2]);
 	}
 
+	#[test]
+	fn refuse_transactions_after_fatal_failure() {
+		// given
+		let jobs = simulated_jobs();
+		let storage = PageStorage::new(
+			Arc::new(MockPhysicalStorageApi::new()),
+			MockPageCacheApi::new(),
+			MockWalApi::new(),
+			Arc::clone(&jobs),
+		);
+		let strategy = FailureStrategy {
+			fatal: true,
+			retries: 0,
+			..Default::default()
+		};
+
+		// when
+		let result = block_on(jobs.run(JobKind::Checkpoint, &strategy, || {
+			Err::<(), _>(StorageError::WalNotInitialized)
+		}));
+
+		// then
+		assert!(result.is_err());
+		assert!(matches!(
+			storage.transaction(),
+			Err(StorageError::Degraded(_))
+		));
+		assert_eq!(
+			storage.degraded().as_deref(),
+			Some("WAL checkpoint failed: The WAL was never initialized!")
+		);
+		assert_eq!(
+			storage.job_status(JobKind::Checkpoint),
+			JobStatus {
+				failed: 1,
+				last_error: Some("The WAL was never initialized!".to_string()),
+				..Default::default()
+			}
+		);
+	}
+
 	#[test]
 	fn integration_transaction() {
 		let tempdir = tempdir().unwrap();
 
-		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()));
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
 		let thread_pool = Arc::new(ThreadPool::new().unwrap());
 		let page_storage = PageStorage::create(folder, thread_pool, &Default::default()).unwrap();
 
@@ -816,23 +1312,310 @@ mod tests {
 		page_storage.flush_sync().unwrap();
 
 		let mut segment_file = File::open(tempdir.path().join("segments/69")).unwrap();
-		const OFFSET: usize = 420 * PAGE_SIZE + 19;
+		const OFFSET: usize = 420 * DEFAULT_PAGE_SIZE + DEFAULT_PAGE_SIZE - DEFAULT_PAGE_BODY_SIZE;
 		segment_file
 			.seek(SeekFrom::Start(OFFSET.try_into().unwrap()))
 			.unwrap();
-		let mut buf = [0; PAGE_BODY_SIZE];
+		let mut buf = [0; DEFAULT_PAGE_BODY_SIZE];
 		segment_file.read_exact(&mut buf).unwrap();
 
-		let mut expected = [0; PAGE_BODY_SIZE];
+		let mut expected = [0; DEFAULT_PAGE_BODY_SIZE];
 		expected[25..29].copy_from_slice(&[1, 2, 3, 4]);
 		assert_buf_eq!(buf, expected);
 	}
 
+	#[test]
+	fn integration_metrics() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
+		let thread_pool = Arc::new(ThreadPool::new().unwrap());
+		let page_storage = PageStorage::create(folder, thread_pool, &Default::default()).unwrap();
+
+		// when
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[1, 2, 3, 4])
+			.unwrap();
+		t.commit().unwrap();
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[5, 6])
+			.unwrap();
+		t.undo().unwrap();
+		let _active = page_storage.transaction().unwrap();
+		let metrics = page_storage.metrics();
+
+		// then
+		assert_eq!(metrics.cache.hits, 1);
+		assert_eq!(metrics.cache.misses, 1);
+		assert_eq!(metrics.wal.write_items, 2);
+		assert_eq!(metrics.wal.undo_items, 1);
+		// one commit item for each completed transaction
+		assert_eq!(metrics.wal.commit_items, 2);
+		assert_eq!(metrics.wal.checkpoint_items, 1);
+		assert_eq!(metrics.wal.num_generations, 1);
+		assert!(metrics.wal.bytes_written > 0);
+		assert_eq!(metrics.wal.flush_latency.count(), 2);
+		assert_eq!(metrics.physical.pages_read, 1);
+		assert_eq!(
+			metrics.transactions,
+			TransactionStats {
+				active: 1,
+				committed: 1,
+				aborted: 1
+			}
+		);
+		let text = metrics.to_prometheus();
+		assert!(text.contains("\nacorn_transactions_committed_total 1\n"));
+		assert!(text.contains("\nacorn_wal_items_total{kind=\"undo\"} 1\n"));
+	}
+
+	#[test]
+	fn integration_in_memory() {
+		// given
+		let page_storage = PageStorage::create_in_memory(&Default::default()).unwrap();
+
+		// when
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[1, 2, 3, 4])
+			.unwrap();
+		t.commit().unwrap();
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[5, 6])
+			.unwrap();
+		t.undo().unwrap();
+		page_storage.flush_sync().unwrap();
+
+		// then
+		let mut buf = vec![0; DEFAULT_PAGE_BODY_SIZE];
+		page_storage
+			.physical
+			.read(ReadOp {
+				page_address: page_address!(69, 420),
+				wal_index: &mut None,
+				buf: &mut buf,
+			})
+			.unwrap();
+		assert_buf_eq!(&buf[25..29], [1, 2, 3, 4]);
+		assert_eq!(page_storage.metrics().physical.pages_written, 1);
+	}
+
+	#[test]
+	fn integration_clean_close() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
+		let thread_pool: Arc<dyn Scheduler> = Arc::new(ThreadPool::new().unwrap());
+		let page_storage =
+			PageStorage::create(folder, Arc::clone(&thread_pool), &Default::default()).unwrap();
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[1, 2, 3, 4])
+			.unwrap();
+		t.commit().unwrap();
+
+		// when
+		page_storage.close().unwrap();
+		let transaction_result = page_storage.transaction().map(|_| ());
+		mem::drop(page_storage);
+		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap());
+		let was_clean_shutdown = folder.was_clean_shutdown();
+		let page_storage = PageStorage::open(folder, thread_pool, &Default::default()).unwrap();
+		page_storage.recover().unwrap();
+
+		// then
+		assert!(matches!(transaction_result, Err(StorageError::Closed)));
+		assert!(was_clean_shutdown);
+		let mut data = [0; 4];
+		page_storage
+			.get_page(page_address!(69, 420))
+			.unwrap()
+			.read(25, &mut data)
+			.unwrap();
+		assert_buf_eq!(data, [1, 2, 3, 4]);
+		assert_eq!(page_storage.metrics().wal.num_generations, 1);
+		assert_eq!(page_storage.metrics().wal.write_items, 0);
+	}
+
+	#[test]
+	fn integration_abort_transactions_on_close() {
+		// given
+		let tempdir = tempdir().unwrap();
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
+		let thread_pool: Arc<dyn Scheduler> = Arc::new(ThreadPool::new().unwrap());
+		let config = PageStorageConfig {
+			shutdown_timeout: Duration::from_millis(10),
+			..Default::default()
+		};
+		let page_storage = PageStorage::create(folder, Arc::clone(&thread_pool), &config).unwrap();
+		let (started_tx, started_rx) = std::sync::mpsc::channel();
+		let (closed_tx, closed_rx) = std::sync::mpsc::channel();
+
+		// when
+		let (close_result, commit_result) = std::thread::scope(|scope| {
+			let storage = &page_storage;
+			let worker = scope.spawn(move || {
+				let mut t = storage.transaction().unwrap();
+				t.get_page_mut(page_address!(69, 420))
+					.unwrap()
+					.write(25, &[1, 2, 3, 4])
+					.unwrap();
+				started_tx.send(()).unwrap();
+				closed_rx.recv().unwrap();
+				t.commit()
+			});
+			started_rx.recv().unwrap();
+			let close_result = page_storage.close();
+			closed_tx.send(()).unwrap();
+			(close_result, worker.join().unwrap())
+		});
+		mem::drop(page_storage);
+		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()).unwrap());
+		let was_clean_shutdown = folder.was_clean_shutdown();
+		let page_storage = PageStorage::open(folder, thread_pool, &config).unwrap();
+		page_storage.recover().unwrap();
+
+		// then
+		assert!(matches!(
+			close_result,
+			Err(StorageError::TransactionsAborted(1))
+		));
+		assert!(matches!(
+			commit_result,
+			Err(StorageError::TransactionAborted)
+		));
+		assert!(!was_clean_shutdown);
+		let mut data = [0; 4];
+		page_storage
+			.get_page(page_address!(69, 420))
+			.unwrap()
+			.read(25, &mut data)
+			.unwrap();
+		assert_buf_eq!(data, [0; 4]);
+	}
+
+	#[test]
+	fn integration_read_only() {
+		let tempdir = tempdir().unwrap();
+		let copy_dir_path = tempdir.path().join("copy");
+
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().join("db"), Default::default()).unwrap(),
+		);
+		let thread_pool: Arc<dyn Scheduler> = Arc::new(ThreadPool::new().unwrap());
+		let page_storage =
+			PageStorage::create(folder, Arc::clone(&thread_pool), &Default::default()).unwrap();
+
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[1, 2, 3, 4])
+			.unwrap();
+		t.commit().unwrap();
+
+		// The change only exists in the WAL at this point
+		copy_dir(&tempdir.path().join("db"), &copy_dir_path);
+		let segment_before = std::fs::read(copy_dir_path.join("segments/69")).unwrap();
+		let wal_before = std::fs::read(copy_dir_path.join("wal/0")).unwrap();
+
+		let folder = Arc::new(DatabaseFolder::open_read_only(copy_dir_path.clone()).unwrap());
+		let read_only_storage =
+			PageStorage::open(folder, thread_pool, &Default::default()).unwrap();
+		read_only_storage.recover().unwrap();
+
+		let mut data = [0; 4];
+		read_only_storage
+			.get_page(page_address!(69, 420))
+			.unwrap()
+			.read(25, &mut data)
+			.unwrap();
+		assert_buf_eq!(data, [1, 2, 3, 4]);
+
+		assert!(matches!(
+			read_only_storage.transaction(),
+			Err(StorageError::ReadOnly)
+		));
+		read_only_storage.close().unwrap();
+
+		assert_buf_eq!(
+			std::fs::read(copy_dir_path.join("segments/69")).unwrap(),
+			segment_before
+		);
+		assert_buf_eq!(
+			std::fs::read(copy_dir_path.join("wal/0")).unwrap(),
+			wal_before
+		);
+	}
+
+	#[test]
+	fn integration_encrypted() {
+		let tempdir = tempdir().unwrap();
+		let key_provider = StaticKeyProvider([25; 32]);
+
+		let folder = Arc::new(
+			DatabaseFolder::create_encrypted(
+				tempdir.path().join("db"),
+				Default::default(),
+				&key_provider,
+			)
+			.unwrap(),
+		);
+		let thread_pool: Arc<dyn Scheduler> = Arc::new(ThreadPool::new().unwrap());
+		let page_storage =
+			PageStorage::create(folder, Arc::clone(&thread_pool), &Default::default()).unwrap();
+
+		let mut t = page_storage.transaction().unwrap();
+		t.get_page_mut(page_address!(69, 420))
+			.unwrap()
+			.write(25, &[0xaa; 64])
+			.unwrap();
+		t.commit().unwrap();
+		// Closing takes a final checkpoint, which deletes the first generation
+		let wal = std::fs::read(tempdir.path().join("db/wal/0")).unwrap();
+		page_storage.close().unwrap();
+
+		let segment = std::fs::read(tempdir.path().join("db/segments/69")).unwrap();
+		assert!(!segment.windows(64).any(|window| window == [0xaa; 64]));
+		assert!(!wal.windows(64).any(|window| window == [0xaa; 64]));
+
+		// The background tasks of the closed storage may still hold the folder lock
+		copy_dir(&tempdir.path().join("db"), &tempdir.path().join("copy"));
+		let folder = Arc::new(
+			DatabaseFolder::open_encrypted(tempdir.path().join("copy"), &key_provider).unwrap(),
+		);
+		let page_storage = PageStorage::open(folder, thread_pool, &Default::default()).unwrap();
+		page_storage.recover().unwrap();
+
+		let mut data = [0; 64];
+		page_storage
+			.get_page(page_address!(69, 420))
+			.unwrap()
+			.read(25, &mut data)
+			.unwrap();
+		assert_buf_eq!(data, [0xaa; 64]);
+	}
+
 	#[bench]
 	fn bench_write_and_commit(b: &mut Bencher) {
 		let tempdir = tempdir().unwrap();
 
-		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()));
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
 		let thread_pool = Arc::new(ThreadPool::new().unwrap());
 		let page_storage = PageStorage::create(folder, thread_pool, &Default::default()).unwrap();
 
@@ -852,7 +1635,9 @@ mod tests {
 	fn bench_flush(b: &mut Bencher) {
 		let tempdir = tempdir().unwrap();
 
-		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()));
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
 		let thread_pool = Arc::new(ThreadPool::new().unwrap());
 		let page_storage = PageStorage::create(folder, thread_pool, &Default::default()).unwrap();
 
@@ -874,7 +1659,9 @@ mod tests {
 	fn bench_multi_page_write_and_commit(b: &mut Bencher) {
 		let tempdir = tempdir().unwrap();
 
-		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()));
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
 		let thread_pool = Arc::new(ThreadPool::new().unwrap());
 		let page_storage = PageStorage::create(folder, thread_pool, &Default::default()).unwrap();
 
@@ -926,7 +1713,9 @@ mod tests {
 	fn bench_multi_page_flush(b: &mut Bencher) {
 		let tempdir = tempdir().unwrap();
 
-		let folder = Arc::new(DatabaseFolder::open(tempdir.path().to_path_buf()));
+		let folder = Arc::new(
+			DatabaseFolder::create(tempdir.path().to_path_buf(), Default::default()).unwrap(),
+		);
 		let thread_pool = Arc::new(ThreadPool::new().unwrap());
 		let page_storage = PageStorage::create(folder, thread_pool, &Default::default()).unwrap();
 
@@ -1,18 +1,28 @@
-use std::{collections::HashMap, mem, sync::Arc};
+use std::{
+	collections::{HashMap, HashSet},
+	mem,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
 
 #[cfg(test)]
 use mockall::automock;
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use static_assertions::assert_impl_all;
 
 use crate::{
-	consts::DEFAULT_MAX_NUM_OPEN_SEGMENTS,
+	consts::{DEFAULT_MAX_NUM_OPEN_SEGMENTS, DEFAULT_REPLACEMENT_POLICY},
 	files::{
-		segment::{SegmentFileApi, SegmentOp, SegmentReadOp, SegmentWriteOp},
-		DatabaseFolder, DatabaseFolderApi,
+		segment::{SegmentFileApi, SegmentOp, SegmentReadOp, SegmentStats, SegmentWriteOp},
+		DatabaseFolder, DatabaseFolderApi, FileError,
+	},
+	utils::{
+		cache::{ReplacementPolicy, ReplacementPolicyKind},
+		metrics::{Histogram, HistogramSnapshot, BATCH_SIZE_BUCKETS},
 	},
-	utils::cache::CacheReplacer,
 };
 
 use super::{PageAddress, StorageError, WalIndex};
@@ -23,19 +33,63 @@ where
 {
 	folder: Arc<DF>,
 	descriptor_cache: RwLock<DescriptorCache<DF>>,
+	/// The segments written to since they were last synced
+	unsynced: Mutex<HashSet<u32>>,
+	stats: AtomicPhysicalStorageStats,
 }
 
 assert_impl_all!(PhysicalStorage: Send, Sync);
 
+/// Statistics about the pages read from and written to segment files since
+/// the physical storage was created.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PhysicalStorageStats {
+	pub pages_read: u64,
+	pub pages_written: u64,
+	/// The number of operations in each batch
+	pub batch_sizes: HistogramSnapshot,
+}
+
+#[derive(Debug)]
+struct AtomicPhysicalStorageStats {
+	pages_read: AtomicU64,
+	pages_written: AtomicU64,
+	batch_sizes: Histogram,
+}
+
+impl Default for AtomicPhysicalStorageStats {
+	fn default() -> Self {
+		Self {
+			pages_read: AtomicU64::new(0),
+			pages_written: AtomicU64::new(0),
+			batch_sizes: Histogram::new(BATCH_SIZE_BUCKETS),
+		}
+	}
+}
+
+impl AtomicPhysicalStorageStats {
+	fn get(&self) -> PhysicalStorageStats {
+		PhysicalStorageStats {
+			pages_read: self.pages_read.load(Ordering::Relaxed),
+			pages_written: self.pages_written.load(Ordering::Relaxed),
+			batch_sizes: self.batch_sizes.get(),
+		}
+	}
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct PhysicalStorageConfig {
 	pub max_num_open_segments: usize,
+	/// The policy that decides which segment files are closed when too many are
+	/// open
+	pub replacement_policy: ReplacementPolicyKind,
 }
 
 impl Default for PhysicalStorageConfig {
 	fn default() -> Self {
 		Self {
 			max_num_open_segments: DEFAULT_MAX_NUM_OPEN_SEGMENTS,
+			replacement_policy: DEFAULT_REPLACEMENT_POLICY,
 		}
 	}
 }
@@ -49,9 +103,23 @@ where
 		Self {
 			folder,
 			descriptor_cache,
+			unsynced: Mutex::new(HashSet::new()),
+			stats: AtomicPhysicalStorageStats::default(),
 		}
 	}
 
+	pub fn folder(&self) -> &DF {
+		&self.folder
+	}
+
+	/// Returns write statistics for a segment, or `None` if it is currently not
+	/// open. Statistics only cover writes since the segment was last opened.
+	pub fn segment_stats(&self, segment_num: u32) -> Option<SegmentStats> {
+		let cache = self.descriptor_cache.read();
+		let segment = cache.descriptors.get(&segment_num)?;
+		Some(segment.stats())
+	}
+
 	fn use_segment(
 		&self,
 		segment_num: u32,
@@ -118,17 +186,43 @@ pub(crate) trait PhysicalStorageApi {
 	fn write<'a>(&self, op: WriteOp<'a>) -> Result<(), StorageError>;
 
 	fn batch<'a>(&self, ops: Box<[Op<'a>]>) -> Result<(), StorageError>;
+
+	/// Makes all pages written so far durable.
+	fn sync(&self) -> Result<(), StorageError>;
+
+	fn stats(&self) -> PhysicalStorageStats;
 }
 
 impl<DF: DatabaseFolderApi> PhysicalStorageApi for PhysicalStorage<DF> {
 	fn read(&self, op: ReadOp) -> Result<(), StorageError> {
-		self.use_segment(op.page_address.segment_num, |segment| {
-			segment.read(op.into())?;
+		let ReadOp {
+			page_address,
+			wal_index,
+			buf,
+		} = op;
+		self.stats.pages_read.fetch_add(1, Ordering::Relaxed);
+		let result = self.use_segment(page_address.segment_num, |segment| {
+			segment.read(SegmentReadOp {
+				page_num: page_address.page_num,
+				wal_index: &mut *wal_index,
+				buf: &mut *buf,
+			})?;
 			Ok(())
-		})
+		});
+
+		// Segments are never created when the database is read-only, but pages in
+		// missing segments are simply uninitialized.
+		if let Err(StorageError::File(FileError::MissingSegment(..))) = result {
+			*wal_index = None;
+			buf.fill(0);
+			return Ok(());
+		}
+		result
 	}
 
 	fn write(&self, op: WriteOp) -> Result<(), StorageError> {
+		self.stats.pages_written.fetch_add(1, Ordering::Relaxed);
+		self.unsynced.lock().insert(op.page_address.segment_num);
 		self.use_segment(op.page_address.segment_num, |segment| {
 			segment.write(op.into())?;
 			Ok(())
@@ -136,18 +230,22 @@ impl<DF: DatabaseFolderApi> PhysicalStorageApi for PhysicalStorage<DF> {
 	}
 
 	fn batch(&self, ops: Box<[Op]>) -> Result<(), StorageError> {
+		self.stats.batch_sizes.observe(ops.len() as u64);
 		let mut segment_batches: HashMap<u32, Vec<SegmentOp>> = HashMap::new();
 		for op in ops {
 			let segment_num: u32;
 			let segment_op: SegmentOp;
 			match op {
 				Op::Read(read_op) => {
+					self.stats.pages_read.fetch_add(1, Ordering::Relaxed);
 					segment_num = read_op.page_address.segment_num;
 					segment_op = SegmentOp::Read(read_op.into());
 				}
 				Op::Write(write_op) => {
+					self.stats.pages_written.fetch_add(1, Ordering::Relaxed);
 					segment_num = write_op.page_address.segment_num;
 					segment_op = SegmentOp::Write(write_op.into());
+					self.unsynced.lock().insert(segment_num);
 				}
 			}
 			segment_batches
@@ -165,18 +263,37 @@ impl<DF: DatabaseFolderApi> PhysicalStorageApi for PhysicalStorage<DF> {
 
 		Ok(())
 	}
+
+	fn sync(&self) -> Result<(), StorageError> {
+		// Segments that were closed since they were written are opened again, since
+		// closing a file doesn't make it durable.
+		let segment_nums: Vec<u32> = mem::take(&mut *self.unsynced.lock()).into_iter().collect();
+		for (i, segment_num) in segment_nums.iter().enumerate() {
+			if let Err(err) = self.use_segment(*segment_num, |segment| Ok(segment.sync()?)) {
+				self.unsynced.lock().extend(&segment_nums[i..]);
+				return Err(err);
+			}
+		}
+		Ok(())
+	}
+
+	fn stats(&self) -> PhysicalStorageStats {
+		self.stats.get()
+	}
 }
 
 struct DescriptorCache<DF: DatabaseFolderApi> {
 	descriptors: HashMap<u32, DF::SegmentFile>,
-	replacer: CacheReplacer<u32>,
+	replacer: Box<dyn ReplacementPolicy<u32>>,
 	max_num_open_segments: usize,
 }
 
 impl<DF: DatabaseFolderApi> DescriptorCache<DF> {
 	fn new(config: &PhysicalStorageConfig) -> Self {
 		let descriptors = HashMap::with_capacity(config.max_num_open_segments);
-		let replacer = CacheReplacer::new(config.max_num_open_segments);
+		let replacer = config
+			.replacement_policy
+			.create(config.max_num_open_segments);
 		Self {
 			descriptors,
 			replacer,
@@ -216,7 +333,7 @@ impl<DF: DatabaseFolderApi> DescriptorCache<DF> {
 mod tests {
 	use crate::{
 		files::{
-			segment::{MockSegmentFileApi, PAGE_BODY_SIZE},
+			segment::{MockSegmentFileApi, DEFAULT_PAGE_BODY_SIZE},
 			test_helpers::{page_address, wal_index},
 			MockDatabaseFolderApi,
 		},
@@ -243,7 +360,7 @@ mod tests {
 						*op == SegmentWriteOp {
 							page_num: non_zero!(420),
 							wal_index: wal_index!(69, 420),
-							buf: &[1; PAGE_BODY_SIZE],
+							buf: &[1; DEFAULT_PAGE_BODY_SIZE],
 						}
 					})
 					.returning(|_| Ok(()));
@@ -257,12 +374,82 @@ mod tests {
 		storage
 			.write(WriteOp {
 				page_address: page_address!(69, 420),
-				buf: &[1; PAGE_BODY_SIZE],
+				buf: &[1; DEFAULT_PAGE_BODY_SIZE],
 				wal_index: wal_index!(69, 420),
 			})
 			.unwrap();
 	}
 
+	#[test]
+	fn sync_written_segments() {
+		// expect
+		let mut folder = MockDatabaseFolderApi::new();
+		folder
+			.expect_open_segment_file()
+			.once()
+			.with(eq(69))
+			.returning(|_| {
+				let mut segment = MockSegmentFileApi::new();
+				segment.expect_write().once().returning(|_| Ok(()));
+				segment.expect_sync().once().returning(|| Ok(()));
+				Ok(segment)
+			});
+
+		// given
+		let storage = PhysicalStorage::new(Arc::new(folder), &Default::default());
+		storage
+			.write(WriteOp {
+				page_address: page_address!(69, 420),
+				buf: &[1; DEFAULT_PAGE_BODY_SIZE],
+				wal_index: wal_index!(69, 420),
+			})
+			.unwrap();
+
+		// when
+		storage.sync().unwrap();
+		storage.sync().unwrap();
+	}
+
+	#[test]
+	fn get_segment_stats() {
+		// expect
+		let mut folder = MockDatabaseFolderApi::new();
+		folder
+			.expect_open_segment_file()
+			.once()
+			.with(eq(69))
+			.returning(|_| {
+				let mut segment = MockSegmentFileApi::new();
+				segment.expect_write().returning(|_| Ok(()));
+				segment.expect_stats().returning(|| SegmentStats {
+					pages_written: 1,
+					compressed_pages_written: 1,
+					body_bytes_written: 400,
+					body_bytes_stored: 100,
+					write_calls: 1,
+				});
+				Ok(segment)
+			});
+
+		// given
+		let storage = PhysicalStorage::new(Arc::new(folder), &Default::default());
+		storage
+			.write(WriteOp {
+				page_address: page_address!(69, 420),
+				buf: &[1; DEFAULT_PAGE_BODY_SIZE],
+				wal_index: wal_index!(69, 420),
+			})
+			.unwrap();
+
+		// when
+		let stats = storage.segment_stats(69).unwrap();
+		let closed_stats = storage.segment_stats(25);
+
+		// then
+		assert_eq!(stats.compression_ratio(), 4.0);
+		assert_eq!(closed_stats, None);
+	}
+
 	#[test]
 	fn read_from_storage() {
 		// expect
@@ -303,4 +490,33 @@ mod tests {
 		assert_eq!(wal_index, Some(wal_index!(69, 420)));
 		assert_eq!(buf[0..3], [1, 2, 3]);
 	}
+
+	#[test]
+	fn read_from_missing_segment() {
+		// expect
+		let mut folder = MockDatabaseFolderApi::new();
+		folder
+			.expect_open_segment_file()
+			.once()
+			.with(eq(69))
+			.returning(|segment_num| Err(FileError::MissingSegment(segment_num)));
+
+		// given
+		let storage = PhysicalStorage::new(Arc::new(folder), &Default::default());
+
+		// when
+		let mut buf = [25; 3];
+		let mut wal_index = Some(wal_index!(69, 420));
+		storage
+			.read(ReadOp {
+				page_address: page_address!(69, 420),
+				wal_index: &mut wal_index,
+				buf: &mut buf,
+			})
+			.unwrap();
+
+		// then
+		assert_eq!(wal_index, None);
+		assert_eq!(buf, [0; 3]);
+	}
 }
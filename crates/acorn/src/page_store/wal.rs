@@ -2,12 +2,13 @@ use std::{
 	borrow::{Borrow, Cow},
 	collections::{hash_map::Entry, HashMap, VecDeque},
 	mem,
-	sync::Arc,
-	time::Duration,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
 };
 
-use futures::executor::ThreadPool;
-use log::error;
 #[cfg(test)]
 use mockall::{automock, concretize};
 
@@ -20,7 +21,8 @@ use crate::{
 		wal::{self, CheckpointData, WalFileApi},
 		DatabaseFolder, DatabaseFolderApi,
 	},
-	tasks::{Timer, TimerHandle},
+	tasks::{FailureStrategy, JobKind, JobManager, Timer, TimerHandle},
+	utils::metrics::{Histogram, HistogramSnapshot},
 };
 
 use super::{PageAddress, StorageError, TransactionState, WalIndex};
@@ -29,6 +31,10 @@ use super::{PageAddress, StorageError, TransactionState, WalIndex};
 pub(crate) struct WalConfig {
 	pub max_generation_size: usize,
 	pub checkpoint_period: Duration,
+	/// How failed checkpoints are retried. By default, a checkpoint that keeps
+	/// failing makes the database read-only, since the WAL can't be cleaned up
+	/// anymore.
+	pub checkpoint_failures: FailureStrategy,
 }
 
 impl Default for WalConfig {
@@ -36,6 +42,92 @@ impl Default for WalConfig {
 		Self {
 			max_generation_size: DEFAULT_MAX_WAL_GENERATION_SIZE,
 			checkpoint_period: DEFAULT_CHECKPOINT_PERIOD,
+			checkpoint_failures: FailureStrategy {
+				fatal: true,
+				..Default::default()
+			},
+		}
+	}
+}
+
+/// Statistics about the WAL since it was opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct WalStats {
+	pub bytes_written: u64,
+	pub write_items: u64,
+	/// The number of compensation items that undo a write
+	pub undo_items: u64,
+	pub commit_items: u64,
+	pub checkpoint_items: u64,
+	/// The number of generations that are currently kept
+	pub num_generations: u64,
+	pub checkpoints: u64,
+	/// The latency of writing buffered items to the current generation, in
+	/// microseconds
+	pub flush_latency: HistogramSnapshot,
+	/// The latency of making the current generation durable, in microseconds
+	pub sync_latency: HistogramSnapshot,
+	/// The duration of checkpoints, in microseconds
+	pub checkpoint_duration: HistogramSnapshot,
+}
+
+#[derive(Debug)]
+struct AtomicWalStats {
+	bytes_written: AtomicU64,
+	write_items: AtomicU64,
+	undo_items: AtomicU64,
+	commit_items: AtomicU64,
+	checkpoint_items: AtomicU64,
+	checkpoints: AtomicU64,
+	flush_latency: Histogram,
+	sync_latency: Histogram,
+	checkpoint_duration: Histogram,
+}
+
+impl Default for AtomicWalStats {
+	fn default() -> Self {
+		Self {
+			bytes_written: AtomicU64::new(0),
+			write_items: AtomicU64::new(0),
+			undo_items: AtomicU64::new(0),
+			commit_items: AtomicU64::new(0),
+			checkpoint_items: AtomicU64::new(0),
+			checkpoints: AtomicU64::new(0),
+			flush_latency: Histogram::latency(),
+			sync_latency: Histogram::latency(),
+			checkpoint_duration: Histogram::latency(),
+		}
+	}
+}
+
+impl AtomicWalStats {
+	/// The counter for items of the same kind as `item`
+	fn items_of_kind(&self, item: &wal::Item) -> &AtomicU64 {
+		match item {
+			wal::Item::Write(data) if data.from.is_none() => &self.undo_items,
+			wal::Item::Write(..) => &self.write_items,
+			wal::Item::Commit(..) => &self.commit_items,
+			wal::Item::Checkpoint(..) => &self.checkpoint_items,
+		}
+	}
+
+	fn record_item(&self, items_of_kind: &AtomicU64, num_bytes: u64) {
+		items_of_kind.fetch_add(1, Ordering::Relaxed);
+		self.bytes_written.fetch_add(num_bytes, Ordering::Relaxed);
+	}
+
+	fn get(&self, num_generations: u64) -> WalStats {
+		WalStats {
+			bytes_written: self.bytes_written.load(Ordering::Relaxed),
+			write_items: self.write_items.load(Ordering::Relaxed),
+			undo_items: self.undo_items.load(Ordering::Relaxed),
+			commit_items: self.commit_items.load(Ordering::Relaxed),
+			checkpoint_items: self.checkpoint_items.load(Ordering::Relaxed),
+			num_generations,
+			checkpoints: self.checkpoints.load(Ordering::Relaxed),
+			flush_latency: self.flush_latency.get(),
+			sync_latency: self.sync_latency.get(),
+			checkpoint_duration: self.checkpoint_duration.get(),
 		}
 	}
 }
@@ -72,34 +164,57 @@ pub(crate) struct CommitLog {
 
 pub(crate) struct Wal<DF: DatabaseFolderApi = DatabaseFolder> {
 	folder: Arc<DF>,
-	thread_pool: Arc<ThreadPool>,
+	jobs: Arc<JobManager>,
 	generations: Arc<RwLock<GenerationQueue<DF>>>,
 	state: Arc<Mutex<State>>,
+	stats: Arc<AtomicWalStats>,
 	max_generation_size: usize,
-	checkpoint_timer_handle: TimerHandle,
+	checkpoint_failures: FailureStrategy,
+	read_only: bool,
+	checkpoint_timer_handle: Option<TimerHandle>,
 }
 assert_impl_all!(Wal: Send, Sync);
 
 impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 	pub fn create(
 		folder: Arc<DF>,
-		thread_pool: Arc<ThreadPool>,
+		jobs: Arc<JobManager>,
 		config: &WalConfig,
 	) -> Result<Self, StorageError> {
 		folder.clear_wal_files()?;
 		let mut gens: GenerationQueue<DF> = GenerationQueue::new();
 		gens.push_generation(0, folder.open_wal_file(0)?);
 
-		let wal = Self::new(folder, thread_pool, config, gens, State::default());
-		Self::log_checkpoint(&wal.generations, &wal.state)?;
+		let wal = Self::new(folder, jobs, config, gens, State::default(), false);
+		Self::log_checkpoint(&wal.generations, &wal.state, &wal.stats)?;
 
 		Ok(wal)
 	}
 
 	pub fn open(
 		folder: Arc<DF>,
-		thread_pool: Arc<ThreadPool>,
+		jobs: Arc<JobManager>,
 		config: &WalConfig,
+	) -> Result<Self, StorageError> {
+		Self::open_impl(folder, jobs, config, false)
+	}
+
+	/// Opens the WAL without ever writing to it. No checkpoints are taken, and
+	/// recovery only applies changes through the handler, without logging
+	/// compensation items.
+	pub fn open_read_only(
+		folder: Arc<DF>,
+		jobs: Arc<JobManager>,
+		config: &WalConfig,
+	) -> Result<Self, StorageError> {
+		Self::open_impl(folder, jobs, config, true)
+	}
+
+	fn open_impl(
+		folder: Arc<DF>,
+		jobs: Arc<JobManager>,
+		config: &WalConfig,
+		read_only: bool,
 	) -> Result<Self, StorageError> {
 		let mut wal_files: Vec<(u64, DF::WalFile)> = Result::from_iter(folder.iter_wal_files()?)?;
 		wal_files.sort_by(|(gen_1, _), (gen_2, _)| u64::cmp(gen_1, gen_2));
@@ -111,37 +226,51 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 
 		Ok(Self::new(
 			folder,
-			thread_pool,
+			jobs,
 			config,
 			gens,
 			State::default(),
+			read_only,
 		))
 	}
 
 	fn new(
 		folder: Arc<DF>,
-		thread_pool: Arc<ThreadPool>,
+		jobs: Arc<JobManager>,
 		config: &WalConfig,
 		generations: GenerationQueue<DF>,
 		state: State,
+		read_only: bool,
 	) -> Self {
 		let generations = Arc::new(RwLock::new(generations));
 		let state = Arc::new(Mutex::new(state));
-
-		let (checkpoint_timer, checkpoint_timer_handle) = Timer::new(config.checkpoint_period);
-		thread_pool.spawn_ok(Self::periodic_checkpoint_task(
-			checkpoint_timer,
-			Arc::clone(&generations),
-			Arc::clone(&state),
-			Arc::clone(&folder),
-		));
+		let stats = Arc::new(AtomicWalStats::default());
+
+		let checkpoint_timer_handle = if read_only {
+			None
+		} else {
+			let (checkpoint_timer, checkpoint_timer_handle) = jobs.timer(config.checkpoint_period);
+			jobs.spawn(Self::periodic_checkpoint_task(
+				checkpoint_timer,
+				Arc::clone(&jobs),
+				config.checkpoint_failures.clone(),
+				Arc::clone(&generations),
+				Arc::clone(&state),
+				Arc::clone(&stats),
+				Arc::clone(&folder),
+			));
+			Some(checkpoint_timer_handle)
+		};
 
 		Self {
 			folder,
-			thread_pool,
+			jobs,
 			generations,
 			state,
+			stats,
 			max_generation_size: config.max_generation_size,
+			checkpoint_failures: config.checkpoint_failures.clone(),
+			read_only,
 			checkpoint_timer_handle,
 		}
 	}
@@ -149,6 +278,7 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 	fn log_checkpoint(
 		generations: &RwLock<GenerationQueue<DF>>,
 		state: &Mutex<State>,
+		stats: &AtomicWalStats,
 	) -> Result<(), StorageError> {
 		let generations = generations.read();
 		let Some(mut wal_file) = generations.current_generation() else {
@@ -156,10 +286,14 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 		};
 
 		let state = state.lock();
-		wal_file.push_item(wal::Item::Checkpoint(CheckpointData {
+		let offset = wal_file.push_item(wal::Item::Checkpoint(CheckpointData {
 			dirty_pages: Cow::Borrowed(&state.dirty_pages),
 			transactions: Cow::Borrowed(&state.transactions),
 		}))?;
+		stats.record_item(
+			&stats.checkpoint_items,
+			(wal_file.size() as u64).saturating_sub(offset.get()),
+		);
 
 		Ok(())
 	}
@@ -188,24 +322,33 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 		Ok(())
 	}
 
-	fn read_initial_state(&self, file: &mut DF::WalFile) -> Result<(), StorageError> {
-		let mut checkpoint_data: Option<wal::CheckpointData> = None;
-		for item_result in file.iter_items()? {
-			if let (_, wal::Item::Checkpoint(data)) = item_result? {
-				checkpoint_data = Some(data);
-				break;
+	/// Restores the state from the newest checkpoint, and returns the position
+	/// of its generation in the queue. A checkpoint that crashed before its item
+	/// was written leaves behind a newer generation without one.
+	fn read_initial_state(&self, gens: &GenerationQueue<DF>) -> Result<usize, StorageError> {
+		let mut checkpoint: Option<(usize, wal::CheckpointData)> = None;
+		'gen_loop: for (i, generation) in gens.generations.iter().enumerate().rev() {
+			for item_result in generation.file.lock().iter_items()? {
+				if let (_, wal::Item::Checkpoint(data)) = item_result? {
+					checkpoint = Some((i, data));
+					break 'gen_loop;
+				}
 			}
 		}
 
 		let mut state = self.state.lock();
-		*state = match checkpoint_data {
-			Some(data) => State::new(
-				data.dirty_pages.into_owned(),
-				data.transactions.into_owned(),
+		let (start, initial_state) = match checkpoint {
+			Some((i, data)) => (
+				i,
+				State::new(
+					data.dirty_pages.into_owned(),
+					data.transactions.into_owned(),
+				),
 			),
-			None => State::default(),
+			None => (0, State::default()),
 		};
-		Ok(())
+		*state = initial_state;
+		Ok(start)
 	}
 
 	fn recover_state(&self, file: &mut DF::WalFile, gen_num: u64) -> Result<(), StorageError> {
@@ -294,47 +437,67 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 		gens: &mut GenerationQueue<DF>,
 		mut handle: impl FnMut(PartialWriteOp) -> Result<(), StorageError>,
 	) -> Result<(), StorageError> {
+		// The writes of each transaction are found by following the chain of its items
+		// backwards, starting at the last one. Transaction IDs are reused after a
+		// restart, so writes with a matching ID that aren't part of the chain belong
+		// to an earlier transaction.
 		let state = self.state.lock();
-		let last_indices: Vec<WalIndex> = transaction_ids
+		let mut next_indices: HashMap<u64, WalIndex> = transaction_ids
 			.iter()
-			.filter_map(|tid| state.transactions.get(tid).map(|ts| ts.last_index))
+			.filter_map(|tid| state.transactions.get(tid).map(|ts| (*tid, ts.last_index)))
 			.collect();
-
-		if last_indices.is_empty() {
-			return Ok(());
-		}
-
-		let lowest_index: WalIndex = *last_indices.iter().min().unwrap();
 		mem::drop(state);
 
-		let mut compensation_items: Vec<UndoLog> = Vec::new();
+		let mut compensation_items: Vec<(WalIndex, UndoLog)> = Vec::new();
 
 		'gen_loop: for generation in gens.generations.iter().rev() {
+			if next_indices.is_empty() {
+				break 'gen_loop;
+			}
 			let mut wal_file = generation.file.lock();
-			'item_loop: for item_result in wal_file.iter_items_reverse()? {
+			for item_result in wal_file.iter_items_reverse()? {
 				let (offset, item) = item_result?;
 				let index = WalIndex::new(generation.gen_num, offset);
-				if index < lowest_index {
-					break 'gen_loop;
-				}
 
-				if let wal::Item::Write(data) = item {
-					if !transaction_ids.contains(&data.transaction_data.transaction_id) {
-						continue 'item_loop;
-					}
-					if let Some(compensation_item) = Self::create_undo_log(data) {
-						compensation_items.push(compensation_item);
-					}
+				let wal::Item::Write(data) = item else {
+					continue;
+				};
+				let transaction_id = data.transaction_data.transaction_id;
+				if next_indices.get(&transaction_id) != Some(&index) {
+					continue;
+				}
+				match data.transaction_data.prev_transaction_item {
+					Some(prev_index) => next_indices.insert(transaction_id, prev_index),
+					None => next_indices.remove(&transaction_id),
+				};
+				if let Some(compensation_item) = Self::create_undo_log(data) {
+					compensation_items.push((index, compensation_item));
+				}
+				if next_indices.is_empty() {
+					break 'gen_loop;
 				}
 			}
 		}
 
-		for item in compensation_items {
-			self.apply_undo_log(item, gens, &mut handle)?;
+		for (index, item) in compensation_items {
+			if self.read_only {
+				// The undo is only applied in memory, so it keeps the index of the write it
+				// reverts.
+				handle(PartialWriteOp {
+					index,
+					page_address: item.page_address,
+					offset: item.offset,
+					buf: &item.to,
+				})?;
+			} else {
+				self.apply_undo_log(item, gens, &mut handle)?;
+			}
 		}
 
 		for tid in transaction_ids {
-			self.push_raw_item(wal::Item::Commit(self.create_transaction_data(*tid)), gens)?;
+			if !self.read_only {
+				self.push_raw_item(wal::Item::Commit(self.create_transaction_data(*tid)), gens)?;
+			}
 
 			let mut state = self.state.lock();
 			state.complete_transaction(*tid);
@@ -358,14 +521,27 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 		state.handle_item(index, &item);
 		mem::drop(state);
 
+		let items_of_kind = self.stats.items_of_kind(&item);
 		wal_file.push_item(item)?;
 
-		if wal_file.size() >= self.max_generation_size {
+		let size = wal_file.size();
+		self.stats.record_item(
+			items_of_kind,
+			(size as u64).saturating_sub(index.offset.get()),
+		);
+		if size >= self.max_generation_size {
 			let generations = Arc::clone(&self.generations);
 			let state = Arc::clone(&self.state);
+			let stats = Arc::clone(&self.stats);
 			let folder = Arc::clone(&self.folder);
-			self.thread_pool
-				.spawn_ok(Self::single_checkpoint_task(generations, state, folder))
+			self.jobs.spawn(Self::single_checkpoint_task(
+				Arc::clone(&self.jobs),
+				self.checkpoint_failures.clone(),
+				generations,
+				state,
+				stats,
+				folder,
+			))
 		}
 
 		Ok(index)
@@ -413,55 +589,104 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> Wal<DF> {
 		self.push_raw_item(wal::Item::Write(write_data), gens)
 	}
 
-	fn flush_impl(gens: &GenerationQueue<DF>) -> Result<(), StorageError> {
+	fn flush_impl(gens: &GenerationQueue<DF>, stats: &AtomicWalStats) -> Result<(), StorageError> {
 		if let Some(mut gen) = gens.current_generation() {
+			let start = Instant::now();
 			gen.flush()?;
+			stats.flush_latency.observe_duration(start.elapsed());
 		}
 		Ok(())
 	}
 
-	async fn checkpoint(
+	/// Makes the current generation durable, and returns the index before which
+	/// all items are durable.
+	fn sync_impl(
+		gens: &GenerationQueue<DF>,
+		stats: &AtomicWalStats,
+	) -> Result<WalIndex, StorageError> {
+		let Some(mut gen) = gens.current_generation() else {
+			return Err(StorageError::WalNotInitialized);
+		};
+		let start = Instant::now();
+		gen.sync()?;
+		stats.sync_latency.observe_duration(start.elapsed());
+		Ok(WalIndex::new(gens.current_gen_num, gen.next_offset()))
+	}
+
+	/// Returns a function that makes all items logged so far durable. The page
+	/// cache calls it before writing pages back, so that no change reaches the
+	/// segment files before the items needed to undo it.
+	pub fn sync_handle(
+		&self,
+	) -> impl Fn() -> Result<WalIndex, StorageError> + Send + Sync + 'static {
+		let generations = Arc::clone(&self.generations);
+		let stats = Arc::clone(&self.stats);
+		move || Self::sync_impl(&generations.read(), &stats)
+	}
+
+	fn checkpoint_impl(
 		generations: &RwLock<GenerationQueue<DF>>,
 		state: &Mutex<State>,
+		stats: &AtomicWalStats,
 		folder: &DF,
 	) -> Result<(), StorageError> {
 		let mut gens_mut = generations.write();
-		Self::flush_impl(&gens_mut)?;
+		// Only the current generation is synced on commit, so it has to be durable
+		// before it is replaced.
+		Self::flush_impl(&gens_mut, stats)?;
+		Self::sync_impl(&gens_mut, stats)?;
 		let gen_num = gens_mut.current_gen_num + 1;
 		let file = folder.open_wal_file(gen_num)?;
 		gens_mut.push_generation(gen_num, file);
 		Self::cleanup_generations(&mut gens_mut, state, folder)?;
 		mem::drop(gens_mut);
-		Self::log_checkpoint(generations, state)?;
+		Self::log_checkpoint(generations, state, stats)?;
 		Ok(())
 	}
 
-	async fn checkpoint_ok(
+	/// Takes a checkpoint as a job, so that it is retried if it fails.
+	async fn checkpoint_job(
+		jobs: &JobManager,
+		strategy: &FailureStrategy,
 		generations: &RwLock<GenerationQueue<DF>>,
 		state: &Mutex<State>,
+		stats: &AtomicWalStats,
 		folder: &DF,
 	) {
-		if let Err(err) = Self::checkpoint(generations, state, folder).await {
-			error!("A WAL checkpoint failed: {err}");
+		let start = Instant::now();
+		let result = jobs
+			.run(JobKind::Checkpoint, strategy, || {
+				Self::checkpoint_impl(generations, state, stats, folder)
+			})
+			.await;
+		if result.is_ok() {
+			stats.checkpoints.fetch_add(1, Ordering::Relaxed);
+			stats.checkpoint_duration.observe_duration(start.elapsed());
 		}
 	}
 
 	async fn single_checkpoint_task(
+		jobs: Arc<JobManager>,
+		strategy: FailureStrategy,
 		generations: Arc<RwLock<GenerationQueue<DF>>>,
 		state: Arc<Mutex<State>>,
+		stats: Arc<AtomicWalStats>,
 		folder: Arc<DF>,
 	) {
-		Self::checkpoint_ok(&generations, &state, &folder).await;
+		Self::checkpoint_job(&jobs, &strategy, &generations, &state, &stats, &folder).await;
 	}
 
 	async fn periodic_checkpoint_task(
-		timer: Timer,
+		mut timer: Timer,
+		jobs: Arc<JobManager>,
+		strategy: FailureStrategy,
 		generations: Arc<RwLock<GenerationQueue<DF>>>,
 		state: Arc<Mutex<State>>,
+		stats: Arc<AtomicWalStats>,
 		folder: Arc<DF>,
 	) {
-		while timer.wait() {
-			Self::checkpoint_ok(&generations, &state, &folder).await;
+		while timer.wait().await {
+			Self::checkpoint_job(&jobs, &strategy, &generations, &state, &stats, &folder).await;
 		}
 	}
 }
@@ -484,20 +709,37 @@ pub(crate) trait WalApi {
 		HFn: FnMut(PartialWriteOp) -> Result<(), StorageError>;
 
 	fn cache_did_flush(&self);
+
+	/// Whether recovery replays every write since the database was created,
+	/// which holds as long as the first generation was not deleted.
+	fn has_full_history(&self) -> bool;
+
+	/// Starts a new generation with a checkpoint, and deletes the generations
+	/// that are no longer needed for recovery.
+	fn checkpoint(&self) -> Result<(), StorageError>;
+
+	fn stats(&self) -> WalStats;
 }
 
 impl<DF: DatabaseFolderApi + Send + Sync + 'static> WalApi for Wal<DF> {
 	fn log_write(&self, log: WriteLog) -> Result<WalIndex, StorageError> {
+		if self.read_only {
+			return Err(StorageError::ReadOnly);
+		}
 		let write_data = self.create_write_data(log);
 		let gens = self.generations.read();
 		self.push_raw_item(wal::Item::Write(write_data), &gens)
 	}
 
 	fn log_commit(&self, log: CommitLog) -> Result<WalIndex, StorageError> {
+		if self.read_only {
+			return Err(StorageError::ReadOnly);
+		}
 		let transaction_data = self.create_transaction_data(log.transaction_id);
 		let gens = self.generations.read();
 		let index = self.push_raw_item(wal::Item::Commit(transaction_data), &gens)?;
-		Self::flush_impl(&gens)?;
+		Self::flush_impl(&gens, &self.stats)?;
+		Self::sync_impl(&gens, &self.stats)?;
 		Ok(index)
 	}
 
@@ -505,8 +747,11 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> WalApi for Wal<DF> {
 	where
 		HFn: FnMut(PartialWriteOp) -> Result<(), StorageError>,
 	{
+		if self.read_only {
+			return Err(StorageError::ReadOnly);
+		}
 		let mut gens = self.generations.write();
-		Self::flush_impl(&gens)?;
+		Self::flush_impl(&gens, &self.stats)?;
 		self.undo_all(&[transaction_id], &mut gens, handle)?;
 		Ok(())
 	}
@@ -517,16 +762,28 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> WalApi for Wal<DF> {
 	{
 		// acquire exclusive gen lock to prevent conflicts
 		let mut gens = self.generations.write();
-
-		let Some(mut file) = gens.current_generation() else {
+		if gens.generations.is_empty() {
 			return Err(StorageError::WalNotInitialized);
-		};
+		}
 
-		self.read_initial_state(&mut file)?;
-		self.recover_state(&mut file, gens.current_gen_num)?;
-		#[allow(clippy::needless_borrows_for_generic_args)]
-		self.redo(&mut file, gens.current_gen_num, &mut handle)?;
-		mem::drop(file);
+		let start = self.read_initial_state(&gens)?;
+		for generation in gens.generations.range(start..) {
+			self.recover_state(&mut generation.file.lock(), generation.gen_num)?;
+		}
+
+		// Writes are redone from the first one that may not have reached the
+		// segment files, which can be in a generation before the checkpoint.
+		let state = self.state.lock();
+		let first_dirty_index = state.dirty_pages.values().min().copied();
+		mem::drop(state);
+		if let Some(first_dirty_index) = first_dirty_index {
+			for generation in &gens.generations {
+				if generation.gen_num >= first_dirty_index.generation {
+					#[allow(clippy::needless_borrows_for_generic_args)]
+					self.redo(&mut generation.file.lock(), generation.gen_num, &mut handle)?;
+				}
+			}
+		}
 
 		let state = self.state.lock();
 		let all_tids = state.transactions.keys().copied().collect::<Vec<_>>();
@@ -541,6 +798,37 @@ impl<DF: DatabaseFolderApi + Send + Sync + 'static> WalApi for Wal<DF> {
 		let mut state = self.state.lock();
 		state.cache_did_flush();
 	}
+
+	fn has_full_history(&self) -> bool {
+		self.generations
+			.read()
+			.generations
+			.front()
+			.is_some_and(|generation| generation.gen_num == 0)
+	}
+
+	fn checkpoint(&self) -> Result<(), StorageError> {
+		if self.read_only {
+			return Err(StorageError::ReadOnly);
+		}
+		let start = Instant::now();
+		Self::checkpoint_impl(&self.generations, &self.state, &self.stats, &self.folder)?;
+		// The checkpoint item itself has to be durable before the database can be
+		// marked clean.
+		let gens = self.generations.read();
+		Self::flush_impl(&gens, &self.stats)?;
+		Self::sync_impl(&gens, &self.stats)?;
+		self.stats.checkpoints.fetch_add(1, Ordering::Relaxed);
+		self.stats
+			.checkpoint_duration
+			.observe_duration(start.elapsed());
+		Ok(())
+	}
+
+	fn stats(&self) -> WalStats {
+		let num_generations = self.generations.read().generations.len() as u64;
+		self.stats.get(num_generations)
+	}
 }
 
 struct WalGeneration<DF: DatabaseFolderApi> {
@@ -627,10 +915,14 @@ impl State {
 		self.dirty_pages.clear();
 	}
 
+	/// The oldest generation that recovery needs, either to undo an open
+	/// transaction, or to redo a write that may not have reached the segment
+	/// files yet.
 	fn first_needed_generation(&self) -> u64 {
-		self.transactions
-			.values()
-			.map(|ts| ts.first_gen)
+		let transaction_gens = self.transactions.values().map(|ts| ts.first_gen);
+		let dirty_page_gens = self.dirty_pages.values().map(|index| index.generation);
+		transaction_gens
+			.chain(dirty_page_gens)
 			.min()
 			.unwrap_or(u64::MAX)
 	}
@@ -646,14 +938,16 @@ impl State {
 
 #[cfg(test)]
 mod tests {
+	use futures::executor::ThreadPool;
 	use mockall::{predicate::*, Sequence};
 
 	use crate::{
-		files::MockDatabaseFolderApi,
+		files::{memory::MemoryFolder, segment::SegmentConfig, MockDatabaseFolderApi},
 		page_store::{
 			test_helpers::{page_address, wal_index},
 			wal::tests::wal::test_helpers::mock_wal_file,
 		},
+		tasks::{test_helpers::SimulatedScheduler, Scheduler},
 		utils::test_helpers::{map, non_zero},
 	};
 
@@ -687,13 +981,14 @@ mod tests {
 						})
 					})
 					.returning(|_| Ok(non_zero!(69)));
+				file.expect_size().returning(|| 100);
 				Ok(file)
 			});
 
 		// when
 		Wal::create(
 			Arc::new(folder),
-			Arc::new(ThreadPool::new().unwrap()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
 			&WalConfig::default(),
 		)
 		.unwrap();
@@ -706,7 +1001,8 @@ mod tests {
 		folder.expect_iter_wal_files().returning(|| {
 			//  WAL content
 
-			// An older generation; has already been flushed to disk.
+			// An older generation; its writes are redone, since the checkpoint of gen 3
+			// lists a page that was changed here as dirty.
 			let generation_2 = mock_wal_file! {
 				// The initial checkpoint. Not relevant to this test case.
 				10 => wal::Item::Checkpoint(wal::CheckpointData {
@@ -835,6 +1131,13 @@ mod tests {
 
 		// when
 		let mut expected_ops = vec![
+			// This reapplies write (2, 20).
+			PartialWriteOp {
+				index: wal_index!(2, 20),
+				page_address: page_address!(100, 200),
+				offset: 25,
+				buf: &[1, 2, 3, 4],
+			},
 			// This reapplies write (3, 10).
 			PartialWriteOp {
 				index: wal_index!(3, 10),
@@ -854,7 +1157,7 @@ mod tests {
 
 		let wal = Wal::open(
 			Arc::new(folder),
-			Arc::new(ThreadPool::new().unwrap()),
+			Arc::new(JobManager::new(Arc::new(ThreadPool::new().unwrap()))),
 			&WalConfig::default(),
 		)
 		.unwrap();
@@ -865,4 +1168,36 @@ mod tests {
 		})
 		.unwrap();
 	}
+
+	#[test]
+	fn checkpoint_periodically() {
+		// given
+		let folder = Arc::new(MemoryFolder::new(SegmentConfig::default()));
+		let scheduler = Arc::new(SimulatedScheduler::new());
+		let wal = Wal::create(
+			Arc::clone(&folder),
+			Arc::new(JobManager::new(Arc::clone(&scheduler) as Arc<dyn Scheduler>)),
+			&WalConfig {
+				checkpoint_period: Duration::from_secs(60),
+				..Default::default()
+			},
+		)
+		.unwrap();
+
+		// when
+		scheduler.advance(Duration::from_secs(59));
+		let checkpoints_before = wal.stats().checkpoints;
+		scheduler.advance(Duration::from_secs(1));
+		let checkpoints_after_one = wal.stats().checkpoints;
+		scheduler.advance(Duration::from_secs(120));
+		let checkpoints = wal.stats().checkpoints;
+		mem::drop(wal);
+		scheduler.run_until_stalled();
+
+		// then
+		assert_eq!(checkpoints_before, 0);
+		assert_eq!(checkpoints_after_one, 1);
+		assert_eq!(checkpoints, 3);
+		assert_eq!(Arc::strong_count(&folder), 1);
+	}
 }
@@ -1,16 +1,36 @@
 use std::{
+	collections::{BTreeMap, HashMap},
+	fmt,
+	future::Future,
+	mem,
+	pin::Pin,
 	sync::{
 		atomic::{AtomicBool, Ordering},
-		Arc,
+		Arc, OnceLock, Weak,
 	},
+	task::{Context, Poll, Waker},
 	thread,
-	time::{Duration, SystemTime},
+	time::{Duration, Instant},
 };
 
-#[derive(Clone)]
+use futures::{
+	executor::ThreadPool,
+	future::{self, BoxFuture, Either},
+};
+use log::{error, warn};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use crate::{consts::DEFAULT_RETRY_BACKOFF, page_store::StorageError};
+
+/// What the job manager does when a background job fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct FailureStrategy {
+	/// Whether the database becomes read-only once all retries have failed
 	pub fatal: bool,
 	pub retries: usize,
+	/// How long to wait before the first retry. The wait doubles with every
+	/// retry after that.
+	pub backoff: Duration,
 }
 
 impl Default for FailureStrategy {
@@ -18,47 +38,236 @@ impl Default for FailureStrategy {
 		Self {
 			fatal: false,
 			retries: 3,
+			backoff: DEFAULT_RETRY_BACKOFF,
+		}
+	}
+}
+
+impl FailureStrategy {
+	fn backoff(&self, retry: usize) -> Duration {
+		self.backoff
+			.saturating_mul(1 << u32::try_from(retry).unwrap_or(u32::MAX).min(16))
+	}
+}
+
+/// Runs background tasks, and keeps the time they are scheduled by.
+///
+/// The thread pool runs tasks on its threads and follows the monotonic system
+/// clock, while tests can use a simulated scheduler, which only runs tasks and
+/// advances time when told to.
+pub(crate) trait Scheduler: Send + Sync {
+	/// Returns the current time, which never goes backwards.
+	fn now(&self) -> Instant;
+
+	/// Returns a future that completes once the time has reached `deadline`.
+	fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+
+	fn spawn(&self, task: BoxFuture<'static, ()>);
+}
+
+impl Scheduler for ThreadPool {
+	fn now(&self) -> Instant {
+		Instant::now()
+	}
+
+	fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+		Box::pin(Sleep::new(Arc::clone(system_timers()), deadline))
+	}
+
+	fn spawn(&self, task: BoxFuture<'static, ()>) {
+		self.spawn_ok(task);
+	}
+}
+
+#[derive(Default)]
+struct TimerState {
+	/// The simulated time, or `None` if the timers follow the system clock
+	now: Option<Instant>,
+	next_id: u64,
+	wakers: BTreeMap<(Instant, u64), Waker>,
+}
+
+impl TimerState {
+	fn now(&self) -> Instant {
+		self.now.unwrap_or_else(Instant::now)
+	}
+
+	fn next_deadline(&self) -> Option<Instant> {
+		self.wakers.keys().next().map(|(deadline, _)| *deadline)
+	}
+
+	/// Removes the wakers of all timers that have expired at `now`.
+	fn expire(&mut self, now: Instant) -> Vec<Waker> {
+		let mut wakers = Vec::new();
+		while let Some(entry) = self.wakers.first_entry() {
+			if entry.key().0 > now {
+				break;
+			}
+			wakers.push(entry.remove());
+		}
+		wakers
+	}
+}
+
+/// The wakers of all sleeping tasks, ordered by their deadlines
+#[derive(Default)]
+struct TimerQueue {
+	state: Mutex<TimerState>,
+	condvar: Condvar,
+}
+
+impl TimerQueue {
+	fn simulated(now: Instant) -> Self {
+		Self {
+			state: Mutex::new(TimerState {
+				now: Some(now),
+				..Default::default()
+			}),
+			condvar: Condvar::new(),
+		}
+	}
+
+	/// Wakes sleeping tasks as their deadlines pass on the system clock.
+	fn run(&self) {
+		let mut state = self.state.lock();
+		loop {
+			let wakers = state.expire(Instant::now());
+			if !wakers.is_empty() {
+				MutexGuard::unlocked(&mut state, || wakers.into_iter().for_each(Waker::wake));
+				continue;
+			}
+			match state.next_deadline() {
+				Some(deadline) => {
+					self.condvar.wait_until(&mut state, deadline);
+				}
+				None => self.condvar.wait(&mut state),
+			}
+		}
+	}
+}
+
+/// Returns the timers of the system clock, which are expired by a dedicated
+/// thread.
+fn system_timers() -> &'static Arc<TimerQueue> {
+	static TIMERS: OnceLock<Arc<TimerQueue>> = OnceLock::new();
+	TIMERS.get_or_init(|| {
+		let timers = Arc::new(TimerQueue::default());
+		let thread_timers = Arc::clone(&timers);
+		thread::Builder::new()
+			.name("acorn-timers".to_string())
+			.spawn(move || thread_timers.run())
+			.expect("Failed to spawn the timer thread");
+		timers
+	})
+}
+
+struct Sleep {
+	timers: Arc<TimerQueue>,
+	deadline: Instant,
+	/// The id of the registered waker, if any
+	id: Option<u64>,
+}
+
+impl Sleep {
+	fn new(timers: Arc<TimerQueue>, deadline: Instant) -> Self {
+		Self {
+			timers,
+			deadline,
+			id: None,
+		}
+	}
+}
+
+impl Future for Sleep {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+		let this = self.get_mut();
+		let mut state = this.timers.state.lock();
+		if state.now() >= this.deadline {
+			if let Some(id) = this.id.take() {
+				state.wakers.remove(&(this.deadline, id));
+			}
+			return Poll::Ready(());
+		}
+
+		let id = *this.id.get_or_insert_with(|| {
+			state.next_id += 1;
+			state.next_id
+		});
+		state.wakers.insert((this.deadline, id), cx.waker().clone());
+		// The timer thread may be waiting for a later deadline.
+		this.timers.condvar.notify_one();
+		Poll::Pending
+	}
+}
+
+impl Drop for Sleep {
+	fn drop(&mut self) {
+		if let Some(id) = self.id {
+			self.timers.state.lock().wakers.remove(&(self.deadline, id));
 		}
 	}
 }
 
+/// Completes at a fixed period, until its handle is dropped.
 pub(crate) struct Timer {
-	last_run: SystemTime,
+	scheduler: Arc<dyn Scheduler>,
+	next_run: Instant,
 	period: Duration,
-	active: Arc<AtomicBool>,
+	shared: Arc<TimerShared>,
+}
+
+#[derive(Default)]
+struct TimerShared {
+	stopped: AtomicBool,
+	waker: Mutex<Option<Waker>>,
+}
+
+impl TimerShared {
+	fn stop(&self) {
+		self.stopped.store(true, Ordering::Release);
+		if let Some(waker) = self.waker.lock().take() {
+			waker.wake();
+		}
+	}
 }
 
 impl Timer {
-	pub fn new(period: Duration) -> (Self, TimerHandle) {
-		let active = Arc::new(AtomicBool::new(true));
+	pub fn new(scheduler: Arc<dyn Scheduler>, period: Duration) -> (Self, TimerHandle) {
+		let shared = Arc::new(TimerShared::default());
 		let timer = Self {
-			last_run: SystemTime::now(),
+			next_run: scheduler.now() + period,
+			scheduler,
 			period,
-			active: Arc::clone(&active),
+			shared: Arc::clone(&shared),
 		};
-		(timer, TimerHandle { active })
+		(timer, TimerHandle { shared })
 	}
 
-	pub fn wait(&self) -> bool {
-		if !self.active.load(Ordering::Relaxed) {
-			return false;
+	/// Waits until the period has passed since the last run. Returns `false`
+	/// as soon as the timer is stopped.
+	pub async fn wait(&mut self) -> bool {
+		let stopped = future::poll_fn(|cx| {
+			*self.shared.waker.lock() = Some(cx.waker().clone());
+			if self.shared.stopped.load(Ordering::Acquire) {
+				Poll::Ready(())
+			} else {
+				Poll::Pending
+			}
+		});
+		match future::select(self.scheduler.sleep_until(self.next_run), stopped).await {
+			Either::Left(_) if !self.shared.stopped.load(Ordering::Acquire) => {
+				self.next_run = self.scheduler.now() + self.period;
+				true
+			}
+			_ => false,
 		}
-		let duration = self.period.saturating_sub(
-			SystemTime::now()
-				.duration_since(self.last_run)
-				.unwrap_or(Duration::ZERO),
-		);
-		thread::sleep(duration);
-		true
-	}
-
-	fn reset(&mut self) {
-		self.last_run = SystemTime::now();
 	}
 }
 
 pub(crate) struct TimerHandle {
-	active: Arc<AtomicBool>,
+	shared: Arc<TimerShared>,
 }
 
 impl TimerHandle {
@@ -67,6 +276,479 @@ impl TimerHandle {
 
 impl Drop for TimerHandle {
 	fn drop(&mut self) {
-		self.active.store(false, Ordering::Relaxed);
+		self.shared.stop();
+	}
+}
+
+/// The kinds of background jobs whose failures are handled by the job manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum JobKind {
+	Checkpoint,
+	WriteBack,
+}
+
+impl fmt::Display for JobKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Checkpoint => write!(f, "WAL checkpoint"),
+			Self::WriteBack => write!(f, "Page write-back"),
+		}
+	}
+}
+
+/// The outcomes of the runs of a job since the database was opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct JobStatus {
+	/// The number of runs that succeeded, possibly after retries
+	pub succeeded: u64,
+	/// The number of runs that failed after all retries
+	pub failed: u64,
+	/// The number of failed attempts that were retried
+	pub retries: u64,
+	/// The error of the last failed attempt
+	pub last_error: Option<String>,
+}
+
+type StopHook = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct JobsState {
+	status: HashMap<JobKind, JobStatus>,
+	/// Why the database became read-only, if a job failed fatally
+	degraded: Option<String>,
+	stopped: bool,
+	/// The number of spawned tasks that haven't finished yet
+	running: usize,
+	timers: Vec<Weak<TimerShared>>,
+	stop_hooks: Vec<StopHook>,
+	stop_wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+struct JobsShared {
+	state: Mutex<JobsState>,
+	finished: Condvar,
+}
+
+/// Counts a spawned task as running until it is dropped, whether it finished
+/// or not.
+struct RunningTask(Arc<JobsShared>);
+
+impl Drop for RunningTask {
+	fn drop(&mut self) {
+		self.0.state.lock().running -= 1;
+		self.0.finished.notify_all();
+	}
+}
+
+/// Owns all background work of a page storage. It retries failed jobs as
+/// their [`FailureStrategy`] allows, makes the database read-only after a
+/// fatal failure, and joins all tasks on shutdown.
+pub(crate) struct JobManager {
+	scheduler: Arc<dyn Scheduler>,
+	shared: Arc<JobsShared>,
+}
+
+impl JobManager {
+	pub fn new(scheduler: Arc<dyn Scheduler>) -> Self {
+		Self {
+			scheduler,
+			shared: Arc::default(),
+		}
+	}
+
+	pub fn scheduler(&self) -> &dyn Scheduler {
+		&*self.scheduler
+	}
+
+	/// Runs the task in the background. It is joined on shutdown.
+	pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+		self.shared.state.lock().running += 1;
+		let running = RunningTask(Arc::clone(&self.shared));
+		self.scheduler.spawn(Box::pin(async move {
+			task.await;
+			mem::drop(running);
+		}));
+	}
+
+	/// Creates a timer that is stopped on shutdown, if its handle isn't
+	/// dropped before.
+	pub fn timer(&self, period: Duration) -> (Timer, TimerHandle) {
+		let (timer, handle) = Timer::new(Arc::clone(&self.scheduler), period);
+		let mut state = self.shared.state.lock();
+		if state.stopped {
+			handle.shared.stop();
+		} else {
+			state.timers.retain(|timer| timer.strong_count() > 0);
+			state.timers.push(Arc::downgrade(&handle.shared));
+		}
+		(timer, handle)
+	}
+
+	/// Registers a function that tells a task to stop on shutdown.
+	pub fn on_stop(&self, hook: impl FnOnce() + Send + 'static) {
+		let mut state = self.shared.state.lock();
+		if state.stopped {
+			mem::drop(state);
+			hook();
+		} else {
+			state.stop_hooks.push(Box::new(hook));
+		}
+	}
+
+	/// Runs a job, and retries it with a growing backoff as long as it fails
+	/// and the strategy allows. Once the strategy gives up, the error is
+	/// returned, and if the failure is fatal, the database becomes read-only.
+	pub async fn run<T>(
+		&self,
+		kind: JobKind,
+		strategy: &FailureStrategy,
+		mut job: impl FnMut() -> Result<T, StorageError>,
+	) -> Result<T, StorageError> {
+		let mut retry = 0;
+		loop {
+			let err = match job() {
+				Ok(value) => {
+					self.record(kind, |status| status.succeeded += 1);
+					return Ok(value);
+				}
+				Err(err) => err,
+			};
+			let retried = retry < strategy.retries && !self.shared.state.lock().stopped;
+			self.record(kind, |status| {
+				status.last_error = Some(err.to_string());
+				if retried {
+					status.retries += 1;
+				} else {
+					status.failed += 1;
+				}
+			});
+			if !retried {
+				error!("{kind} failed: {err}");
+				if strategy.fatal {
+					self.degrade(format!("{kind} failed: {err}"));
+				}
+				return Err(err);
+			}
+
+			warn!("{kind} failed, retrying: {err}");
+			let deadline = self.scheduler.now() + strategy.backoff(retry);
+			future::select(self.scheduler.sleep_until(deadline), self.stopped()).await;
+			retry += 1;
+		}
+	}
+
+	fn record(&self, kind: JobKind, update: impl FnOnce(&mut JobStatus)) {
+		update(self.shared.state.lock().status.entry(kind).or_default());
+	}
+
+	/// Makes the database read-only. Only the first reason is kept.
+	fn degrade(&self, reason: String) {
+		let mut state = self.shared.state.lock();
+		if state.degraded.is_none() {
+			error!("The database is read-only from now on");
+			state.degraded = Some(reason);
+		}
+	}
+
+	/// Returns a future that completes once the job manager is stopped.
+	fn stopped(&self) -> impl Future<Output = ()> + Unpin + '_ {
+		future::poll_fn(|cx| {
+			let mut state = self.shared.state.lock();
+			if state.stopped {
+				return Poll::Ready(());
+			}
+			if !state
+				.stop_wakers
+				.iter()
+				.any(|waker| waker.will_wake(cx.waker()))
+			{
+				state.stop_wakers.push(cx.waker().clone());
+			}
+			Poll::Pending
+		})
+	}
+
+	pub fn status(&self, kind: JobKind) -> JobStatus {
+		self.shared
+			.state
+			.lock()
+			.status
+			.get(&kind)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// Returns why the database became read-only, if a job failed fatally.
+	pub fn degraded(&self) -> Option<String> {
+		self.shared.state.lock().degraded.clone()
+	}
+
+	/// Tells all tasks to stop. Jobs that are running finish, but aren't
+	/// retried anymore.
+	pub fn stop(&self) {
+		let mut state = self.shared.state.lock();
+		state.stopped = true;
+		let timers = mem::take(&mut state.timers);
+		let stop_hooks = mem::take(&mut state.stop_hooks);
+		let stop_wakers = mem::take(&mut state.stop_wakers);
+		mem::drop(state);
+
+		for timer in timers.iter().filter_map(Weak::upgrade) {
+			timer.stop();
+		}
+		for hook in stop_hooks {
+			hook();
+		}
+		for waker in stop_wakers {
+			waker.wake();
+		}
+	}
+
+	/// Waits until all spawned tasks have finished.
+	pub fn join(&self) {
+		let mut state = self.shared.state.lock();
+		while state.running > 0 {
+			self.shared.finished.wait(&mut state);
+		}
+	}
+
+	pub fn shutdown(&self) {
+		self.stop();
+		self.join();
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+	use std::{collections::VecDeque, mem, sync::Weak};
+
+	use futures::task::{waker_ref, ArcWake};
+
+	use super::*;
+
+	/// A scheduler whose time only moves when it is advanced, and which only
+	/// runs tasks when told to, on the calling thread.
+	pub(crate) struct SimulatedScheduler {
+		timers: Arc<TimerQueue>,
+		ready: Arc<Mutex<VecDeque<Arc<SimulatedTask>>>>,
+	}
+
+	struct SimulatedTask {
+		future: Mutex<Option<BoxFuture<'static, ()>>>,
+		ready: Weak<Mutex<VecDeque<Arc<SimulatedTask>>>>,
+	}
+
+	impl ArcWake for SimulatedTask {
+		fn wake_by_ref(arc_self: &Arc<Self>) {
+			if let Some(ready) = arc_self.ready.upgrade() {
+				ready.lock().push_back(Arc::clone(arc_self));
+			}
+		}
+	}
+
+	impl SimulatedScheduler {
+		pub fn new() -> Self {
+			Self {
+				timers: Arc::new(TimerQueue::simulated(Instant::now())),
+				ready: Arc::new(Mutex::new(VecDeque::new())),
+			}
+		}
+
+		/// Runs tasks until all of them are waiting.
+		pub fn run_until_stalled(&self) {
+			loop {
+				let Some(task) = self.ready.lock().pop_front() else {
+					return;
+				};
+				let mut future = task.future.lock();
+				if let Some(running) = future.as_mut() {
+					let waker = waker_ref(&task);
+					if running
+						.as_mut()
+						.poll(&mut Context::from_waker(&waker))
+						.is_ready()
+					{
+						*future = None;
+					}
+				}
+			}
+		}
+
+		/// Moves the time forward by `duration`, stopping at each deadline on the
+		/// way to run the tasks that wake up.
+		pub fn advance(&self, duration: Duration) {
+			let target = self.now() + duration;
+			loop {
+				self.run_until_stalled();
+				let mut state = self.timers.state.lock();
+				let now = match state.next_deadline() {
+					Some(deadline) if deadline <= target => deadline,
+					_ => {
+						state.now = Some(target);
+						break;
+					}
+				};
+				state.now = Some(now);
+				let wakers = state.expire(now);
+				mem::drop(state);
+				wakers.into_iter().for_each(Waker::wake);
+			}
+			self.run_until_stalled();
+		}
+	}
+
+	impl Scheduler for SimulatedScheduler {
+		fn now(&self) -> Instant {
+			self.timers.state.lock().now()
+		}
+
+		fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+			Box::pin(Sleep::new(Arc::clone(&self.timers), deadline))
+		}
+
+		fn spawn(&self, task: BoxFuture<'static, ()>) {
+			self.ready.lock().push_back(Arc::new(SimulatedTask {
+				future: Mutex::new(Some(task)),
+				ready: Arc::downgrade(&self.ready),
+			}));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::atomic::AtomicUsize;
+
+	use futures::executor::block_on;
+
+	use super::{test_helpers::SimulatedScheduler, *};
+
+	fn spawn_counter(
+		scheduler: &Arc<SimulatedScheduler>,
+		period: Duration,
+	) -> (Arc<AtomicUsize>, TimerHandle) {
+		let (mut timer, handle) = Timer::new(Arc::clone(scheduler) as Arc<dyn Scheduler>, period);
+		let count = Arc::new(AtomicUsize::new(0));
+		let task_count = Arc::clone(&count);
+		scheduler.spawn(Box::pin(async move {
+			while timer.wait().await {
+				task_count.fetch_add(1, Ordering::Relaxed);
+			}
+		}));
+		(count, handle)
+	}
+
+	#[test]
+	fn run_timer_once_per_period() {
+		// given
+		let scheduler = Arc::new(SimulatedScheduler::new());
+		let (count, _handle) = spawn_counter(&scheduler, Duration::from_secs(10));
+
+		// when
+		scheduler.advance(Duration::from_secs(9));
+		let count_before = count.load(Ordering::Relaxed);
+		scheduler.advance(Duration::from_secs(1));
+		let count_after_one = count.load(Ordering::Relaxed);
+		scheduler.advance(Duration::from_secs(25));
+
+		// then
+		assert_eq!(count_before, 0);
+		assert_eq!(count_after_one, 1);
+		assert_eq!(count.load(Ordering::Relaxed), 3);
+	}
+
+	#[test]
+	fn stop_waiting_timer() {
+		// given
+		let scheduler = Arc::new(SimulatedScheduler::new());
+		let (count, handle) = spawn_counter(&scheduler, Duration::from_secs(10));
+		scheduler.run_until_stalled();
+
+		// when
+		handle.stop();
+		scheduler.run_until_stalled();
+
+		// then
+		assert_eq!(Arc::strong_count(&count), 1);
+	}
+
+	#[test]
+	fn sleep_on_system_clock() {
+		// given
+		let thread_pool = ThreadPool::new().unwrap();
+		let start = thread_pool.now();
+
+		// when
+		block_on(thread_pool.sleep_until(start + Duration::from_millis(20)));
+
+		// then
+		assert!(thread_pool.now() - start >= Duration::from_millis(20));
+	}
+
+	#[test]
+	fn retry_failed_job_with_backoff() {
+		// given
+		let scheduler = Arc::new(SimulatedScheduler::new());
+		let jobs = Arc::new(JobManager::new(Arc::clone(&scheduler) as Arc<dyn Scheduler>));
+		let attempts = Arc::new(AtomicUsize::new(0));
+		let task_jobs = Arc::clone(&jobs);
+		let task_attempts = Arc::clone(&attempts);
+		jobs.spawn(async move {
+			let _ = task_jobs
+				.run(
+					JobKind::WriteBack,
+					&FailureStrategy::default(),
+					|| match task_attempts.fetch_add(1, Ordering::Relaxed) {
+						0 | 1 => Err(StorageError::WalNotInitialized),
+						_ => Ok(()),
+					},
+				)
+				.await;
+		});
+
+		// when
+		scheduler.run_until_stalled();
+		let attempts_at_start = attempts.load(Ordering::Relaxed);
+		scheduler.advance(DEFAULT_RETRY_BACKOFF);
+		let attempts_after_first_backoff = attempts.load(Ordering::Relaxed);
+		scheduler.advance(2 * DEFAULT_RETRY_BACKOFF);
+		jobs.join();
+
+		// then
+		assert_eq!(attempts_at_start, 1);
+		assert_eq!(attempts_after_first_backoff, 2);
+		assert_eq!(attempts.load(Ordering::Relaxed), 3);
+		assert_eq!(
+			jobs.status(JobKind::WriteBack),
+			JobStatus {
+				succeeded: 1,
+				failed: 0,
+				retries: 2,
+				last_error: Some("The WAL was never initialized!".to_string()),
+			}
+		);
+		assert_eq!(jobs.degraded(), None);
+	}
+
+	#[test]
+	fn stop_and_join_tasks_on_shutdown() {
+		// given
+		let jobs = JobManager::new(Arc::new(ThreadPool::new().unwrap()));
+		let (mut timer, _handle) = jobs.timer(Duration::from_secs(3600));
+		let runs = Arc::new(AtomicUsize::new(0));
+		let task_runs = Arc::clone(&runs);
+		jobs.spawn(async move {
+			while timer.wait().await {
+				task_runs.fetch_add(1, Ordering::Relaxed);
+			}
+		});
+
+		// when
+		jobs.shutdown();
+
+		// then
+		assert_eq!(runs.load(Ordering::Relaxed), 0);
+		assert_eq!(Arc::strong_count(&runs), 1);
 	}
 }
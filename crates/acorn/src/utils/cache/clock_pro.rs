@@ -0,0 +1,381 @@
+use std::hash::Hash;
+
+use super::{
+	list::{IndexedList, Node, NodeId},
+	ReplacementPolicy,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+	Hot,
+	Cold,
+	/// The value was evicted during its test period, and is only kept to detect
+	/// whether it is accessed again before the period ends.
+	NonResident,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Meta {
+	status: Status,
+	/// Whether a cold value is in its test period, during which an access
+	/// promotes it to a hot value.
+	in_test: bool,
+}
+
+/// This is an implementation of the CLOCK-Pro algorithm.
+/// See [Jiang et. al. 2005](https://www.usenix.org/legacy/event/usenix05/tech/general/full_papers/jiang/jiang.pdf).
+pub(crate) struct ClockProPolicy<T> {
+	/// A single clock containing hot, cold and non-resident values. New values
+	/// are inserted right before `hand_hot`.
+	clock: IndexedList<T, Meta>,
+
+	/// The hand that demotes hot values that weren't referenced since it last
+	/// passed them
+	hand_hot: Option<NodeId>,
+
+	/// The hand that looks for cold values to evict
+	hand_cold: Option<NodeId>,
+
+	/// The hand that ends the test periods of cold values
+	hand_test: Option<NodeId>,
+
+	num_hot: usize,
+	num_cold: usize,
+	num_non_resident: usize,
+
+	/// The target number of cold values, which adapts to the workload.
+	cold_target_size: usize,
+
+	/// The total cache size
+	size: usize,
+}
+
+impl<T: Clone + Hash + Eq> ClockProPolicy<T> {
+	pub fn new(size: usize) -> Self {
+		Self {
+			clock: IndexedList::new(),
+			hand_hot: None,
+			hand_cold: None,
+			hand_test: None,
+			num_hot: 0,
+			num_cold: 0,
+			num_non_resident: 0,
+			cold_target_size: 1,
+			size,
+		}
+	}
+
+	fn hot_target_size(&self) -> usize {
+		self.size.saturating_sub(self.cold_target_size)
+	}
+
+	fn increase_cold_target(&mut self) {
+		self.cold_target_size = usize::min(self.cold_target_size + 1, self.size);
+	}
+
+	fn decrease_cold_target(&mut self) {
+		self.cold_target_size = usize::max(self.cold_target_size.saturating_sub(1), 1);
+	}
+
+	fn insert(&mut self, value: T, meta: Meta) {
+		if let Some(hand_hot) = self.hand_hot {
+			self.clock.insert_before(hand_hot, value, meta);
+		} else {
+			let id = self.clock.push_back(value, meta);
+			self.hand_hot = Some(id);
+			self.hand_cold = Some(id);
+			self.hand_test = Some(id);
+		}
+	}
+
+	/// Removes a node from the clock, moving all hands that point to it to the
+	/// next node.
+	fn remove(&mut self, id: NodeId) -> Node<T, Meta> {
+		let next = self.clock.next(id);
+		let next = if next == id { None } else { Some(next) };
+		for hand in [&mut self.hand_hot, &mut self.hand_cold, &mut self.hand_test] {
+			if *hand == Some(id) {
+				*hand = next;
+			}
+		}
+		self.clock.remove_node(id)
+	}
+
+	/// Runs `hand_cold` until it finds a cold value to evict.
+	fn run_hand_cold(&mut self) -> T {
+		loop {
+			if self.num_cold == 0 {
+				self.run_hand_hot();
+				continue;
+			}
+
+			let id = self.hand_cold.unwrap();
+			let node = self.clock.node_mut(id);
+			if node.meta.status != Status::Cold {
+				self.hand_cold = Some(self.clock.next(id));
+				continue;
+			}
+
+			if node.was_referenced() {
+				node.clear_referenced();
+				if node.meta.in_test {
+					// The value was accessed again during its test period, so its reuse distance
+					// is small enough for it to be hot.
+					node.meta = Meta {
+						status: Status::Hot,
+						in_test: false,
+					};
+					self.num_cold -= 1;
+					self.num_hot += 1;
+					self.hand_cold = Some(self.clock.next(id));
+					while self.num_hot > self.hot_target_size() {
+						self.run_hand_hot();
+					}
+				} else {
+					// Otherwise, it gets a new test period at the head of the clock.
+					let node = self.remove(id);
+					self.insert(
+						node.value,
+						Meta {
+							status: Status::Cold,
+							in_test: true,
+						},
+					);
+				}
+				continue;
+			}
+
+			// Values evicted during their test period are kept as non-resident values,
+			// so that we notice if they are accessed again soon.
+			self.num_cold -= 1;
+			let value = if node.meta.in_test {
+				node.meta.status = Status::NonResident;
+				self.num_non_resident += 1;
+				self.hand_cold = Some(self.clock.next(id));
+				self.clock.node(id).value.clone()
+			} else {
+				self.remove(id).value
+			};
+			while self.num_non_resident > self.size {
+				self.run_hand_test();
+			}
+			return value;
+		}
+	}
+
+	/// Runs `hand_hot` until it demotes a hot value. Test periods of the cold
+	/// values it passes end.
+	fn run_hand_hot(&mut self) {
+		debug_assert!(self.num_hot > 0);
+
+		loop {
+			let id = self.hand_hot.unwrap();
+			let node = self.clock.node_mut(id);
+			match node.meta.status {
+				Status::Hot => {
+					if node.was_referenced() {
+						node.clear_referenced();
+					} else {
+						node.meta.status = Status::Cold;
+						self.num_hot -= 1;
+						self.num_cold += 1;
+						self.hand_hot = Some(self.clock.next(id));
+						return;
+					}
+				}
+				Status::Cold => {
+					if node.meta.in_test {
+						node.meta.in_test = false;
+						self.decrease_cold_target();
+					}
+				}
+				Status::NonResident => {
+					self.remove(id);
+					self.num_non_resident -= 1;
+					self.decrease_cold_target();
+					continue;
+				}
+			}
+			self.hand_hot = Some(self.clock.next(id));
+		}
+	}
+
+	/// Runs `hand_test` until it removes a non-resident value. Test periods of
+	/// the cold values it passes end.
+	fn run_hand_test(&mut self) {
+		debug_assert!(self.num_non_resident > 0);
+
+		loop {
+			let id = self.hand_test.unwrap();
+			let node = self.clock.node_mut(id);
+			match node.meta.status {
+				Status::Hot => {}
+				Status::Cold => {
+					if node.meta.in_test {
+						node.meta.in_test = false;
+						self.decrease_cold_target();
+					}
+				}
+				Status::NonResident => {
+					self.remove(id);
+					self.num_non_resident -= 1;
+					self.decrease_cold_target();
+					return;
+				}
+			}
+			self.hand_test = Some(self.clock.next(id));
+		}
+	}
+
+	fn is_resident(&self, value: &T) -> bool {
+		let Some(id) = self.clock.id(value) else {
+			return false;
+		};
+		self.clock.node(id).meta.status != Status::NonResident
+	}
+}
+
+impl<T: Clone + Hash + Eq + Send + Sync> ReplacementPolicy<T> for ClockProPolicy<T> {
+	fn access(&self, value: &T) -> bool {
+		self.is_resident(value) && self.clock.access(value)
+	}
+
+	fn evict_replace(&mut self, value: T) -> Option<T> {
+		debug_assert!(!self.is_resident(&value));
+
+		let mut evicted: Option<T> = None;
+		if self.num_hot + self.num_cold >= self.size {
+			evicted = Some(self.run_hand_cold());
+		}
+
+		if let Some(id) = self.clock.id(&value) {
+			// The value is accessed again during its test period, so cold values should
+			// get more space.
+			self.increase_cold_target();
+			self.remove(id);
+			self.num_non_resident -= 1;
+			self.insert(
+				value,
+				Meta {
+					status: Status::Hot,
+					in_test: false,
+				},
+			);
+			self.num_hot += 1;
+			while self.num_hot > self.hot_target_size() {
+				self.run_hand_hot();
+			}
+		} else {
+			self.insert(
+				value,
+				Meta {
+					status: Status::Cold,
+					in_test: true,
+				},
+			);
+			self.num_cold += 1;
+		}
+		evicted
+	}
+
+	fn replace(&mut self, old: &T, new: T) -> bool {
+		debug_assert!(!self.is_resident(&new));
+
+		if !self.is_resident(old) {
+			return false;
+		}
+		if let Some(id) = self.clock.id(&new) {
+			self.remove(id);
+			self.num_non_resident -= 1;
+		}
+		self.clock.replace(old, new)
+	}
+
+	fn remove(&mut self, value: &T) -> bool {
+		let Some(id) = self.clock.id(value) else {
+			return false;
+		};
+		match self.clock.node(id).meta.status {
+			Status::Hot => self.num_hot -= 1,
+			Status::Cold => self.num_cold -= 1,
+			Status::NonResident => return false,
+		}
+		self.remove(id);
+		true
+	}
+
+	fn resize(&mut self, size: usize) {
+		self.size = size;
+		self.cold_target_size = self.cold_target_size.clamp(1, usize::max(size, 1));
+		while self.num_non_resident > size {
+			self.run_hand_test();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn status(clock_pro: &ClockProPolicy<u32>, value: u32) -> Status {
+		let id = clock_pro.clock.id(&value).unwrap();
+		clock_pro.clock.node(id).meta.status
+	}
+
+	#[test]
+	fn promote_referenced_cold_value() {
+		// given
+		let mut clock_pro = ClockProPolicy::new(3);
+		clock_pro.evict_replace(1);
+		clock_pro.evict_replace(2);
+		clock_pro.evict_replace(3);
+		clock_pro.access(&1);
+
+		// when
+		let evicted = clock_pro.evict_replace(4);
+
+		// then
+		assert_eq!(evicted, Some(2));
+		assert_eq!(status(&clock_pro, 1), Status::Hot);
+		assert_eq!(status(&clock_pro, 2), Status::NonResident);
+		assert!(!clock_pro.access(&2));
+	}
+
+	#[test]
+	fn promote_non_resident_value() {
+		// given
+		let mut clock_pro = ClockProPolicy::new(3);
+		clock_pro.evict_replace(1);
+		clock_pro.evict_replace(2);
+		clock_pro.evict_replace(3);
+		clock_pro.access(&1);
+		clock_pro.evict_replace(4); // 1 is promoted, 2 is evicted
+
+		// when
+		let evicted = clock_pro.evict_replace(2);
+
+		// then
+		assert_eq!(evicted, Some(3));
+		assert_eq!(status(&clock_pro, 2), Status::Hot);
+		assert_eq!(status(&clock_pro, 1), Status::Cold);
+		assert_eq!(clock_pro.cold_target_size, 2);
+	}
+
+	#[test]
+	fn remove_value() {
+		// given
+		let mut clock_pro = ClockProPolicy::new(3);
+		clock_pro.evict_replace(1);
+		clock_pro.evict_replace(2);
+		clock_pro.evict_replace(3);
+		clock_pro.evict_replace(4); // 1 is evicted, but stays as a non-resident value
+
+		// when
+		let removed = ReplacementPolicy::remove(&mut clock_pro, &2);
+
+		// then
+		assert!(removed);
+		assert!(!ReplacementPolicy::remove(&mut clock_pro, &1));
+		assert_eq!(clock_pro.evict_replace(5), None);
+	}
+}
@@ -0,0 +1,301 @@
+use std::{
+	collections::HashMap,
+	hash::Hash,
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+pub(super) type NodeId = usize;
+
+pub(super) struct Node<T, M> {
+	pub value: T,
+	pub meta: M,
+	referenced: AtomicBool,
+	prev: NodeId,
+	next: NodeId,
+}
+
+impl<T, M> Node<T, M> {
+	pub fn was_referenced(&self) -> bool {
+		self.referenced.load(Ordering::Relaxed)
+	}
+
+	pub fn clear_referenced(&mut self) {
+		*self.referenced.get_mut() = false;
+	}
+}
+
+/// A circular doubly linked list that stores its nodes in a slab and keeps an
+/// index from values to nodes, so that lookups, insertions and removals are all
+/// O(1).
+///
+/// Every node carries a reference bit, which can be set through a shared
+/// reference, and some policy-specific metadata.
+pub(super) struct IndexedList<T, M = ()> {
+	nodes: Vec<Option<Node<T, M>>>,
+	free: Vec<NodeId>,
+	indices: HashMap<T, NodeId>,
+	head: Option<NodeId>,
+}
+
+impl<T: Clone + Hash + Eq, M> IndexedList<T, M> {
+	pub fn new() -> Self {
+		Self {
+			nodes: Vec::new(),
+			free: Vec::new(),
+			indices: HashMap::new(),
+			head: None,
+		}
+	}
+
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.indices.len()
+	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.indices.is_empty()
+	}
+
+	#[inline]
+	pub fn contains(&self, value: &T) -> bool {
+		self.indices.contains_key(value)
+	}
+
+	#[inline]
+	pub fn id(&self, value: &T) -> Option<NodeId> {
+		self.indices.get(value).copied()
+	}
+
+	#[inline]
+	pub fn front(&self) -> Option<NodeId> {
+		self.head
+	}
+
+	#[inline]
+	pub fn next(&self, id: NodeId) -> NodeId {
+		self.node(id).next
+	}
+
+	pub fn node(&self, id: NodeId) -> &Node<T, M> {
+		self.nodes[id]
+			.as_ref()
+			.expect("Tried to access a removed list node!")
+	}
+
+	pub fn node_mut(&mut self, id: NodeId) -> &mut Node<T, M> {
+		self.nodes[id]
+			.as_mut()
+			.expect("Tried to access a removed list node!")
+	}
+
+	/// Sets the reference bit of the value's node. Returns `false` if the value
+	/// is not in the list.
+	pub fn access(&self, value: &T) -> bool {
+		let Some(id) = self.id(value) else {
+			return false;
+		};
+		self.node(id).referenced.store(true, Ordering::Relaxed);
+		true
+	}
+
+	/// Inserts a value at the back of the list, which is right before the
+	/// front, since the list is circular.
+	pub fn push_back(&mut self, value: T, meta: M) -> NodeId {
+		match self.head {
+			Some(head) => self.insert_before(head, value, meta),
+			None => {
+				let id = self.allocate(value, meta);
+				self.link(id, id, id);
+				self.head = Some(id);
+				id
+			}
+		}
+	}
+
+	/// Inserts a value right before the given node.
+	pub fn insert_before(&mut self, id: NodeId, value: T, meta: M) -> NodeId {
+		let prev = self.node(id).prev;
+		let new_id = self.allocate(value, meta);
+		self.link(new_id, prev, id);
+		new_id
+	}
+
+	pub fn pop_front(&mut self) -> Option<Node<T, M>> {
+		let head = self.head?;
+		Some(self.remove_node(head))
+	}
+
+	pub fn remove(&mut self, value: &T) -> Option<Node<T, M>> {
+		let id = self.id(value)?;
+		Some(self.remove_node(id))
+	}
+
+	pub fn remove_node(&mut self, id: NodeId) -> Node<T, M> {
+		let node = self.nodes[id]
+			.take()
+			.expect("Tried to remove a removed list node!");
+		self.indices.remove(&node.value);
+		self.free.push(id);
+
+		if node.next == id {
+			self.head = None;
+		} else {
+			self.node_mut(node.prev).next = node.next;
+			self.node_mut(node.next).prev = node.prev;
+			if self.head == Some(id) {
+				self.head = Some(node.next);
+			}
+		}
+		node
+	}
+
+	pub fn move_to_back(&mut self, id: NodeId) {
+		let node = self.remove_node(id);
+		self.push_back(node.value, node.meta);
+	}
+
+	/// Puts `new` in the place of `old`, keeping the node's position, reference
+	/// bit and metadata. Returns `false` if `old` is not in the list.
+	pub fn replace(&mut self, old: &T, new: T) -> bool {
+		debug_assert!(!self.contains(&new));
+
+		let Some(id) = self.indices.remove(old) else {
+			return false;
+		};
+		self.indices.insert(new.clone(), id);
+		self.node_mut(id).value = new;
+		true
+	}
+
+	fn allocate(&mut self, value: T, meta: M) -> NodeId {
+		debug_assert!(!self.contains(&value));
+
+		let node = Node {
+			value: value.clone(),
+			meta,
+			referenced: AtomicBool::new(false),
+			prev: 0,
+			next: 0,
+		};
+		let id = if let Some(id) = self.free.pop() {
+			self.nodes[id] = Some(node);
+			id
+		} else {
+			self.nodes.push(Some(node));
+			self.nodes.len() - 1
+		};
+		self.indices.insert(value, id);
+		id
+	}
+
+	fn link(&mut self, id: NodeId, prev: NodeId, next: NodeId) {
+		let node = self.node_mut(id);
+		node.prev = prev;
+		node.next = next;
+		self.node_mut(prev).next = id;
+		self.node_mut(next).prev = id;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn values(list: &IndexedList<u32>) -> Vec<u32> {
+		let mut values = Vec::new();
+		let Some(head) = list.front() else {
+			return values;
+		};
+		let mut id = head;
+		loop {
+			values.push(list.node(id).value);
+			id = list.next(id);
+			if id == head {
+				return values;
+			}
+		}
+	}
+
+	#[test]
+	fn push_and_pop() {
+		// given
+		let mut list = IndexedList::new();
+
+		// when
+		list.push_back(1, ());
+		list.push_back(2, ());
+		list.push_back(3, ());
+		let popped = list.pop_front().unwrap();
+
+		// then
+		assert_eq!(popped.value, 1);
+		assert_eq!(values(&list), vec![2, 3]);
+		assert_eq!(list.len(), 2);
+		assert!(!list.contains(&1));
+	}
+
+	#[test]
+	fn remove_and_reuse_node() {
+		// given
+		let mut list = IndexedList::new();
+		list.push_back(1, ());
+		list.push_back(2, ());
+		list.push_back(3, ());
+
+		// when
+		list.remove(&2);
+		list.push_back(4, ());
+
+		// then
+		assert_eq!(values(&list), vec![1, 3, 4]);
+		assert_eq!(list.nodes.len(), 3);
+	}
+
+	#[test]
+	fn remove_last_node() {
+		// given
+		let mut list = IndexedList::new();
+		list.push_back(1, ());
+
+		// when
+		list.remove(&1);
+
+		// then
+		assert!(list.is_empty());
+		assert!(list.front().is_none());
+	}
+
+	#[test]
+	fn move_node_to_back() {
+		// given
+		let mut list = IndexedList::new();
+		list.push_back(1, ());
+		list.push_back(2, ());
+		list.push_back(3, ());
+
+		// when
+		list.move_to_back(list.id(&1).unwrap());
+
+		// then
+		assert_eq!(values(&list), vec![2, 3, 1]);
+	}
+
+	#[test]
+	fn replace_value() {
+		// given
+		let mut list = IndexedList::new();
+		list.push_back(1, ());
+		list.push_back(2, ());
+		list.access(&1);
+
+		// when
+		let replaced = list.replace(&1, 3);
+
+		// then
+		assert!(replaced);
+		assert_eq!(values(&list), vec![3, 2]);
+		assert!(list.node(list.id(&3).unwrap()).was_referenced());
+		assert!(!list.access(&1));
+	}
+}
@@ -0,0 +1,84 @@
+use std::hash::Hash;
+
+use parking_lot::Mutex;
+
+use super::{list::IndexedList, ReplacementPolicy};
+
+/// A plain least recently used policy.
+///
+/// Unlike the clock-based policies, every access has to reorder the list, so
+/// accesses take a lock. This makes it scale worse under concurrent reads.
+pub(crate) struct LruPolicy<T> {
+	/// The cached values, from least to most recently used
+	values: Mutex<IndexedList<T>>,
+
+	/// The total cache size
+	size: usize,
+}
+
+impl<T: Clone + Hash + Eq> LruPolicy<T> {
+	pub fn new(size: usize) -> Self {
+		Self {
+			values: Mutex::new(IndexedList::new()),
+			size,
+		}
+	}
+}
+
+impl<T: Clone + Hash + Eq + Send + Sync> ReplacementPolicy<T> for LruPolicy<T> {
+	fn access(&self, value: &T) -> bool {
+		let mut values = self.values.lock();
+		let Some(id) = values.id(value) else {
+			return false;
+		};
+		values.move_to_back(id);
+		true
+	}
+
+	fn evict_replace(&mut self, value: T) -> Option<T> {
+		let values = self.values.get_mut();
+		debug_assert!(!values.contains(&value));
+
+		let mut evicted: Option<T> = None;
+		if values.len() >= self.size {
+			evicted = values.pop_front().map(|node| node.value);
+		}
+		values.push_back(value, ());
+		evicted
+	}
+
+	fn replace(&mut self, old: &T, new: T) -> bool {
+		self.values.get_mut().replace(old, new)
+	}
+
+	fn remove(&mut self, value: &T) -> bool {
+		self.values.get_mut().remove(value).is_some()
+	}
+
+	fn resize(&mut self, size: usize) {
+		self.size = size;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn evict_least_recently_used_value() {
+		// given
+		let mut lru = LruPolicy::new(3);
+		lru.evict_replace(1);
+		lru.evict_replace(2);
+		lru.evict_replace(3);
+		lru.access(&1);
+
+		// when
+		let evicted = lru.evict_replace(4);
+
+		// then
+		assert_eq!(evicted, Some(2));
+		assert!(lru.access(&1));
+		assert!(!lru.access(&2));
+	}
+}
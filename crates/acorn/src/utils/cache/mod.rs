@@ -0,0 +1,66 @@
+use std::hash::Hash;
+
+mod car;
+mod clock_pro;
+mod list;
+mod lru;
+pub(crate) mod trace;
+mod two_q;
+
+pub(crate) use car::CarPolicy;
+pub(crate) use clock_pro::ClockProPolicy;
+pub(crate) use lru::LruPolicy;
+pub(crate) use two_q::TwoQPolicy;
+
+/// Decides which values to evict from a cache of a fixed size.
+///
+/// Accesses are tracked through a shared reference, so that cache hits don't
+/// need exclusive access to the policy.
+pub(crate) trait ReplacementPolicy<T>: Send + Sync {
+	/// Track an access to the given value. Returns `false` if the value is not
+	/// in the cache.
+	fn access(&self, value: &T) -> bool;
+
+	/// Insert a value into the cache, potentially evicting a value to make
+	/// space. The value must not be in the cache already.
+	fn evict_replace(&mut self, value: T) -> Option<T>;
+
+	/// Puts `new` in the place of `old`, which is used to undo an `evict_replace`
+	/// with `old` that evicted `new`. Returns `false` if `old` is not in the
+	/// cache.
+	fn replace(&mut self, old: &T, new: T) -> bool;
+
+	/// Removes a value from the cache without evicting anything else. Returns
+	/// `false` if the value is not in the cache.
+	fn remove(&mut self, value: &T) -> bool;
+
+	/// Changes the cache size. If the cache shrinks, the values that no longer
+	/// fit are not evicted, instead the cache shrinks as values are removed.
+	fn resize(&mut self, size: usize);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReplacementPolicyKind {
+	/// Clock with Adaptive Replacement, see [`CarPolicy`]
+	Car,
+	/// Least Recently Used, see [`LruPolicy`]
+	Lru,
+	/// See [`TwoQPolicy`]
+	TwoQ,
+	/// See [`ClockProPolicy`]
+	ClockPro,
+}
+
+impl ReplacementPolicyKind {
+	pub fn create<T>(self, size: usize) -> Box<dyn ReplacementPolicy<T>>
+	where
+		T: Clone + Hash + Eq + Send + Sync + 'static,
+	{
+		match self {
+			Self::Car => Box::new(CarPolicy::new(size)),
+			Self::Lru => Box::new(LruPolicy::new(size)),
+			Self::TwoQ => Box::new(TwoQPolicy::new(size)),
+			Self::ClockPro => Box::new(ClockProPolicy::new(size)),
+		}
+	}
+}
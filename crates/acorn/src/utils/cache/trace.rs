@@ -0,0 +1,244 @@
+//! Access traces of caches, which are used to compare replacement policies on
+//! real workloads.
+//!
+//! A trace is a sequence of keys, each stored as a little-endian `u64`. Every
+//! key is a request to the cache, whether it was a hit or a miss. The page
+//! cache records a trace if `PageCacheConfig::trace_path` is set.
+//!
+//! Recorded traces are replayed against each replacement policy by the
+//! `bench_replay_*` benchmarks, which log the hit ratios. They always replay
+//! the traces in `fixtures/traces`, and also those listed in
+//! `ACORN_CACHE_TRACES`:
+//!
+//! ```sh
+//! ACORN_CACHE_TRACES=<trace files or directories> cargo bench -p acorn bench_replay
+//! ```
+//!
+//! `fixtures/traces/documents` was recorded from a database with 4 KiB pages
+//! that first had 2000 documents inserted, and then ran transactions that
+//! mostly read and updated a few hot documents, interrupted by scans over all
+//! documents.
+
+use std::{
+	fs::File,
+	io::{self, BufReader, BufWriter, Read, Write},
+	path::Path,
+	time::{Duration, Instant},
+};
+
+use log::error;
+use parking_lot::Mutex;
+
+use super::ReplacementPolicy;
+
+pub(crate) struct TraceRecorder {
+	writer: Mutex<BufWriter<File>>,
+}
+
+impl TraceRecorder {
+	pub fn create(path: &Path) -> io::Result<Self> {
+		Ok(Self {
+			writer: Mutex::new(BufWriter::new(File::create(path)?)),
+		})
+	}
+
+	pub fn record(&self, key: u64) {
+		if let Err(err) = self.writer.lock().write_all(&key.to_le_bytes()) {
+			error!("Failed to record cache trace: {err}");
+		}
+	}
+}
+
+pub(crate) fn read_trace(path: &Path) -> io::Result<Vec<u64>> {
+	let mut reader = BufReader::new(File::open(path)?);
+	let mut trace = Vec::new();
+	let mut buf = [0; 8];
+	loop {
+		match reader.read_exact(&mut buf) {
+			Ok(()) => trace.push(u64::from_le_bytes(buf)),
+			Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(trace),
+			Err(err) => return Err(err),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ReplayResult {
+	pub num_requests: usize,
+	pub num_hits: usize,
+	pub duration: Duration,
+}
+
+impl ReplayResult {
+	pub fn hit_ratio(&self) -> f64 {
+		if self.num_requests == 0 {
+			return 0.0;
+		}
+		self.num_hits as f64 / self.num_requests as f64
+	}
+}
+
+/// Replays a trace against a replacement policy, the same way the page cache
+/// uses it.
+pub(crate) fn replay(policy: &mut dyn ReplacementPolicy<u64>, trace: &[u64]) -> ReplayResult {
+	let start = Instant::now();
+	let mut num_hits = 0;
+	for key in trace {
+		if policy.access(key) {
+			num_hits += 1;
+		} else {
+			policy.evict_replace(*key);
+		}
+	}
+	ReplayResult {
+		num_requests: trace.len(),
+		num_hits,
+		duration: start.elapsed(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashSet, env, fs, path::PathBuf};
+
+	use log::info;
+	use test::Bencher;
+
+	use crate::utils::cache::{LruPolicy, ReplacementPolicyKind};
+
+	use super::*;
+
+	#[test]
+	fn record_and_read_trace() {
+		// given
+		let tempdir = tempfile::tempdir().unwrap();
+		let path = tempdir.path().join("trace");
+		let recorder = TraceRecorder::create(&path).unwrap();
+
+		// when
+		recorder.record(1);
+		recorder.record(69 << 16 | 420);
+		recorder.record(1);
+		drop(recorder);
+
+		// then
+		assert_eq!(read_trace(&path).unwrap(), vec![1, 69 << 16 | 420, 1]);
+	}
+
+	#[test]
+	fn replay_trace() {
+		// given
+		let mut policy = LruPolicy::new(2);
+
+		// when
+		let result = replay(&mut policy, &[1, 2, 1, 3, 2, 1]);
+
+		// then
+		assert_eq!(result.num_requests, 6);
+		assert_eq!(result.num_hits, 1);
+	}
+
+	const TRACES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/traces");
+
+	/// The committed traces, followed by the ones listed in
+	/// `ACORN_CACHE_TRACES`.
+	fn trace_paths() -> Vec<PathBuf> {
+		let mut paths = vec![PathBuf::from(TRACES_DIR)];
+		if let Some(extra_paths) = env::var_os("ACORN_CACHE_TRACES") {
+			paths.extend(env::split_paths(&extra_paths));
+		}
+		let mut trace_paths = Vec::new();
+		for path in paths {
+			if path.is_dir() {
+				let mut entries: Vec<PathBuf> = fs::read_dir(&path)
+					.unwrap()
+					.map(|entry| entry.unwrap().path())
+					.collect();
+				entries.sort();
+				trace_paths.extend(entries);
+			} else {
+				trace_paths.push(path);
+			}
+		}
+		assert!(
+			!trace_paths.is_empty(),
+			"No traces to replay in {TRACES_DIR} or ACORN_CACHE_TRACES"
+		);
+		trace_paths
+	}
+
+	/// Reads the traces, along with a cache size for each of them that holds a
+	/// tenth of its distinct keys.
+	fn read_traces() -> Vec<(PathBuf, Vec<u64>, usize)> {
+		trace_paths()
+			.into_iter()
+			.map(|path| {
+				let trace = read_trace(&path).unwrap();
+				assert!(!trace.is_empty(), "{} is empty", path.display());
+				let size = usize::max(trace.iter().collect::<HashSet<_>>().len() / 10, 1);
+				(path, trace, size)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn replay_recorded_trace() {
+		// given
+		let trace = read_trace(&PathBuf::from(TRACES_DIR).join("documents")).unwrap();
+		let size = trace.iter().collect::<HashSet<_>>().len() / 10;
+
+		// when
+		let hit_ratio =
+			|kind: ReplacementPolicyKind| replay(kind.create(size).as_mut(), &trace).hit_ratio();
+
+		// then
+		for kind in [
+			ReplacementPolicyKind::Car,
+			ReplacementPolicyKind::Lru,
+			ReplacementPolicyKind::TwoQ,
+			ReplacementPolicyKind::ClockPro,
+		] {
+			let ratio = hit_ratio(kind);
+			assert!(ratio > 0.8, "{kind:?} only hit {ratio}");
+		}
+	}
+
+	fn bench_replay(b: &mut Bencher, kind: ReplacementPolicyKind) {
+		let traces = read_traces();
+		for (path, trace, size) in &traces {
+			let result = replay(kind.create(*size).as_mut(), trace);
+			info!(
+				"{kind:?} on {} ({} requests, cache size {size}): {:.2}% hits",
+				path.display(),
+				result.num_requests,
+				result.hit_ratio() * 100.0
+			);
+		}
+
+		b.iter(|| {
+			for (_, trace, size) in &traces {
+				replay(kind.create(*size).as_mut(), trace);
+			}
+		})
+	}
+
+	#[bench]
+	fn bench_replay_car(b: &mut Bencher) {
+		bench_replay(b, ReplacementPolicyKind::Car);
+	}
+
+	#[bench]
+	fn bench_replay_lru(b: &mut Bencher) {
+		bench_replay(b, ReplacementPolicyKind::Lru);
+	}
+
+	#[bench]
+	fn bench_replay_two_q(b: &mut Bencher) {
+		bench_replay(b, ReplacementPolicyKind::TwoQ);
+	}
+
+	#[bench]
+	fn bench_replay_clock_pro(b: &mut Bencher) {
+		bench_replay(b, ReplacementPolicyKind::ClockPro);
+	}
+}
@@ -0,0 +1,177 @@
+use std::hash::Hash;
+
+use parking_lot::Mutex;
+
+use super::{list::IndexedList, ReplacementPolicy};
+
+/// This is an implementation of the full version of the 2Q algorithm.
+/// See [Johnson and Shasha 1994](https://www.vldb.org/conf/1994/P439.PDF).
+pub(crate) struct TwoQPolicy<T> {
+	/// A FIFO queue containing values that were only accessed once
+	recent: IndexedList<T>,
+
+	/// A FIFO queue containing values recently dropped from `recent`
+	recent_history: IndexedList<T>,
+
+	/// A LRU list containing values that were accessed again after they were
+	/// dropped from `recent`
+	frequent: Mutex<IndexedList<T>>,
+
+	/// The target size for `recent`
+	recent_size: usize,
+
+	/// The maximum size of `recent_history`
+	recent_history_size: usize,
+
+	/// The total cache size
+	size: usize,
+}
+
+impl<T: Clone + Hash + Eq> TwoQPolicy<T> {
+	pub fn new(size: usize) -> Self {
+		let mut two_q = Self {
+			recent: IndexedList::new(),
+			recent_history: IndexedList::new(),
+			frequent: Mutex::new(IndexedList::new()),
+			recent_size: 0,
+			recent_history_size: 0,
+			size: 0,
+		};
+		two_q.set_size(size);
+		two_q
+	}
+
+	fn set_size(&mut self, size: usize) {
+		// These are the sizes recommended by the paper.
+		self.recent_size = usize::max(size / 4, 1);
+		self.recent_history_size = usize::max(size / 2, 1);
+		self.size = size;
+	}
+
+	/// Evicts a value from the cache, unless the cache is empty
+	fn evict(&mut self) -> Option<T> {
+		let frequent = self.frequent.get_mut();
+		if self.recent.len() <= self.recent_size && !frequent.is_empty() {
+			return frequent.pop_front().map(|node| node.value);
+		}
+
+		// Values dropped from `recent` are remembered, so that they are put into
+		// `frequent` if they are accessed again soon.
+		let evicted = self.recent.pop_front()?.value;
+		if self.recent_history.len() >= self.recent_history_size {
+			self.recent_history.pop_front();
+		}
+		self.recent_history.push_back(evicted.clone(), ());
+		Some(evicted)
+	}
+}
+
+impl<T: Clone + Hash + Eq + Send + Sync> ReplacementPolicy<T> for TwoQPolicy<T> {
+	fn access(&self, value: &T) -> bool {
+		// Accesses to values in `recent` are ignored, since they are likely to be
+		// correlated with the first one.
+		if self.recent.contains(value) {
+			return true;
+		}
+		let mut frequent = self.frequent.lock();
+		let Some(id) = frequent.id(value) else {
+			return false;
+		};
+		frequent.move_to_back(id);
+		true
+	}
+
+	fn evict_replace(&mut self, value: T) -> Option<T> {
+		debug_assert!(!self.recent.contains(&value) && !self.frequent.get_mut().contains(&value));
+
+		// This has to be checked before evicting, since that might push the value out
+		// of `recent_history`.
+		let in_history = self.recent_history.remove(&value).is_some();
+
+		let mut evicted: Option<T> = None;
+		if self.recent.len() + self.frequent.get_mut().len() >= self.size {
+			evicted = self.evict();
+		}
+
+		if in_history {
+			self.frequent.get_mut().push_back(value, ());
+		} else {
+			self.recent.push_back(value, ());
+		}
+		evicted
+	}
+
+	fn replace(&mut self, old: &T, new: T) -> bool {
+		self.recent_history.remove(&new);
+		self.recent.replace(old, new.clone()) || self.frequent.get_mut().replace(old, new)
+	}
+
+	fn remove(&mut self, value: &T) -> bool {
+		self.recent.remove(value).is_some() || self.frequent.get_mut().remove(value).is_some()
+	}
+
+	fn resize(&mut self, size: usize) {
+		self.set_size(size);
+		while self.recent_history.len() > self.recent_history_size {
+			self.recent_history.pop_front();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn evict_values_accessed_once_first() {
+		// given
+		let mut two_q = TwoQPolicy::new(4);
+		two_q.evict_replace(1);
+		two_q.evict_replace(2);
+		two_q.evict_replace(3);
+		two_q.evict_replace(4);
+		two_q.evict_replace(5); // 1 is evicted to `recent_history`
+		two_q.evict_replace(1); // 1 is moved to `frequent`
+
+		// when
+		let evicted = two_q.evict_replace(6);
+
+		// then
+		assert_eq!(evicted, Some(3));
+		assert!(two_q.frequent.get_mut().contains(&1));
+	}
+
+	#[test]
+	fn evict_frequent_value_if_recent_is_small() {
+		// given
+		let mut two_q = TwoQPolicy::new(2);
+		two_q.evict_replace(1);
+		two_q.evict_replace(2);
+		two_q.evict_replace(3); // 1 is evicted to `recent_history`
+		two_q.evict_replace(1); // 1 is moved to `frequent`, 2 is evicted
+
+		// when
+		let evicted = two_q.evict_replace(4);
+
+		// then
+		assert_eq!(evicted, Some(1));
+	}
+
+	#[test]
+	fn shrink_recent_history() {
+		// given
+		let mut two_q = TwoQPolicy::new(8);
+		for i in 0..12 {
+			two_q.evict_replace(i);
+		}
+		assert_eq!(two_q.recent_history.len(), 4);
+
+		// when
+		two_q.resize(4);
+
+		// then
+		assert_eq!(two_q.recent_history.len(), 2);
+		assert!(two_q.recent_history.contains(&2));
+		assert!(two_q.recent_history.contains(&3));
+	}
+}
@@ -0,0 +1,206 @@
+//! Building blocks for the storage engine metrics, and a renderer for the
+//! [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+
+use std::{
+	fmt::Write,
+	sync::atomic::{AtomicU64, Ordering},
+	time::Duration,
+};
+
+/// Bucket bounds for latencies in microseconds, from 10µs to 10s.
+pub(crate) const LATENCY_BUCKETS_US: &[u64] = &[
+	10, 50, 100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 10_000_000,
+];
+
+/// Bucket bounds for the number of operations in a batch.
+pub(crate) const BATCH_SIZE_BUCKETS: &[u64] = &[1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024];
+
+/// A histogram with fixed buckets that can be updated concurrently.
+#[derive(Debug)]
+pub(crate) struct Histogram {
+	bounds: &'static [u64],
+	/// The number of values in each bucket. The last bucket holds the values
+	/// greater than all bounds.
+	buckets: Box<[AtomicU64]>,
+	sum: AtomicU64,
+}
+
+impl Histogram {
+	pub fn new(bounds: &'static [u64]) -> Self {
+		debug_assert!(bounds.is_sorted());
+		Self {
+			bounds,
+			buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+			sum: AtomicU64::new(0),
+		}
+	}
+
+	pub fn latency() -> Self {
+		Self::new(LATENCY_BUCKETS_US)
+	}
+
+	pub fn observe(&self, value: u64) {
+		let bucket = self.bounds.partition_point(|bound| *bound < value);
+		self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+		self.sum.fetch_add(value, Ordering::Relaxed);
+	}
+
+	/// Records a duration in microseconds. Use with [`LATENCY_BUCKETS_US`].
+	pub fn observe_duration(&self, duration: Duration) {
+		self.observe(u64::try_from(duration.as_micros()).unwrap_or(u64::MAX));
+	}
+
+	pub fn get(&self) -> HistogramSnapshot {
+		HistogramSnapshot {
+			bounds: self.bounds,
+			counts: self
+				.buckets
+				.iter()
+				.map(|bucket| bucket.load(Ordering::Relaxed))
+				.collect(),
+			sum: self.sum.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct HistogramSnapshot {
+	/// The upper bounds of the buckets, inclusive
+	pub bounds: &'static [u64],
+	/// The number of values in each bucket, with one more bucket than there are
+	/// bounds for values greater than all bounds
+	pub counts: Vec<u64>,
+	pub sum: u64,
+}
+
+impl HistogramSnapshot {
+	pub fn count(&self) -> u64 {
+		self.counts.iter().sum()
+	}
+}
+
+/// Renders metrics in the Prometheus text format.
+#[derive(Debug, Default)]
+pub(crate) struct PrometheusEncoder {
+	out: String,
+}
+
+impl PrometheusEncoder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn header(&mut self, name: &str, help: &str, kind: &str) {
+		writeln!(self.out, "# HELP {name} {help}").unwrap();
+		writeln!(self.out, "# TYPE {name} {kind}").unwrap();
+	}
+
+	pub fn counter(&mut self, name: &str, help: &str, value: u64) {
+		self.header(name, help, "counter");
+		writeln!(self.out, "{name} {value}").unwrap();
+	}
+
+	pub fn gauge(&mut self, name: &str, help: &str, value: u64) {
+		self.header(name, help, "gauge");
+		writeln!(self.out, "{name} {value}").unwrap();
+	}
+
+	/// Renders a counter with one sample per value of the given label.
+	pub fn labeled_counter(&mut self, name: &str, help: &str, label: &str, values: &[(&str, u64)]) {
+		self.header(name, help, "counter");
+		for (label_value, value) in values {
+			writeln!(self.out, "{name}{{{label}=\"{label_value}\"}} {value}").unwrap();
+		}
+	}
+
+	/// Renders a histogram. Bounds and the sum are divided by `divisor`, so that
+	/// they can be converted to base units, like seconds.
+	pub fn histogram(
+		&mut self,
+		name: &str,
+		help: &str,
+		histogram: &HistogramSnapshot,
+		divisor: f64,
+	) {
+		self.header(name, help, "histogram");
+		let mut cumulative = 0;
+		for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+			cumulative += count;
+			let bound = *bound as f64 / divisor;
+			writeln!(self.out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}").unwrap();
+		}
+		let count = histogram.count();
+		writeln!(self.out, "{name}_bucket{{le=\"+Inf\"}} {count}").unwrap();
+		writeln!(self.out, "{name}_sum {}", histogram.sum as f64 / divisor).unwrap();
+		writeln!(self.out, "{name}_count {count}").unwrap();
+	}
+
+	pub fn finish(self) -> String {
+		self.out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn observe_values() {
+		// given
+		let histogram = Histogram::new(&[1, 10, 100]);
+
+		// when
+		histogram.observe(0);
+		histogram.observe(1);
+		histogram.observe(50);
+		histogram.observe(1000);
+
+		// then
+		assert_eq!(
+			histogram.get(),
+			HistogramSnapshot {
+				bounds: &[1, 10, 100],
+				counts: vec![2, 0, 1, 1],
+				sum: 1051,
+			}
+		);
+	}
+
+	#[test]
+	fn render_prometheus_text() {
+		// given
+		let histogram = Histogram::new(&[1_000, 10_000]);
+		histogram.observe(500);
+		histogram.observe(2_000);
+		let mut encoder = PrometheusEncoder::new();
+
+		// when
+		encoder.counter("reads_total", "The number of reads", 69);
+		encoder.labeled_counter(
+			"items_total",
+			"The number of items",
+			"kind",
+			&[("write", 4), ("commit", 2)],
+		);
+		encoder.histogram("latency_seconds", "The latency", &histogram.get(), 1e6);
+
+		// then
+		assert_eq!(
+			encoder.finish(),
+			"# HELP reads_total The number of reads\n\
+			# TYPE reads_total counter\n\
+			reads_total 69\n\
+			# HELP items_total The number of items\n\
+			# TYPE items_total counter\n\
+			items_total{kind=\"write\"} 4\n\
+			items_total{kind=\"commit\"} 2\n\
+			# HELP latency_seconds The latency\n\
+			# TYPE latency_seconds histogram\n\
+			latency_seconds_bucket{le=\"0.001\"} 1\n\
+			latency_seconds_bucket{le=\"0.01\"} 2\n\
+			latency_seconds_bucket{le=\"+Inf\"} 2\n\
+			latency_seconds_sum 0.0025\n\
+			latency_seconds_count 2\n"
+		);
+	}
+}
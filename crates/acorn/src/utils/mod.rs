@@ -1,4 +1,5 @@
 pub(crate) mod cache;
+pub(crate) mod metrics;
 pub(crate) mod units;
 
 #[cfg(test)]
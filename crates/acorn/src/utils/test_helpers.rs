@@ -13,3 +13,15 @@ macro_rules! non_zero {
 	};
 }
 pub(crate) use non_zero;
+
+pub(crate) fn copy_dir(from: &std::path::Path, to: &std::path::Path) {
+	std::fs::create_dir_all(to).unwrap();
+	for entry in std::fs::read_dir(from).unwrap() {
+		let entry = entry.unwrap();
+		if entry.path().is_dir() {
+			copy_dir(&entry.path(), &to.join(entry.file_name()));
+		} else {
+			std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+		}
+	}
+}